@@ -0,0 +1,114 @@
+//! Builds the merkle allowlist that `stake` checks against from a CSV of
+//! `pubkey,cap` rows, using the leaf and node hashing of `staking::merkle`.
+//!
+//! The cap column is optional and defaults to zero, meaning the wallet has no
+//! cap beyond the pool's own limits.
+
+use std::{collections::HashSet, str::FromStr};
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{anyhow, bail, Context, Result};
+use staking::merkle::{hash_pair, leaf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub user: Pubkey,
+    pub cap: u64,
+}
+
+/// Parses `pubkey[,cap]` rows. Blank lines, `#` comments and a leading
+/// `pubkey,...` header are skipped; a wallet may only appear once.
+pub fn parse_csv(input: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (number, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split(',').map(str::trim);
+        let user = fields.next().unwrap_or_default();
+        if entries.is_empty() && user.eq_ignore_ascii_case("pubkey") {
+            continue;
+        }
+
+        let user = Pubkey::from_str(user)
+            .map_err(|e| anyhow!("line {}: invalid pubkey {user:?}: {e}", number + 1))?;
+        let cap = match fields.next() {
+            Some("") | None => 0,
+            Some(cap) => cap
+                .parse()
+                .with_context(|| format!("line {}: invalid cap {cap:?}", number + 1))?,
+        };
+        if fields.next().is_some() {
+            bail!("line {}: expected pubkey,cap", number + 1);
+        }
+        if !seen.insert(user) {
+            bail!("line {}: duplicate pubkey {user}", number + 1);
+        }
+
+        entries.push(Entry { user, cap });
+    }
+
+    Ok(entries)
+}
+
+pub struct MerkleTree {
+    /// Leaves first, root last. A node without a sibling is carried up to
+    /// the next layer unchanged.
+    layers: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Builds the tree over `entries` in order. Fails if there are none.
+    pub fn new(entries: &[Entry]) -> Result<Self> {
+        if entries.is_empty() {
+            bail!("allowlist is empty");
+        }
+
+        let mut layers = vec![entries
+            .iter()
+            .map(|entry| leaf(&entry.user, entry.cap))
+            .collect::<Vec<_>>()];
+
+        while layers.last().unwrap().len() > 1 {
+            let next = layers
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_pair(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+
+        Ok(Self { layers })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.layers.last().unwrap()[0]
+    }
+
+    /// Sibling hashes from the leaf of entry `index` up to the root.
+    pub fn proof(&self, mut index: usize) -> Vec<[u8; 32]> {
+        let mut proof = Vec::new();
+
+        for layer in &self.layers[..self.layers.len() - 1] {
+            if let Some(sibling) = layer.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+
+        proof
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,43 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde_json::json;
+use staking_allowlist::{parse_csv, to_hex, MerkleTree};
+
+/// Prints the merkle root to pass to `set_merkle_root` and every wallet's
+/// proof for `stake`, as JSON.
+#[derive(Parser)]
+struct Args {
+    /// CSV of `pubkey,cap` rows; the cap column may be left out.
+    csv: PathBuf,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let input = fs::read_to_string(&args.csv)
+        .with_context(|| format!("failed to read {}", args.csv.display()))?;
+
+    let entries = parse_csv(&input)?;
+    let tree = MerkleTree::new(&entries)?;
+
+    let proofs: Vec<_> = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            json!({
+                "pubkey": entry.user.to_string(),
+                "cap": entry.cap,
+                "proof": tree.proof(index).iter().map(|node| to_hex(node)).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+
+    let output = json!({
+        "root": to_hex(&tree.root()),
+        "entries": proofs,
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    Ok(())
+}
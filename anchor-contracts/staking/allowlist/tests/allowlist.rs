@@ -0,0 +1,355 @@
+use anchor_lang::{
+    prelude::{AccountInfo, Pubkey},
+    solana_program::{entrypoint::ProgramResult, program_pack::Pack},
+    system_program, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::spl_token;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    sysvar,
+    transaction::{Transaction, TransactionError},
+};
+use solana_system_interface::instruction as system_instruction;
+use staking::{
+    error::StakingError,
+    merkle::{leaf, verify},
+    state::{AllowlistProof, StakeLimits},
+};
+use staking_allowlist::{parse_csv, Entry, MerkleTree};
+
+fn staking_entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    staking::entry(program_id, accounts, data)
+}
+
+#[test]
+fn proofs_verify_against_the_on_chain_verifier() {
+    for size in 1..=9 {
+        let entries: Vec<_> = (0..size)
+            .map(|cap| Entry {
+                user: Pubkey::new_unique(),
+                cap,
+            })
+            .collect();
+        let tree = MerkleTree::new(&entries).unwrap();
+
+        for (index, entry) in entries.iter().enumerate() {
+            let proof = tree.proof(index);
+            assert!(verify(&proof, &tree.root(), leaf(&entry.user, entry.cap)));
+            assert!(!verify(
+                &proof,
+                &tree.root(),
+                leaf(&entry.user, entry.cap + 1)
+            ));
+        }
+    }
+}
+
+#[test]
+fn parses_csv_rows() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let csv = format!("pubkey,cap\n# launch list\n{alice},500\n\n{bob}\n");
+
+    assert_eq!(
+        parse_csv(&csv).unwrap(),
+        vec![
+            Entry {
+                user: alice,
+                cap: 500
+            },
+            Entry { user: bob, cap: 0 },
+        ]
+    );
+
+    assert!(parse_csv(&format!("{alice}\n{alice},1\n")).is_err());
+    assert!(parse_csv("not-a-pubkey,1\n").is_err());
+    assert!(parse_csv(&format!("{alice},lots\n")).is_err());
+    assert!(parse_csv(&format!("{alice},1,2\n")).is_err());
+    assert!(MerkleTree::new(&parse_csv("pubkey,cap\n").unwrap()).is_err());
+}
+
+struct Env {
+    context: ProgramTestContext,
+    mint: Pubkey,
+    vault: Pubkey,
+    staking_pool: Pubkey,
+}
+
+async fn send(
+    context: &mut ProgramTestContext,
+    ixs: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let tx = Transaction::new_signed_with_payer(
+        ixs,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await
+}
+
+fn assert_staking_error(result: Result<(), BanksClientError>, expected: StakingError) {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            assert_eq!(code, u32::from(expected))
+        }
+        err => panic!("unexpected error: {err:?}"),
+    }
+}
+
+async fn create_token_account(
+    context: &mut ProgramTestContext,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Pubkey {
+    let account = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+
+    send(
+        context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &account.pubkey(),
+                rent.minimum_balance(spl_token::state::Account::LEN),
+                spl_token::state::Account::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::ID,
+                &account.pubkey(),
+                mint,
+                owner,
+            )
+            .unwrap(),
+        ],
+        &[&account],
+    )
+    .await
+    .unwrap();
+
+    account.pubkey()
+}
+
+/// Creates a pool whose allowlist has the root `merkle_root`.
+async fn setup(merkle_root: [u8; 32]) -> Env {
+    let program_test = ProgramTest::new("staking", staking::ID, processor!(staking_entry));
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.pubkey();
+    let mint = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    send(
+        &mut context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::ID,
+                &mint.pubkey(),
+                &payer,
+                None,
+                6,
+            )
+            .unwrap(),
+        ],
+        &[&mint],
+    )
+    .await
+    .unwrap();
+
+    let (staking_pool, _) =
+        Pubkey::find_program_address(&[b"staking_pool", mint.pubkey().as_ref()], &staking::ID);
+    let vault = create_token_account(&mut context, &mint.pubkey(), &staking_pool).await;
+
+    send(
+        &mut context,
+        &[
+            Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::InitializePool {
+                    staking_pool,
+                    authority: payer,
+                    vault,
+                    mint: mint.pubkey(),
+                    system_program: system_program::ID,
+                    token_program: spl_token::ID,
+                    rent: sysvar::rent::ID,
+                }
+                .to_account_metas(None),
+                data: staking::instruction::InitializePool {
+                    reward_rate: 1,
+                    limits: StakeLimits::default(),
+                }
+                .data(),
+            },
+            Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::SetMerkleRoot {
+                    authority: payer,
+                    staking_pool,
+                }
+                .to_account_metas(None),
+                data: staking::instruction::SetMerkleRoot { merkle_root }.data(),
+            },
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    Env {
+        context,
+        mint: mint.pubkey(),
+        vault,
+        staking_pool,
+    }
+}
+
+/// Funds `user` with lamports and 1000 tokens.
+async fn fund(env: &mut Env, user: &Keypair) -> Pubkey {
+    let payer = env.context.payer.pubkey();
+    let token_account = create_token_account(&mut env.context, &env.mint, &user.pubkey()).await;
+
+    send(
+        &mut env.context,
+        &[
+            system_instruction::transfer(&payer, &user.pubkey(), 1_000_000_000),
+            spl_token::instruction::mint_to(
+                &spl_token::ID,
+                &env.mint,
+                &token_account,
+                &payer,
+                &[],
+                1_000,
+            )
+            .unwrap(),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    token_account
+}
+
+async fn stake(
+    env: &mut Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+    amount: u64,
+    allowlist: Option<AllowlistProof>,
+) -> Result<(), BanksClientError> {
+    let (user_stake_account, _) = Pubkey::find_program_address(
+        &[
+            b"user_stake",
+            env.staking_pool.as_ref(),
+            user.pubkey().as_ref(),
+        ],
+        &staking::ID,
+    );
+
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::Stake {
+                user: user.pubkey(),
+                beneficiary: user.pubkey(),
+                user_token_account,
+                vault: env.vault,
+                mint: env.mint,
+                staking_pool: env.staking_pool,
+                user_stake_account,
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::Stake { amount, allowlist }.data(),
+        }],
+        &[user],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn stake_requires_a_proof_from_the_csv_tree() {
+    let alice = Keypair::new();
+    let bob = Keypair::new();
+    let carol = Keypair::new();
+    let mallory = Keypair::new();
+
+    let csv = format!(
+        "pubkey,cap\n{},500\n{}\n{},100\n",
+        alice.pubkey(),
+        bob.pubkey(),
+        carol.pubkey()
+    );
+    let entries = parse_csv(&csv).unwrap();
+    let tree = MerkleTree::new(&entries).unwrap();
+    let proof_for = |index: usize, cap: u64| {
+        Some(AllowlistProof {
+            cap,
+            proof: tree.proof(index),
+        })
+    };
+
+    let mut env = setup(tree.root()).await;
+    let alice_tokens = fund(&mut env, &alice).await;
+    let bob_tokens = fund(&mut env, &bob).await;
+    let mallory_tokens = fund(&mut env, &mallory).await;
+
+    // Listed wallets stake up to the cap in their leaf.
+    stake(&mut env, &alice, alice_tokens, 400, proof_for(0, 500))
+        .await
+        .unwrap();
+    assert_staking_error(
+        stake(&mut env, &alice, alice_tokens, 101, proof_for(0, 500)).await,
+        StakingError::UserCapExceeded,
+    );
+    stake(&mut env, &bob, bob_tokens, 1_000, proof_for(1, 0))
+        .await
+        .unwrap();
+
+    // Claiming a bigger cap than the leaf commits to breaks the proof.
+    assert_staking_error(
+        stake(&mut env, &alice, alice_tokens, 100, proof_for(0, 0)).await,
+        StakingError::NotAllowlisted,
+    );
+
+    // Unlisted wallets cannot borrow someone else's proof or skip it.
+    assert_staking_error(
+        stake(&mut env, &mallory, mallory_tokens, 10, proof_for(0, 500)).await,
+        StakingError::NotAllowlisted,
+    );
+    assert_staking_error(
+        stake(&mut env, &mallory, mallory_tokens, 10, None).await,
+        StakingError::NotAllowlisted,
+    );
+}
+
+#[tokio::test]
+async fn pools_without_a_root_ignore_the_proof() {
+    let alice = Keypair::new();
+    let mut env = setup([0; 32]).await;
+    let alice_tokens = fund(&mut env, &alice).await;
+
+    stake(&mut env, &alice, alice_tokens, 10, None)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,253 @@
+//! Keeper logic for the staking program: decoding program accounts and
+//! turning them into the transactions the keeper should submit.
+//!
+//! Positions opt in by calling `authorize_claimer` with the keeper's key; the
+//! keeper then claims their rewards into the destination the owner chose.
+//! Owners who chose the pool's vault as destination have them compounded
+//! into their stake instead.
+//! Pools that nobody has touched for a while are cranked with `update_pool`.
+
+use anchor_lang::{
+    prelude::Pubkey,
+    solana_program::{instruction::Instruction, sysvar},
+    AccountDeserialize, Discriminator, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::{spl_token, TokenAccount};
+use staking::{
+    state::{StakingPool, UserStakeAccount},
+    utils::{earned, reward_per_token_at},
+};
+
+/// Offset of `UserStakeAccount::claimer`: discriminator, owner, amount,
+/// pending_rewards and last_stake_time precede it.
+pub const CLAIMER_OFFSET: usize = 8 + 32 + 8 + 8 + 8;
+
+pub struct Pool {
+    pub address: Pubkey,
+    pub state: StakingPool,
+    /// Mint of the pool's vault, which `claim_rewards` mints rewards from.
+    pub mint: Pubkey,
+}
+
+pub struct Position {
+    pub address: Pubkey,
+    pub state: UserStakeAccount,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Job {
+    Claim {
+        pool: Pubkey,
+        position: Pubkey,
+        owner: Pubkey,
+        rewards: u64,
+    },
+    Compound {
+        pool: Pubkey,
+        position: Pubkey,
+        owner: Pubkey,
+        rewards: u64,
+    },
+    Crank {
+        pool: Pubkey,
+    },
+}
+
+/// Decodes `StakingPool`s, skipping accounts with another discriminator.
+/// `vault_mint` resolves each pool's vault to its mint.
+pub fn decode_pools(
+    accounts: &[(Pubkey, Vec<u8>)],
+    mut vault_mint: impl FnMut(&Pubkey) -> Option<Pubkey>,
+) -> Vec<Pool> {
+    accounts
+        .iter()
+        .filter(|(_, data)| data.starts_with(StakingPool::DISCRIMINATOR))
+        .filter_map(|(address, data)| {
+            let state = StakingPool::try_deserialize(&mut data.as_slice()).ok()?;
+            let mint = vault_mint(&state.vault)?;
+            Some(Pool {
+                address: *address,
+                state,
+                mint,
+            })
+        })
+        .collect()
+}
+
+/// Decodes `UserStakeAccount`s, skipping accounts with another discriminator.
+pub fn decode_positions(accounts: &[(Pubkey, Vec<u8>)]) -> Vec<Position> {
+    accounts
+        .iter()
+        .filter(|(_, data)| data.starts_with(UserStakeAccount::DISCRIMINATOR))
+        .filter_map(|(address, data)| {
+            Some(Position {
+                address: *address,
+                state: UserStakeAccount::try_deserialize(&mut data.as_slice()).ok()?,
+            })
+        })
+        .collect()
+}
+
+pub fn decode_token_account_mint(data: &[u8]) -> Option<Pubkey> {
+    TokenAccount::try_deserialize(&mut &data[..])
+        .ok()
+        .map(|account| account.mint)
+}
+
+/// Picks the positions delegated to `keeper` that have at least `min_rewards`
+/// to claim at unix time `now`, and the pools
+/// whose accumulator has not moved for `crank_after` seconds. Positions
+/// paying into the pool's vault are compounded, unless the pool would turn
+/// the deposit away. A claim updates its pool, so pools with a claim planned
+/// are not cranked separately.
+pub fn plan(
+    keeper: &Pubkey,
+    pools: &[Pool],
+    positions: &[Position],
+    now: i64,
+    min_rewards: u64,
+    crank_after: i64,
+) -> Vec<Job> {
+    let mut jobs = Vec::new();
+
+    for pool in pools {
+        let reward_per_token = reward_per_token_at(&pool.state, now).unwrap_or(u128::MAX);
+        let claims_before = jobs.len();
+
+        for position in positions {
+            let state = &position.state;
+            if state.claimer != *keeper || !is_position_of(pool, position) {
+                continue;
+            }
+            let earned = earned(state, &pool.state, reward_per_token, now).unwrap_or(0);
+            let rewards = state.pending_rewards.saturating_add(earned);
+
+            if rewards == 0 || rewards < min_rewards {
+                continue;
+            }
+
+            if state.claim_destination == pool.state.vault {
+                // The keeper has no allowlist proofs to pass.
+                let accepted = pool.state.check_stake(rewards, state.amount, now).is_ok()
+                    && pool
+                        .state
+                        .check_allowlist(&state.owner, None, rewards, state.amount)
+                        .is_ok();
+                if accepted {
+                    jobs.push(Job::Compound {
+                        pool: pool.address,
+                        position: position.address,
+                        owner: state.owner,
+                        rewards,
+                    });
+                }
+            } else {
+                jobs.push(Job::Claim {
+                    pool: pool.address,
+                    position: position.address,
+                    owner: state.owner,
+                    rewards,
+                });
+            }
+        }
+
+        if jobs.len() == claims_before && now - pool.state.last_update_time >= crank_after {
+            jobs.push(Job::Crank { pool: pool.address });
+        }
+    }
+
+    jobs
+}
+
+fn is_position_of(pool: &Pool, position: &Position) -> bool {
+    let (expected, _) = Pubkey::find_program_address(
+        &[
+            b"user_stake",
+            pool.address.as_ref(),
+            position.state.owner.as_ref(),
+        ],
+        &staking::ID,
+    );
+    expected == position.address
+}
+
+/// Builds the instruction for `job`, signed by `keeper`. Cranks of pools that
+/// pay a tip collect it into `tip_account` when one is given.
+pub fn instruction(
+    keeper: &Pubkey,
+    pools: &[Pool],
+    positions: &[Position],
+    job: &Job,
+    tip_account: Option<&Pubkey>,
+) -> Option<Instruction> {
+    match job {
+        Job::Claim { pool, position, .. } => {
+            let pool = pools.iter().find(|p| p.address == *pool)?;
+            let position = positions.iter().find(|p| p.address == *position)?;
+
+            Some(Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::ClaimRewards {
+                    claimer: *keeper,
+                    user: position.state.owner,
+                    user_token_account: position.state.claim_destination,
+                    mint: pool.mint,
+                    vault: pool.state.vault,
+                    staking_pool: pool.address,
+                    user_stake_account: position.address,
+                    token_program: spl_token::ID,
+                    instructions: sysvar::instructions::ID,
+                }
+                .to_account_metas(None),
+                data: staking::instruction::ClaimRewards {}.data(),
+            })
+        }
+        Job::Compound { pool, position, .. } => {
+            let pool = pools.iter().find(|p| p.address == *pool)?;
+            let position = positions.iter().find(|p| p.address == *position)?;
+
+            Some(Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::Compound {
+                    claimer: *keeper,
+                    user: position.state.owner,
+                    mint: pool.mint,
+                    vault: pool.state.vault,
+                    staking_pool: pool.address,
+                    user_stake_account: position.address,
+                    token_program: spl_token::ID,
+                    instructions: sysvar::instructions::ID,
+                }
+                .to_account_metas(None),
+                data: staking::instruction::Compound { allowlist: None }.data(),
+            })
+        }
+        Job::Crank { pool } => {
+            let pool = pools.iter().find(|p| p.address == *pool)?;
+            let tipped = pool.state.crank_tip > 0 && pool.state.tip_vault != Pubkey::default();
+            let tip_account = tip_account.filter(|_| tipped);
+
+            Some(Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::UpdatePool {
+                    cranker: *keeper,
+                    mint: pool.mint,
+                    staking_pool: pool.address,
+                    tip_vault: tip_account.map(|_| pool.state.tip_vault),
+                    cranker_token_account: tip_account.copied(),
+                    token_program: tip_account.map(|_| spl_token::ID),
+                }
+                .to_account_metas(None),
+                data: staking::instruction::UpdatePool {}.data(),
+            })
+        }
+    }
+}
+
+/// Splits `instructions` into transactions of at most `batch_size` each.
+pub fn batch(instructions: Vec<Instruction>, batch_size: usize) -> Vec<Vec<Instruction>> {
+    instructions
+        .chunks(batch_size.max(1))
+        .map(|chunk| chunk.to_vec())
+        .collect()
+}
@@ -0,0 +1,265 @@
+use std::{path::PathBuf, thread, time::Duration};
+
+use anchor_lang::{prelude::Pubkey, Discriminator};
+use anchor_spl::associated_token::get_associated_token_address;
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::{
+    config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{
+    account::from_account,
+    clock::Clock,
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    signature::{read_keypair_file, Keypair, Signer},
+    sysvar,
+    transaction::Transaction,
+};
+use staking::state::{StakingPool, UserStakeAccount};
+use staking_keeper::{
+    batch, decode_pools, decode_positions, decode_token_account_mint, plan, Job, CLAIMER_OFFSET,
+};
+
+/// Claims or compounds rewards for every staking position that authorized
+/// this keeper and cranks idle pools.
+#[derive(Parser)]
+struct Args {
+    /// RPC endpoint to scan and submit to.
+    #[arg(long, default_value = "http://127.0.0.1:8899")]
+    rpc_url: String,
+
+    /// Keypair the positions authorized with `authorize_claimer`; also pays fees.
+    #[arg(long)]
+    keypair: PathBuf,
+
+    /// Simulate transactions instead of sending them.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Instructions per transaction.
+    #[arg(long, default_value_t = 4)]
+    batch_size: usize,
+
+    /// Attempts per transaction after the first failure.
+    #[arg(long, default_value_t = 3)]
+    max_retries: u32,
+
+    /// Skip positions with fewer pending rewards than this.
+    #[arg(long, default_value_t = 1)]
+    min_rewards: u64,
+
+    /// Crank pools whose accumulator has not moved for this many seconds.
+    #[arg(long, default_value_t = 3600)]
+    crank_after: i64,
+
+    /// Collect crank tips into the keeper's associated token account for the
+    /// pool mint, which must already exist.
+    #[arg(long)]
+    claim_tips: bool,
+
+    /// Seconds between scans.
+    #[arg(long, default_value_t = 60)]
+    interval: u64,
+
+    /// Run a single scan and exit.
+    #[arg(long)]
+    once: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let keeper = read_keypair_file(&args.keypair)
+        .map_err(|e| anyhow!("failed to read keypair {}: {e}", args.keypair.display()))?;
+    let rpc = RpcClient::new_with_commitment(args.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    loop {
+        if let Err(e) = run_once(&rpc, &keeper, &args) {
+            eprintln!("scan failed: {e:#}");
+        }
+
+        if args.once {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(args.interval));
+    }
+}
+
+fn run_once(rpc: &RpcClient, keeper: &Keypair, args: &Args) -> Result<()> {
+    let pool_accounts =
+        program_accounts(rpc, vec![discriminator_filter(StakingPool::DISCRIMINATOR)])?;
+    let position_accounts = program_accounts(
+        rpc,
+        vec![
+            discriminator_filter(UserStakeAccount::DISCRIMINATOR),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                CLAIMER_OFFSET,
+                keeper.pubkey().as_ref(),
+            )),
+        ],
+    )?;
+
+    // Pools only record their vault; `claim_rewards` also needs the mint.
+    let pools = decode_pools(&pool_accounts, |vault| {
+        rpc.get_account_data(vault)
+            .ok()
+            .and_then(|data| decode_token_account_mint(&data))
+    });
+    let positions = decode_positions(&position_accounts);
+
+    let clock: Clock = from_account(&rpc.get_account(&sysvar::clock::ID)?)
+        .ok_or_else(|| anyhow!("failed to decode clock sysvar"))?;
+
+    let jobs = plan(
+        &keeper.pubkey(),
+        &pools,
+        &positions,
+        clock.unix_timestamp,
+        args.min_rewards,
+        args.crank_after,
+    );
+    println!(
+        "scanned {} pools and {} delegated positions, {} jobs",
+        pools.len(),
+        positions.len(),
+        jobs.len()
+    );
+
+    let mut instructions = Vec::new();
+    for job in &jobs {
+        let tip_account = match job {
+            Job::Claim {
+                position,
+                owner,
+                rewards,
+                ..
+            } => {
+                println!("claim {rewards} for {owner} (position {position})");
+                None
+            }
+            Job::Compound {
+                position,
+                owner,
+                rewards,
+                ..
+            } => {
+                println!("compound {rewards} for {owner} (position {position})");
+                None
+            }
+            Job::Crank { pool } => {
+                println!("crank pool {pool}");
+                pools
+                    .iter()
+                    .find(|p| p.address == *pool)
+                    .filter(|_| args.claim_tips)
+                    .map(|p| get_associated_token_address(&keeper.pubkey(), &p.mint))
+            }
+        };
+
+        match staking_keeper::instruction(
+            &keeper.pubkey(),
+            &pools,
+            &positions,
+            job,
+            tip_account.as_ref(),
+        ) {
+            Some(ix) => instructions.push(ix),
+            None => eprintln!("skipping {job:?}: pool not found"),
+        }
+    }
+
+    for ixs in batch(instructions, args.batch_size) {
+        if let Err(e) = submit(rpc, keeper, &ixs, args) {
+            eprintln!("batch of {} failed: {e:#}", ixs.len());
+        }
+    }
+
+    Ok(())
+}
+
+fn discriminator_filter(discriminator: &[u8]) -> RpcFilterType {
+    RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, discriminator))
+}
+
+fn program_accounts(
+    rpc: &RpcClient,
+    filters: Vec<RpcFilterType>,
+) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+    let accounts = rpc
+        .get_program_accounts_with_config(
+            &staking::ID,
+            RpcProgramAccountsConfig {
+                filters: Some(filters),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            },
+        )
+        .context("getProgramAccounts")?;
+
+    Ok(accounts
+        .into_iter()
+        .map(|(address, account)| (address, account.data))
+        .collect())
+}
+
+/// Sends (or simulates, with `--dry-run`) one transaction, retrying with a
+/// fresh blockhash and linear backoff.
+fn submit(
+    rpc: &RpcClient,
+    keeper: &Keypair,
+    instructions: &[Instruction],
+    args: &Args,
+) -> Result<()> {
+    let mut attempt = 0;
+
+    loop {
+        let result = rpc
+            .get_latest_blockhash()
+            .map_err(anyhow::Error::from)
+            .and_then(|blockhash| {
+                let tx = Transaction::new_signed_with_payer(
+                    instructions,
+                    Some(&keeper.pubkey()),
+                    &[keeper],
+                    blockhash,
+                );
+
+                if args.dry_run {
+                    let simulation = rpc.simulate_transaction(&tx)?.value;
+                    match simulation.err {
+                        Some(err) => Err(anyhow!(
+                            "simulation failed: {err}, logs: {:?}",
+                            simulation.logs
+                        )),
+                        None => {
+                            println!(
+                                "dry run ok ({} CU)",
+                                simulation.units_consumed.unwrap_or_default()
+                            );
+                            Ok(())
+                        }
+                    }
+                } else {
+                    let signature = rpc.send_and_confirm_transaction(&tx)?;
+                    println!("sent {signature}");
+                    Ok(())
+                }
+            });
+
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < args.max_retries => {
+                attempt += 1;
+                eprintln!("attempt {attempt} failed: {e:#}, retrying");
+                thread::sleep(Duration::from_secs(2 * attempt as u64));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
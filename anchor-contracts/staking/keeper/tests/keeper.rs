@@ -0,0 +1,343 @@
+#[path = "../../programs/staking/tests/common/mod.rs"]
+mod common;
+
+use anchor_lang::{
+    prelude::{Clock, Pubkey},
+    AccountDeserialize, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::spl_token;
+use common::{
+    account_data, create_token_account, new_user, send, setup, stake, token_balance,
+    user_stake_address, Env,
+};
+use solana_sdk::{
+    instruction::Instruction,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solana_system_interface::instruction as system_instruction;
+use staking::state::{StakeLimits, StakingPool, UserStakeAccount};
+use staking_keeper::{batch, decode_pools, decode_positions, decode_token_account_mint, plan, Job};
+
+const REWARD_RATE: u64 = 3;
+const STAKE_AMOUNT: u64 = 500;
+
+/// Funds a fresh user and stakes `STAKE_AMOUNT` for them, returning the user
+/// and their position.
+async fn stake_new_user(env: &mut Env) -> (Keypair, Pubkey) {
+    let (user, token_account) = new_user(env, STAKE_AMOUNT).await;
+    stake(env, &user, token_account, STAKE_AMOUNT)
+        .await
+        .unwrap();
+    let position = user_stake_address(env, &user);
+    (user, position)
+}
+
+#[tokio::test]
+async fn keeper_claims_only_for_delegated_positions() {
+    let mut env = setup(REWARD_RATE, StakeLimits::default()).await;
+    let (delegating_user, delegated_position) = stake_new_user(&mut env).await;
+    let (_, other_position) = stake_new_user(&mut env).await;
+    let Env {
+        mut context,
+        mint,
+        vault,
+        staking_pool,
+    } = env;
+    let payer = context.payer.pubkey();
+    let keeper = Keypair::new();
+
+    let destination = create_token_account(&mut context, &mint, &delegating_user.pubkey()).await;
+    send(
+        &mut context,
+        &[
+            Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::AuthorizeClaimer {
+                    user: delegating_user.pubkey(),
+                    staking_pool,
+                    user_stake_account: delegated_position,
+                }
+                .to_account_metas(None),
+                data: staking::instruction::AuthorizeClaimer {
+                    claimer: keeper.pubkey(),
+                    destination,
+                }
+                .data(),
+            },
+            spl_token::instruction::set_authority(
+                &spl_token::ID,
+                &mint,
+                Some(&staking_pool),
+                spl_token::instruction::AuthorityType::MintTokens,
+                &payer,
+                &[],
+            )
+            .unwrap(),
+            system_instruction::transfer(&payer, &keeper.pubkey(), 1_000_000_000),
+        ],
+        &[&delegating_user],
+    )
+    .await
+    .unwrap();
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += 60;
+    context.set_sysvar(&clock);
+
+    // Feed the keeper what getProgramAccounts would have returned.
+    let pool_accounts = vec![(staking_pool, account_data(&mut context, staking_pool).await)];
+    let position_accounts = vec![
+        (
+            delegated_position,
+            account_data(&mut context, delegated_position).await,
+        ),
+        (
+            other_position,
+            account_data(&mut context, other_position).await,
+        ),
+    ];
+    let vault_data = account_data(&mut context, vault).await;
+
+    let pools = decode_pools(&pool_accounts, |_| decode_token_account_mint(&vault_data));
+    let mut positions = decode_positions(&position_accounts);
+    assert_eq!(pools.len(), 1);
+    assert_eq!(positions.len(), 2);
+
+    // Stake still warming up has earned nothing to claim.
+    for position in &mut positions {
+        position.state.warming_amount = position.state.amount;
+        position.state.last_stake_time = clock.unix_timestamp;
+    }
+    assert!(plan(
+        &keeper.pubkey(),
+        &pools,
+        &positions,
+        clock.unix_timestamp,
+        1,
+        3600
+    )
+    .is_empty());
+    for position in &mut positions {
+        position.state.warming_amount = 0;
+    }
+
+    let jobs = plan(
+        &keeper.pubkey(),
+        &pools,
+        &positions,
+        clock.unix_timestamp,
+        1,
+        3600,
+    );
+    let expected_rewards = 60 * REWARD_RATE * STAKE_AMOUNT;
+    assert!(matches!(
+        jobs.as_slice(),
+        [Job::Claim { position, rewards, .. }] if *position == delegated_position && *rewards >= expected_rewards
+    ));
+
+    let instructions = jobs
+        .iter()
+        .map(|job| {
+            staking_keeper::instruction(&keeper.pubkey(), &pools, &positions, job, None).unwrap()
+        })
+        .collect();
+
+    for ixs in batch(instructions, 4) {
+        let blockhash = context.get_new_latest_blockhash().await.unwrap();
+        let tx =
+            Transaction::new_signed_with_payer(&ixs, Some(&keeper.pubkey()), &[&keeper], blockhash);
+        context.banks_client.process_transaction(tx).await.unwrap();
+    }
+
+    assert!(token_balance(&mut context, destination).await >= expected_rewards);
+}
+
+#[tokio::test]
+async fn keeper_cranks_idle_pools_and_collects_the_tip() {
+    let Env {
+        mut context,
+        mint,
+        vault,
+        staking_pool,
+    } = setup(REWARD_RATE, StakeLimits::default()).await;
+    let payer = context.payer.pubkey();
+    let keeper = Keypair::new();
+
+    let tip_vault = create_token_account(&mut context, &mint, &staking_pool).await;
+    let keeper_token_account = create_token_account(&mut context, &mint, &keeper.pubkey()).await;
+    send(
+        &mut context,
+        &[
+            spl_token::instruction::mint_to(&spl_token::ID, &mint, &tip_vault, &payer, &[], 100)
+                .unwrap(),
+            Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::SetCrankTip {
+                    authority: payer,
+                    mint,
+                    staking_pool,
+                    tip_vault,
+                }
+                .to_account_metas(None),
+                data: staking::instruction::SetCrankTip { crank_tip: 7 }.data(),
+            },
+            system_instruction::transfer(&payer, &keeper.pubkey(), 1_000_000_000),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let pool_accounts = vec![(staking_pool, account_data(&mut context, staking_pool).await)];
+    let vault_data = account_data(&mut context, vault).await;
+    let pools = decode_pools(&pool_accounts, |_| decode_token_account_mint(&vault_data));
+    let last_update_time = pools[0].state.last_update_time;
+
+    // Not idle long enough yet.
+    assert!(plan(
+        &keeper.pubkey(),
+        &pools,
+        &[],
+        last_update_time + 10,
+        1,
+        3600
+    )
+    .is_empty());
+
+    let now = last_update_time + 3600;
+    let jobs = plan(&keeper.pubkey(), &pools, &[], now, 1, 3600);
+    assert_eq!(jobs, vec![Job::Crank { pool: staking_pool }]);
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp = now;
+    context.set_sysvar(&clock);
+
+    let ix = staking_keeper::instruction(
+        &keeper.pubkey(),
+        &pools,
+        &[],
+        &jobs[0],
+        Some(&keeper_token_account),
+    )
+    .unwrap();
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx =
+        Transaction::new_signed_with_payer(&[ix], Some(&keeper.pubkey()), &[&keeper], blockhash);
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    assert_eq!(token_balance(&mut context, keeper_token_account).await, 7);
+    assert_eq!(token_balance(&mut context, tip_vault).await, 93);
+
+    let data = account_data(&mut context, staking_pool).await;
+    let pool = StakingPool::try_deserialize(&mut data.as_slice()).unwrap();
+    assert_eq!(pool.last_update_time, now);
+    assert_eq!(pool.reward_per_token, 3600 * REWARD_RATE as u128);
+}
+
+#[tokio::test]
+async fn keeper_compounds_positions_that_pay_into_the_vault() {
+    let mut env = setup(REWARD_RATE, StakeLimits::default()).await;
+    let (user, position) = stake_new_user(&mut env).await;
+    let Env {
+        mut context,
+        mint,
+        vault,
+        staking_pool,
+    } = env;
+    let payer = context.payer.pubkey();
+    let keeper = Keypair::new();
+
+    send(
+        &mut context,
+        &[
+            Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::AuthorizeClaimer {
+                    user: user.pubkey(),
+                    staking_pool,
+                    user_stake_account: position,
+                }
+                .to_account_metas(None),
+                data: staking::instruction::AuthorizeClaimer {
+                    claimer: keeper.pubkey(),
+                    destination: vault,
+                }
+                .data(),
+            },
+            spl_token::instruction::set_authority(
+                &spl_token::ID,
+                &mint,
+                Some(&staking_pool),
+                spl_token::instruction::AuthorityType::MintTokens,
+                &payer,
+                &[],
+            )
+            .unwrap(),
+            system_instruction::transfer(&payer, &keeper.pubkey(), 1_000_000_000),
+        ],
+        &[&user],
+    )
+    .await
+    .unwrap();
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += 60;
+    context.set_sysvar(&clock);
+
+    let pool_accounts = vec![(staking_pool, account_data(&mut context, staking_pool).await)];
+    let position_accounts = vec![(position, account_data(&mut context, position).await)];
+    let vault_data = account_data(&mut context, vault).await;
+    let mut pools = decode_pools(&pool_accounts, |_| decode_token_account_mint(&vault_data));
+    let positions = decode_positions(&position_accounts);
+
+    // A paused pool would reject the deposit, so nothing is planned.
+    pools[0].state.paused = true;
+    assert!(plan(
+        &keeper.pubkey(),
+        &pools,
+        &positions,
+        clock.unix_timestamp,
+        1,
+        3600
+    )
+    .is_empty());
+    pools[0].state.paused = false;
+
+    let jobs = plan(
+        &keeper.pubkey(),
+        &pools,
+        &positions,
+        clock.unix_timestamp,
+        1,
+        3600,
+    );
+    let expected_rewards = 60 * REWARD_RATE * STAKE_AMOUNT;
+    let [Job::Compound {
+        position: planned,
+        rewards,
+        ..
+    }] = jobs.as_slice()
+    else {
+        panic!("expected a single compound job, got {jobs:?}");
+    };
+    assert_eq!(*planned, position);
+    assert!(*rewards >= expected_rewards);
+
+    let ix =
+        staking_keeper::instruction(&keeper.pubkey(), &pools, &positions, &jobs[0], None).unwrap();
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx =
+        Transaction::new_signed_with_payer(&[ix], Some(&keeper.pubkey()), &[&keeper], blockhash);
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    // The rewards were minted into the vault and restaked.
+    let data = account_data(&mut context, position).await;
+    let state = UserStakeAccount::try_deserialize(&mut data.as_slice()).unwrap();
+    let data = account_data(&mut context, staking_pool).await;
+    let pool = StakingPool::try_deserialize(&mut data.as_slice()).unwrap();
+    assert_eq!(state.pending_rewards, 0);
+    assert!(state.amount >= STAKE_AMOUNT + expected_rewards);
+    assert_eq!(pool.total_stake, state.amount);
+    assert_eq!(token_balance(&mut context, vault).await, state.amount);
+}
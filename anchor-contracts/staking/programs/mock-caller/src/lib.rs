@@ -0,0 +1,181 @@
+//! Minimal program used by the staking integration tests to exercise
+//! `staking::cpi` with a PDA as the position owner.
+
+// Only for the IDL instruction handlers `#[program]` generates at the crate
+// root, which still call the deprecated `AccountInfo::realloc`.
+#![allow(deprecated)]
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+use staking::{
+    cpi::accounts::{ClaimRewards, Stake},
+    program::Staking,
+    state::StakingPool,
+};
+
+declare_id!("2CRYRpQzKj2t8foHUFjKiRTawtSiWuUv2xfmKP2iEVwj");
+
+pub const AUTHORITY_SEED: &[u8] = b"authority";
+
+#[program]
+pub mod mock_caller {
+    use super::*;
+
+    /// Stakes tokens held by this program's authority PDA into a position the
+    /// PDA owns.
+    pub fn stake(ctx: Context<StakeAsPda>, amount: u64) -> Result<()> {
+        let bump = &[ctx.bumps.authority];
+        let signer_seeds = &[&[AUTHORITY_SEED, bump][..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.staking_program.to_account_info(),
+            Stake {
+                user: ctx.accounts.authority.to_account_info(),
+                beneficiary: ctx.accounts.authority.to_account_info(),
+                user_token_account: ctx.accounts.authority_token_account.to_account_info(),
+                vault: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                staking_pool: ctx.accounts.staking_pool.to_account_info(),
+                user_stake_account: ctx.accounts.user_stake_account.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+            },
+            signer_seeds,
+        );
+
+        staking::cpi::stake(cpi_ctx, amount, None)
+    }
+
+    /// Stakes `funder`'s tokens into the authority PDA's position, signing for
+    /// the PDA as its beneficiary.
+    pub fn fund(ctx: Context<FundPda>, amount: u64) -> Result<()> {
+        let bump = &[ctx.bumps.authority];
+        let signer_seeds = &[&[AUTHORITY_SEED, bump][..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.staking_program.to_account_info(),
+            Stake {
+                user: ctx.accounts.funder.to_account_info(),
+                beneficiary: ctx.accounts.authority.to_account_info(),
+                user_token_account: ctx.accounts.funder_token_account.to_account_info(),
+                vault: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                staking_pool: ctx.accounts.staking_pool.to_account_info(),
+                user_stake_account: ctx.accounts.user_stake_account.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+            },
+            signer_seeds,
+        );
+
+        staking::cpi::stake(cpi_ctx, amount, None)
+    }
+
+    /// Claims the authority PDA's rewards into its token account.
+    pub fn claim(ctx: Context<ClaimAsPda>) -> Result<()> {
+        let bump = &[ctx.bumps.authority];
+        let signer_seeds = &[&[AUTHORITY_SEED, bump][..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.staking_program.to_account_info(),
+            ClaimRewards {
+                claimer: ctx.accounts.authority.to_account_info(),
+                user: ctx.accounts.authority.to_account_info(),
+                user_token_account: ctx.accounts.authority_token_account.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                vault: ctx.accounts.vault.to_account_info(),
+                staking_pool: ctx.accounts.staking_pool.to_account_info(),
+                user_stake_account: ctx.accounts.user_stake_account.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                instructions: ctx.accounts.instructions.to_account_info(),
+            },
+            signer_seeds,
+        );
+
+        staking::cpi::claim_rewards(cpi_ctx)
+    }
+}
+
+#[derive(Accounts)]
+pub struct StakeAsPda<'info> {
+    #[account(mut, seeds = [AUTHORITY_SEED], bump)]
+    pub authority: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub authority_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub vault: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    /// CHECK: created and validated by the staking program.
+    #[account(mut)]
+    pub user_stake_account: UncheckedAccount<'info>,
+
+    pub staking_program: Program<'info, Staking>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct FundPda<'info> {
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    #[account(mut)]
+    pub funder_token_account: Account<'info, TokenAccount>,
+
+    #[account(seeds = [AUTHORITY_SEED], bump)]
+    pub authority: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub vault: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    /// CHECK: created and validated by the staking program.
+    #[account(mut)]
+    pub user_stake_account: UncheckedAccount<'info>,
+
+    pub staking_program: Program<'info, Staking>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAsPda<'info> {
+    #[account(mut, seeds = [AUTHORITY_SEED], bump)]
+    pub authority: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub authority_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub mint: Account<'info, Mint>,
+
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    /// CHECK: validated by the staking program.
+    #[account(mut)]
+    pub user_stake_account: UncheckedAccount<'info>,
+
+    pub staking_program: Program<'info, Staking>,
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: checked by the staking program.
+    pub instructions: UncheckedAccount<'info>,
+}
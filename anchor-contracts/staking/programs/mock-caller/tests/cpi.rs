@@ -0,0 +1,371 @@
+use anchor_lang::{
+    prelude::{AccountInfo, Clock, Pubkey},
+    solana_program::{entrypoint::ProgramResult, program_pack::Pack},
+    system_program, AccountDeserialize, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::{spl_token, TokenAccount};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::Instruction,
+    signature::{Keypair, Signer},
+    sysvar,
+    transaction::Transaction,
+};
+use solana_system_interface::instruction as system_instruction;
+use staking::state::StakeLimits;
+
+const REWARD_RATE: u64 = 2;
+const STAKE_AMOUNT: u64 = 1_000;
+
+// Anchor's generated `entry` ties the accounts slice to the `AccountInfo`
+// lifetime, which `processor!` cannot express, so leak the slice instead.
+fn staking_entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    staking::entry(program_id, accounts, data)
+}
+
+fn mock_caller_entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    mock_caller::entry(program_id, accounts, data)
+}
+
+struct Env {
+    context: ProgramTestContext,
+    mint: Pubkey,
+    vault: Pubkey,
+    staking_pool: Pubkey,
+}
+
+async fn send(context: &mut ProgramTestContext, ixs: &[Instruction], signers: &[&Keypair]) {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let tx = Transaction::new_signed_with_payer(
+        ixs,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+}
+
+async fn create_token_account(
+    context: &mut ProgramTestContext,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Pubkey {
+    let account = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+
+    send(
+        context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &account.pubkey(),
+                rent.minimum_balance(spl_token::state::Account::LEN),
+                spl_token::state::Account::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::ID,
+                &account.pubkey(),
+                mint,
+                owner,
+            )
+            .unwrap(),
+        ],
+        &[&account],
+    )
+    .await;
+
+    account.pubkey()
+}
+
+async fn token_balance(context: &mut ProgramTestContext, account: &Pubkey) -> u64 {
+    let account = context
+        .banks_client
+        .get_account(*account)
+        .await
+        .unwrap()
+        .unwrap();
+    TokenAccount::try_deserialize(&mut account.data.as_slice())
+        .unwrap()
+        .amount
+}
+
+/// Creates a mint and vault, initializes a pool over them and hands mint
+/// authority to the pool so `claim_rewards` can mint.
+async fn setup() -> Env {
+    let mut program_test = ProgramTest::new(
+        "mock_caller",
+        mock_caller::ID,
+        processor!(mock_caller_entry),
+    );
+    program_test.add_program("staking", staking::ID, processor!(staking_entry));
+    let mut context = program_test.start_with_context().await;
+
+    let payer = context.payer.pubkey();
+    let mint = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    send(
+        &mut context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::ID,
+                &mint.pubkey(),
+                &payer,
+                None,
+                6,
+            )
+            .unwrap(),
+        ],
+        &[&mint],
+    )
+    .await;
+
+    let (staking_pool, _) =
+        Pubkey::find_program_address(&[b"staking_pool", mint.pubkey().as_ref()], &staking::ID);
+    let vault = create_token_account(&mut context, &mint.pubkey(), &staking_pool).await;
+
+    send(
+        &mut context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::InitializePool {
+                staking_pool,
+                authority: payer,
+                vault,
+                mint: mint.pubkey(),
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: sysvar::rent::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::InitializePool {
+                reward_rate: REWARD_RATE,
+                limits: StakeLimits::default(),
+            }
+            .data(),
+        }],
+        &[],
+    )
+    .await;
+
+    Env {
+        context,
+        mint: mint.pubkey(),
+        vault,
+        staking_pool,
+    }
+}
+
+#[tokio::test]
+async fn pda_stakes_and_claims_through_cpi() {
+    let mut env = setup().await;
+    let payer = env.context.payer.pubkey();
+
+    let (authority, _) =
+        Pubkey::find_program_address(&[mock_caller::AUTHORITY_SEED], &mock_caller::ID);
+    let (user_stake_account, _) = Pubkey::find_program_address(
+        &[b"user_stake", env.staking_pool.as_ref(), authority.as_ref()],
+        &staking::ID,
+    );
+
+    // The PDA pays rent for its own position, so it needs lamports as well as
+    // tokens before it can stake.
+    let authority_token_account =
+        create_token_account(&mut env.context, &env.mint, &authority).await;
+    send(
+        &mut env.context,
+        &[
+            system_instruction::transfer(&payer, &authority, 1_000_000_000),
+            spl_token::instruction::mint_to(
+                &spl_token::ID,
+                &env.mint,
+                &authority_token_account,
+                &payer,
+                &[],
+                STAKE_AMOUNT,
+            )
+            .unwrap(),
+            spl_token::instruction::set_authority(
+                &spl_token::ID,
+                &env.mint,
+                Some(&env.staking_pool),
+                spl_token::instruction::AuthorityType::MintTokens,
+                &payer,
+                &[],
+            )
+            .unwrap(),
+        ],
+        &[],
+    )
+    .await;
+
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: mock_caller::ID,
+            accounts: mock_caller::accounts::StakeAsPda {
+                authority,
+                authority_token_account,
+                vault: env.vault,
+                mint: env.mint,
+                staking_pool: env.staking_pool,
+                user_stake_account,
+                staking_program: staking::ID,
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            }
+            .to_account_metas(None),
+            data: mock_caller::instruction::Stake {
+                amount: STAKE_AMOUNT,
+            }
+            .data(),
+        }],
+        &[],
+    )
+    .await;
+
+    assert_eq!(
+        token_balance(&mut env.context, &env.vault).await,
+        STAKE_AMOUNT
+    );
+    assert_eq!(
+        token_balance(&mut env.context, &authority_token_account).await,
+        0
+    );
+
+    let account = env
+        .context
+        .banks_client
+        .get_account(user_stake_account)
+        .await
+        .unwrap()
+        .unwrap();
+    let position =
+        staking::state::UserStakeAccount::try_deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(position.owner, authority);
+    assert_eq!(position.amount, STAKE_AMOUNT);
+
+    let mut clock: Clock = env.context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp = position.last_stake_time + 100;
+    env.context.set_sysvar(&clock);
+
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: mock_caller::ID,
+            accounts: mock_caller::accounts::ClaimAsPda {
+                authority,
+                authority_token_account,
+                mint: env.mint,
+                vault: env.vault,
+                staking_pool: env.staking_pool,
+                user_stake_account,
+                staking_program: staking::ID,
+                token_program: spl_token::ID,
+                instructions: sysvar::instructions::ID,
+            }
+            .to_account_metas(None),
+            data: mock_caller::instruction::Claim {}.data(),
+        }],
+        &[],
+    )
+    .await;
+
+    assert_eq!(
+        token_balance(&mut env.context, &authority_token_account).await,
+        100 * REWARD_RATE * STAKE_AMOUNT
+    );
+}
+
+#[tokio::test]
+async fn wallet_funds_a_position_owned_by_a_pda() {
+    let mut env = setup().await;
+    let payer = env.context.payer.pubkey();
+
+    let (beneficiary, _) =
+        Pubkey::find_program_address(&[mock_caller::AUTHORITY_SEED], &mock_caller::ID);
+    let (user_stake_account, _) = Pubkey::find_program_address(
+        &[
+            b"user_stake",
+            env.staking_pool.as_ref(),
+            beneficiary.as_ref(),
+        ],
+        &staking::ID,
+    );
+
+    let payer_token_account = create_token_account(&mut env.context, &env.mint, &payer).await;
+    send(
+        &mut env.context,
+        &[spl_token::instruction::mint_to(
+            &spl_token::ID,
+            &env.mint,
+            &payer_token_account,
+            &payer,
+            &[],
+            STAKE_AMOUNT,
+        )
+        .unwrap()],
+        &[],
+    )
+    .await;
+
+    // The position's owner has to sign for the deposit, so the wallet goes
+    // through the program that controls the PDA.
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: mock_caller::ID,
+            accounts: mock_caller::accounts::FundPda {
+                funder: payer,
+                funder_token_account: payer_token_account,
+                authority: beneficiary,
+                vault: env.vault,
+                mint: env.mint,
+                staking_pool: env.staking_pool,
+                user_stake_account,
+                staking_program: staking::ID,
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            }
+            .to_account_metas(None),
+            data: mock_caller::instruction::Fund {
+                amount: STAKE_AMOUNT,
+            }
+            .data(),
+        }],
+        &[],
+    )
+    .await;
+
+    let account = env
+        .context
+        .banks_client
+        .get_account(user_stake_account)
+        .await
+        .unwrap()
+        .unwrap();
+    let position =
+        staking::state::UserStakeAccount::try_deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(position.owner, beneficiary);
+    assert_eq!(position.amount, STAKE_AMOUNT);
+    assert_eq!(
+        token_balance(&mut env.context, &env.vault).await,
+        STAKE_AMOUNT
+    );
+}
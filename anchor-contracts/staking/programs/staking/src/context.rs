@@ -1,6 +1,10 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Token, Mint, TokenAccount};
-use crate::state::{StakingPool, UserStakeAccount};
+use crate::{
+    error::StakingError,
+    state::{Multisig, Proposal, StakingPool, UserStakeAccount},
+    utils::is_tip_vault,
+};
 
 #[derive(Accounts)]
 #[instruction(_reward_rate: u64)]
@@ -8,7 +12,7 @@ pub struct InitializePool<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + 32 + 8 + 32 + 8 + 1, 
+        space = StakingPool::INIT_SPACE,
         seeds = [b"staking_pool", mint.key().as_ref()],
         bump
     )]
@@ -31,41 +35,55 @@ pub struct InitializePool<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
+/// Deposits `user`'s tokens into a position owned by `beneficiary`.
+///
+/// `user` funds the stake and pays rent for a new position, while
+/// `beneficiary` is the key that may later unstake and claim; pass the same key
+/// for both to stake for yourself. Either may be a PDA of a calling program
+/// that signs through `staking::cpi::stake`.
 #[derive(Accounts)]
-
 pub struct Stake<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
+    /// Signs too, since a deposit joins the position's warming stake and
+    /// would otherwise let anyone hold back the owner's rewards.
+    pub beneficiary: Signer<'info>,
+
     #[account(mut)]
     pub user_token_account: Account<'info, TokenAccount>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = vault.key() == staking_pool.vault
+    )]
     pub vault: Account<'info, TokenAccount>,
 
+    pub mint: Account<'info, Mint>,
+
     #[account(
         mut,
-        seeds = [b"staking_pool", vault.key().as_ref()],
-        bump
+        seeds = [b"staking_pool", mint.key().as_ref()],
+        bump = staking_pool.bump
     )]
     pub staking_pool: Account<'info, StakingPool>,
 
     #[account(
-        init,
+        init_if_needed,
         payer = user,
         space = 8 + UserStakeAccount::INIT_SPACE,
-        seeds = [b"user_stake", staking_pool.key().as_ref(), user.key().as_ref()],
+        seeds = [b"user_stake", staking_pool.key().as_ref(), beneficiary.key().as_ref()],
         bump
     )]
     pub user_stake_account: Account<'info, UserStakeAccount>,
 
-    pub mint: Account<'info, Mint>,
-
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
+/// Withdraws principal from the position owned by `user`. A calling program
+/// unstakes for its PDA by signing for it through `staking::cpi::unstake`.
 #[derive(Accounts)]
 pub struct Unstake<'info> {
     #[account(mut)]
@@ -74,13 +92,18 @@ pub struct Unstake<'info> {
     #[account(mut)]
     pub user_token_account: Account<'info, TokenAccount>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = vault.key() == staking_pool.vault
+    )]
     pub vault: Account<'info, TokenAccount>,
 
+    pub mint: Account<'info, Mint>,
+
     #[account(
         mut,
-        seeds = [b"staking_pool", vault.key().as_ref()],
-        bump
+        seeds = [b"staking_pool", mint.key().as_ref()],
+        bump = staking_pool.bump
     )]
     pub staking_pool: Account<'info, StakingPool>,
 
@@ -96,33 +119,404 @@ pub struct Unstake<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
+/// Pulls the principal of the position owned by `user` out of a pool in
+/// emergency mode and closes the position.
 #[derive(Accounts)]
-pub struct ClaimRewards<'info> {
+pub struct EmergencyWithdraw<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key()
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = vault.key() == staking_pool.vault
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"staking_pool", mint.key().as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"user_stake", staking_pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub user_stake_account: Account<'info, UserStakeAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Mints the rewards of the position owned by `user`. `claimer` is either the
+/// owner or the delegate set with `authorize_claimer`. A calling program claims
+/// for its PDA by signing for it through `staking::cpi::claim_rewards`.
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    pub claimer: Signer<'info>,
+
+    /// CHECK: only used to derive the position address.
+    pub user: UncheckedAccount<'info>,
+
     #[account(mut)]
     pub user_token_account: Account<'info, TokenAccount>,
 
     #[account(mut)]
     pub mint: Account<'info, Mint>,
 
+    #[account(constraint = vault.key() == staking_pool.vault)]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"staking_pool", mint.key().as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        seeds = [b"user_stake", staking_pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub user_stake_account: Account<'info, UserStakeAccount>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: the instructions sysvar, checked by address.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Compound<'info> {
+    pub claimer: Signer<'info>,
+
+    /// CHECK: only used to derive the position address.
+    pub user: UncheckedAccount<'info>,
+
     #[account(mut)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut, constraint = vault.key() == staking_pool.vault)]
     pub vault: Account<'info, TokenAccount>,
 
     #[account(
         mut,
-        seeds = [b"staking_pool", vault.key().as_ref()],
+        seeds = [b"staking_pool", mint.key().as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+    #[account(
+        mut,
+        seeds = [b"user_stake", staking_pool.key().as_ref(), user.key().as_ref()],
         bump
     )]
+    pub user_stake_account: Account<'info, UserStakeAccount>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: the instructions sysvar, checked by address.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AuthorizeClaimer<'info> {
+    pub user: Signer<'info>,
+
     pub staking_pool: Account<'info, StakingPool>,
+
     #[account(
         mut,
         seeds = [b"user_stake", staking_pool.key().as_ref(), user.key().as_ref()],
         bump
     )]
     pub user_stake_account: Account<'info, UserStakeAccount>,
+}
+
+/// Advances `staking_pool`'s reward accumulator. Anyone may crank; passing the
+/// three optional accounts pays the pool's `crank_tip` out of its tip vault.
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    pub cranker: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"staking_pool", mint.key().as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    #[account(
+        mut,
+        constraint = tip_vault.key() == staking_pool.tip_vault
+    )]
+    pub tip_vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = cranker_token_account.owner == cranker.key()
+    )]
+    pub cranker_token_account: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct SetCrankTip<'info> {
+    pub authority: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"staking_pool", mint.key().as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    /// Must not be the stake vault, or tips would be paid out of principal.
+    #[account(constraint = is_tip_vault(&staking_pool, &mint.key(), &tip_vault))]
+    pub tip_vault: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct SetStakeLimits<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub staking_pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+pub struct SetMerkleRoot<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub staking_pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+pub struct SetRewardRate<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub staking_pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub staking_pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+pub struct TransferAuthority<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub staking_pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawExcessRewards<'info> {
+    pub authority: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        has_one = authority,
+        has_one = vault,
+        seeds = [b"staking_pool", mint.key().as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    #[account(mut)]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(mut, constraint = destination.mint == mint.key())]
+    pub destination: Account<'info, TokenAccount>,
 
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct SetEmergencyMode<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub staking_pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+pub struct SetTimelockDelay<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub staking_pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+pub struct QueueAction<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub staking_pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+pub struct CancelQueuedAction<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority)]
+    pub staking_pool: Account<'info, StakingPool>,
+}
+
+/// Applies `staking_pool`'s queued action. Anyone may call it; the optional
+/// accounts are only needed by the actions that move tokens or name a tip
+/// vault.
+#[derive(Accounts)]
+pub struct ExecuteQueuedAction<'info> {
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"staking_pool", mint.key().as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    #[account(
+        mut,
+        constraint = vault.key() == staking_pool.vault
+    )]
+    pub vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub destination: Option<Account<'info, TokenAccount>>,
+
+    pub tip_vault: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+/// Creates a multisig at an address derived from `create_key`, which only has
+/// to sign once so nobody else can claim the address.
+#[derive(Accounts)]
+pub struct CreateMultisig<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub create_key: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = Multisig::INIT_SPACE,
+        seeds = [b"multisig", create_key.key().as_ref()],
+        bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateProposal<'info> {
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    #[account(mut)]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(constraint = staking_pool.authority == multisig.key() @ StakingError::NotPoolAuthority)]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = Proposal::INIT_SPACE,
+        seeds = [b"proposal", multisig.key().as_ref(), &multisig.proposal_count.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Approve<'info> {
+    pub owner: Signer<'info>,
+
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(mut, has_one = multisig)]
+    pub proposal: Account<'info, Proposal>,
+}
+
+/// Applies an approved proposal to `staking_pool`. The optional accounts are
+/// only needed by the actions that move tokens or name a tip vault.
+#[derive(Accounts)]
+pub struct Execute<'info> {
+    pub executor: Signer<'info>,
+
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(mut, has_one = multisig, has_one = staking_pool)]
+    pub proposal: Account<'info, Proposal>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"staking_pool", mint.key().as_ref()],
+        bump = staking_pool.bump,
+        constraint = staking_pool.authority == multisig.key() @ StakingError::NotPoolAuthority
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    #[account(
+        mut,
+        constraint = vault.key() == staking_pool.vault
+    )]
+    pub vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub destination: Option<Account<'info, TokenAccount>>,
+
+    pub tip_vault: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct GetPoolStats<'info> {
+    pub staking_pool: Account<'info, StakingPool>,
+}
+
+#[derive(Accounts)]
+pub struct GetPosition<'info> {
+    pub staking_pool: Account<'info, StakingPool>,
+
+    #[account(
+        seeds = [b"user_stake", staking_pool.key().as_ref(), user_stake_account.owner.as_ref()],
+        bump
+    )]
+    pub user_stake_account: Account<'info, UserStakeAccount>,
+}
@@ -0,0 +1,101 @@
+use anchor_lang::prelude::*;
+
+#[error_code]
+pub enum StakingError {
+    #[msg("Stake is below the pool's minimum stake amount")]
+    StakeBelowMinimum,
+
+    #[msg("Stake would exceed the pool's total stake cap")]
+    PoolCapExceeded,
+
+    #[msg("Stake would exceed the pool's per-user stake cap")]
+    UserCapExceeded,
+
+    #[msg("The pool's stake window has not opened yet")]
+    StakeWindowNotOpen,
+
+    #[msg("The pool's stake window has closed")]
+    StakeWindowClosed,
+
+    #[msg("Stake window must end after it starts")]
+    InvalidStakeWindow,
+
+    #[msg("Beneficiary is not on the pool's allowlist")]
+    NotAllowlisted,
+
+    #[msg("The pool is paused")]
+    PoolPaused,
+
+    #[msg("Multisig needs between 1 and 10 owners")]
+    InvalidOwnerCount,
+
+    #[msg("Multisig owners must be unique")]
+    DuplicateOwner,
+
+    #[msg("Multisig threshold must be between 1 and the number of owners")]
+    InvalidThreshold,
+
+    #[msg("Signer is not an owner of the multisig")]
+    NotMultisigOwner,
+
+    #[msg("Owner has already approved this proposal")]
+    AlreadyApproved,
+
+    #[msg("Proposal has already been executed")]
+    ProposalExecuted,
+
+    #[msg("Proposal does not have enough approvals")]
+    ThresholdNotMet,
+
+    #[msg("Pool authority is not this multisig")]
+    NotPoolAuthority,
+
+    #[msg("Account passed does not match the one in the proposal")]
+    ProposalAccountMismatch,
+
+    #[msg("Vault holds nothing beyond staked principal")]
+    NoExcessRewards,
+
+    #[msg("Pool has a timelock; queue the action instead")]
+    TimelockActive,
+
+    #[msg("Queued action's timelock has not expired")]
+    TimelockNotExpired,
+
+    #[msg("Pool already has a queued action")]
+    ActionAlreadyQueued,
+
+    #[msg("Pool has no queued action")]
+    NoQueuedAction,
+
+    #[msg("Action is not subject to the timelock and cannot be queued")]
+    ActionNotTimelocked,
+
+    #[msg("Pool is in emergency mode")]
+    PoolInEmergency,
+
+    #[msg("Pool is not in emergency mode")]
+    EmergencyModeOff,
+
+    #[msg("Position was staked earlier in this transaction")]
+    StakedInSameTransaction,
+
+    #[msg("Signer is neither the position's owner nor its authorized claimer")]
+    UnauthorizedClaimer,
+
+    #[msg("Delegated claims must pay into the position's claim destination")]
+    InvalidClaimDestination,
+}
+
+impl From<staking_core::Error> for StakingError {
+    fn from(e: staking_core::Error) -> Self {
+        match e {
+            staking_core::Error::StakeWindowNotOpen => StakingError::StakeWindowNotOpen,
+            staking_core::Error::StakeWindowClosed => StakingError::StakeWindowClosed,
+            staking_core::Error::StakeBelowMinimum => StakingError::StakeBelowMinimum,
+            staking_core::Error::PoolCapExceeded => StakingError::PoolCapExceeded,
+            staking_core::Error::UserCapExceeded => StakingError::UserCapExceeded,
+            staking_core::Error::InvalidStakeWindow => StakingError::InvalidStakeWindow,
+        }
+    }
+}
@@ -1,24 +1,42 @@
+// The IDL instruction handlers `#[program]` generates next to the program
+// module resize their account with the deprecated `AccountInfo::realloc`.
+// They are emitted at the crate root, where only crate-level lint attributes
+// reach them, and are compiled even with `no-idl`.
+#![allow(deprecated)]
+
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, MintTo, Transfer};
+use staking_core::{crank, lock, penalty, rewards};
 
 
 pub mod constants;
 pub mod context;
+pub mod error;
+pub mod merkle;
 pub mod state;
 pub mod utils;
 
 use context::*;
+use error::StakingError;
+use state::{AdminAction, AllowlistProof, Multisig, PoolStats, PositionInfo, StakeLimits};
+use utils::{
+    apply_action, check_no_stake_before, earned, reward_per_token_at, settle, update_accumulator,
+    withdraw_excess, ActionAccounts,
+};
 
 
 declare_id!("8FsVBkGy8ro1bNKDWMWE1mntbdx2b53a47cZWnBz6sgL"); 
 
 #[program]
-
 pub mod staking {
     use super::*;
 
-    pub fn initialize_pool(ctx: Context<InitializePool>, reward_rate: u64) -> Result<()> {
-        let bump = ctx.accounts.staking_pool.bump;
+    pub fn initialize_pool(
+        ctx: Context<InitializePool>,
+        reward_rate: u64,
+        limits: StakeLimits,
+    ) -> Result<()> {
+        let bump = ctx.bumps.staking_pool;
         let staking_pool = &mut ctx.accounts.staking_pool;
 
         staking_pool.authority = ctx.accounts.authority.key();
@@ -26,12 +44,36 @@ pub mod staking {
         staking_pool.vault = ctx.accounts.vault.key();
         staking_pool.total_stake = 0;
         staking_pool.bump = bump;
+        staking_pool.reward_per_token = 0;
+        staking_pool.last_update_time = Clock::get()?.unix_timestamp;
+        staking_pool.tip_vault = Pubkey::default();
+        staking_pool.crank_tip = 0;
+        staking_pool.set_limits(&limits)?;
+        staking_pool.merkle_root = [0; 32];
+        staking_pool.paused = false;
+        staking_pool.timelock_delay = 0;
+        staking_pool.queued_action = None;
+        staking_pool.queued_eta = 0;
+        staking_pool.emergency_mode = false;
 
         Ok(())
     }
 
-    pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()> {
+    /// Stakes `amount` of `user`'s tokens into the position of `beneficiary`.
+    ///
+    /// CPI entry point: a program can stake its own tokens by passing its PDA
+    /// as both `user` and `beneficiary` and signing with the PDA's seeds, or
+    /// fund a position for a PDA it controls by signing for it as `beneficiary`.
+    ///
+    /// Pools with an allowlist require `allowlist` to prove `beneficiary` is
+    /// on it; other pools ignore the argument.
+    pub fn stake(
+        ctx: Context<Stake>,
+        amount: u64,
+        allowlist: Option<AllowlistProof>,
+    ) -> Result<()> {
         let user = &ctx.accounts.user;
+        let beneficiary = &ctx.accounts.beneficiary;
         let user_token_account = &ctx.accounts.user_token_account;
         let vault = &ctx.accounts.vault;
         let staking_pool = &mut ctx.accounts.staking_pool;
@@ -47,13 +89,26 @@ pub mod staking {
             return Err(ProgramError::InvalidAccountData.into());
         }
 
-        if user_stake.amount > 0 {
-            let now = Clock::get()?.unix_timestamp;
-            let elapsed_time = now - user_stake.last_stake_time;
-            let rewards = elapsed_time as u64 * staking_pool.reward_rate * user_stake.amount;
-            user_stake.pending_rewards += rewards;
+        // `init_if_needed` hands us a zeroed account on the first stake; any
+        // later call must top up the position's existing owner.
+        if user_stake.owner == Pubkey::default() {
+            user_stake.owner = beneficiary.key();
+        } else if user_stake.owner != beneficiary.key() {
+            return Err(ProgramError::IllegalOwner.into());
         }
 
+        let now = Clock::get()?.unix_timestamp;
+        staking_pool.check_stake(amount, user_stake.amount, now)?;
+        staking_pool.check_allowlist(
+            &beneficiary.key(),
+            allowlist.as_ref(),
+            amount,
+            user_stake.amount,
+        )?;
+
+        update_accumulator(staking_pool, now)?;
+        settle(user_stake, staking_pool, now)?;
+
         let cpi_ctx = CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
             Transfer {
@@ -65,13 +120,27 @@ pub mod staking {
         token::transfer(cpi_ctx, amount)?;
 
         user_stake.amount += amount;
-        user_stake.last_stake_time = Clock::get()?.unix_timestamp;
-        user_stake.owner = user.key();
+        // New stake only starts earning once it has sat through the minimum
+        // stake age, so a flash deposit earns nothing. A top-up joins stake
+        // that is still warming at their weighted deposit time.
+        user_stake.last_stake_time = lock::top_up_stake_time(
+            user_stake.last_stake_time,
+            user_stake.warming_amount,
+            amount,
+            now,
+        );
+        if staking_pool.min_stake_age != 0 {
+            user_stake.warming_amount += amount;
+        }
         staking_pool.total_stake += amount;
 
         Ok(())
     }
 
+    /// Returns `amount` of principal to the position owner.
+    ///
+    /// CPI entry point: the position owner must sign as `user`, so a program
+    /// withdrawing for its PDA signs with the PDA's seeds.
     pub fn unstake(ctx: Context<Unstake>, amount: u64) -> Result<()> {
         let user_token_account = &ctx.accounts.user_token_account;
         let vault = &ctx.accounts.vault;
@@ -83,17 +152,18 @@ pub mod staking {
         }
 
         let now = Clock::get()?.unix_timestamp;
-        let elapsed_time = now - user_stake_account.last_stake_time;
-        let rewards = elapsed_time as u64 * staking_pool.reward_rate * user_stake_account.amount;
-        user_stake_account.pending_rewards += rewards;
+        update_accumulator(staking_pool, now)?;
+        settle(user_stake_account, staking_pool, now)?;
 
+        // Withdraw stake that is not earning yet first.
         user_stake_account.amount -= amount;
+        user_stake_account.warming_amount =
+            user_stake_account.warming_amount.saturating_sub(amount);
         staking_pool.total_stake -= amount;
-        user_stake_account.last_stake_time = now;
 
-        let staking_pool_key = staking_pool.key();
+        let mint_key = ctx.accounts.mint.key();
         let bump = &[staking_pool.bump];
-        let signer_seeds = &[&[b"vault", staking_pool_key.as_ref(), bump][..]];
+        let signer_seeds = &[&[b"staking_pool", mint_key.as_ref(), bump][..]];
 
         let cpi_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
@@ -107,23 +177,106 @@ pub mod staking {
 
         token::transfer(cpi_ctx, amount)?;
 
+        // Return the position's rent once it holds nothing left to claim.
+        if user_stake_account.amount == 0 && user_stake_account.pending_rewards == 0 {
+            user_stake_account.close(ctx.accounts.user.to_account_info())?;
+        }
+
         Ok(())
     }
 
+    /// Returns the position's whole principal and closes it, forfeiting any
+    /// rewards. Only available in emergency mode, and deliberately touches no
+    /// reward state, so it keeps working when the reward math does not.
+    pub fn emergency_withdraw(ctx: Context<EmergencyWithdraw>) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        let amount = ctx.accounts.user_stake_account.amount;
+
+        if !staking_pool.emergency_mode {
+            return err!(StakingError::EmergencyModeOff);
+        }
+
+        if amount > 0 {
+            let mint_key = ctx.accounts.mint.key();
+            let bump = &[staking_pool.bump];
+            let signer_seeds = &[&[b"staking_pool", mint_key.as_ref(), bump][..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: staking_pool.to_account_info(),
+                },
+                signer_seeds,
+            );
+            token::transfer(cpi_ctx, amount)?;
+        }
+
+        staking_pool.total_stake = staking_pool.total_stake.saturating_sub(amount);
+
+        let position = &ctx.accounts.user_stake_account;
+        let forfeited = penalty::forfeited_rewards(
+            amount,
+            position.pending_rewards,
+            staking_pool.reward_per_token,
+            position.reward_per_token_paid,
+        )
+        .unwrap_or(position.pending_rewards);
+        msg!(
+            "Emergency withdrew {} tokens, forfeiting {} in rewards",
+            amount,
+            forfeited
+        );
+
+        Ok(())
+    }
+
+    /// Mints everything the position has earned up to now to `user_token_account`.
+    ///
+    /// `claimer` must be the position owner or its authorized delegate; a
+    /// delegate can only pay into the owner's chosen `claim_destination`.
+    ///
+    /// The position's latest stake must not come earlier in the same
+    /// transaction. A position whose only stake just matured has nothing to
+    /// claim yet, but the call succeeds so that stake starts earning.
+    ///
+    /// CPI entry point: a program claiming for its PDA passes the PDA as both
+    /// `claimer` and `user` and signs with the PDA's seeds.
     pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
         let mint = &ctx.accounts.mint;
-        let vault = &ctx.accounts.vault;
         let user_token_account = &ctx.accounts.user_token_account;
         let user_stake_account = &mut ctx.accounts.user_stake_account;
         let staking_pool = &mut ctx.accounts.staking_pool;
 
+        let claimer = ctx.accounts.claimer.key();
+        if claimer != user_stake_account.owner {
+            if user_stake_account.claimer == Pubkey::default()
+                || claimer != user_stake_account.claimer
+            {
+                return err!(StakingError::UnauthorizedClaimer);
+            }
+            if user_token_account.key() != user_stake_account.claim_destination {
+                return err!(StakingError::InvalidClaimDestination);
+            }
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        check_no_stake_before(&ctx.accounts.instructions, &user_stake_account.key())?;
+
+        update_accumulator(staking_pool, now)?;
+        let matured = settle(user_stake_account, staking_pool, now)?;
+
+        if user_stake_account.pending_rewards == 0 && matured {
+            return Ok(());
+        }
         if user_stake_account.pending_rewards == 0 {
             return Err(ProgramError::InvalidArgument.into());
         }
 
-        let vault_key = vault.key();
+        let mint_key = mint.key();
         let bump = &[staking_pool.bump];
-        let signer_seeds = &[&[b"staking_pool", vault_key.as_ref(), bump][..]];
+        let signer_seeds = &[&[b"staking_pool", mint_key.as_ref(), bump][..]];
 
         let cpi_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
@@ -141,4 +294,443 @@ pub mod staking {
 
         Ok(())
     }
+
+    /// Mints everything the position has earned up to now into the pool's
+    /// vault and adds it to the position's stake. Compounded rewards earn
+    /// right away and do not reset the stake age.
+    ///
+    /// Compounding counts as a deposit, so it is subject to the pool's pause,
+    /// stake window, caps and allowlist like `stake`. `claimer` must be the
+    /// position owner or, if the owner chose the vault as its
+    /// `claim_destination`, its authorized delegate.
+    pub fn compound(ctx: Context<Compound>, allowlist: Option<AllowlistProof>) -> Result<()> {
+        let mint = &ctx.accounts.mint;
+        let vault = &ctx.accounts.vault;
+        let user_stake_account = &mut ctx.accounts.user_stake_account;
+        let staking_pool = &mut ctx.accounts.staking_pool;
+
+        let claimer = ctx.accounts.claimer.key();
+        if claimer != user_stake_account.owner {
+            if user_stake_account.claimer == Pubkey::default()
+                || claimer != user_stake_account.claimer
+            {
+                return err!(StakingError::UnauthorizedClaimer);
+            }
+            if vault.key() != user_stake_account.claim_destination {
+                return err!(StakingError::InvalidClaimDestination);
+            }
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        check_no_stake_before(&ctx.accounts.instructions, &user_stake_account.key())?;
+
+        update_accumulator(staking_pool, now)?;
+        settle(user_stake_account, staking_pool, now)?;
+
+        let amount = user_stake_account.pending_rewards;
+        if amount == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        staking_pool.check_stake(amount, user_stake_account.amount, now)?;
+        staking_pool.check_allowlist(
+            &user_stake_account.owner,
+            allowlist.as_ref(),
+            amount,
+            user_stake_account.amount,
+        )?;
+
+        let mint_key = mint.key();
+        let bump = &[staking_pool.bump];
+        let signer_seeds = &[&[b"staking_pool", mint_key.as_ref(), bump][..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            MintTo {
+                mint: mint.to_account_info(),
+                to: vault.to_account_info(),
+                authority: staking_pool.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::mint_to(cpi_ctx, amount)?;
+
+        user_stake_account.pending_rewards = 0;
+        user_stake_account.amount = user_stake_account
+            .amount
+            .checked_add(amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        staking_pool.total_stake = staking_pool
+            .total_stake
+            .checked_add(amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    /// Advances the pool's reward accumulator to now, so the next user
+    /// interaction after a quiet period stays cheap. Anyone may call it; when
+    /// the tip accounts are passed and the accumulator has stood still for
+    /// `crank::TIP_INTERVAL`, the cranker is paid `crank_tip` out of the
+    /// pool's tip vault.
+    pub fn update_pool(ctx: Context<UpdatePool>) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        let now = Clock::get()?.unix_timestamp;
+        let tip_due = crank::tip_due(staking_pool.last_update_time, now);
+        update_accumulator(staking_pool, now)?;
+
+        let (Some(tip_vault), Some(cranker_token_account), Some(token_program)) = (
+            &ctx.accounts.tip_vault,
+            &ctx.accounts.cranker_token_account,
+            &ctx.accounts.token_program,
+        ) else {
+            return Ok(());
+        };
+
+        // Pay what is left rather than failing once the vault runs dry.
+        let tip = staking_pool.crank_tip.min(tip_vault.amount);
+        if !tip_due || tip == 0 {
+            return Ok(());
+        }
+
+        let mint_key = ctx.accounts.mint.key();
+        let bump = &[staking_pool.bump];
+        let signer_seeds = &[&[b"staking_pool", mint_key.as_ref(), bump][..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: tip_vault.to_account_info(),
+                to: cranker_token_account.to_account_info(),
+                authority: staking_pool.to_account_info(),
+            },
+            signer_seeds,
+        );
+
+        token::transfer(cpi_ctx, tip)
+    }
+
+    /// Sets the tip paid for an `update_pool` after the pool has been idle
+    /// for `crank::TIP_INTERVAL`, so at most once per interval. Pass a
+    /// `crank_tip` of zero to stop rewarding cranks.
+    pub fn set_crank_tip(ctx: Context<SetCrankTip>, crank_tip: u64) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.check_unlocked()?;
+
+        staking_pool.tip_vault = ctx.accounts.tip_vault.key();
+        staking_pool.crank_tip = crank_tip;
+
+        Ok(())
+    }
+
+    /// Replaces the pool's deposit caps and stake window. Existing positions
+    /// above a lowered cap are kept; they just cannot grow.
+    pub fn set_stake_limits(ctx: Context<SetStakeLimits>, limits: StakeLimits) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.check_unlocked()?;
+
+        staking_pool.set_limits(&limits)
+    }
+
+    /// Restricts staking to the wallets in the allowlist with this merkle root.
+    /// Pass all zeroes to let anyone stake.
+    pub fn set_merkle_root(ctx: Context<SetMerkleRoot>, merkle_root: [u8; 32]) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.check_unlocked()?;
+
+        staking_pool.merkle_root = merkle_root;
+
+        Ok(())
+    }
+
+    /// Changes the reward rate for all positions from now on.
+    pub fn set_reward_rate(ctx: Context<SetRewardRate>, reward_rate: u64) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.check_unlocked()?;
+
+        utils::set_reward_rate(staking_pool, reward_rate, Clock::get()?.unix_timestamp)
+    }
+
+    /// Stops or resumes new stakes. Unstaking and claiming are never paused,
+    /// and pausing is not subject to the pool's timelock.
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        ctx.accounts.staking_pool.paused = paused;
+
+        Ok(())
+    }
+
+    /// Hands the pool's admin rights to `new_authority`, which may be a
+    /// multisig created with `create_multisig`.
+    pub fn transfer_authority(
+        ctx: Context<TransferAuthority>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.check_unlocked()?;
+
+        staking_pool.authority = new_authority;
+
+        Ok(())
+    }
+
+    /// Sends whatever the stake vault holds beyond staked principal to
+    /// `destination`.
+    pub fn withdraw_excess_rewards(ctx: Context<WithdrawExcessRewards>) -> Result<()> {
+        ctx.accounts.staking_pool.check_unlocked()?;
+
+        withdraw_excess(
+            &ctx.accounts.staking_pool,
+            &ctx.accounts.mint.key(),
+            &ctx.accounts.vault,
+            ctx.accounts.destination.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Turns emergency mode on or off. Not subject to the timelock, so the
+    /// authority can react at once.
+    pub fn set_emergency_mode(ctx: Context<SetEmergencyMode>, enabled: bool) -> Result<()> {
+        ctx.accounts.staking_pool.emergency_mode = enabled;
+
+        Ok(())
+    }
+
+    /// Sets the pool's timelock. Only possible directly while the pool has
+    /// none; afterwards changing it has to wait out the current delay.
+    pub fn set_timelock_delay(ctx: Context<SetTimelockDelay>, timelock_delay: i64) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.check_unlocked()?;
+
+        staking_pool.set_timelock_delay(timelock_delay)
+    }
+
+    /// Schedules `action` to become executable once the pool's timelock
+    /// delay has passed. The queued action and its eta are public on the pool.
+    pub fn queue_action(ctx: Context<QueueAction>, action: AdminAction) -> Result<()> {
+        ctx.accounts
+            .staking_pool
+            .queue(action, Clock::get()?.unix_timestamp)
+    }
+
+    pub fn cancel_queued_action(ctx: Context<CancelQueuedAction>) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+
+        if staking_pool.queued_action.take().is_none() {
+            return err!(StakingError::NoQueuedAction);
+        }
+        staking_pool.queued_eta = 0;
+
+        Ok(())
+    }
+
+    /// Applies the pool's queued action once its eta has passed. Anyone may
+    /// call it.
+    pub fn execute_queued_action(ctx: Context<ExecuteQueuedAction>) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        let now = Clock::get()?.unix_timestamp;
+
+        let Some(action) = staking_pool.queued_action.clone() else {
+            return err!(StakingError::NoQueuedAction);
+        };
+        if now < staking_pool.queued_eta {
+            return err!(StakingError::TimelockNotExpired);
+        }
+
+        staking_pool.queued_action = None;
+        staking_pool.queued_eta = 0;
+        apply_action(
+            staking_pool,
+            action,
+            ActionAccounts {
+                mint: &ctx.accounts.mint,
+                vault: ctx.accounts.vault.as_ref(),
+                destination: ctx.accounts.destination.as_ref(),
+                tip_vault: ctx.accounts.tip_vault.as_ref(),
+                token_program: ctx.accounts.token_program.as_ref(),
+            },
+            now,
+        )
+    }
+
+    /// Creates a multisig that any `threshold` of `owners` can act through.
+    /// Make it a pool's admin by passing its address to `transfer_authority`.
+    pub fn create_multisig(
+        ctx: Context<CreateMultisig>,
+        owners: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        if owners.is_empty() || owners.len() > Multisig::MAX_OWNERS {
+            return err!(StakingError::InvalidOwnerCount);
+        }
+        if owners
+            .iter()
+            .enumerate()
+            .any(|(i, owner)| owners[..i].contains(owner))
+        {
+            return err!(StakingError::DuplicateOwner);
+        }
+        if threshold == 0 || threshold as usize > owners.len() {
+            return err!(StakingError::InvalidThreshold);
+        }
+
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.create_key = ctx.accounts.create_key.key();
+        multisig.owners = owners;
+        multisig.threshold = threshold;
+        multisig.proposal_count = 0;
+        multisig.bump = ctx.bumps.multisig;
+
+        Ok(())
+    }
+
+    /// Proposes `action` on a pool administered by the multisig. The proposer
+    /// must be an owner and counts as its first approval.
+    pub fn create_proposal(ctx: Context<CreateProposal>, action: AdminAction) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let proposal = &mut ctx.accounts.proposal;
+        let owner_index = multisig.owner_index(&ctx.accounts.proposer.key())?;
+
+        proposal.multisig = multisig.key();
+        proposal.staking_pool = ctx.accounts.staking_pool.key();
+        proposal.index = multisig.proposal_count;
+        proposal.proposer = ctx.accounts.proposer.key();
+        proposal.action = action;
+        proposal.approvals = vec![false; multisig.owners.len()];
+        proposal.approvals[owner_index] = true;
+        proposal.executed = false;
+        proposal.bump = ctx.bumps.proposal;
+
+        multisig.proposal_count += 1;
+
+        Ok(())
+    }
+
+    pub fn approve(ctx: Context<Approve>) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+        let owner_index = ctx
+            .accounts
+            .multisig
+            .owner_index(&ctx.accounts.owner.key())?;
+
+        if proposal.executed {
+            return err!(StakingError::ProposalExecuted);
+        }
+        if proposal.approvals[owner_index] {
+            return err!(StakingError::AlreadyApproved);
+        }
+        proposal.approvals[owner_index] = true;
+
+        Ok(())
+    }
+
+    /// Applies a proposal once `threshold` owners have approved it. Any owner
+    /// may execute, and a proposal executes at most once. On a pool with a
+    /// timelock, timelocked actions are queued instead of applied.
+    pub fn execute(ctx: Context<Execute>) -> Result<()> {
+        let multisig = &ctx.accounts.multisig;
+        let proposal = &mut ctx.accounts.proposal;
+        let staking_pool = &mut ctx.accounts.staking_pool;
+
+        multisig.owner_index(&ctx.accounts.executor.key())?;
+        if proposal.executed {
+            return err!(StakingError::ProposalExecuted);
+        }
+        if proposal.approval_count() < multisig.threshold as usize {
+            return err!(StakingError::ThresholdNotMet);
+        }
+
+        let action = proposal.action.clone();
+        let now = Clock::get()?.unix_timestamp;
+        if action.is_timelocked() && staking_pool.timelock_delay != 0 {
+            staking_pool.queue(action, now)?;
+        } else {
+            apply_action(
+                staking_pool,
+                action,
+                ActionAccounts {
+                    mint: &ctx.accounts.mint,
+                    vault: ctx.accounts.vault.as_ref(),
+                    destination: ctx.accounts.destination.as_ref(),
+                    tip_vault: ctx.accounts.tip_vault.as_ref(),
+                    token_program: ctx.accounts.token_program.as_ref(),
+                },
+                now,
+            )?;
+        }
+
+        proposal.executed = true;
+
+        Ok(())
+    }
+
+    /// Lets `claimer` call `claim_rewards` for this position, paying only into
+    /// `destination`. Naming the pool's vault as `destination` lets it
+    /// `compound` instead. Pass the default key as `claimer` to revoke.
+    pub fn authorize_claimer(
+        ctx: Context<AuthorizeClaimer>,
+        claimer: Pubkey,
+        destination: Pubkey,
+    ) -> Result<()> {
+        let user_stake_account = &mut ctx.accounts.user_stake_account;
+
+        user_stake_account.claimer = claimer;
+        user_stake_account.claim_destination = destination;
+
+        Ok(())
+    }
+
+    pub fn get_pending_rewards(ctx: Context<GetPosition>) -> Result<u64> {
+        let staking_pool = &ctx.accounts.staking_pool;
+        let user_stake_account = &ctx.accounts.user_stake_account;
+        let now = Clock::get()?.unix_timestamp;
+
+        let reward_per_token = reward_per_token_at(staking_pool, now)?;
+        let rewards = earned(user_stake_account, staking_pool, reward_per_token, now)?;
+
+        user_stake_account
+            .pending_rewards
+            .checked_add(rewards)
+            .ok_or_else(|| ProgramError::ArithmeticOverflow.into())
+    }
+
+    pub fn get_pool_stats(ctx: Context<GetPoolStats>) -> Result<PoolStats> {
+        let staking_pool = &ctx.accounts.staking_pool;
+        let now = Clock::get()?.unix_timestamp;
+
+        Ok(PoolStats {
+            authority: staking_pool.authority,
+            vault: staking_pool.vault,
+            reward_rate: staking_pool.reward_rate,
+            total_stake: staking_pool.total_stake,
+            emission_per_second: rewards::emission_per_second(
+                staking_pool.reward_rate,
+                staking_pool.total_stake,
+            ),
+            reward_per_token: reward_per_token_at(staking_pool, now)?,
+            last_update_time: staking_pool.last_update_time,
+            timestamp: now,
+        })
+    }
+
+    pub fn get_position(ctx: Context<GetPosition>) -> Result<PositionInfo> {
+        let staking_pool = &ctx.accounts.staking_pool;
+        let user_stake_account = &ctx.accounts.user_stake_account;
+        let now = Clock::get()?.unix_timestamp;
+
+        let reward_per_token = reward_per_token_at(staking_pool, now)?;
+        let rewards = earned(user_stake_account, staking_pool, reward_per_token, now)?;
+        let pending_rewards = user_stake_account
+            .pending_rewards
+            .checked_add(rewards)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        Ok(PositionInfo {
+            owner: user_stake_account.owner,
+            amount: user_stake_account.amount,
+            last_stake_time: user_stake_account.last_stake_time,
+            pending_rewards,
+            timestamp: now,
+        })
+    }
 }
@@ -0,0 +1,27 @@
+use anchor_lang::{prelude::*, solana_program::hash::hashv};
+
+// Leaves and inner nodes hash under different prefixes so a proof can never
+// present an inner node as a leaf.
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// Leaf committing to `user` being allowed to stake up to `cap` tokens, or
+/// without a per-user cap when `cap` is zero.
+pub fn leaf(user: &Pubkey, cap: u64) -> [u8; 32] {
+    hashv(&[LEAF_PREFIX, user.as_ref(), &cap.to_le_bytes()]).to_bytes()
+}
+
+/// Parent of two nodes. Children are sorted first, so proofs do not need to
+/// say which side each sibling is on.
+pub fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    hashv(&[NODE_PREFIX, left, right]).to_bytes()
+}
+
+pub fn verify(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof
+        .iter()
+        .fold(leaf, |node, sibling| hash_pair(&node, sibling));
+
+    computed == *root
+}
@@ -1,5 +1,8 @@
 use anchor_lang::prelude::*;
 
+use crate::{error::StakingError, merkle};
+use staking_core::limits::DepositLimits;
+
 #[account]
 pub struct StakingPool {
     pub authority: Pubkey,
@@ -7,10 +10,278 @@ pub struct StakingPool {
     pub vault: Pubkey,
     pub total_stake: u64,
     pub bump: u8,
+    /// Rewards earned by one staked token since the pool was created.
+    pub reward_per_token: u128,
+    /// Unix time `reward_per_token` was last advanced to.
+    pub last_update_time: i64,
+    /// Pool-owned token account that pays `update_pool` tips, or the default
+    /// key if cranking is not rewarded.
+    pub tip_vault: Pubkey,
+    /// Paid to whoever advances the accumulator, capped by the tip vault balance.
+    pub crank_tip: u64,
+    /// Cap on `total_stake`, or zero for no cap.
+    pub max_total_stake: u64,
+    /// Cap on a single position's `amount`, or zero for no cap.
+    pub max_stake_per_user: u64,
+    /// Smallest amount a single `stake` may deposit.
+    pub min_stake_amount: u64,
+    /// Unix time deposits open at.
+    pub stake_start: i64,
+    /// Unix time deposits close at, or zero if they never close.
+    pub stake_end: i64,
+    /// Root of the allowlist built with `merkle::leaf`, or all zeroes if
+    /// anyone may stake.
+    pub merkle_root: [u8; 32],
+    /// Rejects new stakes while set; unstaking and claiming keep working.
+    pub paused: bool,
+    /// Seconds a queued admin action waits before it can execute. While
+    /// non-zero, timelocked actions can only go through `queue_action`.
+    pub timelock_delay: i64,
+    /// Admin action waiting out the timelock, if any.
+    pub queued_action: Option<AdminAction>,
+    /// Unix time from which `queued_action` may execute.
+    pub queued_eta: i64,
+    /// Lets stakers pull their principal out with `emergency_withdraw`,
+    /// forfeiting rewards, and stops new stakes.
+    pub emergency_mode: bool,
+    /// Seconds a position must stay staked after its latest deposit before
+    /// it can claim.
+    pub min_stake_age: i64,
 }
 
 impl StakingPool {
-    pub const INIT_SPACE: usize = 8 + 32 + 8 + 32 + 8 + 1;
+    pub const INIT_SPACE: usize = 8
+        + 32
+        + 8
+        + 32
+        + 8
+        + 1
+        + 16
+        + 8
+        + 32
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 32
+        + 1
+        + 8
+        + 1
+        + AdminAction::MAX_SPACE
+        + 8
+        + 1
+        + 8;
+
+    /// Fails if admin actions have to be queued rather than applied directly.
+    pub fn check_unlocked(&self) -> Result<()> {
+        if self.timelock_delay != 0 {
+            return err!(StakingError::TimelockActive);
+        }
+        Ok(())
+    }
+
+    pub fn set_timelock_delay(&mut self, timelock_delay: i64) -> Result<()> {
+        if timelock_delay < 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        self.timelock_delay = timelock_delay;
+        Ok(())
+    }
+
+    /// Schedules `action` to become executable `timelock_delay` seconds after
+    /// `now`. A pool has at most one queued action at a time.
+    pub fn queue(&mut self, action: AdminAction, now: i64) -> Result<()> {
+        if !action.is_timelocked() {
+            return err!(StakingError::ActionNotTimelocked);
+        }
+        if self.queued_action.is_some() {
+            return err!(StakingError::ActionAlreadyQueued);
+        }
+
+        self.queued_action = Some(action);
+        self.queued_eta = now.saturating_add(self.timelock_delay);
+        Ok(())
+    }
+
+    pub fn set_limits(&mut self, limits: &StakeLimits) -> Result<()> {
+        DepositLimits {
+            stake_start: limits.stake_start,
+            stake_end: limits.stake_end,
+            ..DepositLimits::default()
+        }
+        .validate()
+        .map_err(StakingError::from)?;
+        if limits.min_stake_age < 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        self.max_total_stake = limits.max_total_stake;
+        self.max_stake_per_user = limits.max_stake_per_user;
+        self.min_stake_amount = limits.min_stake_amount;
+        self.stake_start = limits.stake_start;
+        self.stake_end = limits.stake_end;
+        self.min_stake_age = limits.min_stake_age;
+        Ok(())
+    }
+
+    /// Checks that a deposit of `amount` at `now` into a position already
+    /// holding `position_amount` stays within the pool's limits.
+    pub fn check_stake(&self, amount: u64, position_amount: u64, now: i64) -> Result<()> {
+        if self.emergency_mode {
+            return err!(StakingError::PoolInEmergency);
+        }
+        if self.paused {
+            return err!(StakingError::PoolPaused);
+        }
+        self.deposit_limits()
+            .check_deposit(amount, position_amount, self.total_stake, now)
+            .map_err(StakingError::from)?;
+        Ok(())
+    }
+
+    fn deposit_limits(&self) -> DepositLimits {
+        DepositLimits {
+            max_total_stake: self.max_total_stake,
+            max_stake_per_user: self.max_stake_per_user,
+            min_stake_amount: self.min_stake_amount,
+            stake_start: self.stake_start,
+            stake_end: self.stake_end,
+        }
+    }
+
+    /// Checks that `beneficiary` is on the allowlist, if the pool has one,
+    /// and that a deposit of `amount` into a position already holding
+    /// `position_amount` stays within the cap its leaf commits to.
+    pub fn check_allowlist(
+        &self,
+        beneficiary: &Pubkey,
+        allowlist: Option<&AllowlistProof>,
+        amount: u64,
+        position_amount: u64,
+    ) -> Result<()> {
+        if self.merkle_root == [0; 32] {
+            return Ok(());
+        }
+
+        let Some(allowlist) = allowlist else {
+            return err!(StakingError::NotAllowlisted);
+        };
+        let leaf = merkle::leaf(beneficiary, allowlist.cap);
+        if !merkle::verify(&allowlist.proof, &self.merkle_root, leaf) {
+            return err!(StakingError::NotAllowlisted);
+        }
+
+        if allowlist.cap != 0 && position_amount.saturating_add(amount) > allowlist.cap {
+            return err!(StakingError::UserCapExceeded);
+        }
+        Ok(())
+    }
+}
+
+/// Proves the beneficiary of a `stake` is on the pool's allowlist with the
+/// given per-user `cap` (zero for none).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AllowlistProof {
+    pub cap: u64,
+    pub proof: Vec<[u8; 32]>,
+}
+
+/// Deposit limits passed to `initialize_pool` and `set_stake_limits`, along
+/// with how long a deposit must sit before it earns rewards. The default
+/// places no limits on staking.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct StakeLimits {
+    pub max_total_stake: u64,
+    pub max_stake_per_user: u64,
+    pub min_stake_amount: u64,
+    pub stake_start: i64,
+    pub stake_end: i64,
+    pub min_stake_age: i64,
+}
+
+/// `owners` that jointly act as a pool's `authority` once the pool's
+/// authority is transferred to this account's address.
+#[account]
+pub struct Multisig {
+    /// Signer the address is derived from, so nobody can front-run creation.
+    pub create_key: Pubkey,
+    pub owners: Vec<Pubkey>,
+    /// Approvals a proposal needs before it can execute.
+    pub threshold: u8,
+    /// Number of proposals created so far; seeds the next proposal's address.
+    pub proposal_count: u64,
+    pub bump: u8,
+}
+
+impl Multisig {
+    pub const MAX_OWNERS: usize = 10;
+    pub const INIT_SPACE: usize = 8 + 32 + 4 + 32 * Self::MAX_OWNERS + 1 + 8 + 1;
+
+    pub fn owner_index(&self, key: &Pubkey) -> Result<usize> {
+        self.owners
+            .iter()
+            .position(|owner| owner == key)
+            .ok_or_else(|| error!(StakingError::NotMultisigOwner))
+    }
+}
+
+/// An admin action a multisig's owners vote on.
+#[account]
+pub struct Proposal {
+    pub multisig: Pubkey,
+    pub staking_pool: Pubkey,
+    pub index: u64,
+    pub proposer: Pubkey,
+    pub action: AdminAction,
+    /// One flag per multisig owner, in the order of `Multisig::owners`.
+    pub approvals: Vec<bool>,
+    pub executed: bool,
+    pub bump: u8,
+}
+
+impl Proposal {
+    pub const INIT_SPACE: usize =
+        8 + 32 + 32 + 8 + 32 + AdminAction::MAX_SPACE + 4 + Multisig::MAX_OWNERS + 1 + 1;
+
+    pub fn approval_count(&self) -> usize {
+        self.approvals.iter().filter(|approved| **approved).count()
+    }
+}
+
+/// Pool configuration changes, each mirroring the single-key instruction of
+/// the same name.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub enum AdminAction {
+    SetRewardRate { reward_rate: u64 },
+    SetPaused { paused: bool },
+    TransferAuthority { new_authority: Pubkey },
+    WithdrawExcessRewards { destination: Pubkey },
+    SetCrankTip { tip_vault: Pubkey, crank_tip: u64 },
+    SetStakeLimits { limits: StakeLimits },
+    SetMerkleRoot { merkle_root: [u8; 32] },
+    SetTimelockDelay { timelock_delay: i64 },
+    CancelQueuedAction,
+    SetEmergencyMode { enabled: bool },
+}
+
+impl AdminAction {
+    /// Variant tag plus the largest payload, `StakeLimits`.
+    pub const MAX_SPACE: usize = 1 + 48;
+
+    /// Whether the pool's timelock applies. Pausing only stops deposits,
+    /// cancelling only stops a change and emergency mode has to act at once,
+    /// so none of them wait.
+    pub fn is_timelocked(&self) -> bool {
+        !matches!(
+            self,
+            AdminAction::SetPaused { .. }
+                | AdminAction::CancelQueuedAction
+                | AdminAction::SetEmergencyMode { .. }
+        )
+    }
 }
 
 #[account]
@@ -19,8 +290,42 @@ pub struct UserStakeAccount {
     pub amount: u64,
     pub pending_rewards: u64,
     pub last_stake_time: i64,
+    /// Key allowed to claim on the owner's behalf, or the default key if none.
+    pub claimer: Pubkey,
+    /// Token account that delegated claims must pay into.
+    pub claim_destination: Pubkey,
+    /// Pool `reward_per_token` when `pending_rewards` was last settled.
+    pub reward_per_token_paid: u128,
+    /// Part of `amount` staked at `last_stake_time` that earns nothing until
+    /// it is the pool's `min_stake_age` old. A top-up while it is still
+    /// warming joins it, moving `last_stake_time` to the two deposits' times
+    /// weighted by amount.
+    pub warming_amount: u64,
 }
 
 impl UserStakeAccount {
-    pub const INIT_SPACE: usize = 8 + 32 + 8 + 8 + 8;
+    pub const INIT_SPACE: usize = 8 + 32 + 8 + 8 + 8 + 32 + 32 + 16 + 8;
+}
+
+/// Returned by `get_pool_stats`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PoolStats {
+    pub authority: Pubkey,
+    pub vault: Pubkey,
+    pub reward_rate: u64,
+    pub total_stake: u64,
+    pub emission_per_second: u64,
+    pub reward_per_token: u128,
+    pub last_update_time: i64,
+    pub timestamp: i64,
+}
+
+/// Returned by `get_position`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PositionInfo {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub last_stake_time: i64,
+    pub pending_rewards: u64,
+    pub timestamp: i64,
 }
@@ -0,0 +1,220 @@
+use anchor_lang::{
+    prelude::*,
+    solana_program::sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
+    Discriminator,
+};
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use staking_core::{lock, rewards};
+
+use crate::{
+    error::StakingError,
+    state::{AdminAction, StakingPool, UserStakeAccount},
+};
+
+/// `pool.reward_per_token` as it would be after an update at `now`.
+pub fn reward_per_token_at(pool: &StakingPool, now: i64) -> Result<u128> {
+    rewards::reward_per_token_at(
+        pool.reward_per_token,
+        pool.reward_rate,
+        pool.last_update_time,
+        now,
+    )
+    .ok_or_else(|| ProgramError::ArithmeticOverflow.into())
+}
+
+/// Advances the pool's accumulator to `now`.
+pub fn update_accumulator(pool: &mut StakingPool, now: i64) -> Result<()> {
+    if now <= pool.last_update_time {
+        return Ok(());
+    }
+
+    pool.reward_per_token = reward_per_token_at(pool, now)?;
+    pool.last_update_time = now;
+    Ok(())
+}
+
+/// Rewards earned on top of `pending_rewards` once the pool's accumulator
+/// reaches `reward_per_token` at `now`. The warming tranche earns only what
+/// accrued after it matured.
+pub fn earned(
+    position: &UserStakeAccount,
+    pool: &StakingPool,
+    reward_per_token: u128,
+    now: i64,
+) -> Result<u64> {
+    let earning = position.amount - position.warming_amount;
+    let matured_from = lock::maturity_reward_per_token(
+        position.reward_per_token_paid,
+        reward_per_token,
+        pool.reward_rate,
+        lock::matures_at(position.last_stake_time, pool.min_stake_age),
+        now,
+    );
+
+    let warming = position.warming_amount;
+    rewards::earned(earning, reward_per_token, position.reward_per_token_paid)
+        .zip(rewards::earned(warming, reward_per_token, matured_from))
+        .and_then(|(earned, matured)| earned.checked_add(matured))
+        .ok_or_else(|| ProgramError::ArithmeticOverflow.into())
+}
+
+/// Fails if an instruction earlier in this transaction stakes into
+/// `position`, so a deposit cannot be staked and claimed in one go. Stakes
+/// made through CPI do not show up here; in pools with a `min_stake_age`
+/// they earn nothing anyway.
+pub fn check_no_stake_before(instructions: &AccountInfo, position: &Pubkey) -> Result<()> {
+    let current = load_current_index_checked(instructions)?;
+
+    for index in 0..current {
+        let ix = load_instruction_at_checked(index as usize, instructions)?;
+        if ix.program_id == crate::ID
+            && ix.data.starts_with(crate::instruction::Stake::DISCRIMINATOR)
+            && ix.accounts.iter().any(|meta| meta.pubkey == *position)
+        {
+            return err!(StakingError::StakedInSameTransaction);
+        }
+    }
+    Ok(())
+}
+
+/// Moves everything earned up to the pool's accumulator into
+/// `pending_rewards`, including what the warming tranche earned since
+/// maturing, and lets the tranche earn like the rest of the stake from then
+/// on if it is old enough at `now`. The pool must be updated first. Returns
+/// whether the tranche started earning.
+pub fn settle(position: &mut UserStakeAccount, pool: &StakingPool, now: i64) -> Result<bool> {
+    position.pending_rewards = position
+        .pending_rewards
+        .checked_add(earned(position, pool, pool.reward_per_token, now)?)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    position.reward_per_token_paid = pool.reward_per_token;
+
+    let matured = position.warming_amount != 0
+        && !lock::is_warming(position.last_stake_time, pool.min_stake_age, now);
+    if matured {
+        position.warming_amount = 0;
+    }
+    Ok(matured)
+}
+
+/// Changes the reward rate from `now` on, settling what accrued at the old rate.
+pub fn set_reward_rate(pool: &mut StakingPool, reward_rate: u64, now: i64) -> Result<()> {
+    update_accumulator(pool, now)?;
+    pool.reward_rate = reward_rate;
+    Ok(())
+}
+
+/// Whether `tip_vault` can fund crank tips: a pool-owned account of the pool
+/// mint that is not the stake vault, or tips would be paid out of principal.
+pub fn is_tip_vault(
+    pool: &Account<StakingPool>,
+    mint: &Pubkey,
+    tip_vault: &Account<TokenAccount>,
+) -> bool {
+    tip_vault.owner == pool.key() && tip_vault.mint == *mint && tip_vault.key() != pool.vault
+}
+
+/// Moves whatever the stake vault holds beyond `total_stake` into
+/// `destination`, returning the amount moved.
+pub fn withdraw_excess<'info>(
+    pool: &Account<'info, StakingPool>,
+    mint: &Pubkey,
+    vault: &Account<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+) -> Result<u64> {
+    let excess = vault.amount.saturating_sub(pool.total_stake);
+    if excess == 0 {
+        return err!(StakingError::NoExcessRewards);
+    }
+
+    let bump = &[pool.bump];
+    let signer_seeds = &[&[b"staking_pool", mint.as_ref(), bump][..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program,
+        Transfer {
+            from: vault.to_account_info(),
+            to: destination,
+            authority: pool.to_account_info(),
+        },
+        signer_seeds,
+    );
+    token::transfer(cpi_ctx, excess)?;
+
+    Ok(excess)
+}
+
+/// Accounts an `AdminAction` may need beyond the pool. Only withdrawals need
+/// the token accounts, and only `SetCrankTip` needs the tip vault.
+pub struct ActionAccounts<'a, 'info> {
+    pub mint: &'a Account<'info, Mint>,
+    pub vault: Option<&'a Account<'info, TokenAccount>>,
+    pub destination: Option<&'a Account<'info, TokenAccount>>,
+    pub tip_vault: Option<&'a Account<'info, TokenAccount>>,
+    pub token_program: Option<&'a Program<'info, Token>>,
+}
+
+/// Applies an approved or matured admin action to the pool.
+pub fn apply_action<'info>(
+    pool: &mut Account<'info, StakingPool>,
+    action: AdminAction,
+    accounts: ActionAccounts<'_, 'info>,
+    now: i64,
+) -> Result<()> {
+    match action {
+        AdminAction::SetRewardRate { reward_rate } => set_reward_rate(pool, reward_rate, now)?,
+        AdminAction::SetPaused { paused } => pool.paused = paused,
+        AdminAction::TransferAuthority { new_authority } => pool.authority = new_authority,
+        AdminAction::WithdrawExcessRewards { destination } => {
+            let (Some(vault), Some(destination_account), Some(token_program)) =
+                (accounts.vault, accounts.destination, accounts.token_program)
+            else {
+                return Err(ProgramError::NotEnoughAccountKeys.into());
+            };
+            if destination_account.key() != destination
+                || destination_account.mint != accounts.mint.key()
+            {
+                return err!(StakingError::ProposalAccountMismatch);
+            }
+
+            withdraw_excess(
+                pool,
+                &accounts.mint.key(),
+                vault,
+                destination_account.to_account_info(),
+                token_program.to_account_info(),
+            )?;
+        }
+        AdminAction::SetCrankTip {
+            tip_vault,
+            crank_tip,
+        } => {
+            let Some(tip_vault_account) = accounts.tip_vault else {
+                return Err(ProgramError::NotEnoughAccountKeys.into());
+            };
+            if tip_vault_account.key() != tip_vault {
+                return err!(StakingError::ProposalAccountMismatch);
+            }
+            if !is_tip_vault(pool, &accounts.mint.key(), tip_vault_account) {
+                return Err(ProgramError::InvalidAccountData.into());
+            }
+
+            pool.tip_vault = tip_vault;
+            pool.crank_tip = crank_tip;
+        }
+        AdminAction::SetStakeLimits { limits } => pool.set_limits(&limits)?,
+        AdminAction::SetMerkleRoot { merkle_root } => pool.merkle_root = merkle_root,
+        AdminAction::SetTimelockDelay { timelock_delay } => {
+            pool.set_timelock_delay(timelock_delay)?
+        }
+        AdminAction::CancelQueuedAction => {
+            if pool.queued_action.take().is_none() {
+                return err!(StakingError::NoQueuedAction);
+            }
+            pool.queued_eta = 0;
+        }
+        AdminAction::SetEmergencyMode { enabled } => pool.emergency_mode = enabled,
+    }
+    Ok(())
+}
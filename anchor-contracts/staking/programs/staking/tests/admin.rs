@@ -0,0 +1,553 @@
+mod common;
+
+use anchor_lang::{
+    error::ErrorCode,
+    prelude::{Clock, Pubkey},
+    system_program, AccountDeserialize, AnchorDeserialize, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::spl_token;
+use common::{
+    account_data, create_mint, create_token_account, new_user, send, setup, stake, token_balance,
+    user_stake_address, Env,
+};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    sysvar,
+    transaction::{Transaction, TransactionError},
+};
+use staking::{
+    error::StakingError,
+    state::{AdminAction, StakeLimits, StakingPool},
+};
+
+/// Runs a view instruction and decodes what it returned.
+async fn view<T: AnchorDeserialize>(context: &mut ProgramTestContext, ix: Instruction) -> T {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        blockhash,
+    );
+
+    let simulation = context.banks_client.simulate_transaction(tx).await.unwrap();
+    simulation.result.unwrap().unwrap();
+    let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+    T::try_from_slice(&return_data.data).unwrap()
+}
+
+fn assert_error(result: Result<(), BanksClientError>, expected: InstructionError) {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(_, err) => assert_eq!(err, expected),
+        err => panic!("unexpected error: {err:?}"),
+    }
+}
+
+fn custom(code: impl Into<u32>) -> InstructionError {
+    InstructionError::Custom(code.into())
+}
+
+async fn warp(context: &mut ProgramTestContext, seconds: i64) {
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += seconds;
+    context.set_sysvar(&clock);
+}
+
+async fn now(context: &mut ProgramTestContext) -> i64 {
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp
+}
+
+async fn pool(env: &mut Env) -> StakingPool {
+    let data = account_data(&mut env.context, env.staking_pool).await;
+    StakingPool::try_deserialize(&mut data.as_slice()).unwrap()
+}
+
+fn unstake_accounts(
+    env: &Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+) -> staking::accounts::Unstake {
+    staking::accounts::Unstake {
+        user: user.pubkey(),
+        user_token_account,
+        vault: env.vault,
+        mint: env.mint,
+        staking_pool: env.staking_pool,
+        user_stake_account: user_stake_address(env, user),
+        system_program: system_program::ID,
+        token_program: spl_token::ID,
+        rent: sysvar::rent::ID,
+    }
+}
+
+async fn unstake(
+    env: &mut Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+    amount: u64,
+) -> Result<(), BanksClientError> {
+    let accounts = unstake_accounts(env, user, user_token_account);
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: accounts.to_account_metas(None),
+            data: staking::instruction::Unstake { amount }.data(),
+        }],
+        &[user],
+    )
+    .await
+}
+
+fn position_ix(env: &Env, user: &Keypair, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::GetPosition {
+            staking_pool: env.staking_pool,
+            user_stake_account: user_stake_address(env, user),
+        }
+        .to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+async fn pending_rewards(env: &mut Env, user: &Keypair) -> u64 {
+    let ix = position_ix(env, user, staking::instruction::GetPendingRewards {});
+    view(&mut env.context, ix).await
+}
+
+/// Sends a single-key admin instruction signed by `authority`.
+async fn admin(
+    env: &mut Env,
+    authority: &Keypair,
+    data: impl InstructionData,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            // Every single-key admin instruction takes these two accounts.
+            accounts: staking::accounts::SetRewardRate {
+                authority: authority.pubkey(),
+                staking_pool: env.staking_pool,
+            }
+            .to_account_metas(None),
+            data: data.data(),
+        }],
+        &[authority],
+    )
+    .await
+}
+
+async fn set_crank_tip(
+    env: &mut Env,
+    authority: &Keypair,
+    tip_vault: Pubkey,
+    crank_tip: u64,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::SetCrankTip {
+                authority: authority.pubkey(),
+                mint: env.mint,
+                staking_pool: env.staking_pool,
+                tip_vault,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::SetCrankTip { crank_tip }.data(),
+        }],
+        &[authority],
+    )
+    .await
+}
+
+async fn withdraw_excess_rewards(
+    env: &mut Env,
+    authority: &Keypair,
+    vault: Pubkey,
+    destination: Pubkey,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::WithdrawExcessRewards {
+                authority: authority.pubkey(),
+                mint: env.mint,
+                staking_pool: env.staking_pool,
+                vault,
+                destination,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::WithdrawExcessRewards {}.data(),
+        }],
+        &[authority],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn admin_instructions_reject_anyone_but_the_authority() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let (stranger, stranger_tokens) = new_user(&mut env, 0).await;
+    let tip_vault = create_token_account(&mut env.context, &env.mint, &env.staking_pool).await;
+
+    let has_one = || custom(ErrorCode::ConstraintHasOne);
+    let limits = StakeLimits {
+        min_stake_amount: 10,
+        ..StakeLimits::default()
+    };
+    assert_error(
+        admin(
+            &mut env,
+            &stranger,
+            staking::instruction::SetStakeLimits { limits },
+        )
+        .await,
+        has_one(),
+    );
+    let merkle_root = [7; 32];
+    assert_error(
+        admin(
+            &mut env,
+            &stranger,
+            staking::instruction::SetMerkleRoot { merkle_root },
+        )
+        .await,
+        has_one(),
+    );
+    let reward_rate = 9;
+    assert_error(
+        admin(
+            &mut env,
+            &stranger,
+            staking::instruction::SetRewardRate { reward_rate },
+        )
+        .await,
+        has_one(),
+    );
+    assert_error(
+        admin(
+            &mut env,
+            &stranger,
+            staking::instruction::SetPaused { paused: true },
+        )
+        .await,
+        has_one(),
+    );
+    let new_authority = stranger.pubkey();
+    assert_error(
+        admin(
+            &mut env,
+            &stranger,
+            staking::instruction::TransferAuthority { new_authority },
+        )
+        .await,
+        has_one(),
+    );
+    assert_error(
+        admin(
+            &mut env,
+            &stranger,
+            staking::instruction::SetEmergencyMode { enabled: true },
+        )
+        .await,
+        has_one(),
+    );
+    let timelock_delay = 60;
+    assert_error(
+        admin(
+            &mut env,
+            &stranger,
+            staking::instruction::SetTimelockDelay { timelock_delay },
+        )
+        .await,
+        has_one(),
+    );
+    let action = AdminAction::SetPaused { paused: true };
+    assert_error(
+        admin(
+            &mut env,
+            &stranger,
+            staking::instruction::QueueAction { action },
+        )
+        .await,
+        has_one(),
+    );
+    assert_error(
+        admin(
+            &mut env,
+            &stranger,
+            staking::instruction::CancelQueuedAction {},
+        )
+        .await,
+        has_one(),
+    );
+    assert_error(
+        set_crank_tip(&mut env, &stranger, tip_vault, 5).await,
+        has_one(),
+    );
+    let vault = env.vault;
+    assert_error(
+        withdraw_excess_rewards(&mut env, &stranger, vault, stranger_tokens).await,
+        has_one(),
+    );
+
+    let pool = pool(&mut env).await;
+    assert_eq!(pool.authority, env.context.payer.pubkey());
+    assert_eq!(pool.reward_rate, 1);
+    assert_eq!(pool.min_stake_amount, 0);
+    assert_eq!(pool.merkle_root, [0; 32]);
+    assert_eq!(pool.tip_vault, Pubkey::default());
+    assert!(!pool.paused);
+    assert!(!pool.emergency_mode);
+    assert_eq!(pool.timelock_delay, 0);
+}
+
+#[tokio::test]
+async fn set_reward_rate_settles_accrual_at_the_old_rate() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let authority = env.context.payer.insecure_clone();
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    stake(&mut env, &user, user_tokens, 100).await.unwrap();
+    warp(&mut env.context, 10).await;
+
+    let reward_rate = 5;
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetRewardRate { reward_rate },
+    )
+    .await
+    .unwrap();
+    let pool_state = pool(&mut env).await;
+    assert_eq!(pool_state.reward_rate, 5);
+    assert_eq!(pool_state.reward_per_token, 10);
+    assert_eq!(pool_state.last_update_time, now(&mut env.context).await);
+
+    warp(&mut env.context, 10).await;
+    assert_eq!(
+        pending_rewards(&mut env, &user).await,
+        100 * 10 + 100 * 5 * 10
+    );
+}
+
+#[tokio::test]
+async fn paused_pools_take_no_new_stakes() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let authority = env.context.payer.insecure_clone();
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    stake(&mut env, &user, user_tokens, 100).await.unwrap();
+
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetPaused { paused: true },
+    )
+    .await
+    .unwrap();
+    assert!(pool(&mut env).await.paused);
+    assert_error(
+        stake(&mut env, &user, user_tokens, 100).await,
+        custom(StakingError::PoolPaused),
+    );
+
+    // Withdrawals keep working while paused.
+    unstake(&mut env, &user, user_tokens, 40).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, user_tokens).await, 940);
+
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetPaused { paused: false },
+    )
+    .await
+    .unwrap();
+    stake(&mut env, &user, user_tokens, 100).await.unwrap();
+    assert_eq!(pool(&mut env).await.total_stake, 160);
+}
+
+#[tokio::test]
+async fn transfer_authority_hands_over_admin_rights() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let authority = env.context.payer.insecure_clone();
+    let (successor, _) = new_user(&mut env, 0).await;
+
+    let new_authority = successor.pubkey();
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::TransferAuthority { new_authority },
+    )
+    .await
+    .unwrap();
+    assert_eq!(pool(&mut env).await.authority, successor.pubkey());
+
+    assert_error(
+        admin(
+            &mut env,
+            &authority,
+            staking::instruction::SetPaused { paused: true },
+        )
+        .await,
+        custom(ErrorCode::ConstraintHasOne),
+    );
+    admin(
+        &mut env,
+        &successor,
+        staking::instruction::SetPaused { paused: true },
+    )
+    .await
+    .unwrap();
+    assert!(pool(&mut env).await.paused);
+}
+
+#[tokio::test]
+async fn withdraw_excess_rewards_leaves_principal_in_the_vault() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let authority = env.context.payer.insecure_clone();
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    let treasury = create_token_account(&mut env.context, &env.mint, &authority.pubkey()).await;
+    let other_mint = create_mint(&mut env.context).await;
+    let other_treasury =
+        create_token_account(&mut env.context, &other_mint, &authority.pubkey()).await;
+    let stray_vault = create_token_account(&mut env.context, &env.mint, &env.staking_pool).await;
+    stake(&mut env, &user, user_tokens, 500).await.unwrap();
+
+    let vault = env.vault;
+    assert_error(
+        withdraw_excess_rewards(&mut env, &authority, vault, treasury).await,
+        custom(StakingError::NoExcessRewards),
+    );
+
+    send(
+        &mut env.context,
+        &[spl_token::instruction::mint_to(
+            &spl_token::ID,
+            &env.mint,
+            &vault,
+            &authority.pubkey(),
+            &[],
+            300,
+        )
+        .unwrap()],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert_error(
+        withdraw_excess_rewards(&mut env, &authority, stray_vault, treasury).await,
+        custom(ErrorCode::ConstraintHasOne),
+    );
+    assert_error(
+        withdraw_excess_rewards(&mut env, &authority, vault, other_treasury).await,
+        custom(ErrorCode::ConstraintRaw),
+    );
+
+    withdraw_excess_rewards(&mut env, &authority, vault, treasury)
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut env.context, treasury).await, 300);
+    assert_eq!(token_balance(&mut env.context, vault).await, 500);
+    assert_error(
+        withdraw_excess_rewards(&mut env, &authority, vault, treasury).await,
+        custom(StakingError::NoExcessRewards),
+    );
+}
+
+#[tokio::test]
+async fn set_crank_tip_only_accepts_a_separate_pool_vault() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let authority = env.context.payer.insecure_clone();
+    let other_mint = create_mint(&mut env.context).await;
+    let foreign = create_token_account(&mut env.context, &env.mint, &authority.pubkey()).await;
+    let wrong_mint = create_token_account(&mut env.context, &other_mint, &env.staking_pool).await;
+    let tip_vault = create_token_account(&mut env.context, &env.mint, &env.staking_pool).await;
+
+    // Paying tips out of the stake vault would spend principal.
+    let vault = env.vault;
+    for invalid in [vault, foreign, wrong_mint] {
+        assert_error(
+            set_crank_tip(&mut env, &authority, invalid, 5).await,
+            custom(ErrorCode::ConstraintRaw),
+        );
+    }
+
+    set_crank_tip(&mut env, &authority, tip_vault, 5)
+        .await
+        .unwrap();
+    let pool_state = pool(&mut env).await;
+    assert_eq!(pool_state.tip_vault, tip_vault);
+    assert_eq!(pool_state.crank_tip, 5);
+}
+
+#[tokio::test]
+async fn direct_changes_stop_once_a_timelock_is_set() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let authority = env.context.payer.insecure_clone();
+
+    let merkle_root = [7; 32];
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetMerkleRoot { merkle_root },
+    )
+    .await
+    .unwrap();
+    assert_eq!(pool(&mut env).await.merkle_root, merkle_root);
+
+    assert_error(
+        admin(
+            &mut env,
+            &authority,
+            staking::instruction::SetTimelockDelay { timelock_delay: -1 },
+        )
+        .await,
+        InstructionError::InvalidArgument,
+    );
+    let timelock_delay = 60;
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetTimelockDelay { timelock_delay },
+    )
+    .await
+    .unwrap();
+    assert_eq!(pool(&mut env).await.timelock_delay, 60);
+
+    let merkle_root = [0; 32];
+    assert_error(
+        admin(
+            &mut env,
+            &authority,
+            staking::instruction::SetMerkleRoot { merkle_root },
+        )
+        .await,
+        custom(StakingError::TimelockActive),
+    );
+    // Pausing and emergency mode are never held back.
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetPaused { paused: true },
+    )
+    .await
+    .unwrap();
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetEmergencyMode { enabled: true },
+    )
+    .await
+    .unwrap();
+    let pool_state = pool(&mut env).await;
+    assert!(pool_state.paused);
+    assert!(pool_state.emergency_mode);
+}
@@ -0,0 +1,299 @@
+//! Helpers shared by the program's integration tests and the keeper's: the
+//! program running natively under `solana-program-test`, a pool over a fresh
+//! mint administered by the payer, and funded users staking into it. Each
+//! test crate uses only some of them.
+#![allow(dead_code)]
+
+use anchor_lang::{
+    prelude::{AccountInfo, Pubkey},
+    solana_program::{entrypoint::ProgramResult, program_pack::Pack},
+    system_program, AccountDeserialize, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::{spl_token, TokenAccount};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::Instruction,
+    signature::{Keypair, Signer},
+    sysvar,
+    transaction::Transaction,
+};
+use solana_system_interface::instruction as system_instruction;
+use staking::state::StakeLimits;
+
+pub fn staking_entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    staking::entry(program_id, accounts, data)
+}
+
+pub struct Env {
+    pub context: ProgramTestContext,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub staking_pool: Pubkey,
+}
+
+pub async fn send(
+    context: &mut ProgramTestContext,
+    ixs: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let tx = Transaction::new_signed_with_payer(
+        ixs,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await
+}
+
+pub async fn account_data(context: &mut ProgramTestContext, address: Pubkey) -> Vec<u8> {
+    context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap()
+        .data
+}
+
+pub async fn token_balance(context: &mut ProgramTestContext, account: Pubkey) -> u64 {
+    let data = account_data(context, account).await;
+    TokenAccount::try_deserialize(&mut data.as_slice())
+        .unwrap()
+        .amount
+}
+
+/// Creates a mint with the payer as its mint authority.
+pub async fn create_mint(context: &mut ProgramTestContext) -> Pubkey {
+    let mint = Keypair::new();
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    send(
+        context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::ID,
+                &mint.pubkey(),
+                &payer,
+                None,
+                6,
+            )
+            .unwrap(),
+        ],
+        &[&mint],
+    )
+    .await
+    .unwrap();
+
+    mint.pubkey()
+}
+
+pub async fn create_token_account(
+    context: &mut ProgramTestContext,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Pubkey {
+    let account = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+
+    send(
+        context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &account.pubkey(),
+                rent.minimum_balance(spl_token::state::Account::LEN),
+                spl_token::state::Account::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::ID,
+                &account.pubkey(),
+                mint,
+                owner,
+            )
+            .unwrap(),
+        ],
+        &[&account],
+    )
+    .await
+    .unwrap();
+
+    account.pubkey()
+}
+
+pub fn pool_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"staking_pool", mint.as_ref()], &staking::ID).0
+}
+
+pub async fn initialize_pool(
+    context: &mut ProgramTestContext,
+    mint: Pubkey,
+    vault: Pubkey,
+    reward_rate: u64,
+    limits: StakeLimits,
+) -> Result<(), BanksClientError> {
+    let authority = context.payer.pubkey();
+
+    send(
+        context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::InitializePool {
+                staking_pool: pool_address(&mint),
+                authority,
+                vault,
+                mint,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: sysvar::rent::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::InitializePool {
+                reward_rate,
+                limits,
+            }
+            .data(),
+        }],
+        &[],
+    )
+    .await
+}
+
+pub async fn start() -> ProgramTestContext {
+    let program_test = ProgramTest::new("staking", staking::ID, processor!(staking_entry));
+    program_test.start_with_context().await
+}
+
+/// Creates a pool over a fresh mint, administered by the payer, who keeps
+/// mint authority until `hand_mint_to_pool`.
+pub async fn setup(reward_rate: u64, limits: StakeLimits) -> Env {
+    let mut context = start().await;
+    let mint = create_mint(&mut context).await;
+    let staking_pool = pool_address(&mint);
+    let vault = create_token_account(&mut context, &mint, &staking_pool).await;
+    initialize_pool(&mut context, mint, vault, reward_rate, limits)
+        .await
+        .unwrap();
+
+    Env {
+        context,
+        mint,
+        vault,
+        staking_pool,
+    }
+}
+
+/// Funds a fresh user with `balance` tokens.
+pub async fn new_user(env: &mut Env, balance: u64) -> (Keypair, Pubkey) {
+    let user = Keypair::new();
+    let payer = env.context.payer.pubkey();
+    let token_account = create_token_account(&mut env.context, &env.mint, &user.pubkey()).await;
+
+    send(
+        &mut env.context,
+        &[
+            system_instruction::transfer(&payer, &user.pubkey(), 1_000_000_000),
+            spl_token::instruction::mint_to(
+                &spl_token::ID,
+                &env.mint,
+                &token_account,
+                &payer,
+                &[],
+                balance,
+            )
+            .unwrap(),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    (user, token_account)
+}
+
+/// Gives the pool mint authority so claims can mint rewards.
+pub async fn hand_mint_to_pool(env: &mut Env) {
+    let payer = env.context.payer.pubkey();
+    send(
+        &mut env.context,
+        &[spl_token::instruction::set_authority(
+            &spl_token::ID,
+            &env.mint,
+            Some(&env.staking_pool),
+            spl_token::instruction::AuthorityType::MintTokens,
+            &payer,
+            &[],
+        )
+        .unwrap()],
+        &[],
+    )
+    .await
+    .unwrap();
+}
+
+pub fn user_stake_address(env: &Env, user: &Keypair) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"user_stake",
+            env.staking_pool.as_ref(),
+            user.pubkey().as_ref(),
+        ],
+        &staking::ID,
+    )
+    .0
+}
+
+pub fn stake_accounts(
+    env: &Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+) -> staking::accounts::Stake {
+    staking::accounts::Stake {
+        user: user.pubkey(),
+        beneficiary: user.pubkey(),
+        user_token_account,
+        vault: env.vault,
+        mint: env.mint,
+        staking_pool: env.staking_pool,
+        user_stake_account: user_stake_address(env, user),
+        token_program: spl_token::ID,
+        system_program: system_program::ID,
+        rent: sysvar::rent::ID,
+    }
+}
+
+pub fn stake_ix(env: &Env, user: &Keypair, user_token_account: Pubkey, amount: u64) -> Instruction {
+    Instruction {
+        program_id: staking::ID,
+        accounts: stake_accounts(env, user, user_token_account).to_account_metas(None),
+        data: staking::instruction::Stake {
+            amount,
+            allowlist: None,
+        }
+        .data(),
+    }
+}
+
+pub async fn stake(
+    env: &mut Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+    amount: u64,
+) -> Result<(), BanksClientError> {
+    let ix = stake_ix(env, user, user_token_account, amount);
+    send(&mut env.context, &[ix], &[user]).await
+}
@@ -0,0 +1,196 @@
+mod common;
+
+use anchor_lang::{
+    error::ErrorCode,
+    prelude::{Clock, Pubkey},
+    AccountDeserialize, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::spl_token;
+use common::{account_data, new_user, send, setup, stake, token_balance, user_stake_address, Env};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    sysvar,
+    transaction::TransactionError,
+};
+use staking::{
+    error::StakingError,
+    state::{StakeLimits, StakingPool},
+};
+
+fn assert_error(result: Result<(), BanksClientError>, expected: impl Into<u32>) {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            assert_eq!(code, expected.into())
+        }
+        err => panic!("unexpected error: {err:?}"),
+    }
+}
+
+/// Sends a single-key admin instruction signed by `authority`.
+async fn admin(
+    env: &mut Env,
+    authority: &Keypair,
+    data: impl InstructionData,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            // Every single-key admin instruction takes these two accounts.
+            accounts: staking::accounts::SetEmergencyMode {
+                authority: authority.pubkey(),
+                staking_pool: env.staking_pool,
+            }
+            .to_account_metas(None),
+            data: data.data(),
+        }],
+        &[authority],
+    )
+    .await
+}
+
+async fn claim_rewards(
+    env: &mut Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+) -> Result<(), BanksClientError> {
+    let user_stake_account = user_stake_address(env, user);
+
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::ClaimRewards {
+                claimer: user.pubkey(),
+                user: user.pubkey(),
+                user_token_account,
+                mint: env.mint,
+                vault: env.vault,
+                staking_pool: env.staking_pool,
+                user_stake_account,
+                token_program: spl_token::ID,
+                instructions: sysvar::instructions::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::ClaimRewards {}.data(),
+        }],
+        &[user],
+    )
+    .await
+}
+
+async fn emergency_withdraw(
+    env: &mut Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+) -> Result<(), BanksClientError> {
+    let user_stake_account = user_stake_address(env, user);
+
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::EmergencyWithdraw {
+                user: user.pubkey(),
+                user_token_account,
+                vault: env.vault,
+                mint: env.mint,
+                staking_pool: env.staking_pool,
+                user_stake_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::EmergencyWithdraw {}.data(),
+        }],
+        &[user],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn emergency_withdraw_returns_principal_when_rewards_are_broken() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let authority = env.context.payer.insecure_clone();
+    let (user, user_tokens) = new_user(&mut env, 500).await;
+    stake(&mut env, &user, user_tokens, 500).await.unwrap();
+
+    assert_error(
+        emergency_withdraw(&mut env, &user, user_tokens).await,
+        StakingError::EmergencyModeOff,
+    );
+
+    // Break the reward math so settling the position overflows.
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetRewardRate {
+            reward_rate: u64::MAX,
+        },
+    )
+    .await
+    .unwrap();
+    let mut clock: Clock = env.context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += 10;
+    env.context.set_sysvar(&clock);
+
+    assert_eq!(
+        claim_rewards(&mut env, &user, user_tokens)
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(0, InstructionError::ArithmeticOverflow)
+    );
+
+    // Only the authority can switch emergency mode on, and the timelock does
+    // not delay it.
+    assert_error(
+        admin(
+            &mut env,
+            &user,
+            staking::instruction::SetEmergencyMode { enabled: true },
+        )
+        .await,
+        ErrorCode::ConstraintHasOne,
+    );
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetTimelockDelay {
+            timelock_delay: 100,
+        },
+    )
+    .await
+    .unwrap();
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetEmergencyMode { enabled: true },
+    )
+    .await
+    .unwrap();
+
+    assert_error(
+        stake(&mut env, &user, user_tokens, 1).await,
+        StakingError::PoolInEmergency,
+    );
+
+    emergency_withdraw(&mut env, &user, user_tokens)
+        .await
+        .unwrap();
+
+    assert_eq!(token_balance(&mut env.context, user_tokens).await, 500);
+    assert_eq!(token_balance(&mut env.context, env.vault).await, 0);
+    assert!(env
+        .context
+        .banks_client
+        .get_account(user_stake_address(&env, &user))
+        .await
+        .unwrap()
+        .is_none());
+
+    let data = account_data(&mut env.context, env.staking_pool).await;
+    let pool = StakingPool::try_deserialize(&mut data.as_slice()).unwrap();
+    assert_eq!(pool.total_stake, 0);
+}
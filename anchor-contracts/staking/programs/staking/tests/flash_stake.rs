@@ -0,0 +1,290 @@
+mod common;
+
+use anchor_lang::{
+    error::ErrorCode,
+    prelude::{Clock, Pubkey},
+    AccountDeserialize, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::spl_token;
+use common::{
+    account_data, hand_mint_to_pool, new_user, send, setup, stake_accounts, stake_ix,
+    token_balance, user_stake_address, Env,
+};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    sysvar,
+    transaction::TransactionError,
+};
+use staking::{
+    error::StakingError,
+    state::{StakeLimits, UserStakeAccount},
+};
+
+fn assert_error(result: Result<(), BanksClientError>, index: u8, expected: impl Into<u32>) {
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(index, InstructionError::Custom(expected.into()))
+    );
+}
+
+fn claim_ix(env: &Env, user: &Keypair, user_token_account: Pubkey) -> Instruction {
+    Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::ClaimRewards {
+            claimer: user.pubkey(),
+            user: user.pubkey(),
+            user_token_account,
+            mint: env.mint,
+            vault: env.vault,
+            staking_pool: env.staking_pool,
+            user_stake_account: user_stake_address(env, user),
+            token_program: spl_token::ID,
+            instructions: sysvar::instructions::ID,
+        }
+        .to_account_metas(None),
+        data: staking::instruction::ClaimRewards {}.data(),
+    }
+}
+
+async fn warp(env: &mut Env, seconds: i64) {
+    let mut clock: Clock = env.context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += seconds;
+    env.context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn claim_in_the_staking_transaction_is_rejected() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    hand_mint_to_pool(&mut env).await;
+
+    let stake = stake_ix(&env, &user, user_tokens, 500);
+    send(&mut env.context, &[stake], &[&user]).await.unwrap();
+    warp(&mut env, 100).await;
+
+    // Topping up and claiming in one transaction would let a flash-loaned
+    // deposit ride along with the claim.
+    let ixs = [
+        stake_ix(&env, &user, user_tokens, 500),
+        claim_ix(&env, &user, user_tokens),
+    ];
+    assert_error(
+        send(&mut env.context, &ixs, &[&user]).await,
+        1,
+        StakingError::StakedInSameTransaction,
+    );
+
+    // Claiming before the top-up is fine.
+    let ixs = [
+        claim_ix(&env, &user, user_tokens),
+        stake_ix(&env, &user, user_tokens, 500),
+    ];
+    send(&mut env.context, &ixs, &[&user]).await.unwrap();
+    assert!(token_balance(&mut env.context, user_tokens).await >= 100);
+}
+
+#[tokio::test]
+async fn stake_earns_nothing_until_min_stake_age() {
+    let mut env = setup(
+        1,
+        StakeLimits {
+            min_stake_age: 60,
+            ..StakeLimits::default()
+        },
+    )
+    .await;
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    hand_mint_to_pool(&mut env).await;
+
+    let stake = stake_ix(&env, &user, user_tokens, 500);
+    send(&mut env.context, &[stake], &[&user]).await.unwrap();
+    warp(&mut env, 30).await;
+
+    // Nothing has been earned while the stake warms up.
+    let claim = claim_ix(&env, &user, user_tokens);
+    assert_eq!(
+        send(&mut env.context, &[claim], &[&user])
+            .await
+            .unwrap_err()
+            .unwrap(),
+        TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+    );
+
+    // Claiming once it is old enough pays nothing but starts it earning.
+    warp(&mut env, 30).await;
+    let claim = claim_ix(&env, &user, user_tokens);
+    send(&mut env.context, &[claim], &[&user]).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, user_tokens).await, 500);
+
+    warp(&mut env, 10).await;
+    let claim = claim_ix(&env, &user, user_tokens);
+    send(&mut env.context, &[claim], &[&user]).await.unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, user_tokens).await,
+        500 + 500 * 10
+    );
+}
+
+#[tokio::test]
+async fn top_up_warms_only_the_new_stake() {
+    let mut env = setup(
+        1,
+        StakeLimits {
+            min_stake_age: 60,
+            ..StakeLimits::default()
+        },
+    )
+    .await;
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    hand_mint_to_pool(&mut env).await;
+
+    let stake = stake_ix(&env, &user, user_tokens, 100);
+    send(&mut env.context, &[stake], &[&user]).await.unwrap();
+    warp(&mut env, 60).await;
+    let claim = claim_ix(&env, &user, user_tokens);
+    send(&mut env.context, &[claim], &[&user]).await.unwrap();
+
+    // The existing stake keeps earning and stays claimable after a top-up,
+    // while the top-up earns nothing.
+    warp(&mut env, 10).await;
+    let stake = stake_ix(&env, &user, user_tokens, 400);
+    send(&mut env.context, &[stake], &[&user]).await.unwrap();
+    warp(&mut env, 10).await;
+    let claim = claim_ix(&env, &user, user_tokens);
+    send(&mut env.context, &[claim], &[&user]).await.unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, user_tokens).await,
+        500 + 100 * 20
+    );
+
+    let position = user_stake_address(&env, &user);
+    let data = account_data(&mut env.context, position).await;
+    let position = UserStakeAccount::try_deserialize(&mut data.as_slice()).unwrap();
+    assert_eq!((position.amount, position.warming_amount), (500, 400));
+
+    // Once 60 seconds old the top-up earns too.
+    warp(&mut env, 50).await;
+    let claim = claim_ix(&env, &user, user_tokens);
+    send(&mut env.context, &[claim], &[&user]).await.unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, user_tokens).await,
+        2_500 + 100 * 50
+    );
+
+    warp(&mut env, 10).await;
+    let claim = claim_ix(&env, &user, user_tokens);
+    send(&mut env.context, &[claim], &[&user]).await.unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, user_tokens).await,
+        7_500 + 500 * 10
+    );
+}
+
+#[tokio::test]
+async fn warming_stake_earns_from_maturity() {
+    let mut env = setup(
+        1,
+        StakeLimits {
+            min_stake_age: 60,
+            ..StakeLimits::default()
+        },
+    )
+    .await;
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    let (late_user, late_user_tokens) = new_user(&mut env, 1_000).await;
+    hand_mint_to_pool(&mut env).await;
+
+    // Stake left alone long past maturity earns everything after its first
+    // 60 seconds.
+    let stake = stake_ix(&env, &user, user_tokens, 100);
+    send(&mut env.context, &[stake], &[&user]).await.unwrap();
+    warp(&mut env, 1_000).await;
+    let claim = claim_ix(&env, &user, user_tokens);
+    send(&mut env.context, &[claim], &[&user]).await.unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, user_tokens).await,
+        900 + 100 * 940
+    );
+
+    // Topping up the same amount 30 seconds into the wait delays maturity by
+    // 15 seconds instead of restarting it.
+    let stake = stake_ix(&env, &late_user, late_user_tokens, 100);
+    send(&mut env.context, &[stake], &[&late_user])
+        .await
+        .unwrap();
+    warp(&mut env, 30).await;
+    let stake = stake_ix(&env, &late_user, late_user_tokens, 100);
+    send(&mut env.context, &[stake], &[&late_user])
+        .await
+        .unwrap();
+    warp(&mut env, 40).await;
+    let claim = claim_ix(&env, &late_user, late_user_tokens);
+    assert!(send(&mut env.context, &[claim], &[&late_user])
+        .await
+        .is_err());
+
+    warp(&mut env, 10).await;
+    let claim = claim_ix(&env, &late_user, late_user_tokens);
+    send(&mut env.context, &[claim], &[&late_user])
+        .await
+        .unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, late_user_tokens).await,
+        800 + 200 * 5
+    );
+}
+
+#[tokio::test]
+async fn others_cannot_hold_back_a_warming_position() {
+    let mut env = setup(
+        1,
+        StakeLimits {
+            min_stake_age: 60,
+            ..StakeLimits::default()
+        },
+    )
+    .await;
+    let (owner, owner_tokens) = new_user(&mut env, 1_000).await;
+    let (griefer, griefer_tokens) = new_user(&mut env, 1_000).await;
+    hand_mint_to_pool(&mut env).await;
+
+    let stake = stake_ix(&env, &owner, owner_tokens, 100);
+    send(&mut env.context, &[stake], &[&owner]).await.unwrap();
+    warp(&mut env, 30).await;
+
+    // A deposit into the owner's position would join its warming stake, so
+    // it needs the owner's signature too.
+    let mut accounts = staking::accounts::Stake {
+        beneficiary: owner.pubkey(),
+        user_stake_account: user_stake_address(&env, &owner),
+        ..stake_accounts(&env, &griefer, griefer_tokens)
+    }
+    .to_account_metas(None);
+    accounts[1].is_signer = false;
+    let stake = Instruction {
+        program_id: staking::ID,
+        accounts,
+        data: staking::instruction::Stake {
+            amount: 1_000,
+            allowlist: None,
+        }
+        .data(),
+    };
+    assert_error(
+        send(&mut env.context, &[stake], &[&griefer]).await,
+        0,
+        ErrorCode::AccountNotSigner,
+    );
+
+    // The owner's stake matures on schedule.
+    warp(&mut env, 40).await;
+    let claim = claim_ix(&env, &owner, owner_tokens);
+    send(&mut env.context, &[claim], &[&owner]).await.unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, owner_tokens).await,
+        900 + 100 * 10
+    );
+    assert_eq!(token_balance(&mut env.context, griefer_tokens).await, 1_000);
+}
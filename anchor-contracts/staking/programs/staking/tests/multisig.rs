@@ -0,0 +1,397 @@
+mod common;
+
+use anchor_lang::{
+    error::ErrorCode, prelude::Pubkey, system_program, AccountDeserialize, InstructionData,
+    ToAccountMetas,
+};
+use anchor_spl::token::spl_token;
+use common::{account_data, create_token_account, send, setup, token_balance, Env};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    sysvar,
+    transaction::TransactionError,
+};
+use solana_system_interface::instruction as system_instruction;
+use staking::{
+    error::StakingError,
+    state::{AdminAction, StakeLimits, StakingPool},
+};
+
+fn assert_error(result: Result<(), BanksClientError>, expected: impl Into<u32>) {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            assert_eq!(code, expected.into())
+        }
+        err => panic!("unexpected error: {err:?}"),
+    }
+}
+
+async fn pool(env: &mut Env) -> StakingPool {
+    let data = account_data(&mut env.context, env.staking_pool).await;
+    StakingPool::try_deserialize(&mut data.as_slice()).unwrap()
+}
+
+/// Creates funded owners and a multisig over them with `threshold`.
+async fn create_multisig(
+    env: &mut Env,
+    owner_count: usize,
+    threshold: u8,
+) -> Result<(Pubkey, Vec<Keypair>), BanksClientError> {
+    let payer = env.context.payer.pubkey();
+    let owners: Vec<_> = (0..owner_count).map(|_| Keypair::new()).collect();
+    let create_key = Keypair::new();
+    let (multisig, _) =
+        Pubkey::find_program_address(&[b"multisig", create_key.pubkey().as_ref()], &staking::ID);
+
+    let mut ixs: Vec<_> = owners
+        .iter()
+        .map(|owner| system_instruction::transfer(&payer, &owner.pubkey(), 1_000_000_000))
+        .collect();
+    ixs.push(Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::CreateMultisig {
+            payer,
+            create_key: create_key.pubkey(),
+            multisig,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: staking::instruction::CreateMultisig {
+            owners: owners.iter().map(Keypair::pubkey).collect(),
+            threshold,
+        }
+        .data(),
+    });
+    send(&mut env.context, &ixs, &[&create_key]).await?;
+
+    Ok((multisig, owners))
+}
+
+async fn transfer_authority(env: &mut Env, new_authority: Pubkey) {
+    let payer = env.context.payer.pubkey();
+
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::TransferAuthority {
+                authority: payer,
+                staking_pool: env.staking_pool,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::TransferAuthority { new_authority }.data(),
+        }],
+        &[],
+    )
+    .await
+    .unwrap();
+}
+
+fn proposal_address(multisig: &Pubkey, index: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"proposal", multisig.as_ref(), &index.to_le_bytes()],
+        &staking::ID,
+    )
+    .0
+}
+
+async fn propose(
+    env: &mut Env,
+    multisig: Pubkey,
+    index: u64,
+    proposer: &Keypair,
+    action: AdminAction,
+) -> Pubkey {
+    let proposal = proposal_address(&multisig, index);
+
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::CreateProposal {
+                proposer: proposer.pubkey(),
+                multisig,
+                staking_pool: env.staking_pool,
+                proposal,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::CreateProposal { action }.data(),
+        }],
+        &[proposer],
+    )
+    .await
+    .unwrap();
+
+    proposal
+}
+
+async fn approve(
+    env: &mut Env,
+    multisig: Pubkey,
+    proposal: Pubkey,
+    owner: &Keypair,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::Approve {
+                owner: owner.pubkey(),
+                multisig,
+                proposal,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::Approve {}.data(),
+        }],
+        &[owner],
+    )
+    .await
+}
+
+/// Executes `proposal`, passing the token accounts a withdrawal needs when
+/// `destination` is set.
+async fn execute(
+    env: &mut Env,
+    multisig: Pubkey,
+    proposal: Pubkey,
+    executor: &Keypair,
+    destination: Option<Pubkey>,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::Execute {
+                executor: executor.pubkey(),
+                multisig,
+                proposal,
+                mint: env.mint,
+                staking_pool: env.staking_pool,
+                vault: destination.map(|_| env.vault),
+                destination,
+                tip_vault: None,
+                token_program: destination.map(|_| spl_token::ID),
+            }
+            .to_account_metas(None),
+            data: staking::instruction::Execute {}.data(),
+        }],
+        &[executor],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn multisig_approves_and_executes_admin_actions() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let payer = env.context.payer.pubkey();
+    let (multisig, owners) = create_multisig(&mut env, 3, 2).await.unwrap();
+    let [alice, bob, carol] = &owners[..] else {
+        unreachable!()
+    };
+    transfer_authority(&mut env, multisig).await;
+
+    // The old admin key no longer controls the pool.
+    let result = send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::SetRewardRate {
+                authority: payer,
+                staking_pool: env.staking_pool,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::SetRewardRate { reward_rate: 5 }.data(),
+        }],
+        &[],
+    )
+    .await;
+    assert_error(result, ErrorCode::ConstraintHasOne);
+
+    let proposal = propose(
+        &mut env,
+        multisig,
+        0,
+        alice,
+        AdminAction::SetRewardRate { reward_rate: 5 },
+    )
+    .await;
+
+    assert_error(
+        execute(&mut env, multisig, proposal, alice, None).await,
+        StakingError::ThresholdNotMet,
+    );
+    assert_error(
+        approve(&mut env, multisig, proposal, &Keypair::new()).await,
+        StakingError::NotMultisigOwner,
+    );
+    assert_error(
+        approve(&mut env, multisig, proposal, alice).await,
+        StakingError::AlreadyApproved,
+    );
+
+    approve(&mut env, multisig, proposal, bob).await.unwrap();
+    execute(&mut env, multisig, proposal, carol, None)
+        .await
+        .unwrap();
+    assert_eq!(pool(&mut env).await.reward_rate, 5);
+
+    assert_error(
+        execute(&mut env, multisig, proposal, carol, None).await,
+        StakingError::ProposalExecuted,
+    );
+
+    // Pausing through the multisig blocks new stakes.
+    let proposal = propose(
+        &mut env,
+        multisig,
+        1,
+        bob,
+        AdminAction::SetPaused { paused: true },
+    )
+    .await;
+    approve(&mut env, multisig, proposal, carol).await.unwrap();
+    execute(&mut env, multisig, proposal, alice, None)
+        .await
+        .unwrap();
+    assert!(pool(&mut env).await.paused);
+
+    let payer_tokens = create_token_account(&mut env.context, &env.mint, &payer).await;
+    let (user_stake_account, _) = Pubkey::find_program_address(
+        &[b"user_stake", env.staking_pool.as_ref(), payer.as_ref()],
+        &staking::ID,
+    );
+    let result = send(
+        &mut env.context,
+        &[
+            spl_token::instruction::mint_to(
+                &spl_token::ID,
+                &env.mint,
+                &payer_tokens,
+                &payer,
+                &[],
+                100,
+            )
+            .unwrap(),
+            Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::Stake {
+                    user: payer,
+                    beneficiary: payer,
+                    user_token_account: payer_tokens,
+                    vault: env.vault,
+                    mint: env.mint,
+                    staking_pool: env.staking_pool,
+                    user_stake_account,
+                    token_program: spl_token::ID,
+                    system_program: system_program::ID,
+                    rent: sysvar::rent::ID,
+                }
+                .to_account_metas(None),
+                data: staking::instruction::Stake {
+                    amount: 100,
+                    allowlist: None,
+                }
+                .data(),
+            },
+        ],
+        &[],
+    )
+    .await;
+    assert_error(result, StakingError::PoolPaused);
+}
+
+#[tokio::test]
+async fn withdraw_excess_rewards_pays_the_approved_destination() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let payer = env.context.payer.pubkey();
+    let (multisig, owners) = create_multisig(&mut env, 2, 2).await.unwrap();
+    transfer_authority(&mut env, multisig).await;
+
+    // Tokens sent straight to the vault are not backing any position.
+    send(
+        &mut env.context,
+        &[
+            spl_token::instruction::mint_to(&spl_token::ID, &env.mint, &env.vault, &payer, &[], 50)
+                .unwrap(),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let treasury = create_token_account(&mut env.context, &env.mint, &payer).await;
+    let attacker = create_token_account(&mut env.context, &env.mint, &owners[1].pubkey()).await;
+    let proposal = propose(
+        &mut env,
+        multisig,
+        0,
+        &owners[0],
+        AdminAction::WithdrawExcessRewards {
+            destination: treasury,
+        },
+    )
+    .await;
+    approve(&mut env, multisig, proposal, &owners[1])
+        .await
+        .unwrap();
+
+    assert_error(
+        execute(&mut env, multisig, proposal, &owners[1], Some(attacker)).await,
+        StakingError::ProposalAccountMismatch,
+    );
+    execute(&mut env, multisig, proposal, &owners[1], Some(treasury))
+        .await
+        .unwrap();
+
+    assert_eq!(token_balance(&mut env.context, treasury).await, 50);
+    assert_eq!(token_balance(&mut env.context, env.vault).await, 0);
+}
+
+#[tokio::test]
+async fn create_multisig_validates_owners_and_threshold() {
+    let mut env = setup(1, StakeLimits::default()).await;
+
+    for (owner_count, threshold, expected) in [
+        (0, 1, StakingError::InvalidOwnerCount),
+        (11, 1, StakingError::InvalidOwnerCount),
+        (2, 0, StakingError::InvalidThreshold),
+        (2, 3, StakingError::InvalidThreshold),
+    ] {
+        assert_error(
+            create_multisig(&mut env, owner_count, threshold)
+                .await
+                .map(|_| ()),
+            expected,
+        );
+    }
+
+    let owner = Pubkey::new_unique();
+    let create_key = Keypair::new();
+    let payer = env.context.payer.pubkey();
+    let (multisig, _) =
+        Pubkey::find_program_address(&[b"multisig", create_key.pubkey().as_ref()], &staking::ID);
+    let result = send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::CreateMultisig {
+                payer,
+                create_key: create_key.pubkey(),
+                multisig,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::CreateMultisig {
+                owners: vec![owner, owner],
+                threshold: 1,
+            }
+            .data(),
+        }],
+        &[&create_key],
+    )
+    .await;
+    assert_error(result, StakingError::DuplicateOwner);
+}
@@ -0,0 +1,838 @@
+mod common;
+
+use anchor_lang::{
+    error::ErrorCode,
+    prelude::{Clock, Pubkey},
+    system_program, AccountDeserialize, AccountSerialize, AnchorDeserialize, InstructionData,
+    ToAccountMetas,
+};
+use anchor_spl::token::spl_token;
+use common::{
+    account_data, create_mint, create_token_account, hand_mint_to_pool, initialize_pool, new_user,
+    pool_address, send, setup, stake, stake_accounts, start, token_balance, user_stake_address,
+    Env,
+};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    sysvar,
+    transaction::{Transaction, TransactionError},
+};
+use staking::{
+    error::StakingError,
+    state::{PoolStats, PositionInfo, StakeLimits, StakingPool, UserStakeAccount},
+};
+use staking_core::crank::TIP_INTERVAL;
+
+/// Runs a view instruction and decodes what it returned.
+async fn view<T: AnchorDeserialize>(context: &mut ProgramTestContext, ix: Instruction) -> T {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        blockhash,
+    );
+
+    let simulation = context.banks_client.simulate_transaction(tx).await.unwrap();
+    simulation.result.unwrap().unwrap();
+    let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+    T::try_from_slice(&return_data.data).unwrap()
+}
+
+fn assert_error(result: Result<(), BanksClientError>, expected: InstructionError) {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(_, err) => assert_eq!(err, expected),
+        err => panic!("unexpected error: {err:?}"),
+    }
+}
+
+fn custom(code: impl Into<u32>) -> InstructionError {
+    InstructionError::Custom(code.into())
+}
+
+async fn warp(context: &mut ProgramTestContext, seconds: i64) {
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += seconds;
+    context.set_sysvar(&clock);
+}
+
+async fn now(context: &mut ProgramTestContext) -> i64 {
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp
+}
+
+async fn pool(env: &mut Env) -> StakingPool {
+    let data = account_data(&mut env.context, env.staking_pool).await;
+    StakingPool::try_deserialize(&mut data.as_slice()).unwrap()
+}
+
+async fn position(env: &mut Env, user: &Keypair) -> Option<UserStakeAccount> {
+    let account = env
+        .context
+        .banks_client
+        .get_account(user_stake_address(env, user))
+        .await
+        .unwrap()?;
+    Some(UserStakeAccount::try_deserialize(&mut account.data.as_slice()).unwrap())
+}
+
+fn unstake_accounts(
+    env: &Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+) -> staking::accounts::Unstake {
+    staking::accounts::Unstake {
+        user: user.pubkey(),
+        user_token_account,
+        vault: env.vault,
+        mint: env.mint,
+        staking_pool: env.staking_pool,
+        user_stake_account: user_stake_address(env, user),
+        system_program: system_program::ID,
+        token_program: spl_token::ID,
+        rent: sysvar::rent::ID,
+    }
+}
+
+fn claim_accounts(
+    env: &Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+) -> staking::accounts::ClaimRewards {
+    staking::accounts::ClaimRewards {
+        claimer: user.pubkey(),
+        user: user.pubkey(),
+        user_token_account,
+        mint: env.mint,
+        vault: env.vault,
+        staking_pool: env.staking_pool,
+        user_stake_account: user_stake_address(env, user),
+        token_program: spl_token::ID,
+        instructions: sysvar::instructions::ID,
+    }
+}
+
+async fn stake_with(
+    env: &mut Env,
+    signer: &Keypair,
+    accounts: staking::accounts::Stake,
+    amount: u64,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: accounts.to_account_metas(None),
+            data: staking::instruction::Stake {
+                amount,
+                allowlist: None,
+            }
+            .data(),
+        }],
+        &[signer],
+    )
+    .await
+}
+
+async fn unstake_with(
+    env: &mut Env,
+    signer: &Keypair,
+    accounts: staking::accounts::Unstake,
+    amount: u64,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: accounts.to_account_metas(None),
+            data: staking::instruction::Unstake { amount }.data(),
+        }],
+        &[signer],
+    )
+    .await
+}
+
+async fn claim_with(
+    env: &mut Env,
+    signer: &Keypair,
+    accounts: staking::accounts::ClaimRewards,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: accounts.to_account_metas(None),
+            data: staking::instruction::ClaimRewards {}.data(),
+        }],
+        &[signer],
+    )
+    .await
+}
+
+async fn unstake(
+    env: &mut Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+    amount: u64,
+) -> Result<(), BanksClientError> {
+    let accounts = unstake_accounts(env, user, user_token_account);
+    unstake_with(env, user, accounts, amount).await
+}
+
+async fn claim_rewards(
+    env: &mut Env,
+    user: &Keypair,
+    user_token_account: Pubkey,
+) -> Result<(), BanksClientError> {
+    let accounts = claim_accounts(env, user, user_token_account);
+    claim_with(env, user, accounts).await
+}
+
+fn position_ix(env: &Env, user: &Keypair, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::GetPosition {
+            staking_pool: env.staking_pool,
+            user_stake_account: user_stake_address(env, user),
+        }
+        .to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+async fn pending_rewards(env: &mut Env, user: &Keypair) -> u64 {
+    let ix = position_ix(env, user, staking::instruction::GetPendingRewards {});
+    view(&mut env.context, ix).await
+}
+
+fn update_pool_ix(env: &Env, cranker: &Keypair, tip: Option<(Pubkey, Pubkey)>) -> Instruction {
+    Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::UpdatePool {
+            cranker: cranker.pubkey(),
+            mint: env.mint,
+            staking_pool: env.staking_pool,
+            tip_vault: tip.map(|(tip_vault, _)| tip_vault),
+            cranker_token_account: tip.map(|(_, cranker_tokens)| cranker_tokens),
+            token_program: tip.map(|_| spl_token::ID),
+        }
+        .to_account_metas(None),
+        data: staking::instruction::UpdatePool {}.data(),
+    }
+}
+
+#[tokio::test]
+async fn initialize_pool_checks_the_vault() {
+    let mut context = start().await;
+    let payer = context.payer.pubkey();
+    let mint = create_mint(&mut context).await;
+    let other_mint = create_mint(&mut context).await;
+    let staking_pool = pool_address(&mint);
+
+    // The vault must hold the pool's mint and be owned by the pool.
+    let wrong_mint = create_token_account(&mut context, &other_mint, &staking_pool).await;
+    assert_error(
+        initialize_pool(&mut context, mint, wrong_mint, 1, StakeLimits::default()).await,
+        custom(ErrorCode::ConstraintRaw),
+    );
+    let wrong_owner = create_token_account(&mut context, &mint, &payer).await;
+    assert_error(
+        initialize_pool(&mut context, mint, wrong_owner, 1, StakeLimits::default()).await,
+        custom(ErrorCode::ConstraintRaw),
+    );
+
+    let vault = create_token_account(&mut context, &mint, &staking_pool).await;
+    initialize_pool(&mut context, mint, vault, 7, StakeLimits::default())
+        .await
+        .unwrap();
+    let now = now(&mut context).await;
+
+    let data = account_data(&mut context, staking_pool).await;
+    let pool = StakingPool::try_deserialize(&mut data.as_slice()).unwrap();
+    assert_eq!(pool.authority, payer);
+    assert_eq!(pool.vault, vault);
+    assert_eq!(pool.reward_rate, 7);
+    assert_eq!(pool.total_stake, 0);
+    assert_eq!(pool.reward_per_token, 0);
+    assert_eq!(pool.last_update_time, now);
+
+    // A mint has exactly one pool.
+    assert!(
+        initialize_pool(&mut context, mint, vault, 7, StakeLimits::default())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn rewards_accrue_while_staked_and_are_minted_on_claim() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let (alice, alice_tokens) = new_user(&mut env, 1_000).await;
+    let (bob, bob_tokens) = new_user(&mut env, 1_000).await;
+    hand_mint_to_pool(&mut env).await;
+
+    stake(&mut env, &alice, alice_tokens, 500).await.unwrap();
+    warp(&mut env.context, 100).await;
+    stake(&mut env, &bob, bob_tokens, 500).await.unwrap();
+    warp(&mut env.context, 100).await;
+
+    assert_eq!(token_balance(&mut env.context, env.vault).await, 1_000);
+    assert_eq!(pool(&mut env).await.total_stake, 1_000);
+    assert_eq!(pending_rewards(&mut env, &alice).await, 500 * 200);
+    assert_eq!(pending_rewards(&mut env, &bob).await, 500 * 100);
+
+    claim_rewards(&mut env, &alice, alice_tokens).await.unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, alice_tokens).await,
+        500 + 500 * 200
+    );
+    assert_eq!(position(&mut env, &alice).await.unwrap().pending_rewards, 0);
+    assert_error(
+        claim_rewards(&mut env, &alice, alice_tokens).await,
+        InstructionError::InvalidArgument,
+    );
+
+    // Unstaking settles what the position earned so far.
+    unstake(&mut env, &bob, bob_tokens, 200).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, bob_tokens).await, 700);
+    let bob_position = position(&mut env, &bob).await.unwrap();
+    assert_eq!(bob_position.amount, 300);
+    assert_eq!(bob_position.pending_rewards, 500 * 100);
+
+    warp(&mut env.context, 10).await;
+    let now = now(&mut env.context).await;
+    let ix = position_ix(&env, &bob, staking::instruction::GetPosition {});
+    let info: PositionInfo = view(&mut env.context, ix).await;
+    assert_eq!(info.owner, bob.pubkey());
+    assert_eq!(info.amount, 300);
+    assert_eq!(info.pending_rewards, 500 * 100 + 300 * 10);
+    assert_eq!(info.timestamp, now);
+
+    assert_error(
+        unstake(&mut env, &bob, bob_tokens, 301).await,
+        InstructionError::InvalidArgument,
+    );
+
+    // A position with rewards left stays open after its principal is out,
+    // and closes once it holds nothing.
+    unstake(&mut env, &bob, bob_tokens, 300).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, bob_tokens).await, 1_000);
+    assert!(position(&mut env, &bob).await.is_some());
+
+    claim_rewards(&mut env, &bob, bob_tokens).await.unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, bob_tokens).await,
+        1_000 + 500 * 100 + 300 * 10
+    );
+    unstake(&mut env, &bob, bob_tokens, 0).await.unwrap();
+    assert!(position(&mut env, &bob).await.is_none());
+    assert_eq!(pool(&mut env).await.total_stake, 500);
+}
+
+#[tokio::test]
+async fn unstaking_everything_closes_the_position_and_refunds_its_rent() {
+    let mut env = setup(0, StakeLimits::default()).await;
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    stake(&mut env, &user, user_tokens, 600).await.unwrap();
+
+    let position_address = user_stake_address(&env, &user);
+    let banks = &mut env.context.banks_client;
+    let rent = banks.get_balance(position_address).await.unwrap();
+    let lamports = banks.get_balance(user.pubkey()).await.unwrap();
+    assert!(rent > 0);
+
+    // Nothing accrues at a zero rate, so the last unstake leaves the
+    // position empty and closes it.
+    unstake(&mut env, &user, user_tokens, 600).await.unwrap();
+
+    assert!(position(&mut env, &user).await.is_none());
+    let banks = &mut env.context.banks_client;
+    assert_eq!(banks.get_balance(position_address).await.unwrap(), 0);
+    assert_eq!(
+        banks.get_balance(user.pubkey()).await.unwrap(),
+        lamports + rent
+    );
+    assert_eq!(token_balance(&mut env.context, user_tokens).await, 1_000);
+    assert_eq!(pool(&mut env).await.total_stake, 0);
+}
+
+#[tokio::test]
+async fn pool_stats_include_rewards_accrued_since_the_last_update() {
+    let mut env = setup(3, StakeLimits::default()).await;
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    stake(&mut env, &user, user_tokens, 400).await.unwrap();
+    let staked_at = now(&mut env.context).await;
+    warp(&mut env.context, 50).await;
+
+    let ix = Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::GetPoolStats {
+            staking_pool: env.staking_pool,
+        }
+        .to_account_metas(None),
+        data: staking::instruction::GetPoolStats {}.data(),
+    };
+    let stats: PoolStats = view(&mut env.context, ix).await;
+
+    assert_eq!(stats.authority, env.context.payer.pubkey());
+    assert_eq!(stats.vault, env.vault);
+    assert_eq!(stats.reward_rate, 3);
+    assert_eq!(stats.total_stake, 400);
+    assert_eq!(stats.emission_per_second, 3 * 400);
+    assert_eq!(stats.reward_per_token, 3 * 50);
+    assert_eq!(stats.last_update_time, staked_at);
+    assert_eq!(stats.timestamp, staked_at + 50);
+
+    // Views do not write the accumulator back.
+    assert_eq!(pool(&mut env).await.reward_per_token, 0);
+}
+
+#[tokio::test]
+async fn position_views_fail_instead_of_overflowing() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    stake(&mut env, &user, user_tokens, 100).await.unwrap();
+    warp(&mut env.context, 10).await;
+
+    // Leave the position one settlement away from overflowing.
+    let address = user_stake_address(&env, &user);
+    let mut account = env
+        .context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut position = UserStakeAccount::try_deserialize(&mut account.data.as_slice()).unwrap();
+    position.pending_rewards = u64::MAX;
+    let mut data = Vec::new();
+    position.try_serialize(&mut data).unwrap();
+    account.data[..data.len()].copy_from_slice(&data);
+    env.context.set_account(&address, &account.into());
+
+    for ix in [
+        position_ix(&env, &user, staking::instruction::GetPendingRewards {}),
+        position_ix(&env, &user, staking::instruction::GetPosition {}),
+    ] {
+        let blockhash = env.context.get_new_latest_blockhash().await.unwrap();
+        let payer = &env.context.payer;
+        let tx =
+            Transaction::new_signed_with_payer(&[ix], Some(&payer.pubkey()), &[payer], blockhash);
+        let simulation = env.context.banks_client.simulate_transaction(tx).await;
+        assert_eq!(
+            simulation.unwrap().result.unwrap(),
+            Err(TransactionError::InstructionError(
+                0,
+                InstructionError::ArithmeticOverflow
+            ))
+        );
+    }
+}
+
+#[tokio::test]
+async fn update_pool_advances_the_accumulator_and_pays_the_crank_tip() {
+    let mut env = setup(2, StakeLimits::default()).await;
+    let authority = env.context.payer.insecure_clone();
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    let (cranker, cranker_tokens) = new_user(&mut env, 0).await;
+    stake(&mut env, &user, user_tokens, 100).await.unwrap();
+    warp(&mut env.context, 10).await;
+
+    // Anyone may crank without collecting a tip.
+    let ix = update_pool_ix(&env, &cranker, None);
+    send(&mut env.context, &[ix], &[&cranker]).await.unwrap();
+    let pool_state = pool(&mut env).await;
+    assert_eq!(pool_state.reward_per_token, 2 * 10);
+    assert_eq!(pool_state.last_update_time, now(&mut env.context).await);
+
+    let tip_vault = create_token_account(&mut env.context, &env.mint, &env.staking_pool).await;
+    send(
+        &mut env.context,
+        &[
+            spl_token::instruction::mint_to(
+                &spl_token::ID,
+                &env.mint,
+                &tip_vault,
+                &authority.pubkey(),
+                &[],
+                25,
+            )
+            .unwrap(),
+            Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::SetCrankTip {
+                    authority: authority.pubkey(),
+                    mint: env.mint,
+                    staking_pool: env.staking_pool,
+                    tip_vault,
+                }
+                .to_account_metas(None),
+                data: staking::instruction::SetCrankTip { crank_tip: 10 }.data(),
+            },
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    // Only a crank after the pool has been idle for the tip interval is
+    // paid, and never more than the tip vault holds.
+    let tip = Some((tip_vault, cranker_tokens));
+    let ix = update_pool_ix(&env, &cranker, tip);
+    send(&mut env.context, &[ix], &[&cranker]).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, cranker_tokens).await, 0);
+
+    warp(&mut env.context, TIP_INTERVAL).await;
+    let ix = update_pool_ix(&env, &cranker, tip);
+    send(&mut env.context, &[ix], &[&cranker]).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, cranker_tokens).await, 10);
+
+    // Cranking again right away moves the accumulator but is not tipped.
+    warp(&mut env.context, 5).await;
+    let ix = update_pool_ix(&env, &cranker, tip);
+    send(&mut env.context, &[ix], &[&cranker]).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, cranker_tokens).await, 10);
+
+    for expected in [20, 25, 25] {
+        warp(&mut env.context, TIP_INTERVAL).await;
+        let ix = update_pool_ix(&env, &cranker, tip);
+        send(&mut env.context, &[ix], &[&cranker]).await.unwrap();
+        assert_eq!(
+            token_balance(&mut env.context, cranker_tokens).await,
+            expected
+        );
+    }
+    let elapsed = 10 + 4 * TIP_INTERVAL + 5;
+    assert_eq!(pool(&mut env).await.reward_per_token, 2 * elapsed as u128);
+
+    // The tip vault passed in must be the pool's.
+    let (_, other_tokens) = new_user(&mut env, 0).await;
+    warp(&mut env.context, 5).await;
+    let ix = update_pool_ix(&env, &cranker, Some((other_tokens, cranker_tokens)));
+    assert_error(
+        send(&mut env.context, &[ix], &[&cranker]).await,
+        custom(ErrorCode::ConstraintRaw),
+    );
+}
+
+#[tokio::test]
+async fn delegated_claims_pay_only_the_chosen_destination() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let (owner, owner_tokens) = new_user(&mut env, 1_000).await;
+    let (delegate, delegate_tokens) = new_user(&mut env, 0).await;
+    let (stranger, _) = new_user(&mut env, 0).await;
+    hand_mint_to_pool(&mut env).await;
+    stake(&mut env, &owner, owner_tokens, 100).await.unwrap();
+    warp(&mut env.context, 10).await;
+
+    let authorize = |env: &Env, claimer: Pubkey| Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::AuthorizeClaimer {
+            user: owner.pubkey(),
+            staking_pool: env.staking_pool,
+            user_stake_account: user_stake_address(env, &owner),
+        }
+        .to_account_metas(None),
+        data: staking::instruction::AuthorizeClaimer {
+            claimer,
+            destination: owner_tokens,
+        }
+        .data(),
+    };
+    let delegated = |env: &Env, claimer: &Keypair, user_token_account: Pubkey| {
+        staking::accounts::ClaimRewards {
+            claimer: claimer.pubkey(),
+            ..claim_accounts(env, &owner, user_token_account)
+        }
+    };
+
+    let accounts = delegated(&env, &delegate, owner_tokens);
+    assert_error(
+        claim_with(&mut env, &delegate, accounts).await,
+        custom(StakingError::UnauthorizedClaimer),
+    );
+
+    let ix = authorize(&env, delegate.pubkey());
+    send(&mut env.context, &[ix], &[&owner]).await.unwrap();
+    let stake_account = position(&mut env, &owner).await.unwrap();
+    assert_eq!(stake_account.claimer, delegate.pubkey());
+    assert_eq!(stake_account.claim_destination, owner_tokens);
+
+    let accounts = delegated(&env, &delegate, delegate_tokens);
+    assert_error(
+        claim_with(&mut env, &delegate, accounts).await,
+        custom(StakingError::InvalidClaimDestination),
+    );
+    let accounts = delegated(&env, &stranger, owner_tokens);
+    assert_error(
+        claim_with(&mut env, &stranger, accounts).await,
+        custom(StakingError::UnauthorizedClaimer),
+    );
+
+    let accounts = delegated(&env, &delegate, owner_tokens);
+    claim_with(&mut env, &delegate, accounts).await.unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, owner_tokens).await,
+        900 + 100 * 10
+    );
+
+    // Authorizing the default key revokes the delegate.
+    let ix = authorize(&env, Pubkey::default());
+    send(&mut env.context, &[ix], &[&owner]).await.unwrap();
+    warp(&mut env.context, 10).await;
+    let accounts = delegated(&env, &delegate, owner_tokens);
+    assert_error(
+        claim_with(&mut env, &delegate, accounts).await,
+        custom(StakingError::UnauthorizedClaimer),
+    );
+
+    // Only the owner can authorize a claimer for its position.
+    let ix = Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::AuthorizeClaimer {
+            user: stranger.pubkey(),
+            staking_pool: env.staking_pool,
+            user_stake_account: user_stake_address(&env, &owner),
+        }
+        .to_account_metas(None),
+        data: staking::instruction::AuthorizeClaimer {
+            claimer: stranger.pubkey(),
+            destination: owner_tokens,
+        }
+        .data(),
+    };
+    assert_error(
+        send(&mut env.context, &[ix], &[&stranger]).await,
+        custom(ErrorCode::ConstraintSeeds),
+    );
+}
+
+#[tokio::test]
+async fn compound_restakes_rewards_for_the_owner_or_a_vault_delegate() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let (owner, owner_tokens) = new_user(&mut env, 1_000).await;
+    let (delegate, _) = new_user(&mut env, 0).await;
+    hand_mint_to_pool(&mut env).await;
+    stake(&mut env, &owner, owner_tokens, 100).await.unwrap();
+    warp(&mut env.context, 10).await;
+
+    let compound = |env: &Env, claimer: &Keypair| Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::Compound {
+            claimer: claimer.pubkey(),
+            user: owner.pubkey(),
+            mint: env.mint,
+            vault: env.vault,
+            staking_pool: env.staking_pool,
+            user_stake_account: user_stake_address(env, &owner),
+            token_program: spl_token::ID,
+            instructions: sysvar::instructions::ID,
+        }
+        .to_account_metas(None),
+        data: staking::instruction::Compound { allowlist: None }.data(),
+    };
+    let authorize = |env: &Env, destination: Pubkey| Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::AuthorizeClaimer {
+            user: owner.pubkey(),
+            staking_pool: env.staking_pool,
+            user_stake_account: user_stake_address(env, &owner),
+        }
+        .to_account_metas(None),
+        data: staking::instruction::AuthorizeClaimer {
+            claimer: delegate.pubkey(),
+            destination,
+        }
+        .data(),
+    };
+
+    let ix = compound(&env, &delegate);
+    assert_error(
+        send(&mut env.context, &[ix], &[&delegate]).await,
+        custom(StakingError::UnauthorizedClaimer),
+    );
+
+    // The owner restakes the 100 * 10 it earned without touching its stake age.
+    let staked_at = position(&mut env, &owner).await.unwrap().last_stake_time;
+    let ix = compound(&env, &owner);
+    send(&mut env.context, &[ix], &[&owner]).await.unwrap();
+    let stake_account = position(&mut env, &owner).await.unwrap();
+    assert_eq!(stake_account.amount, 100 + 100 * 10);
+    assert_eq!(stake_account.pending_rewards, 0);
+    assert_eq!(stake_account.last_stake_time, staked_at);
+    assert_eq!(pool(&mut env).await.total_stake, 1_100);
+    assert_eq!(token_balance(&mut env.context, env.vault).await, 1_100);
+    assert_eq!(token_balance(&mut env.context, owner_tokens).await, 900);
+
+    // Nothing has accrued since.
+    let ix = compound(&env, &owner);
+    assert_error(
+        send(&mut env.context, &[ix], &[&owner]).await,
+        InstructionError::InvalidArgument,
+    );
+
+    // A delegate may only compound for owners who chose the vault.
+    warp(&mut env.context, 10).await;
+    let ix = authorize(&env, owner_tokens);
+    send(&mut env.context, &[ix], &[&owner]).await.unwrap();
+    let ix = compound(&env, &delegate);
+    assert_error(
+        send(&mut env.context, &[ix], &[&delegate]).await,
+        custom(StakingError::InvalidClaimDestination),
+    );
+
+    let ix = authorize(&env, env.vault);
+    send(&mut env.context, &[ix], &[&owner]).await.unwrap();
+    let ix = compound(&env, &delegate);
+    send(&mut env.context, &[ix], &[&delegate]).await.unwrap();
+    assert_eq!(
+        position(&mut env, &owner).await.unwrap().amount,
+        1_100 + 1_100 * 10
+    );
+    assert_eq!(token_balance(&mut env.context, env.vault).await, 12_100);
+}
+
+#[tokio::test]
+async fn stake_rejects_the_wrong_vault_mint_and_signer() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    let (other, other_tokens) = new_user(&mut env, 1_000).await;
+    let other_mint = create_mint(&mut env.context).await;
+    let other_mint_tokens =
+        create_token_account(&mut env.context, &other_mint, &user.pubkey()).await;
+    let stray_vault = create_token_account(&mut env.context, &env.mint, &env.staking_pool).await;
+
+    let accounts = staking::accounts::Stake {
+        vault: stray_vault,
+        ..stake_accounts(&env, &user, user_tokens)
+    };
+    assert_error(
+        stake_with(&mut env, &user, accounts, 100).await,
+        custom(ErrorCode::ConstraintRaw),
+    );
+
+    let accounts = staking::accounts::Stake {
+        mint: other_mint,
+        ..stake_accounts(&env, &user, user_tokens)
+    };
+    assert_error(
+        stake_with(&mut env, &user, accounts, 100).await,
+        custom(ErrorCode::ConstraintSeeds),
+    );
+
+    let accounts = stake_accounts(&env, &user, other_mint_tokens);
+    assert_error(
+        stake_with(&mut env, &user, accounts, 100).await,
+        InstructionError::InvalidAccountData,
+    );
+
+    // Signing does not let a user spend someone else's tokens.
+    let accounts = stake_accounts(&env, &user, other_tokens);
+    assert_error(
+        stake_with(&mut env, &user, accounts, 100).await,
+        InstructionError::IllegalOwner,
+    );
+    let accounts = stake_accounts(&env, &other, user_tokens);
+    assert_error(
+        stake_with(&mut env, &other, accounts, 100).await,
+        InstructionError::IllegalOwner,
+    );
+
+    assert_eq!(token_balance(&mut env.context, user_tokens).await, 1_000);
+    assert_eq!(token_balance(&mut env.context, other_tokens).await, 1_000);
+    assert_eq!(token_balance(&mut env.context, env.vault).await, 0);
+    assert_eq!(pool(&mut env).await.total_stake, 0);
+}
+
+#[tokio::test]
+async fn unstake_and_claim_reject_the_wrong_vault_mint_and_signer() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let (user, user_tokens) = new_user(&mut env, 1_000).await;
+    let (other, other_tokens) = new_user(&mut env, 1_000).await;
+    let other_mint = create_mint(&mut env.context).await;
+    let stray_vault = create_token_account(&mut env.context, &env.mint, &env.staking_pool).await;
+    hand_mint_to_pool(&mut env).await;
+    stake(&mut env, &user, user_tokens, 500).await.unwrap();
+    warp(&mut env.context, 10).await;
+
+    let accounts = staking::accounts::Unstake {
+        vault: stray_vault,
+        ..unstake_accounts(&env, &user, user_tokens)
+    };
+    assert_error(
+        unstake_with(&mut env, &user, accounts, 100).await,
+        custom(ErrorCode::ConstraintRaw),
+    );
+    let accounts = staking::accounts::Unstake {
+        mint: other_mint,
+        ..unstake_accounts(&env, &user, user_tokens)
+    };
+    assert_error(
+        unstake_with(&mut env, &user, accounts, 100).await,
+        custom(ErrorCode::ConstraintSeeds),
+    );
+    // Another signer cannot withdraw from the user's position.
+    let accounts = staking::accounts::Unstake {
+        user: other.pubkey(),
+        user_token_account: other_tokens,
+        ..unstake_accounts(&env, &user, user_tokens)
+    };
+    assert_error(
+        unstake_with(&mut env, &other, accounts, 100).await,
+        custom(ErrorCode::ConstraintSeeds),
+    );
+
+    let accounts = staking::accounts::ClaimRewards {
+        vault: stray_vault,
+        ..claim_accounts(&env, &user, user_tokens)
+    };
+    assert_error(
+        claim_with(&mut env, &user, accounts).await,
+        custom(ErrorCode::ConstraintRaw),
+    );
+    let accounts = staking::accounts::ClaimRewards {
+        mint: other_mint,
+        ..claim_accounts(&env, &user, user_tokens)
+    };
+    assert_error(
+        claim_with(&mut env, &user, accounts).await,
+        custom(ErrorCode::ConstraintSeeds),
+    );
+    let accounts = staking::accounts::ClaimRewards {
+        claimer: other.pubkey(),
+        user_token_account: other_tokens,
+        ..claim_accounts(&env, &user, user_tokens)
+    };
+    assert_error(
+        claim_with(&mut env, &other, accounts).await,
+        custom(StakingError::UnauthorizedClaimer),
+    );
+
+    // A user without a position has nothing to unstake or claim.
+    assert_error(
+        unstake(&mut env, &other, other_tokens, 0).await,
+        custom(ErrorCode::AccountNotInitialized),
+    );
+    assert_error(
+        claim_rewards(&mut env, &other, other_tokens).await,
+        custom(ErrorCode::AccountNotInitialized),
+    );
+
+    assert_eq!(token_balance(&mut env.context, user_tokens).await, 500);
+    assert_eq!(token_balance(&mut env.context, other_tokens).await, 1_000);
+    assert_eq!(token_balance(&mut env.context, env.vault).await, 500);
+    let stake_account = position(&mut env, &user).await.unwrap();
+    assert_eq!(stake_account.amount, 500);
+    assert_eq!(stake_account.pending_rewards, 0);
+    assert_eq!(pending_rewards(&mut env, &user).await, 500 * 10);
+
+    // The position is untouched by the rejected attempts.
+    claim_rewards(&mut env, &user, user_tokens).await.unwrap();
+    unstake(&mut env, &user, user_tokens, 500).await.unwrap();
+    assert_eq!(
+        token_balance(&mut env.context, user_tokens).await,
+        1_000 + 500 * 10
+    );
+}
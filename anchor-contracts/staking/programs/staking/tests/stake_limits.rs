@@ -0,0 +1,129 @@
+mod common;
+
+use anchor_lang::{
+    prelude::{Clock, Pubkey},
+    InstructionData, ToAccountMetas,
+};
+use common::{new_user, send, setup, stake};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::Signer,
+    transaction::TransactionError,
+};
+use staking::{error::StakingError, state::StakeLimits};
+
+const START: i64 = 1_000;
+const END: i64 = 2_000;
+const LIMITS: StakeLimits = StakeLimits {
+    max_total_stake: 1_000,
+    max_stake_per_user: 600,
+    min_stake_amount: 10,
+    stake_start: START,
+    stake_end: END,
+    min_stake_age: 0,
+};
+
+fn assert_staking_error(result: Result<(), BanksClientError>, expected: StakingError) {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            assert_eq!(code, u32::from(expected))
+        }
+        err => panic!("unexpected error: {err:?}"),
+    }
+}
+
+async fn warp_to(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn stake_enforces_caps_and_minimum() {
+    let mut env = setup(1, LIMITS).await;
+    warp_to(&mut env.context, START).await;
+
+    let (alice, alice_tokens) = new_user(&mut env, 1_000).await;
+    let (bob, bob_tokens) = new_user(&mut env, 1_000).await;
+
+    assert_staking_error(
+        stake(&mut env, &alice, alice_tokens, 9).await,
+        StakingError::StakeBelowMinimum,
+    );
+
+    stake(&mut env, &alice, alice_tokens, 600).await.unwrap();
+    assert_staking_error(
+        stake(&mut env, &alice, alice_tokens, 10).await,
+        StakingError::UserCapExceeded,
+    );
+
+    assert_staking_error(
+        stake(&mut env, &bob, bob_tokens, 401).await,
+        StakingError::PoolCapExceeded,
+    );
+    stake(&mut env, &bob, bob_tokens, 400).await.unwrap();
+}
+
+#[tokio::test]
+async fn stake_is_only_accepted_inside_the_window() {
+    let mut env = setup(1, LIMITS).await;
+    let (alice, alice_tokens) = new_user(&mut env, 1_000).await;
+
+    warp_to(&mut env.context, START - 1).await;
+    assert_staking_error(
+        stake(&mut env, &alice, alice_tokens, 100).await,
+        StakingError::StakeWindowNotOpen,
+    );
+
+    warp_to(&mut env.context, END - 1).await;
+    stake(&mut env, &alice, alice_tokens, 100).await.unwrap();
+
+    warp_to(&mut env.context, END).await;
+    assert_staking_error(
+        stake(&mut env, &alice, alice_tokens, 100).await,
+        StakingError::StakeWindowClosed,
+    );
+}
+
+#[tokio::test]
+async fn only_the_authority_can_change_limits() {
+    let mut env = setup(1, LIMITS).await;
+    let payer = env.context.payer.pubkey();
+    let (mallory, _) = new_user(&mut env, 0).await;
+
+    let set_limits = |authority: Pubkey, limits: StakeLimits| Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::SetStakeLimits {
+            authority,
+            staking_pool: env.staking_pool,
+        }
+        .to_account_metas(None),
+        data: staking::instruction::SetStakeLimits { limits }.data(),
+    };
+
+    let mallory_ix = set_limits(mallory.pubkey(), StakeLimits::default());
+    let inverted_ix = set_limits(
+        payer,
+        StakeLimits {
+            stake_start: END,
+            stake_end: START,
+            ..StakeLimits::default()
+        },
+    );
+    let lift_ix = set_limits(payer, StakeLimits::default());
+
+    assert!(send(&mut env.context, &[mallory_ix], &[&mallory])
+        .await
+        .is_err());
+    assert_staking_error(
+        send(&mut env.context, &[inverted_ix], &[]).await,
+        StakingError::InvalidStakeWindow,
+    );
+    send(&mut env.context, &[lift_ix], &[]).await.unwrap();
+
+    // With the limits lifted, a tiny stake outside the old window goes through.
+    warp_to(&mut env.context, END + 1).await;
+    let (alice, alice_tokens) = new_user(&mut env, 5).await;
+    stake(&mut env, &alice, alice_tokens, 5).await.unwrap();
+}
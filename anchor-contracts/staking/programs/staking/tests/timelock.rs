@@ -0,0 +1,408 @@
+mod common;
+
+use anchor_lang::{
+    error::ErrorCode,
+    prelude::{Clock, Pubkey},
+    system_program, AccountDeserialize, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::spl_token;
+use common::{account_data, send, setup, Env};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solana_system_interface::instruction as system_instruction;
+use staking::{
+    error::StakingError,
+    state::{AdminAction, StakeLimits, StakingPool},
+};
+
+fn assert_error(result: Result<(), BanksClientError>, expected: impl Into<u32>) {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            assert_eq!(code, expected.into())
+        }
+        err => panic!("unexpected error: {err:?}"),
+    }
+}
+
+async fn warp_to(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+async fn now(context: &mut ProgramTestContext) -> i64 {
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp
+}
+
+async fn pool(env: &mut Env) -> StakingPool {
+    let data = account_data(&mut env.context, env.staking_pool).await;
+    StakingPool::try_deserialize(&mut data.as_slice()).unwrap()
+}
+
+/// Creates funded owners and a multisig over them with `threshold`.
+async fn create_multisig(
+    env: &mut Env,
+    owner_count: usize,
+    threshold: u8,
+) -> Result<(Pubkey, Vec<Keypair>), BanksClientError> {
+    let payer = env.context.payer.pubkey();
+    let owners: Vec<_> = (0..owner_count).map(|_| Keypair::new()).collect();
+    let create_key = Keypair::new();
+    let (multisig, _) =
+        Pubkey::find_program_address(&[b"multisig", create_key.pubkey().as_ref()], &staking::ID);
+
+    let mut ixs: Vec<_> = owners
+        .iter()
+        .map(|owner| system_instruction::transfer(&payer, &owner.pubkey(), 1_000_000_000))
+        .collect();
+    ixs.push(Instruction {
+        program_id: staking::ID,
+        accounts: staking::accounts::CreateMultisig {
+            payer,
+            create_key: create_key.pubkey(),
+            multisig,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: staking::instruction::CreateMultisig {
+            owners: owners.iter().map(Keypair::pubkey).collect(),
+            threshold,
+        }
+        .data(),
+    });
+    send(&mut env.context, &ixs, &[&create_key]).await?;
+
+    Ok((multisig, owners))
+}
+
+async fn transfer_authority(env: &mut Env, new_authority: Pubkey) {
+    let payer = env.context.payer.pubkey();
+
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::TransferAuthority {
+                authority: payer,
+                staking_pool: env.staking_pool,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::TransferAuthority { new_authority }.data(),
+        }],
+        &[],
+    )
+    .await
+    .unwrap();
+}
+
+fn proposal_address(multisig: &Pubkey, index: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"proposal", multisig.as_ref(), &index.to_le_bytes()],
+        &staking::ID,
+    )
+    .0
+}
+
+async fn propose(
+    env: &mut Env,
+    multisig: Pubkey,
+    index: u64,
+    proposer: &Keypair,
+    action: AdminAction,
+) -> Pubkey {
+    let proposal = proposal_address(&multisig, index);
+
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::CreateProposal {
+                proposer: proposer.pubkey(),
+                multisig,
+                staking_pool: env.staking_pool,
+                proposal,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::CreateProposal { action }.data(),
+        }],
+        &[proposer],
+    )
+    .await
+    .unwrap();
+
+    proposal
+}
+
+/// Executes `proposal`, passing the token accounts a withdrawal needs when
+/// `destination` is set.
+async fn execute(
+    env: &mut Env,
+    multisig: Pubkey,
+    proposal: Pubkey,
+    executor: &Keypair,
+    destination: Option<Pubkey>,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::Execute {
+                executor: executor.pubkey(),
+                multisig,
+                proposal,
+                mint: env.mint,
+                staking_pool: env.staking_pool,
+                vault: destination.map(|_| env.vault),
+                destination,
+                tip_vault: None,
+                token_program: destination.map(|_| spl_token::ID),
+            }
+            .to_account_metas(None),
+            data: staking::instruction::Execute {}.data(),
+        }],
+        &[executor],
+    )
+    .await
+}
+
+/// Sends a single-key admin instruction signed by `authority`.
+async fn admin(
+    env: &mut Env,
+    authority: &Keypair,
+    data: impl InstructionData,
+) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            // Every single-key admin instruction takes these two accounts.
+            accounts: staking::accounts::SetRewardRate {
+                authority: authority.pubkey(),
+                staking_pool: env.staking_pool,
+            }
+            .to_account_metas(None),
+            data: data.data(),
+        }],
+        &[authority],
+    )
+    .await
+}
+
+async fn execute_queued_action(env: &mut Env) -> Result<(), BanksClientError> {
+    send(
+        &mut env.context,
+        &[Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::ExecuteQueuedAction {
+                mint: env.mint,
+                staking_pool: env.staking_pool,
+                vault: None,
+                destination: None,
+                tip_vault: None,
+                token_program: None,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::ExecuteQueuedAction {}.data(),
+        }],
+        &[],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn queued_actions_wait_out_the_timelock() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let authority = env.context.payer.insecure_clone();
+    let start = now(&mut env.context).await;
+
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetTimelockDelay {
+            timelock_delay: 100,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Rate changes now have to be queued, but pausing does not wait.
+    assert_error(
+        admin(
+            &mut env,
+            &authority,
+            staking::instruction::SetRewardRate { reward_rate: 0 },
+        )
+        .await,
+        StakingError::TimelockActive,
+    );
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::SetPaused { paused: true },
+    )
+    .await
+    .unwrap();
+    assert_error(
+        admin(
+            &mut env,
+            &authority,
+            staking::instruction::QueueAction {
+                action: AdminAction::SetPaused { paused: false },
+            },
+        )
+        .await,
+        StakingError::ActionNotTimelocked,
+    );
+
+    let rate_cut = AdminAction::SetRewardRate { reward_rate: 0 };
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::QueueAction {
+            action: rate_cut.clone(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let state = pool(&mut env).await;
+    assert_eq!(state.queued_action, Some(rate_cut));
+    assert_eq!(state.queued_eta, start + 100);
+
+    assert_error(
+        admin(
+            &mut env,
+            &authority,
+            staking::instruction::QueueAction {
+                action: AdminAction::SetRewardRate { reward_rate: 7 },
+            },
+        )
+        .await,
+        StakingError::ActionAlreadyQueued,
+    );
+
+    warp_to(&mut env.context, start + 99).await;
+    assert_error(
+        execute_queued_action(&mut env).await,
+        StakingError::TimelockNotExpired,
+    );
+
+    // Only the authority can cancel.
+    let stranger = Keypair::new();
+    assert_error(
+        admin(
+            &mut env,
+            &stranger,
+            staking::instruction::CancelQueuedAction {},
+        )
+        .await,
+        ErrorCode::ConstraintHasOne,
+    );
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::CancelQueuedAction {},
+    )
+    .await
+    .unwrap();
+    assert_error(
+        execute_queued_action(&mut env).await,
+        StakingError::NoQueuedAction,
+    );
+
+    admin(
+        &mut env,
+        &authority,
+        staking::instruction::QueueAction {
+            action: AdminAction::SetRewardRate { reward_rate: 5 },
+        },
+    )
+    .await
+    .unwrap();
+    warp_to(&mut env.context, start + 199).await;
+    execute_queued_action(&mut env).await.unwrap();
+
+    let state = pool(&mut env).await;
+    assert_eq!(state.reward_rate, 5);
+    assert_eq!(state.queued_action, None);
+    // Rewards up to the switch accrued at the old rate.
+    assert_eq!(state.last_update_time, start + 199);
+}
+
+#[tokio::test]
+async fn multisig_actions_are_queued_behind_the_timelock() {
+    let mut env = setup(1, StakeLimits::default()).await;
+    let (multisig, owners) = create_multisig(&mut env, 1, 1).await.unwrap();
+    let owner = &owners[0];
+    transfer_authority(&mut env, multisig).await;
+    let start = now(&mut env.context).await;
+
+    // Without a timelock the proposal applies straight away.
+    let proposal = propose(
+        &mut env,
+        multisig,
+        0,
+        owner,
+        AdminAction::SetTimelockDelay {
+            timelock_delay: 100,
+        },
+    )
+    .await;
+    execute(&mut env, multisig, proposal, owner, None)
+        .await
+        .unwrap();
+    assert_eq!(pool(&mut env).await.timelock_delay, 100);
+
+    // With one, executing only queues it, and cancelling goes through at once.
+    let proposal = propose(
+        &mut env,
+        multisig,
+        1,
+        owner,
+        AdminAction::SetRewardRate { reward_rate: 5 },
+    )
+    .await;
+    execute(&mut env, multisig, proposal, owner, None)
+        .await
+        .unwrap();
+    let state = pool(&mut env).await;
+    assert_eq!(state.reward_rate, 1);
+    assert_eq!(
+        state.queued_action,
+        Some(AdminAction::SetRewardRate { reward_rate: 5 })
+    );
+
+    let proposal = propose(
+        &mut env,
+        multisig,
+        2,
+        owner,
+        AdminAction::CancelQueuedAction,
+    )
+    .await;
+    execute(&mut env, multisig, proposal, owner, None)
+        .await
+        .unwrap();
+    assert_eq!(pool(&mut env).await.queued_action, None);
+
+    let proposal = propose(
+        &mut env,
+        multisig,
+        3,
+        owner,
+        AdminAction::SetRewardRate { reward_rate: 7 },
+    )
+    .await;
+    execute(&mut env, multisig, proposal, owner, None)
+        .await
+        .unwrap();
+    warp_to(&mut env.context, start + 100).await;
+    execute_queued_action(&mut env).await.unwrap();
+    assert_eq!(pool(&mut env).await.reward_rate, 7);
+}
@@ -58,7 +58,13 @@ describe("staking", () => {
     vault = vaultKeypair.publicKey;
 
     await program.methods
-      .initializePool(new anchor.BN(1_000_000))
+      .initializePool(new anchor.BN(1_000_000), {
+        maxTotalStake: new anchor.BN(0),
+        maxStakePerUser: new anchor.BN(0),
+        minStakeAmount: new anchor.BN(0),
+        stakeStart: new anchor.BN(0),
+        stakeEnd: new anchor.BN(0),
+      })
       .accounts({
         stakingPool: staking_pool,
         authority: authority.publicKey,
@@ -76,4 +82,15 @@ describe("staking", () => {
     assert.equal(poolState.rewardRate.toNumber(), 1_000_000);
     assert.ok(poolState.vault.equals(vault));
   });
+
+  it("returns pool stats from a simulated view call", async () => {
+    const stats = await program.methods
+      .getPoolStats()
+      .accounts({ stakingPool: staking_pool } as any)
+      .view();
+
+    assert.ok(stats.authority.equals(authority.publicKey));
+    assert.equal(stats.rewardRate.toNumber(), 1_000_000);
+    assert.equal(stats.totalStake.toNumber(), 0);
+  });
 });
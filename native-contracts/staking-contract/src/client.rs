@@ -0,0 +1,644 @@
+//! Off-chain helpers for building `StakingInstruction`s and reading program
+//! accounts. Enabled with the `client` feature.
+
+use crate::{
+    instruction::StakingInstruction,
+    state::{
+        AdminAction, QueuedChange, StakeLimits, StakingPool, UserStakeInfo, ZeroCopyAccount,
+    },
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use solana_rpc_client::rpc_client::RpcClient;
+use std::fmt;
+
+// `Display`/`Error` are implemented by hand rather than derived with
+// `thiserror` so that shank does not export these as program errors.
+#[derive(Debug)]
+pub enum ClientError {
+    Rpc(Box<solana_rpc_client_api::client_error::Error>),
+    Deserialize(std::io::Error),
+    InvalidAccount(ProgramError),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Rpc(e) => write!(f, "RPC request failed: {e}"),
+            ClientError::Deserialize(e) => write!(f, "Failed to deserialize account data: {e}"),
+            ClientError::InvalidAccount(e) => write!(f, "Account data is invalid: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> Self {
+        ClientError::Deserialize(e)
+    }
+}
+
+impl From<ProgramError> for ClientError {
+    fn from(e: ProgramError) -> Self {
+        ClientError::InvalidAccount(e)
+    }
+}
+
+impl From<solana_rpc_client_api::client_error::Error> for ClientError {
+    fn from(e: solana_rpc_client_api::client_error::Error) -> Self {
+        ClientError::Rpc(Box::new(e))
+    }
+}
+
+pub fn find_user_stake_address(program_id: &Pubkey, user: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"user-stake", user.as_ref(), pool.as_ref()], program_id)
+}
+
+pub fn find_queued_change_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"queued-change", pool.as_ref()], program_id)
+}
+
+pub fn find_vault_authority_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"vault-auth", pool.as_ref()], program_id)
+}
+
+/// Records which pool migrated from the first release owns `token_account`,
+/// one of the program-wide vault authority's.
+pub fn find_vault_claim_address(program_id: &Pubkey, token_account: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"vault-claim", token_account.as_ref()], program_id)
+}
+
+/// The vault authority every pool shared before each got its own. Pools with
+/// `legacy_vault_authority` set still use it until `migrate_vault_authority`.
+pub fn find_legacy_vault_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"vault-auth"], program_id)
+}
+
+/// The vault authority that moves the tokens of `pool`, whose state is
+/// `pool_state`: the program-wide one while it has `legacy_vault_authority`
+/// set, its own otherwise. Builders that pay out of the pool's token
+/// accounts take it as `vault_authority`.
+pub fn pool_vault_authority_address(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    pool_state: &StakingPool,
+) -> Pubkey {
+    if pool_state.legacy_vault_authority != 0 {
+        find_legacy_vault_authority_address(program_id).0
+    } else {
+        find_vault_authority_address(program_id, pool).0
+    }
+}
+
+fn instruction(
+    program_id: &Pubkey,
+    accounts: Vec<AccountMeta>,
+    data: &StakingInstruction,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: borsh::to_vec(data).expect("instruction serialization cannot fail"),
+    }
+}
+
+pub fn initialize_pool(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    vault: &Pubkey,
+    reward_vault: &Pubkey,
+    reward_rate: u64,
+    limits: StakeLimits,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(*vault, false),
+            AccountMeta::new_readonly(*reward_vault, false),
+        ],
+        &StakingInstruction::InitializePool { reward_rate, limits },
+    )
+}
+
+pub fn stake(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    vault: &Pubkey,
+    user: &Pubkey,
+    user_token_account: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (user_stake, _) = find_user_stake_address(program_id, user, pool);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new(*user_token_account, false),
+            AccountMeta::new(user_stake, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        &StakingInstruction::Stake { amount },
+    )
+}
+
+pub fn unstake(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    vault: &Pubkey,
+    vault_authority: &Pubkey,
+    user: &Pubkey,
+    user_token_account: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (user_stake, _) = find_user_stake_address(program_id, user, pool);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new(*user_token_account, false),
+            AccountMeta::new(user_stake, false),
+            AccountMeta::new_readonly(*vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        &StakingInstruction::UnStake { amount },
+    )
+}
+
+pub fn claim_rewards(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    reward_vault: &Pubkey,
+    vault_authority: &Pubkey,
+    user: &Pubkey,
+    user_token_account: &Pubkey,
+) -> Instruction {
+    claim_rewards_for(
+        program_id,
+        pool,
+        reward_vault,
+        vault_authority,
+        user,
+        user,
+        user_token_account,
+    )
+}
+
+/// Claims `staker`'s rewards signed by `claimer`, which is either the staker
+/// or the delegate it set with `authorize_claimer`.
+pub fn claim_rewards_for(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    reward_vault: &Pubkey,
+    vault_authority: &Pubkey,
+    staker: &Pubkey,
+    claimer: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    let (user_stake, _) = find_user_stake_address(program_id, staker, pool);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new(*reward_vault, false),
+            AccountMeta::new(*claimer, true),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new(user_stake, false),
+            AccountMeta::new_readonly(*vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ],
+        &StakingInstruction::ClaimRewards,
+    )
+}
+
+pub fn authorize_claimer(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    user: &Pubkey,
+    claimer: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    let (user_stake, _) = find_user_stake_address(program_id, user, pool);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*pool, false),
+            AccountMeta::new_readonly(*user, true),
+            AccountMeta::new(user_stake, false),
+        ],
+        &StakingInstruction::AuthorizeClaimer {
+            claimer: *claimer,
+            destination: *destination,
+        },
+    )
+}
+
+/// Advances the pool's reward accumulator without claiming a tip.
+pub fn update_pool(program_id: &Pubkey, pool: &Pubkey) -> Instruction {
+    instruction(
+        program_id,
+        vec![AccountMeta::new(*pool, false)],
+        &StakingInstruction::UpdatePool,
+    )
+}
+
+/// Advances the pool's reward accumulator and pays the crank tip into
+/// `cranker_token_account`.
+pub fn update_pool_with_tip(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    tip_vault: &Pubkey,
+    vault_authority: &Pubkey,
+    cranker_token_account: &Pubkey,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new(*tip_vault, false),
+            AccountMeta::new(*cranker_token_account, false),
+            AccountMeta::new_readonly(*vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        &StakingInstruction::UpdatePool,
+    )
+}
+
+pub fn set_crank_tip(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    tip_vault: &Pubkey,
+    crank_tip: u64,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(*tip_vault, false),
+        ],
+        &StakingInstruction::SetCrankTip { crank_tip },
+    )
+}
+
+pub fn set_stake_limits(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    limits: StakeLimits,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new_readonly(*admin, true),
+        ],
+        &StakingInstruction::SetStakeLimits { limits },
+    )
+}
+
+pub fn set_reward_rate(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    reward_rate: u64,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new_readonly(*admin, true),
+        ],
+        &StakingInstruction::SetRewardRate { reward_rate },
+    )
+}
+
+pub fn set_timelock_delay(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    timelock_delay: u64,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new_readonly(*admin, true),
+        ],
+        &StakingInstruction::SetTimelockDelay { timelock_delay },
+    )
+}
+
+pub fn queue_action(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    action: AdminAction,
+) -> Instruction {
+    let (queued_change, _) = find_queued_change_address(program_id, pool);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*pool, false),
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(queued_change, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        &StakingInstruction::QueueAction { action },
+    )
+}
+
+pub fn cancel_action(program_id: &Pubkey, pool: &Pubkey, admin: &Pubkey) -> Instruction {
+    let (queued_change, _) = find_queued_change_address(program_id, pool);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*pool, false),
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(queued_change, false),
+        ],
+        &StakingInstruction::CancelAction,
+    )
+}
+
+/// Applies the pool's queued action. `tip_vault` is only needed when the
+/// queued action is a `SetCrankTip`.
+pub fn execute_action(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    tip_vault: Option<&Pubkey>,
+) -> Instruction {
+    let (queued_change, _) = find_queued_change_address(program_id, pool);
+    let mut accounts = vec![
+        AccountMeta::new(*pool, false),
+        AccountMeta::new(*admin, false),
+        AccountMeta::new(queued_change, false),
+    ];
+    if let Some(tip_vault) = tip_vault {
+        accounts.push(AccountMeta::new_readonly(*tip_vault, false));
+    }
+
+    instruction(program_id, accounts, &StakingInstruction::ExecuteAction)
+}
+
+pub fn set_emergency_mode(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    enabled: bool,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new_readonly(*admin, true),
+        ],
+        &StakingInstruction::SetEmergencyMode { enabled },
+    )
+}
+
+pub fn emergency_withdraw(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    vault: &Pubkey,
+    vault_authority: &Pubkey,
+    user: &Pubkey,
+    user_token_account: &Pubkey,
+) -> Instruction {
+    let (user_stake, _) = find_user_stake_address(program_id, user, pool);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new(*user_token_account, false),
+            AccountMeta::new(user_stake, false),
+            AccountMeta::new_readonly(*vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        &StakingInstruction::EmergencyWithdraw,
+    )
+}
+
+/// Moves `amount` of tokens that are neither stake nor committed rewards out of
+/// `source`, a token account owned by `vault_authority`.
+pub fn recover_tokens(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    source: &Pubkey,
+    vault_authority: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*pool, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(*source, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(*vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        &StakingInstruction::RecoverTokens { amount },
+    )
+}
+
+/// Fails unless the pool's vaults cover its stake and committed rewards.
+pub fn assert_invariants(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    vault: &Pubkey,
+    reward_vault: &Pubkey,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*pool, false),
+            AccountMeta::new_readonly(*vault, false),
+            AccountMeta::new_readonly(*reward_vault, false),
+        ],
+        &StakingInstruction::AssertInvariants,
+    )
+}
+
+/// Upgrades `account`, written by an older build of the program, to the
+/// current layout. `payer` covers any extra rent. `pool` is only needed when
+/// `account` is a position.
+pub fn migrate_account(
+    program_id: &Pubkey,
+    account: &Pubkey,
+    payer: &Pubkey,
+    pool: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*account, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(pool) = pool {
+        accounts.push(AccountMeta::new(*pool, false));
+    }
+
+    instruction(program_id, accounts, &StakingInstruction::MigrateAccount)
+}
+
+/// Upgrades a pool written by the first release, which recorded no vaults.
+/// `pool` and its `admin` both sign, and the vaults must be owned by the
+/// program-wide vault authority and not yet claimed by another pool. Its
+/// positions are migrated afterwards with `migrate_account`.
+pub fn migrate_baseline_pool(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    vault: &Pubkey,
+    reward_vault: &Pubkey,
+) -> Instruction {
+    let (vault_claim, _) = find_vault_claim_address(program_id, vault);
+    let (reward_vault_claim, _) = find_vault_claim_address(program_id, reward_vault);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, true),
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(*pool, true),
+            AccountMeta::new_readonly(*vault, false),
+            AccountMeta::new_readonly(*reward_vault, false),
+            AccountMeta::new(vault_claim, false),
+            AccountMeta::new(reward_vault_claim, false),
+        ],
+        &StakingInstruction::MigrateAccount,
+    )
+}
+
+/// Moves a legacy pool's vaults to its own vault authority.
+pub fn migrate_vault_authority(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    admin: &Pubkey,
+    vault: &Pubkey,
+    reward_vault: &Pubkey,
+) -> Instruction {
+    let (vault_authority, _) = find_legacy_vault_authority_address(program_id);
+    let (vault_claim, _) = find_vault_claim_address(program_id, vault);
+    let (reward_vault_claim, _) = find_vault_claim_address(program_id, reward_vault);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*pool, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new(*reward_vault, false),
+            AccountMeta::new_readonly(vault_claim, false),
+            AccountMeta::new_readonly(reward_vault_claim, false),
+        ],
+        &StakingInstruction::MigrateVaultAuthority,
+    )
+}
+
+pub fn get_pending_rewards(program_id: &Pubkey, pool: &Pubkey, user: &Pubkey) -> Instruction {
+    let (user_stake, _) = find_user_stake_address(program_id, user, pool);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*pool, false),
+            AccountMeta::new_readonly(user_stake, false),
+        ],
+        &StakingInstruction::GetPendingRewards,
+    )
+}
+
+pub fn get_pool_stats(program_id: &Pubkey, pool: &Pubkey) -> Instruction {
+    instruction(
+        program_id,
+        vec![AccountMeta::new_readonly(*pool, false)],
+        &StakingInstruction::GetPoolStats,
+    )
+}
+
+pub fn get_position(program_id: &Pubkey, pool: &Pubkey, user: &Pubkey) -> Instruction {
+    let (user_stake, _) = find_user_stake_address(program_id, user, pool);
+
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*pool, false),
+            AccountMeta::new_readonly(user_stake, false),
+        ],
+        &StakingInstruction::GetPosition,
+    )
+}
+
+pub fn unpack_pool(data: &[u8]) -> Result<StakingPool, ClientError> {
+    Ok(*StakingPool::load(data)?)
+}
+
+pub fn unpack_user_stake(data: &[u8]) -> Result<UserStakeInfo, ClientError> {
+    Ok(*UserStakeInfo::load(data)?)
+}
+
+pub fn unpack_queued_change(data: &[u8]) -> Result<QueuedChange, ClientError> {
+    Ok(QueuedChange::load(data)?)
+}
+
+pub fn fetch_pool(rpc: &RpcClient, pool: &Pubkey) -> Result<StakingPool, ClientError> {
+    unpack_pool(&rpc.get_account_data(pool)?)
+}
+
+pub fn fetch_user_stake(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    user: &Pubkey,
+    pool: &Pubkey,
+) -> Result<UserStakeInfo, ClientError> {
+    let (user_stake, _) = find_user_stake_address(program_id, user, pool);
+    unpack_user_stake(&rpc.get_account_data(&user_stake)?)
+}
+
+pub fn fetch_queued_change(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    pool: &Pubkey,
+) -> Result<QueuedChange, ClientError> {
+    let (queued_change, _) = find_queued_change_address(program_id, pool);
+    unpack_queued_change(&rpc.get_account_data(&queued_change)?)
+}
+
+/// Amount `process_claim_rewards` would pay out at unix time `now`, or `None`
+/// if the reward calculation overflows on-chain.
+pub fn pending_rewards(pool: &StakingPool, stake_info: &UserStakeInfo, now: u64) -> Option<u64> {
+    pool.pending_rewards_at(stake_info, now)
+}
@@ -0,0 +1,404 @@
+//! Helpers for invoking the staking program from other on-chain programs.
+//! Enabled with the `cpi` feature, which also disables the entrypoint.
+//!
+//! `signer_seeds` are forwarded to `invoke_signed`, so a calling program can
+//! stake or claim on behalf of one of its PDAs by passing that PDA as `user`.
+
+use crate::{
+    instruction::StakingInstruction,
+    state::{PoolStats, PositionInfo, StakeLimits},
+};
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::{get_return_data, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+pub struct InitializePool<'info> {
+    pub pool: AccountInfo<'info>,
+    pub admin: AccountInfo<'info>,
+    pub rent: AccountInfo<'info>,
+    pub vault: AccountInfo<'info>,
+    pub reward_vault: AccountInfo<'info>,
+}
+
+pub struct Stake<'info> {
+    pub pool: AccountInfo<'info>,
+    pub vault: AccountInfo<'info>,
+    pub user: AccountInfo<'info>,
+    pub user_token_account: AccountInfo<'info>,
+    pub user_stake_info: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub rent: AccountInfo<'info>,
+}
+
+pub struct UnStake<'info> {
+    pub pool: AccountInfo<'info>,
+    pub vault: AccountInfo<'info>,
+    pub user: AccountInfo<'info>,
+    pub user_token_account: AccountInfo<'info>,
+    pub user_stake_info: AccountInfo<'info>,
+    pub vault_authority: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+}
+
+pub struct EmergencyWithdraw<'info> {
+    pub pool: AccountInfo<'info>,
+    pub vault: AccountInfo<'info>,
+    pub user: AccountInfo<'info>,
+    pub user_token_account: AccountInfo<'info>,
+    pub user_stake_info: AccountInfo<'info>,
+    pub vault_authority: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+}
+
+pub struct ClaimRewards<'info> {
+    pub pool: AccountInfo<'info>,
+    pub reward_vault: AccountInfo<'info>,
+    pub user: AccountInfo<'info>,
+    pub user_token_account: AccountInfo<'info>,
+    pub user_stake_info: AccountInfo<'info>,
+    pub vault_authority: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub clock: AccountInfo<'info>,
+    pub instructions: AccountInfo<'info>,
+}
+
+pub struct AuthorizeClaimer<'info> {
+    pub pool: AccountInfo<'info>,
+    pub user: AccountInfo<'info>,
+    pub user_stake_info: AccountInfo<'info>,
+}
+
+pub struct UpdatePool<'info> {
+    pub pool: AccountInfo<'info>,
+    /// Accounts to collect the crank tip with, if any.
+    pub tip: Option<CrankTip<'info>>,
+}
+
+pub struct CrankTip<'info> {
+    pub tip_vault: AccountInfo<'info>,
+    pub cranker_token_account: AccountInfo<'info>,
+    pub vault_authority: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+}
+
+pub struct AssertInvariants<'info> {
+    pub pool: AccountInfo<'info>,
+    pub vault: AccountInfo<'info>,
+    pub reward_vault: AccountInfo<'info>,
+}
+
+pub struct GetPosition<'info> {
+    pub pool: AccountInfo<'info>,
+    pub user_stake_info: AccountInfo<'info>,
+}
+
+pub struct GetPoolStats<'info> {
+    pub pool: AccountInfo<'info>,
+}
+
+fn invoke_staking(
+    program_id: &Pubkey,
+    accounts: Vec<AccountMeta>,
+    account_infos: &[AccountInfo],
+    data: &StakingInstruction,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = Instruction {
+        program_id: *program_id,
+        accounts,
+        data: borsh::to_vec(data)?,
+    };
+
+    invoke_signed(&ix, account_infos, signer_seeds)
+}
+
+fn read_return_data<T: BorshDeserialize>(program_id: &Pubkey) -> Result<T, ProgramError> {
+    match get_return_data() {
+        Some((returned_by, data)) if returned_by == *program_id => Ok(T::try_from_slice(&data)?),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+pub fn initialize_pool(
+    program_id: &Pubkey,
+    accounts: InitializePool,
+    reward_rate: u64,
+    limits: StakeLimits,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_staking(
+        program_id,
+        vec![
+            AccountMeta::new(*accounts.pool.key, false),
+            AccountMeta::new_readonly(*accounts.admin.key, true),
+            AccountMeta::new_readonly(*accounts.rent.key, false),
+            AccountMeta::new_readonly(*accounts.vault.key, false),
+            AccountMeta::new_readonly(*accounts.reward_vault.key, false),
+        ],
+        &[
+            accounts.pool,
+            accounts.admin,
+            accounts.rent,
+            accounts.vault,
+            accounts.reward_vault,
+        ],
+        &StakingInstruction::InitializePool { reward_rate, limits },
+        signer_seeds,
+    )
+}
+
+pub fn stake(
+    program_id: &Pubkey,
+    accounts: Stake,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_staking(
+        program_id,
+        vec![
+            AccountMeta::new(*accounts.pool.key, false),
+            AccountMeta::new(*accounts.vault.key, false),
+            AccountMeta::new(*accounts.user.key, true),
+            AccountMeta::new(*accounts.user_token_account.key, false),
+            AccountMeta::new(*accounts.user_stake_info.key, false),
+            AccountMeta::new_readonly(*accounts.token_program.key, false),
+            AccountMeta::new_readonly(*accounts.system_program.key, false),
+            AccountMeta::new_readonly(*accounts.rent.key, false),
+        ],
+        &[
+            accounts.pool,
+            accounts.vault,
+            accounts.user,
+            accounts.user_token_account,
+            accounts.user_stake_info,
+            accounts.token_program,
+            accounts.system_program,
+            accounts.rent,
+        ],
+        &StakingInstruction::Stake { amount },
+        signer_seeds,
+    )
+}
+
+pub fn unstake(
+    program_id: &Pubkey,
+    accounts: UnStake,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_staking(
+        program_id,
+        vec![
+            AccountMeta::new(*accounts.pool.key, false),
+            AccountMeta::new(*accounts.vault.key, false),
+            AccountMeta::new(*accounts.user.key, true),
+            AccountMeta::new(*accounts.user_token_account.key, false),
+            AccountMeta::new(*accounts.user_stake_info.key, false),
+            AccountMeta::new_readonly(*accounts.vault_authority.key, false),
+            AccountMeta::new_readonly(*accounts.token_program.key, false),
+        ],
+        &[
+            accounts.pool,
+            accounts.vault,
+            accounts.user,
+            accounts.user_token_account,
+            accounts.user_stake_info,
+            accounts.vault_authority,
+            accounts.token_program,
+        ],
+        &StakingInstruction::UnStake { amount },
+        signer_seeds,
+    )
+}
+
+pub fn emergency_withdraw(
+    program_id: &Pubkey,
+    accounts: EmergencyWithdraw,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_staking(
+        program_id,
+        vec![
+            AccountMeta::new(*accounts.pool.key, false),
+            AccountMeta::new(*accounts.vault.key, false),
+            AccountMeta::new(*accounts.user.key, true),
+            AccountMeta::new(*accounts.user_token_account.key, false),
+            AccountMeta::new(*accounts.user_stake_info.key, false),
+            AccountMeta::new_readonly(*accounts.vault_authority.key, false),
+            AccountMeta::new_readonly(*accounts.token_program.key, false),
+        ],
+        &[
+            accounts.pool,
+            accounts.vault,
+            accounts.user,
+            accounts.user_token_account,
+            accounts.user_stake_info,
+            accounts.vault_authority,
+            accounts.token_program,
+        ],
+        &StakingInstruction::EmergencyWithdraw,
+        signer_seeds,
+    )
+}
+
+pub fn claim_rewards(
+    program_id: &Pubkey,
+    accounts: ClaimRewards,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_staking(
+        program_id,
+        vec![
+            AccountMeta::new(*accounts.pool.key, false),
+            AccountMeta::new(*accounts.reward_vault.key, false),
+            AccountMeta::new_readonly(*accounts.user.key, true),
+            AccountMeta::new(*accounts.user_token_account.key, false),
+            AccountMeta::new(*accounts.user_stake_info.key, false),
+            AccountMeta::new_readonly(*accounts.vault_authority.key, false),
+            AccountMeta::new_readonly(*accounts.token_program.key, false),
+            AccountMeta::new_readonly(*accounts.clock.key, false),
+            AccountMeta::new_readonly(*accounts.instructions.key, false),
+        ],
+        &[
+            accounts.pool,
+            accounts.reward_vault,
+            accounts.user,
+            accounts.user_token_account,
+            accounts.user_stake_info,
+            accounts.vault_authority,
+            accounts.token_program,
+            accounts.clock,
+            accounts.instructions,
+        ],
+        &StakingInstruction::ClaimRewards,
+        signer_seeds,
+    )
+}
+
+pub fn authorize_claimer(
+    program_id: &Pubkey,
+    accounts: AuthorizeClaimer,
+    claimer: Pubkey,
+    destination: Pubkey,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_staking(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*accounts.pool.key, false),
+            AccountMeta::new_readonly(*accounts.user.key, true),
+            AccountMeta::new(*accounts.user_stake_info.key, false),
+        ],
+        &[accounts.pool, accounts.user, accounts.user_stake_info],
+        &StakingInstruction::AuthorizeClaimer {
+            claimer,
+            destination,
+        },
+        signer_seeds,
+    )
+}
+
+pub fn update_pool(program_id: &Pubkey, accounts: UpdatePool) -> ProgramResult {
+    let mut metas = vec![AccountMeta::new(*accounts.pool.key, false)];
+    let mut infos = vec![accounts.pool];
+
+    if let Some(tip) = accounts.tip {
+        metas.extend([
+            AccountMeta::new(*tip.tip_vault.key, false),
+            AccountMeta::new(*tip.cranker_token_account.key, false),
+            AccountMeta::new_readonly(*tip.vault_authority.key, false),
+            AccountMeta::new_readonly(*tip.token_program.key, false),
+        ]);
+        infos.extend([
+            tip.tip_vault,
+            tip.cranker_token_account,
+            tip.vault_authority,
+            tip.token_program,
+        ]);
+    }
+
+    invoke_staking(
+        program_id,
+        metas,
+        &infos,
+        &StakingInstruction::UpdatePool,
+        &[],
+    )
+}
+
+/// Fails, aborting the caller, unless the pool's vaults cover its stake and
+/// committed rewards.
+pub fn assert_invariants(program_id: &Pubkey, accounts: AssertInvariants) -> ProgramResult {
+    invoke_staking(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*accounts.pool.key, false),
+            AccountMeta::new_readonly(*accounts.vault.key, false),
+            AccountMeta::new_readonly(*accounts.reward_vault.key, false),
+        ],
+        &[accounts.pool, accounts.vault, accounts.reward_vault],
+        &StakingInstruction::AssertInvariants,
+        &[],
+    )
+}
+
+/// Invokes `GetPendingRewards` and decodes the returned amount.
+pub fn get_pending_rewards(
+    program_id: &Pubkey,
+    accounts: GetPosition,
+) -> Result<u64, ProgramError> {
+    invoke_staking(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*accounts.pool.key, false),
+            AccountMeta::new_readonly(*accounts.user_stake_info.key, false),
+        ],
+        &[accounts.pool, accounts.user_stake_info],
+        &StakingInstruction::GetPendingRewards,
+        &[],
+    )?;
+
+    read_return_data(program_id)
+}
+
+/// Invokes `GetPoolStats` and decodes the returned stats.
+pub fn get_pool_stats(
+    program_id: &Pubkey,
+    accounts: GetPoolStats,
+) -> Result<PoolStats, ProgramError> {
+    invoke_staking(
+        program_id,
+        vec![AccountMeta::new_readonly(*accounts.pool.key, false)],
+        &[accounts.pool],
+        &StakingInstruction::GetPoolStats,
+        &[],
+    )?;
+
+    read_return_data(program_id)
+}
+
+/// Invokes `GetPosition` and decodes the returned position.
+pub fn get_position(
+    program_id: &Pubkey,
+    accounts: GetPosition,
+) -> Result<PositionInfo, ProgramError> {
+    invoke_staking(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*accounts.pool.key, false),
+            AccountMeta::new_readonly(*accounts.user_stake_info.key, false),
+        ],
+        &[accounts.pool, accounts.user_stake_info],
+        &StakingInstruction::GetPosition,
+        &[],
+    )?;
+
+    read_return_data(program_id)
+}
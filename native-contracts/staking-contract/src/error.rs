@@ -9,11 +9,85 @@ pub enum StakingError {
     #[error("Pool already initialized")]
     PoolAlreadyInitialized,
 
+    /// Never raised; kept so the codes after it stay where the deployed
+    /// program put them.
     #[error("User stake account already exists")]
     UserAlreadyStaked,
 
     #[error("Nothing to claim")]
     NothingToClaim,
+
+    #[error("Signer is neither the staker nor its authorized claimer")]
+    UnauthorizedClaimer,
+
+    #[error("Delegated claims must pay into the staker's claim destination")]
+    InvalidClaimDestination,
+
+    #[error("Stake is below the pool's minimum stake amount")]
+    StakeBelowMinimum,
+
+    #[error("Stake would exceed the pool's total stake cap")]
+    PoolCapExceeded,
+
+    #[error("Stake would exceed the pool's per-user stake cap")]
+    UserCapExceeded,
+
+    #[error("The pool's stake window has not opened yet")]
+    StakeWindowNotOpen,
+
+    #[error("The pool's stake window has closed")]
+    StakeWindowClosed,
+
+    #[error("Stake window must end after it starts")]
+    InvalidStakeWindow,
+
+    #[error("Pool has a timelock; queue the action instead")]
+    TimelockActive,
+
+    #[error("Queued action's timelock has not expired")]
+    TimelockNotExpired,
+
+    #[error("Pool already has a queued action")]
+    ActionAlreadyQueued,
+
+    #[error("Pool has no queued action")]
+    NoQueuedAction,
+
+    #[error("Pool is in emergency mode")]
+    PoolInEmergency,
+
+    #[error("Pool is not in emergency mode")]
+    EmergencyModeOff,
+
+    #[error("Amount exceeds what can be recovered without touching stake or committed rewards")]
+    RecoveryExceedsExcess,
+
+    #[error("Vault holds less than the pool's total stake")]
+    VaultUnderfunded,
+
+    #[error("Reward vault holds less than the rewards committed to stakers")]
+    RewardVaultUnderfunded,
+
+    #[error("Position was staked earlier in this transaction")]
+    StakedInSameTransaction,
+
+    #[error("Account is not of the expected type")]
+    InvalidAccountDiscriminator,
+
+    #[error("Account layout version is not supported, migrate it with MigrateAccount")]
+    AccountVersionMismatch,
+
+    #[error("Account already uses the current layout")]
+    AccountAlreadyMigrated,
+
+    #[error("Pool still uses the program-wide vault authority, see MigrateVaultAuthority")]
+    LegacyVaultAuthority,
+
+    #[error("Pool's vaults are already owned by its own vault authority")]
+    VaultAuthorityAlreadyMigrated,
+
+    #[error("Token account already belongs to another pool migrated from the first release")]
+    VaultAlreadyClaimed,
 }
 
 impl From<StakingError> for ProgramError {
@@ -21,3 +95,16 @@ impl From<StakingError> for ProgramError {
         ProgramError::Custom(e as u32)
     }
 }
+
+impl From<staking_core::Error> for StakingError {
+    fn from(e: staking_core::Error) -> Self {
+        match e {
+            staking_core::Error::StakeWindowNotOpen => StakingError::StakeWindowNotOpen,
+            staking_core::Error::StakeWindowClosed => StakingError::StakeWindowClosed,
+            staking_core::Error::StakeBelowMinimum => StakingError::StakeBelowMinimum,
+            staking_core::Error::PoolCapExceeded => StakingError::PoolCapExceeded,
+            staking_core::Error::UserCapExceeded => StakingError::UserCapExceeded,
+            staking_core::Error::InvalidStakeWindow => StakingError::InvalidStakeWindow,
+        }
+    }
+}
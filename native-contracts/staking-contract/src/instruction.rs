@@ -1,9 +1,147 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use shank::ShankInstruction;
+use crate::state::{AdminAction, StakeLimits};
+use solana_program::pubkey::Pubkey;
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, ShankInstruction)]
 pub enum StakingInstruction {
-    InitializePool { reward_rate: u64 },
+    #[account(0, writable, name = "pool", desc = "Staking pool account owned by the program")]
+    #[account(1, signer, name = "admin", desc = "Pool admin")]
+    #[account(2, name = "rent", desc = "Rent sysvar")]
+    #[account(3, name = "vault", desc = "Token account owned by the vault authority that will hold the stake")]
+    #[account(4, name = "reward_vault", desc = "Token account owned by the vault authority that will pay rewards")]
+    InitializePool { reward_rate: u64, limits: StakeLimits },
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, writable, name = "vault", desc = "Pool vault receiving the stake")]
+    #[account(2, writable, signer, name = "user", desc = "Staker and rent payer")]
+    #[account(3, writable, name = "user_token_account", desc = "Staker's source token account")]
+    #[account(4, writable, name = "user_stake_info", desc = "User stake PDA seeded by user-stake, user, pool")]
+    #[account(5, name = "token_program", desc = "SPL Token program")]
+    #[account(6, name = "system_program", desc = "System program")]
+    #[account(7, name = "rent", desc = "Rent sysvar")]
     Stake { amount: u64 },
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, writable, name = "vault", desc = "Pool vault holding the stake")]
+    #[account(2, writable, signer, name = "user", desc = "Staker")]
+    #[account(3, writable, name = "user_token_account", desc = "Staker's destination token account")]
+    #[account(4, writable, name = "user_stake_info", desc = "User stake PDA seeded by user-stake, user, pool")]
+    #[account(5, name = "vault_authority", desc = "Vault authority PDA seeded by vault-auth, pool")]
+    #[account(6, name = "token_program", desc = "SPL Token program")]
     UnStake { amount: u64 },
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, writable, name = "reward_vault", desc = "Pool reward vault")]
+    #[account(2, signer, name = "user", desc = "Staker, or the claimer it authorized")]
+    #[account(3, writable, name = "user_token_account", desc = "Destination token account")]
+    #[account(4, writable, name = "user_stake_info", desc = "User stake PDA seeded by user-stake, user, pool")]
+    #[account(5, name = "vault_authority", desc = "Vault authority PDA seeded by vault-auth, pool")]
+    #[account(6, name = "token_program", desc = "SPL Token program")]
+    #[account(7, name = "clock", desc = "Clock sysvar")]
+    #[account(8, name = "instructions", desc = "Instructions sysvar")]
     ClaimRewards,
+
+    #[account(0, name = "pool", desc = "Staking pool account")]
+    #[account(1, signer, name = "user", desc = "Staker")]
+    #[account(2, writable, name = "user_stake_info", desc = "User stake PDA seeded by user-stake, user, pool")]
+    AuthorizeClaimer { claimer: Pubkey, destination: Pubkey },
+
+    #[account(0, name = "pool", desc = "Staking pool account")]
+    #[account(1, name = "user_stake_info", desc = "User stake PDA seeded by user-stake, user, pool")]
+    GetPendingRewards,
+
+    #[account(0, name = "pool", desc = "Staking pool account")]
+    GetPoolStats,
+
+    #[account(0, name = "pool", desc = "Staking pool account")]
+    #[account(1, name = "user_stake_info", desc = "User stake PDA seeded by user-stake, user, pool")]
+    GetPosition,
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, optional, writable, name = "tip_vault", desc = "Pool tip vault, to be paid a crank tip")]
+    #[account(2, optional, writable, name = "cranker_token_account", desc = "Token account receiving the tip")]
+    #[account(3, optional, name = "vault_authority", desc = "Vault authority PDA seeded by vault-auth, pool")]
+    #[account(4, optional, name = "token_program", desc = "SPL Token program")]
+    UpdatePool,
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin")]
+    #[account(2, name = "tip_vault", desc = "Token account owned by the vault authority that pays tips, other than the pool's vaults")]
+    SetCrankTip { crank_tip: u64 },
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin")]
+    SetStakeLimits { limits: StakeLimits },
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin")]
+    SetRewardRate { reward_rate: u64 },
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin")]
+    SetTimelockDelay { timelock_delay: u64 },
+
+    #[account(0, name = "pool", desc = "Staking pool account")]
+    #[account(1, writable, signer, name = "admin", desc = "Pool admin and rent payer")]
+    #[account(2, writable, name = "queued_change", desc = "Queued change PDA seeded by queued-change, pool")]
+    #[account(3, name = "system_program", desc = "System program")]
+    QueueAction { action: AdminAction },
+
+    #[account(0, name = "pool", desc = "Staking pool account")]
+    #[account(1, writable, signer, name = "admin", desc = "Pool admin, refunded the queued change's rent")]
+    #[account(2, writable, name = "queued_change", desc = "Queued change PDA seeded by queued-change, pool")]
+    CancelAction,
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, writable, name = "admin", desc = "Pool admin, refunded the queued change's rent")]
+    #[account(2, writable, name = "queued_change", desc = "Queued change PDA seeded by queued-change, pool")]
+    #[account(3, optional, name = "tip_vault", desc = "Tip vault named by a queued SetCrankTip")]
+    ExecuteAction,
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin")]
+    SetEmergencyMode { enabled: bool },
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, writable, name = "vault", desc = "Pool vault holding the stake")]
+    #[account(2, writable, signer, name = "user", desc = "Staker, refunded the position's rent")]
+    #[account(3, writable, name = "user_token_account", desc = "Staker's destination token account")]
+    #[account(4, writable, name = "user_stake_info", desc = "User stake PDA seeded by user-stake, user, pool")]
+    #[account(5, name = "vault_authority", desc = "Vault authority PDA seeded by vault-auth, pool")]
+    #[account(6, name = "token_program", desc = "SPL Token program")]
+    EmergencyWithdraw,
+
+    #[account(0, name = "pool", desc = "Staking pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin")]
+    #[account(2, writable, name = "source", desc = "Token account owned by the vault authority")]
+    #[account(3, writable, name = "destination", desc = "Token account receiving the recovered tokens")]
+    #[account(4, name = "vault_authority", desc = "Vault authority PDA seeded by vault-auth, pool")]
+    #[account(5, name = "token_program", desc = "SPL Token program")]
+    RecoverTokens { amount: u64 },
+
+    #[account(0, name = "pool", desc = "Staking pool account")]
+    #[account(1, name = "vault", desc = "Pool vault holding the stake")]
+    #[account(2, name = "reward_vault", desc = "Pool reward vault")]
+    AssertInvariants,
+
+    #[account(0, writable, name = "account", desc = "Program account in an older layout, signing if it is a first-release pool")]
+    #[account(1, writable, signer, name = "payer", desc = "Pays rent for the account's larger size; the admin of a first-release pool")]
+    #[account(2, name = "system_program", desc = "System program")]
+    #[account(3, optional, writable, name = "pool", desc = "Pool the account belongs to, needed to migrate a position; the account itself for a first-release pool")]
+    #[account(4, optional, name = "vault", desc = "Vault of a first-release pool")]
+    #[account(5, optional, name = "reward_vault", desc = "Reward vault of a first-release pool")]
+    #[account(6, optional, writable, name = "vault_claim", desc = "Vault claim PDA seeded by vault-claim, vault, created for a first-release pool")]
+    #[account(7, optional, writable, name = "reward_vault_claim", desc = "Vault claim PDA seeded by vault-claim, reward_vault, created for a first-release pool")]
+    MigrateAccount,
+
+    #[account(0, writable, name = "pool", desc = "Staking pool account")]
+    #[account(1, signer, name = "admin", desc = "Pool admin")]
+    #[account(2, name = "vault_authority", desc = "Program-wide vault authority PDA seeded by vault-auth")]
+    #[account(3, name = "token_program", desc = "SPL Token program")]
+    #[account(4, writable, name = "vault", desc = "Pool vault holding the stake")]
+    #[account(5, writable, name = "reward_vault", desc = "Pool reward vault")]
+    #[account(6, name = "vault_claim", desc = "Vault claim PDA seeded by vault-claim, vault, naming the pool")]
+    #[account(7, name = "reward_vault_claim", desc = "Vault claim PDA seeded by vault-claim, reward_vault, naming the pool")]
+    MigrateVaultAuthority,
 }
@@ -1,6 +1,5 @@
 use solana_program::{
     account_info::AccountInfo,
-    entrypoint,
     entrypoint::ProgramResult,
     pubkey::Pubkey,
 };
@@ -13,7 +12,16 @@ pub mod processor;
 pub mod state;
 pub mod error;
 
-entrypoint!(process_instruction);
+#[cfg(feature = "client")]
+pub mod client;
+
+#[cfg(feature = "cpi")]
+pub mod cpi;
+
+solana_program::declare_id!("9q9ijMUqWBVfWSdWCNuVzyVsBBeazBj9GKripzqapmu6");
+
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 
 pub fn process_instruction(
     program_id: &Pubkey,
@@ -2,9 +2,13 @@
 use crate::{
     error::StakingError,
     instruction::StakingInstruction,
-    state::{StakingPool, UserStakeInfo},
+    state::{
+        AccountLocation, AdminAction, BASELINE_LEN, BaselinePool, BaselinePosition, PoolStats,
+        PositionInfo, ProgramAccount, QueuedChange, StakeLimits, StakingPool, UserStakeInfo,
+        VaultClaim, ZeroCopyAccount, migrate,
+    },
 };
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{AccountInfo, next_account_info},
     clock::Clock,
@@ -12,13 +16,20 @@ use solana_program::{
     msg,
     program::invoke,
     program::invoke_signed,
+    program::set_return_data,
     program_error::ProgramError,
+    program_pack::Pack,
     pubkey::Pubkey,
     rent::Rent,
     system_instruction,
-    sysvar::Sysvar,
+    sysvar::{
+        Sysvar,
+        instructions::{load_current_index_checked, load_instruction_at_checked},
+    },
 };
 use spl_token::instruction::transfer;
+use staking_core::{lock, penalty, rewards, timestamp};
+use std::cell::RefMut;
 
 pub fn process(
     program_id: &Pubkey,
@@ -28,8 +39,8 @@ pub fn process(
     let instruction = StakingInstruction::try_from_slice(instruction_data)?;
 
     match instruction {
-        StakingInstruction::InitializePool { reward_rate } => {
-            process_initialize_pool(accounts, reward_rate, program_id)
+        StakingInstruction::InitializePool { reward_rate, limits } => {
+            process_initialize_pool(accounts, reward_rate, limits, program_id)
         }
         StakingInstruction::Stake { amount } => {
             process_stake(accounts, amount, program_id)
@@ -40,6 +51,60 @@ pub fn process(
         StakingInstruction::ClaimRewards => {
             process_claim_rewards(accounts, program_id)
         }
+        StakingInstruction::AuthorizeClaimer { claimer, destination } => {
+            process_authorize_claimer(accounts, claimer, destination, program_id)
+        }
+        StakingInstruction::GetPendingRewards => {
+            process_get_pending_rewards(accounts, program_id)
+        }
+        StakingInstruction::GetPoolStats => {
+            process_get_pool_stats(accounts, program_id)
+        }
+        StakingInstruction::GetPosition => {
+            process_get_position(accounts, program_id)
+        }
+        StakingInstruction::UpdatePool => {
+            process_update_pool(accounts, program_id)
+        }
+        StakingInstruction::SetCrankTip { crank_tip } => {
+            process_set_crank_tip(accounts, crank_tip, program_id)
+        }
+        StakingInstruction::SetStakeLimits { limits } => {
+            process_set_stake_limits(accounts, limits, program_id)
+        }
+        StakingInstruction::SetRewardRate { reward_rate } => {
+            process_set_reward_rate(accounts, reward_rate, program_id)
+        }
+        StakingInstruction::SetTimelockDelay { timelock_delay } => {
+            process_set_timelock_delay(accounts, timelock_delay, program_id)
+        }
+        StakingInstruction::QueueAction { action } => {
+            process_queue_action(accounts, action, program_id)
+        }
+        StakingInstruction::CancelAction => {
+            process_cancel_action(accounts, program_id)
+        }
+        StakingInstruction::ExecuteAction => {
+            process_execute_action(accounts, program_id)
+        }
+        StakingInstruction::SetEmergencyMode { enabled } => {
+            process_set_emergency_mode(accounts, enabled, program_id)
+        }
+        StakingInstruction::EmergencyWithdraw => {
+            process_emergency_withdraw(accounts, program_id)
+        }
+        StakingInstruction::RecoverTokens { amount } => {
+            process_recover_tokens(accounts, amount, program_id)
+        }
+        StakingInstruction::AssertInvariants => {
+            process_assert_invariants(accounts, program_id)
+        }
+        StakingInstruction::MigrateAccount => {
+            process_migrate_account(accounts, program_id)
+        }
+        StakingInstruction::MigrateVaultAuthority => {
+            process_migrate_vault_authority(accounts, program_id)
+        }
     }
 }
 
@@ -47,12 +112,15 @@ pub fn process(
 pub fn process_initialize_pool(
     accounts: &[AccountInfo],
     reward_rate: u64,
+    limits: StakeLimits,
     program_id: &Pubkey,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let pool_account = next_account_info(account_info_iter)?;
     let admin_account = next_account_info(account_info_iter)?;
     let rent_sysvar = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let reward_vault = next_account_info(account_info_iter)?;
 
     let rent = &Rent::from_account_info(rent_sysvar)?;
     if !rent.is_exempt(pool_account.lamports(), pool_account.data_len()) {
@@ -63,18 +131,49 @@ pub fn process_initialize_pool(
         return Err(ProgramError::IncorrectProgramId);
     }
 
-    let pool_data = StakingPool::try_from_slice(&pool_account.data.borrow())?;
-    if pool_data.total_staked != 0 {
-        return Err(ProgramError::AccountAlreadyInitialized);
+    let pool_data = pool_account.data.borrow();
+    if pool_data.len() != StakingPool::LEN {
+        return Err(ProgramError::InvalidAccountData);
     }
+    if pool_data[..8] != [0; 8] {
+        return Err(StakingError::PoolAlreadyInitialized.into());
+    }
+    drop(pool_data);
+
+    // Searched for once here; every other handler derives the authority from
+    // the stored bump.
+    let (vault_authority, vault_authority_bump) =
+        Pubkey::find_program_address(&[b"vault-auth", pool_account.key.as_ref()], program_id);
+    check_vault_account(vault_account, &vault_authority)?;
+    check_vault_account(reward_vault, &vault_authority)?;
 
-    let pool = StakingPool {
+    let mut pool = StakingPool {
+        discriminator: StakingPool::DISCRIMINATOR,
+        version: StakingPool::VERSION,
         admin: admin_account.key.to_bytes(),
         reward_rate,
         total_staked: 0,
+        reward_per_token: 0,
+        last_update_time: Clock::get()?.unix_timestamp as u64,
+        tip_vault: [0; 32],
+        crank_tip: 0,
+        max_total_stake: 0,
+        max_stake_per_user: 0,
+        min_stake_amount: 0,
+        stake_start: 0,
+        stake_end: 0,
+        timelock_delay: 0,
+        emergency_mode: 0,
+        vault: vault_account.key.to_bytes(),
+        reward_vault: reward_vault.key.to_bytes(),
+        committed_rewards: 0,
+        min_stake_age: 0,
+        vault_authority_bump,
+        legacy_vault_authority: 0,
     };
+    pool.set_limits(&limits)?;
 
-    pool.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
+    pool.write_to(&mut pool_account.data.borrow_mut())?;
     msg!("Staking pool initialized");
     Ok(())
 }
@@ -90,22 +189,50 @@ pub fn process_stake(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey)
     let system_program = next_account_info(account_info_iter)?;
     let rent_sysvar = next_account_info(account_info_iter)?;
 
-    let (expected_pda, bump) = Pubkey::find_program_address(
-        &[
-            b"user-stake",
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Only a new position has its bump searched for; after that it is read
+    // back from the position.
+    let existing_amount = if user_stake_info_account.data_is_empty() {
+        None
+    } else {
+        let stake_info = UserStakeInfo::from_account(user_stake_info_account)?;
+        check_position_address(
+            user_stake_info_account,
+            &stake_info,
             user_wallet.key.as_ref(),
-            pool_account.key.as_ref(),
-        ],
-        program_id,
-    );
+            pool_account.key,
+            program_id,
+        )?;
+        Some(stake_info.amount)
+    };
 
-    if user_stake_info_account.key != &expected_pda {
-        return Err(ProgramError::InvalidArgument);
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp as u64;
+
+    let mut pool_data = StakingPool::from_account_mut(pool_account)?;
+    if vault_account.key.to_bytes() != pool_data.vault {
+        return Err(ProgramError::InvalidAccountData);
     }
+    pool_data.check_stake(amount, existing_amount.unwrap_or(0), now)?;
+
+    if existing_amount.is_none() {
+        let (expected_pda, bump) = Pubkey::find_program_address(
+            &[
+                b"user-stake",
+                user_wallet.key.as_ref(),
+                pool_account.key.as_ref(),
+            ],
+            program_id,
+        );
+        if user_stake_info_account.key != &expected_pda {
+            return Err(ProgramError::InvalidArgument);
+        }
 
-    if user_stake_info_account.data_is_empty() {
         let rent = Rent::from_account_info(rent_sysvar)?;
-        let space = std::mem::size_of::<UserStakeInfo>();
+        let space = UserStakeInfo::LEN;
         let lamports = rent.minimum_balance(space);
 
         invoke_signed(
@@ -128,6 +255,21 @@ pub fn process_stake(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey)
                 &[bump],
             ]],
         )?;
+
+        UserStakeInfo {
+            discriminator: UserStakeInfo::DISCRIMINATOR,
+            version: UserStakeInfo::VERSION,
+            staker: user_wallet.key.to_bytes(),
+            amount: 0,
+            last_stake_time: now,
+            claimer: [0; 32],
+            claim_destination: [0; 32],
+            reward_per_token_paid: pool_data.reward_per_token,
+            rewards: 0,
+            bump,
+            warming_amount: 0,
+        }
+        .write_to(&mut user_stake_info_account.data.borrow_mut())?;
     }
 
     let ix = transfer(
@@ -148,18 +290,31 @@ pub fn process_stake(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey)
             user_wallet.clone(),
         ],
     )?;
-    let clock = Clock::get()?;
-    let user_stake_info = UserStakeInfo {
-        staker: user_wallet.key.to_bytes(),
+    pool_data.update(now).ok_or(ProgramError::InvalidArgument)?;
+
+    let mut user_stake_info = UserStakeInfo::from_account_mut(user_stake_info_account)?;
+    pool_data
+        .settle(&mut user_stake_info, now)
+        .ok_or(ProgramError::InvalidArgument)?;
+    user_stake_info.amount = user_stake_info
+        .amount
+        .checked_add(amount)
+        .ok_or(ProgramError::InvalidArgument)?;
+    // New stake only starts earning once it has sat through the minimum
+    // stake age, so a flash deposit earns nothing. A top-up joins stake that
+    // is still warming at their weighted deposit time.
+    user_stake_info.last_stake_time = lock::top_up_stake_time(
+        timestamp(user_stake_info.last_stake_time),
+        user_stake_info.warming_amount,
         amount,
-        last_stake_time: clock.unix_timestamp as u64,
-    };
+        timestamp(now),
+    ) as u64;
+    if pool_data.min_stake_age != 0 {
+        user_stake_info.warming_amount += amount;
+    }
 
-    let mut pool_data = StakingPool::try_from_slice(&pool_account.data.borrow())?;
     pool_data.total_staked += amount;
-    pool_data.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
 
-    user_stake_info.serialize(&mut &mut user_stake_info_account.data.borrow_mut()[..])?;
     msg!("User staked {} tokens at {}", amount, clock.unix_timestamp);
     Ok(())
 }
@@ -176,33 +331,42 @@ pub fn process_unstake(
     let user_wallet = next_account_info(account_info_iter)?;
     let user_token_account = next_account_info(account_info_iter)?;
     let user_stake_info_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
 
-    let (expected_pda, _bump) = Pubkey::find_program_address(
-        &[
-            b"user-stake",
-            user_wallet.key.as_ref(),
-            pool_account.key.as_ref(),
-        ],
-        program_id,
-    );
-
-    if user_stake_info_account.key != &expected_pda {
-        return Err(StakingError::InvalidPda.into());
+    if !user_wallet.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
     }
 
-    let mut stake_info = UserStakeInfo::try_from_slice(&user_stake_info_account.data.borrow())?;
+    let mut stake_info = UserStakeInfo::from_account_mut(user_stake_info_account)?;
+    check_position_address(
+        user_stake_info_account,
+        &stake_info,
+        user_wallet.key.as_ref(),
+        pool_account.key,
+        program_id,
+    )?;
     if stake_info.amount < amount {
         return Err(ProgramError::InsufficientFunds);
     }
 
-    let (vault_authority, vault_bump) = Pubkey::find_program_address(&[b"vault-auth"], program_id);
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    if vault_account.key.to_bytes() != pool.vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if token_program.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let vault_bump = check_vault_authority(pool_account.key, &pool, vault_authority, program_id)?;
 
     let ix = transfer(
         token_program.key,
         vault_account.key,
         user_token_account.key,
-        &vault_authority,
+        vault_authority.key,
         &[],
         amount,
     )?;
@@ -212,19 +376,25 @@ pub fn process_unstake(
         &[
             vault_account.clone(),
             user_token_account.clone(),
+            vault_authority.clone(),
             token_program.clone(),
         ],
-        &[&[b"vault-auth", &[vault_bump]]],
+        &[&vault_authority_seeds(pool_account.key, &pool, &[vault_bump])],
     )?;
 
-    stake_info.amount -= amount;
-    stake_info.serialize(&mut &mut user_stake_info_account.data.borrow_mut()[..])?;
+    let now = Clock::get()?.unix_timestamp as u64;
+    pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+    pool.settle(&mut stake_info, now).ok_or(ProgramError::InvalidArgument)?;
 
-    let mut pool = StakingPool::try_from_slice(&pool_account.data.borrow())?;
+    // Withdraw stake that is not earning yet first.
+    stake_info.amount -= amount;
+    stake_info.warming_amount = stake_info.warming_amount.saturating_sub(amount);
     pool.total_staked -= amount;
-    pool.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
 
-    if stake_info.amount == 0 {
+    // Keep the position around while it still has rewards to claim.
+    let closed = stake_info.amount == 0 && stake_info.rewards == 0;
+    drop(stake_info);
+    if closed {
         **user_wallet.lamports.borrow_mut() += **user_stake_info_account.lamports.borrow();
         **user_stake_info_account.lamports.borrow_mut() = 0;
 
@@ -248,45 +418,69 @@ pub fn process_claim_rewards(
     let user_wallet = next_account_info(account_info_iter)?;
     let user_token_account = next_account_info(account_info_iter)?;
     let user_stake_info_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
     let clock_sysvar = next_account_info(account_info_iter)?;
+    let instructions_sysvar = next_account_info(account_info_iter)?;
 
-    let (expected_pda, _bump) = Pubkey::find_program_address(
-        &[b"user-stake", user_wallet.key.as_ref(), pool_account.key.as_ref()],
-        program_id,
-    );
+    if !user_wallet.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
 
-    if user_stake_info_account.key != &expected_pda {
-        return Err(StakingError::InvalidPda.into());
+    let mut stake_info = load_position(pool_account, user_stake_info_account, program_id)?;
+
+    // The signer is either the staker itself or the claimer it authorized,
+    // who may only pay into the staker's chosen destination.
+    if user_wallet.key.to_bytes() != stake_info.staker {
+        if stake_info.claimer == [0; 32] || user_wallet.key.to_bytes() != stake_info.claimer {
+            return Err(StakingError::UnauthorizedClaimer.into());
+        }
+        if user_token_account.key.to_bytes() != stake_info.claim_destination {
+            return Err(StakingError::InvalidClaimDestination.into());
+        }
     }
 
-    let mut stake_info = UserStakeInfo::try_from_slice(&user_stake_info_account.data.borrow())?;
-    let pool = StakingPool::try_from_slice(&pool_account.data.borrow())?;
-    let clock = Clock::from_account_info(clock_sysvar)?;
-    let now = clock.unix_timestamp as u64;
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
-    if now <= stake_info.last_stake_time {
-        return Err(ProgramError::InvalidArgument);
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    if reward_vault.key.to_bytes() != pool.reward_vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if token_program.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
     }
+    let vault_bump = check_vault_authority(pool_account.key, &pool, vault_authority, program_id)?;
+
+    // A position must not have been topped up earlier in this transaction,
+    // so a flash-loaned deposit cannot be staked and claimed against in one
+    // go. Stake younger than the minimum stake age earns nothing anyway.
+    let clock = Clock::from_account_info(clock_sysvar)?;
+    let now = clock.unix_timestamp as u64;
+    check_no_stake_before(instructions_sysvar, user_stake_info_account.key, program_id)?;
 
-    let duration = now - stake_info.last_stake_time;
-    let reward = duration
-        .checked_mul(pool.reward_rate)
-        .and_then(|r| r.checked_mul(stake_info.amount))
+    pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+    let matured = pool
+        .settle(&mut stake_info, now)
         .ok_or(ProgramError::InvalidArgument)?;
 
+    // A position whose only stake just matured has nothing to claim yet, but
+    // keeps the settlement so that stake starts earning.
+    let reward = stake_info.rewards;
+    if reward == 0 && matured {
+        msg!("Matured stake starts earning");
+        return Ok(());
+    }
     if reward == 0 {
         return Err(StakingError::NothingToClaim.into());
     }
 
-    let (vault_authority, vault_bump) =
-        Pubkey::find_program_address(&[b"vault-auth"], program_id);
-
     let ix = transfer(
         token_program.key,
         reward_vault.key,
         user_token_account.key,
-        &vault_authority,
+        vault_authority.key,
         &[],
         reward,
     )?;
@@ -296,13 +490,1125 @@ pub fn process_claim_rewards(
         &[
             reward_vault.clone(),
             user_token_account.clone(),
+            vault_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&vault_authority_seeds(pool_account.key, &pool, &[vault_bump])],
+    )?;
+
+    pool.committed_rewards = pool.committed_rewards.saturating_sub(reward);
+    stake_info.rewards = 0;
+
+    Ok(())
+}
+
+pub fn process_authorize_claimer(
+    accounts: &[AccountInfo],
+    claimer: Pubkey,
+    destination: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_wallet = next_account_info(account_info_iter)?;
+    let user_stake_info_account = next_account_info(account_info_iter)?;
+
+    if !user_wallet.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut stake_info = load_position(pool_account, user_stake_info_account, program_id)?;
+    if stake_info.staker != user_wallet.key.to_bytes() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    stake_info.claimer = claimer.to_bytes();
+    stake_info.claim_destination = destination.to_bytes();
+
+    msg!("Authorized claimer {} paying into {}", claimer, destination);
+    Ok(())
+}
+
+fn load_position<'a>(
+    pool_account: &AccountInfo,
+    user_stake_info_account: &'a AccountInfo,
+    program_id: &Pubkey,
+) -> Result<RefMut<'a, UserStakeInfo>, ProgramError> {
+    if user_stake_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let stake_info = UserStakeInfo::from_account_mut(user_stake_info_account)?;
+    check_position_address(
+        user_stake_info_account,
+        &stake_info,
+        &{ stake_info.staker },
+        pool_account.key,
+        program_id,
+    )?;
+
+    Ok(stake_info)
+}
+
+pub fn process_get_pending_rewards(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_stake_info_account = next_account_info(account_info_iter)?;
+
+    let stake_info = load_position(pool_account, user_stake_info_account, program_id)?;
+    let pool = StakingPool::from_account(pool_account)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+
+    let reward = pool
+        .pending_rewards_at(&stake_info, now)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    set_return_data(&borsh::to_vec(&reward)?);
+    Ok(())
+}
+
+pub fn process_get_pool_stats(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let pool = StakingPool::from_account(pool_account)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+
+    let stats = PoolStats {
+        admin: pool.admin,
+        reward_rate: pool.reward_rate,
+        total_staked: pool.total_staked,
+        emission_per_second: rewards::emission_per_second(pool.reward_rate, pool.total_staked),
+        reward_per_token: pool
+            .reward_per_token_at(now)
+            .ok_or(ProgramError::InvalidArgument)?,
+        last_update_time: pool.last_update_time,
+        timestamp: now,
+    };
+
+    set_return_data(&borsh::to_vec(&stats)?);
+    Ok(())
+}
+
+pub fn process_get_position(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_stake_info_account = next_account_info(account_info_iter)?;
+
+    let stake_info = load_position(pool_account, user_stake_info_account, program_id)?;
+    let pool = StakingPool::from_account(pool_account)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+
+    let position = PositionInfo {
+        staker: stake_info.staker,
+        amount: stake_info.amount,
+        last_stake_time: stake_info.last_stake_time,
+        pending_rewards: pool
+            .pending_rewards_at(&stake_info, now)
+            .ok_or(ProgramError::InvalidArgument)?,
+        timestamp: now,
+    };
+
+    set_return_data(&borsh::to_vec(&position)?);
+    Ok(())
+}
+
+/// Advances the pool's reward accumulator. Anyone may call it; when the tip
+/// accounts are passed and time has moved on, the caller is paid the pool's
+/// `crank_tip` out of its tip vault.
+pub fn process_update_pool(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+    let tip_due = pool.crank_tip_due(now);
+    pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+
+    let tip_accounts = (
+        account_info_iter.next(),
+        account_info_iter.next(),
+        account_info_iter.next(),
+        account_info_iter.next(),
+    );
+    let (Some(tip_vault), Some(cranker_token_account), Some(vault_authority), Some(token_program)) =
+        tip_accounts
+    else {
+        return Ok(());
+    };
+
+    if !tip_due || pool.crank_tip == 0 {
+        return Ok(());
+    }
+
+    if tip_vault.key.to_bytes() != pool.tip_vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if token_program.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let vault_bump = check_vault_authority(pool_account.key, &pool, vault_authority, program_id)?;
+
+    // Pay what is left rather than failing once the vault runs dry.
+    let balance = spl_token::state::Account::unpack(&tip_vault.data.borrow())?.amount;
+    let tip = pool.crank_tip.min(balance);
+    if tip == 0 {
+        return Ok(());
+    }
+
+    let ix = transfer(
+        token_program.key,
+        tip_vault.key,
+        cranker_token_account.key,
+        vault_authority.key,
+        &[],
+        tip,
+    )?;
+
+    invoke_signed(
+        &ix,
+        &[
+            tip_vault.clone(),
+            cranker_token_account.clone(),
+            vault_authority.clone(),
             token_program.clone(),
         ],
-        &[&[b"vault-auth", &[vault_bump]]],
+        &[&vault_authority_seeds(pool_account.key, &pool, &[vault_bump])],
     )?;
 
-    stake_info.last_stake_time = now;
-    stake_info.serialize(&mut &mut user_stake_info_account.data.borrow_mut()[..])?;
+    msg!("Pool updated to {}, paid crank tip {}", now, tip);
+    Ok(())
+}
+
+pub fn process_set_crank_tip(
+    accounts: &[AccountInfo],
+    crank_tip: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+    let tip_vault = next_account_info(account_info_iter)?;
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    pool.check_unlocked()?;
+    check_tip_vault(tip_vault, pool_account.key, &pool, program_id)?;
+
+    pool.tip_vault = tip_vault.key.to_bytes();
+    pool.crank_tip = crank_tip;
+
+    msg!("Crank tip set to {} from {}", crank_tip, tip_vault.key);
+    Ok(())
+}
+
+pub fn process_set_stake_limits(
+    accounts: &[AccountInfo],
+    limits: StakeLimits,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    pool.check_unlocked()?;
+
+    pool.set_limits(&limits)?;
+
+    msg!("Stake limits updated");
+    Ok(())
+}
+
+pub fn process_set_reward_rate(
+    accounts: &[AccountInfo],
+    reward_rate: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    pool.check_unlocked()?;
+
+    let now = Clock::get()?.unix_timestamp as u64;
+    apply_action(
+        &mut pool,
+        pool_account.key,
+        AdminAction::SetRewardRate { reward_rate },
+        None,
+        now,
+        program_id,
+    )?;
 
+    msg!("Reward rate set to {}", reward_rate);
     Ok(())
 }
+
+/// Sets the pool's timelock. Only possible directly while the pool has none;
+/// afterwards changing it has to wait out the current delay like any other
+/// admin action.
+pub fn process_set_timelock_delay(
+    accounts: &[AccountInfo],
+    timelock_delay: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    pool.check_unlocked()?;
+
+    pool.timelock_delay = timelock_delay;
+
+    msg!("Timelock delay set to {}", timelock_delay);
+    Ok(())
+}
+
+/// Schedules `action` to become executable once the pool's timelock delay has
+/// passed. A pool has at most one queued action at a time.
+pub fn process_queue_action(
+    accounts: &[AccountInfo],
+    action: AdminAction,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+    let queued_change_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    let pool = load_admin_pool(pool_account, admin_account, program_id)?;
+
+    let (expected_pda, bump) = Pubkey::find_program_address(
+        &[b"queued-change", pool_account.key.as_ref()],
+        program_id,
+    );
+    if queued_change_account.key != &expected_pda {
+        return Err(StakingError::InvalidPda.into());
+    }
+    if !queued_change_account.data_is_empty() {
+        return Err(StakingError::ActionAlreadyQueued.into());
+    }
+
+    let now = Clock::get()?.unix_timestamp as u64;
+    let queued_change = QueuedChange {
+        discriminator: QueuedChange::DISCRIMINATOR,
+        version: QueuedChange::VERSION,
+        pool: pool_account.key.to_bytes(),
+        eta: now.saturating_add(pool.timelock_delay),
+        action,
+    };
+    let data = borsh::to_vec(&queued_change)?;
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            admin_account.key,
+            &expected_pda,
+            rent.minimum_balance(data.len()),
+            data.len() as u64,
+            program_id,
+        ),
+        &[
+            admin_account.clone(),
+            queued_change_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[b"queued-change", pool_account.key.as_ref(), &[bump]]],
+    )?;
+    queued_change_account.data.borrow_mut().copy_from_slice(&data);
+
+    msg!("Queued {:?} until {}", queued_change.action, queued_change.eta);
+    Ok(())
+}
+
+pub fn process_cancel_action(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+    let queued_change_account = next_account_info(account_info_iter)?;
+
+    load_admin_pool(pool_account, admin_account, program_id)?;
+    let queued_change = load_queued_change(pool_account, queued_change_account, program_id)?;
+    close_account(queued_change_account, admin_account);
+
+    msg!("Cancelled {:?}", queued_change.action);
+    Ok(())
+}
+
+/// Applies the pool's queued action once its eta has passed. Anyone may call
+/// it; the queued change's rent goes back to the admin.
+pub fn process_execute_action(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+    let queued_change_account = next_account_info(account_info_iter)?;
+    let tip_vault = account_info_iter.next();
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    if pool.admin != admin_account.key.to_bytes() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let queued_change = load_queued_change(pool_account, queued_change_account, program_id)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+    if now < queued_change.eta {
+        return Err(StakingError::TimelockNotExpired.into());
+    }
+
+    apply_action(
+        &mut pool,
+        pool_account.key,
+        queued_change.action.clone(),
+        tip_vault,
+        now,
+        program_id,
+    )?;
+    close_account(queued_change_account, admin_account);
+
+    msg!("Executed {:?}", queued_change.action);
+    Ok(())
+}
+
+/// Turns emergency mode on or off. Not subject to the timelock, so the admin
+/// can react at once.
+pub fn process_set_emergency_mode(
+    accounts: &[AccountInfo],
+    enabled: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    pool.emergency_mode = enabled as u8;
+
+    msg!("Emergency mode {}", if enabled { "on" } else { "off" });
+    Ok(())
+}
+
+/// Returns a position's whole principal and closes it, forfeiting any rewards.
+/// Only available in emergency mode, and deliberately touches no reward
+/// state, so it keeps working when the reward math does not.
+pub fn process_emergency_withdraw(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let user_wallet = next_account_info(account_info_iter)?;
+    let user_token_account = next_account_info(account_info_iter)?;
+    let user_stake_info_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !user_wallet.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    if pool.emergency_mode == 0 {
+        return Err(StakingError::EmergencyModeOff.into());
+    }
+    if vault_account.key.to_bytes() != pool.vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let stake_info = load_position(pool_account, user_stake_info_account, program_id)?;
+    if stake_info.staker != user_wallet.key.to_bytes() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    if token_program.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let vault_bump = check_vault_authority(pool_account.key, &pool, vault_authority, program_id)?;
+
+    let amount = stake_info.amount;
+    if amount > 0 {
+        let ix = transfer(
+            token_program.key,
+            vault_account.key,
+            user_token_account.key,
+            vault_authority.key,
+            &[],
+            amount,
+        )?;
+
+        invoke_signed(
+            &ix,
+            &[
+                vault_account.clone(),
+                user_token_account.clone(),
+                vault_authority.clone(),
+                token_program.clone(),
+            ],
+            &[&vault_authority_seeds(pool_account.key, &pool, &[vault_bump])],
+        )?;
+    }
+
+    // Release what the pool had committed to the position up to the last
+    // update. If the reward math has broken, it simply stays committed.
+    let forfeited = penalty::forfeited_rewards(
+        amount,
+        stake_info.rewards,
+        pool.reward_per_token,
+        stake_info.reward_per_token_paid,
+    );
+    if let Some(forfeited) = forfeited {
+        pool.committed_rewards = pool.committed_rewards.saturating_sub(forfeited);
+    }
+    pool.total_staked = pool.total_staked.saturating_sub(amount);
+    let forfeited = forfeited.unwrap_or(stake_info.rewards);
+    drop(stake_info);
+    close_account(user_stake_info_account, user_wallet);
+
+    msg!("Emergency withdrew {} tokens, forfeiting {} in rewards", amount, forfeited);
+    Ok(())
+}
+
+/// Moves `amount` out of a token account owned by the pool's vault authority,
+/// such as tokens sent to a vault by mistake. The vault always keeps
+/// `total_staked` and the reward vault the rewards committed so far, so
+/// recovery never reaches stakers' funds and is not held up by the timelock.
+pub fn process_recover_tokens(
+    accounts: &[AccountInfo],
+    amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+    let source = next_account_info(account_info_iter)?;
+    let destination = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    let pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    // The program-wide authority also owns other pools' vaults.
+    if pool.legacy_vault_authority != 0 {
+        return Err(StakingError::LegacyVaultAuthority.into());
+    }
+    if token_program.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let vault_bump = check_vault_authority(pool_account.key, &pool, vault_authority, program_id)?;
+    let balance = check_vault_account(source, vault_authority.key)?.amount;
+
+    let mut locked = 0u64;
+    if source.key.to_bytes() == pool.vault {
+        locked = pool.total_staked;
+    }
+    if source.key.to_bytes() == pool.reward_vault {
+        let committed = pool
+            .committed_rewards_at(Clock::get()?.unix_timestamp as u64)
+            .ok_or(ProgramError::InvalidArgument)?;
+        locked = locked.saturating_add(committed);
+    }
+    if amount > balance.saturating_sub(locked) {
+        return Err(StakingError::RecoveryExceedsExcess.into());
+    }
+
+    let ix = transfer(
+        token_program.key,
+        source.key,
+        destination.key,
+        vault_authority.key,
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &ix,
+        &[
+            source.clone(),
+            destination.clone(),
+            vault_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&vault_authority_seeds(pool_account.key, &pool, &[vault_bump])],
+    )?;
+
+    msg!("Recovered {} tokens from {}", amount, source.key);
+    Ok(())
+}
+
+/// Fails unless the vault covers `total_staked` and the reward vault covers
+/// the rewards committed up to now. Anyone may call it, so monitoring can
+/// simulate it and other programs can prepend it to their transactions.
+pub fn process_assert_invariants(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let reward_vault = next_account_info(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let pool = StakingPool::from_account(pool_account)?;
+    if vault_account.key.to_bytes() != pool.vault
+        || reward_vault.key.to_bytes() != pool.reward_vault
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let vault_authority = vault_authority_address(pool_account.key, &pool, program_id)?;
+    let vault_balance = check_vault_account(vault_account, &vault_authority)?.amount;
+    let mut reward_balance = check_vault_account(reward_vault, &vault_authority)?.amount;
+    let committed = pool
+        .committed_rewards_at(Clock::get()?.unix_timestamp as u64)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    if vault_balance < pool.total_staked {
+        return Err(StakingError::VaultUnderfunded.into());
+    }
+    // A pool paying rewards out of its vault needs both on top of each other.
+    if pool.vault == pool.reward_vault {
+        reward_balance -= pool.total_staked;
+    }
+    if reward_balance < committed {
+        return Err(StakingError::RewardVaultUnderfunded.into());
+    }
+
+    msg!(
+        "Vault holds {} of {} staked, reward vault {} of {} committed",
+        vault_balance,
+        { pool.total_staked },
+        reward_balance,
+        committed
+    );
+    Ok(())
+}
+
+/// Upgrades an account written by an older build of the program to the
+/// current layout in place, growing it if needed. Anyone may pay for this,
+/// since the account's contents carry over unchanged apart from the PDA
+/// bumps later layouts store, which are derived rather than supplied.
+pub fn process_migrate_account(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let pool = account_info_iter.next();
+
+    if account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let data = account.data.borrow();
+    let migrated = if data.len() == BASELINE_LEN && data.iter().any(|byte| *byte != 0) {
+        // First-release pools and positions share a length, so the caller
+        // says which it is by passing the account itself as the pool.
+        let pool = pool.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if pool.key == account.key {
+            let vault = next_account_info(account_info_iter)?;
+            let reward_vault = next_account_info(account_info_iter)?;
+            let vault_claim = next_account_info(account_info_iter)?;
+            let reward_vault_claim = next_account_info(account_info_iter)?;
+            migrate_baseline_pool(
+                account,
+                &data,
+                payer,
+                system_program,
+                [(vault, vault_claim), (reward_vault, reward_vault_claim)],
+                program_id,
+            )?
+        } else {
+            migrate_baseline_position(account, &data, pool, program_id)?
+        }
+    } else {
+        let location = AccountLocation {
+            address: account.key,
+            pool: pool.map(|pool| pool.key),
+            program_id,
+        };
+        migrate(&data, &location)?
+    };
+    drop(data);
+
+    let required = Rent::get()?.minimum_balance(migrated.len());
+    if account.lamports() < required {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, required - account.lamports()),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    account.realloc(migrated.len(), false)?;
+    account.data.borrow_mut().copy_from_slice(&migrated);
+
+    msg!("Migrated account {}", account.key);
+    Ok(())
+}
+
+/// Upgrades a pool written by the first release, which recorded no vaults, so
+/// its admin names them, each with the `VaultClaim` that binds it to this
+/// pool alone. The pool must sign: it was created from a keypair, while a
+/// position of the same length is a PDA and cannot.
+fn migrate_baseline_pool<'a>(
+    pool_account: &AccountInfo<'a>,
+    data: &[u8],
+    admin_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    vaults: [(&AccountInfo<'a>, &AccountInfo<'a>); 2],
+    program_id: &Pubkey,
+) -> Result<Vec<u8>, ProgramError> {
+    if !pool_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let baseline = BaselinePool::try_from_slice(data)?;
+    if baseline.admin != *admin_account.key {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let (vault_authority, vault_authority_bump) =
+        Pubkey::find_program_address(&[b"vault-auth"], program_id);
+    let [(vault_account, _), (reward_vault, _)] = vaults;
+    check_vault_account(vault_account, &vault_authority)?;
+    check_vault_account(reward_vault, &vault_authority)?;
+
+    // A pool paying rewards out of its stake vault claims it once.
+    let claims = if reward_vault.key == vault_account.key { &vaults[..1] } else { &vaults[..] };
+    for (token_account, claim_account) in claims {
+        claim_vault(
+            pool_account.key,
+            token_account,
+            claim_account,
+            admin_account,
+            system_program,
+            program_id,
+        )?;
+    }
+
+    let pool = StakingPool::from_baseline(
+        &baseline,
+        vault_account.key,
+        reward_vault.key,
+        vault_authority_bump,
+        Clock::get()?.unix_timestamp as u64,
+    );
+    Ok(bytemuck::bytes_of(&pool).to_vec())
+}
+
+/// Creates the `VaultClaim` binding `token_account` to the pool, failing if
+/// another pool already claimed it.
+fn claim_vault<'a>(
+    pool_key: &Pubkey,
+    token_account: &AccountInfo<'a>,
+    claim_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (expected_pda, bump) =
+        Pubkey::find_program_address(&[b"vault-claim", token_account.key.as_ref()], program_id);
+    if claim_account.key != &expected_pda {
+        return Err(StakingError::InvalidPda.into());
+    }
+    if !claim_account.data_is_empty() {
+        return Err(StakingError::VaultAlreadyClaimed.into());
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            &expected_pda,
+            Rent::get()?.minimum_balance(VaultClaim::LEN),
+            VaultClaim::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), claim_account.clone(), system_program.clone()],
+        &[&[b"vault-claim", token_account.key.as_ref(), &[bump]]],
+    )?;
+    VaultClaim {
+        discriminator: VaultClaim::DISCRIMINATOR,
+        version: VaultClaim::VERSION,
+        pool: pool_key.to_bytes(),
+    }
+    .write_to(&mut claim_account.data.borrow_mut())
+}
+
+/// Upgrades a position written by the first release, crediting what it had
+/// earned and adding its stake to `pool_account`, which must already be
+/// migrated.
+fn migrate_baseline_position(
+    account: &AccountInfo,
+    data: &[u8],
+    pool_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<Vec<u8>, ProgramError> {
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let baseline = BaselinePosition::try_from_slice(data)?;
+    let (address, bump) = Pubkey::find_program_address(
+        &[b"user-stake", baseline.staker.as_ref(), pool_account.key.as_ref()],
+        program_id,
+    );
+    if address != *account.key {
+        return Err(StakingError::InvalidPda.into());
+    }
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+    pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+    let position = UserStakeInfo::from_baseline(&baseline, bump, &pool, now)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    pool.total_staked = pool
+        .total_staked
+        .checked_add(position.amount)
+        .ok_or(ProgramError::InvalidArgument)?;
+    pool.committed_rewards = pool
+        .committed_rewards
+        .checked_add(position.rewards)
+        .ok_or(ProgramError::InvalidArgument)?;
+    Ok(bytemuck::bytes_of(&position).to_vec())
+}
+
+/// Hands the pool's vault and reward vault from the program-wide vault
+/// authority to the pool's own. Pools migrated from the first release keep
+/// working without this, but share their authority with every other such
+/// pool until they run it. Each account must carry the `VaultClaim` binding
+/// it to this pool, so no pool can take over another's vault. Legacy pools
+/// have no tip vault, since `SetCrankTip` refuses them. Not subject to the
+/// timelock, since no tokens move.
+pub fn process_migrate_vault_authority(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let reward_vault = next_account_info(account_info_iter)?;
+    let vault_claim = next_account_info(account_info_iter)?;
+    let reward_vault_claim = next_account_info(account_info_iter)?;
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    if pool.legacy_vault_authority == 0 {
+        return Err(StakingError::VaultAuthorityAlreadyMigrated.into());
+    }
+    if token_program.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let legacy_bump = check_vault_authority(pool_account.key, &pool, vault_authority, program_id)?;
+    let (new_authority, new_bump) =
+        Pubkey::find_program_address(&[b"vault-auth", pool_account.key.as_ref()], program_id);
+
+    let mut moved: Vec<Pubkey> = Vec::new();
+    let passed = [
+        (pool.vault, vault_account, vault_claim),
+        (pool.reward_vault, reward_vault, reward_vault_claim),
+    ];
+    for (expected_key, account, claim_account) in passed {
+        if account.key.to_bytes() != expected_key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if moved.contains(account.key) {
+            continue;
+        }
+        check_vault_claim(pool_account.key, account, claim_account, program_id)?;
+        check_vault_account(account, vault_authority.key)?;
+
+        let ix = spl_token::instruction::set_authority(
+            token_program.key,
+            account.key,
+            Some(&new_authority),
+            spl_token::instruction::AuthorityType::AccountOwner,
+            vault_authority.key,
+            &[],
+        )?;
+        invoke_signed(
+            &ix,
+            &[account.clone(), vault_authority.clone(), token_program.clone()],
+            &[&vault_authority_seeds(pool_account.key, &pool, &[legacy_bump])],
+        )?;
+        moved.push(*account.key);
+    }
+
+    pool.vault_authority_bump = new_bump;
+    pool.legacy_vault_authority = 0;
+
+    msg!("Moved {} token accounts to vault authority {}", moved.len(), new_authority);
+    Ok(())
+}
+
+fn apply_action(
+    pool: &mut StakingPool,
+    pool_key: &Pubkey,
+    action: AdminAction,
+    tip_vault: Option<&AccountInfo>,
+    now: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    match action {
+        AdminAction::SetRewardRate { reward_rate } => {
+            // Settle what accrued at the old rate before switching.
+            pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+            pool.reward_rate = reward_rate;
+        }
+        AdminAction::SetCrankTip {
+            tip_vault: expected_tip_vault,
+            crank_tip,
+        } => {
+            let tip_vault = tip_vault.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if tip_vault.key != &expected_tip_vault {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            check_tip_vault(tip_vault, pool_key, pool, program_id)?;
+
+            pool.tip_vault = tip_vault.key.to_bytes();
+            pool.crank_tip = crank_tip;
+        }
+        AdminAction::SetStakeLimits { limits } => pool.set_limits(&limits)?,
+        AdminAction::SetTimelockDelay { timelock_delay } => pool.timelock_delay = timelock_delay,
+    }
+    Ok(())
+}
+
+/// Loads the pool, checking that `admin_account` is its admin and signed.
+fn load_admin_pool<'a>(
+    pool_account: &'a AccountInfo,
+    admin_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<RefMut<'a, StakingPool>, ProgramError> {
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !admin_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pool = StakingPool::from_account_mut(pool_account)?;
+    if pool.admin != admin_account.key.to_bytes() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    Ok(pool)
+}
+
+fn load_queued_change(
+    pool_account: &AccountInfo,
+    queued_change_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<QueuedChange, ProgramError> {
+    let (expected_pda, _bump) = Pubkey::find_program_address(
+        &[b"queued-change", pool_account.key.as_ref()],
+        program_id,
+    );
+    if queued_change_account.key != &expected_pda {
+        return Err(StakingError::InvalidPda.into());
+    }
+    if queued_change_account.data_is_empty() {
+        return Err(StakingError::NoQueuedAction.into());
+    }
+    if queued_change_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    QueuedChange::load(&queued_change_account.data.borrow())
+}
+
+/// Seeds of the pool's vault authority with its `bump`: vault-auth and the
+/// pool, or vault-auth alone for pools still on the program-wide authority.
+fn vault_authority_seeds<'a>(
+    pool_key: &'a Pubkey,
+    pool: &StakingPool,
+    bump: &'a [u8],
+) -> Vec<&'a [u8]> {
+    if pool.legacy_vault_authority != 0 {
+        vec![b"vault-auth", bump]
+    } else {
+        vec![b"vault-auth", pool_key.as_ref(), bump]
+    }
+}
+
+/// The pool's vault authority, the PDA that owns its token accounts, derived
+/// from the bump stored on the pool.
+fn vault_authority_address(
+    pool_key: &Pubkey,
+    pool: &StakingPool,
+    program_id: &Pubkey,
+) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(
+        &vault_authority_seeds(pool_key, pool, &[pool.vault_authority_bump]),
+        program_id,
+    )
+    .map_err(|_| StakingError::InvalidPda.into())
+}
+
+/// Checks that `vault_authority` is the pool's vault authority and returns
+/// its bump.
+fn check_vault_authority(
+    pool_key: &Pubkey,
+    pool: &StakingPool,
+    vault_authority: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<u8, ProgramError> {
+    if *vault_authority.key != vault_authority_address(pool_key, pool, program_id)? {
+        return Err(StakingError::InvalidPda.into());
+    }
+    Ok(pool.vault_authority_bump)
+}
+
+/// Checks that `user_stake_info_account` is the PDA of a position held by
+/// `staker` in the pool, derived from the bump stored on the position.
+fn check_position_address(
+    user_stake_info_account: &AccountInfo,
+    stake_info: &UserStakeInfo,
+    staker: &[u8],
+    pool_key: &Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let expected_pda = Pubkey::create_program_address(
+        &[b"user-stake", staker, pool_key.as_ref(), &[stake_info.bump]],
+        program_id,
+    )
+    .map_err(|_| StakingError::InvalidPda)?;
+    if user_stake_info_account.key != &expected_pda {
+        return Err(StakingError::InvalidPda.into());
+    }
+    Ok(())
+}
+
+/// Checks that `claim_account` is the `VaultClaim` of `token_account` and
+/// names the pool.
+fn check_vault_claim(
+    pool_key: &Pubkey,
+    token_account: &AccountInfo,
+    claim_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (expected_pda, _bump) =
+        Pubkey::find_program_address(&[b"vault-claim", token_account.key.as_ref()], program_id);
+    if claim_account.key != &expected_pda {
+        return Err(StakingError::InvalidPda.into());
+    }
+    if claim_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if VaultClaim::from_account(claim_account)?.pool != pool_key.to_bytes() {
+        return Err(StakingError::VaultAlreadyClaimed.into());
+    }
+    Ok(())
+}
+
+/// Unpacks `account`, checking that it is a token account owned by
+/// `vault_authority`.
+fn check_vault_account(
+    account: &AccountInfo,
+    vault_authority: &Pubkey,
+) -> Result<spl_token::state::Account, ProgramError> {
+    if account.owner != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let token_account = spl_token::state::Account::unpack(&account.data.borrow())?;
+    if token_account.owner != *vault_authority {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(token_account)
+}
+
+/// Checks that `tip_vault` can fund crank tips: a token account of the
+/// pool's own vault authority that is neither its vault nor its reward vault,
+/// or tips would be paid out of stake or committed rewards.
+fn check_tip_vault(
+    tip_vault: &AccountInfo,
+    pool_key: &Pubkey,
+    pool: &StakingPool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    // The program-wide authority also owns other pools' vaults.
+    if pool.legacy_vault_authority != 0 {
+        return Err(StakingError::LegacyVaultAuthority.into());
+    }
+    let key = tip_vault.key.to_bytes();
+    if key == pool.vault || key == pool.reward_vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    check_vault_account(tip_vault, &vault_authority_address(pool_key, pool, program_id)?)?;
+    Ok(())
+}
+
+/// Fails if an instruction before the current one in this transaction is a
+/// `Stake` into `position`.
+fn check_no_stake_before(
+    instructions: &AccountInfo,
+    position: &Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let current = load_current_index_checked(instructions)?;
+
+    for index in 0..current {
+        let ix = load_instruction_at_checked(index as usize, instructions)?;
+        if ix.program_id == *program_id
+            && matches!(
+                StakingInstruction::try_from_slice(&ix.data),
+                Ok(StakingInstruction::Stake { .. })
+            )
+            && ix.accounts.iter().any(|meta| meta.pubkey == *position)
+        {
+            return Err(StakingError::StakedInSameTransaction.into());
+        }
+    }
+    Ok(())
+}
+
+fn close_account(account: &AccountInfo, destination: &AccountInfo) {
+    **destination.lamports.borrow_mut() += **account.lamports.borrow();
+    **account.lamports.borrow_mut() = 0;
+    account.data.borrow_mut().fill(0);
+}
@@ -1,15 +1,672 @@
+use crate::error::StakingError;
 use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
+use shank::{ShankAccount, ShankType};
+use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+use staking_core::{crank, limits::DepositLimits, lock, rewards, timestamp};
+use std::cell::{Ref, RefMut};
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+/// Bytes every account starts with: an 8-byte discriminator naming its type,
+/// followed by the version of its layout.
+pub const ACCOUNT_HEADER_LEN: usize = 8 + 1;
+
+/// An account owned by the program, tagged with a discriminator and a layout
+/// version so one account type cannot be passed off as another.
+pub trait ProgramAccount: Sized {
+    /// First 8 bytes of `sha256("account:<TypeName>")`.
+    const DISCRIMINATOR: [u8; 8];
+    /// Layout version written by this build of the program.
+    const VERSION: u8;
+
+    /// Checks the discriminator and layout version at the start of `data`.
+    fn check_header(data: &[u8]) -> Result<(), ProgramError> {
+        if data.len() < ACCOUNT_HEADER_LEN || data[..8] != Self::DISCRIMINATOR {
+            return Err(StakingError::InvalidAccountDiscriminator.into());
+        }
+        if data[8] != Self::VERSION {
+            return Err(StakingError::AccountVersionMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// Rewrites `body`, the data after the header of an account saved at
+    /// layout `version`, into the current layout. Returns `None` if `body` is
+    /// not a `Self` at that version.
+    fn upgrade(
+        version: u8,
+        body: &[u8],
+        location: &AccountLocation,
+    ) -> Result<Option<Vec<u8>>, ProgramError>;
+
+    /// `body` behind the current header, followed by `appended`.
+    fn with_header(body: &[u8], appended: &[u8]) -> Vec<u8> {
+        let mut upgraded = Self::DISCRIMINATOR.to_vec();
+        upgraded.push(Self::VERSION);
+        upgraded.extend_from_slice(body);
+        upgraded.extend_from_slice(appended);
+        upgraded
+    }
+}
+
+/// Where an account being migrated lives, for layouts that store the bump of
+/// a PDA derived from it.
+pub struct AccountLocation<'a> {
+    pub address: &'a Pubkey,
+    /// Pool a position belongs to, which positions do not record themselves.
+    pub pool: Option<&'a Pubkey>,
+    pub program_id: &'a Pubkey,
+}
+
+/// A `ProgramAccount` read and written in place rather than deserialized.
+///
+/// Implementors are `#[repr(C, packed)]`, which lays them out byte for byte
+/// like their borsh encoding, so switching to in-place access changed no
+/// account data. Fields of a packed struct cannot be borrowed: copy them out
+/// with `{ pool.total_staked }` where a reference would be taken, as in
+/// `assert_eq!` or `msg!`.
+pub trait ZeroCopyAccount: ProgramAccount + Pod {
+    fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        Self::check_header(data)?;
+        bytemuck::try_from_bytes(data).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    fn load_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        Self::check_header(data)?;
+        bytemuck::try_from_bytes_mut(data).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Borrows `account`'s data as `Self`.
+    fn from_account<'a>(account: &'a AccountInfo) -> Result<Ref<'a, Self>, ProgramError> {
+        let data = account.try_borrow_data()?;
+        Self::load(&data)?;
+        Ok(Ref::map(data, |data| bytemuck::from_bytes(data)))
+    }
+
+    /// Mutably borrows `account`'s data as `Self`. Changes land in the
+    /// account as they are made, with nothing to write back.
+    fn from_account_mut<'a>(account: &'a AccountInfo) -> Result<RefMut<'a, Self>, ProgramError> {
+        let data = account.try_borrow_mut_data()?;
+        Self::load(&data)?;
+        Ok(RefMut::map(data, |data| bytemuck::from_bytes_mut(data)))
+    }
+
+    /// Writes `self` over a newly allocated account.
+    fn write_to(&self, data: &mut [u8]) -> Result<(), ProgramError> {
+        if data.len() != size_of::<Self>() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        data.copy_from_slice(bytemuck::bytes_of(self));
+        Ok(())
+    }
+}
+
+/// Rewrites an account's data into the current layout of its type. Accounts
+/// written by the first release have no header and need more than their own
+/// data to upgrade; see `BaselinePool` and `BaselinePosition`.
+pub fn migrate(data: &[u8], location: &AccountLocation) -> Result<Vec<u8>, ProgramError> {
+    if data.len() >= ACCOUNT_HEADER_LEN {
+        let (version, body) = (data[8], &data[ACCOUNT_HEADER_LEN..]);
+        let upgraded = match data[..8].try_into() {
+            Ok(StakingPool::DISCRIMINATOR) => {
+                Some(upgrade_tagged::<StakingPool>(version, body, location))
+            }
+            Ok(UserStakeInfo::DISCRIMINATOR) => {
+                Some(upgrade_tagged::<UserStakeInfo>(version, body, location))
+            }
+            Ok(QueuedChange::DISCRIMINATOR) => {
+                Some(upgrade_tagged::<QueuedChange>(version, body, location))
+            }
+            Ok(VaultClaim::DISCRIMINATOR) => {
+                Some(upgrade_tagged::<VaultClaim>(version, body, location))
+            }
+            _ => None,
+        };
+        if let Some(upgraded) = upgraded {
+            return upgraded;
+        }
+    }
+    if data.iter().all(|byte| *byte == 0) {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Err(ProgramError::InvalidAccountData)
+}
+
+/// Size of the untagged pools and positions written by the first release,
+/// which share a length and so cannot be told apart by their data alone.
+pub const BASELINE_LEN: usize = 32 + 8 + 8;
+
+/// A pool as the first release wrote it. It recorded no vaults, and its
+/// token accounts are owned by the program-wide vault authority.
+#[derive(BorshDeserialize, Debug)]
+pub struct BaselinePool {
+    pub admin: Pubkey,
+    pub reward_rate: u64,
+    pub total_staked: u64,
+}
+
+/// A position as the first release wrote it, at the same PDA positions use
+/// now. It earned `reward_rate` per token for every second since
+/// `last_stake_time`, paid out at the pool's rate when claimed.
+#[derive(BorshDeserialize, Debug)]
+pub struct BaselinePosition {
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub last_stake_time: u64,
+}
+
+fn upgrade_tagged<T: ProgramAccount>(
+    version: u8,
+    body: &[u8],
+    location: &AccountLocation,
+) -> Result<Vec<u8>, ProgramError> {
+    if version == T::VERSION {
+        return Err(StakingError::AccountAlreadyMigrated.into());
+    }
+    T::upgrade(version, body, location)?.ok_or(StakingError::AccountVersionMismatch.into())
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, ShankAccount)]
 pub struct StakingPool {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    #[idl_type(Pubkey)]
     pub admin: [u8; 32],
     pub reward_rate: u64,
     pub total_staked: u64,
+    /// Rewards earned by one staked token since the pool was created.
+    pub reward_per_token: u128,
+    /// Unix time `reward_per_token` was last advanced to.
+    pub last_update_time: u64,
+    /// Token account owned by the vault authority that pays `UpdatePool` tips,
+    /// all zeroes if cranking is not rewarded.
+    #[idl_type(Pubkey)]
+    pub tip_vault: [u8; 32],
+    /// Paid to whoever advances the accumulator, capped by the tip vault balance.
+    pub crank_tip: u64,
+    /// Cap on `total_staked`, or zero for no cap.
+    pub max_total_stake: u64,
+    /// Cap on a single position's `amount`, or zero for no cap.
+    pub max_stake_per_user: u64,
+    /// Smallest amount a single `Stake` may deposit.
+    pub min_stake_amount: u64,
+    /// Unix time deposits open at.
+    pub stake_start: u64,
+    /// Unix time deposits close at, or zero if they never close.
+    pub stake_end: u64,
+    /// Seconds a queued admin action waits before it can execute. While
+    /// non-zero, admin actions can only go through `QueueAction`.
+    pub timelock_delay: u64,
+    /// Lets stakers pull their principal out with `EmergencyWithdraw`,
+    /// forfeiting rewards, and stops new stakes. A `u8` flag, since `bool`
+    /// is not `Pod`.
+    #[idl_type(bool)]
+    pub emergency_mode: u8,
+    /// Token account owned by the vault authority that holds the stake.
+    #[idl_type(Pubkey)]
+    pub vault: [u8; 32],
+    /// Token account owned by the vault authority that rewards are paid from,
+    /// possibly the same as `vault`.
+    #[idl_type(Pubkey)]
+    pub reward_vault: [u8; 32],
+    /// Rewards stakers earned up to `last_update_time` and have not claimed.
+    pub committed_rewards: u64,
+    /// Seconds a deposit must stay staked before it starts earning rewards.
+    pub min_stake_age: u64,
+    /// Bump of the vault authority PDA, so handlers can check it without
+    /// searching for it again.
+    pub vault_authority_bump: u8,
+    /// Set on pools whose token accounts are still owned by the program-wide
+    /// vault authority, seeded by vault-auth alone, that every pool used
+    /// before each got its own. Cleared by `MigrateVaultAuthority`.
+    #[idl_type(bool)]
+    pub legacy_vault_authority: u8,
+}
+
+impl ProgramAccount for StakingPool {
+    const DISCRIMINATOR: [u8; 8] = [203, 19, 214, 220, 220, 154, 24, 102];
+    const VERSION: u8 = 3;
+
+    fn upgrade(
+        version: u8,
+        body: &[u8],
+        location: &AccountLocation,
+    ) -> Result<Option<Vec<u8>>, ProgramError> {
+        // Version 2 appended `vault_authority_bump` and version 3
+        // `legacy_vault_authority`.
+        let current = Self::LEN - ACCOUNT_HEADER_LEN;
+        match version {
+            1 if body.len() == current - 2 => {
+                let (_, bump) = Pubkey::find_program_address(
+                    &[b"vault-auth", location.address.as_ref()],
+                    location.program_id,
+                );
+                Ok(Some(Self::with_header(body, &[bump, 0])))
+            }
+            2 if body.len() == current - 1 => Ok(Some(Self::with_header(body, &[0]))),
+            _ => Ok(None),
+        }
+    }
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+impl ZeroCopyAccount for StakingPool {}
+
+// The packed layout must stay identical to the borsh one accounts were
+// written in.
+const _: () = assert!(size_of::<StakingPool>() == StakingPool::LEN);
+
+impl StakingPool {
+    pub const LEN: usize = ACCOUNT_HEADER_LEN
+        + 32 + 8 + 8 + 16 + 8 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 1 + 32 + 32 + 8 + 8 + 1 + 1;
+
+    /// Upgrades a pool written by the first release, which pays from `vault`
+    /// and `reward_vault` under the program-wide vault authority whose bump is
+    /// `vault_authority_bump`. Its stake is left out of `total_staked` until
+    /// each position is migrated, since what positions earned before then is
+    /// only known to them.
+    pub fn from_baseline(
+        baseline: &BaselinePool,
+        vault: &Pubkey,
+        reward_vault: &Pubkey,
+        vault_authority_bump: u8,
+        now: u64,
+    ) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            admin: baseline.admin.to_bytes(),
+            reward_rate: baseline.reward_rate,
+            total_staked: 0,
+            reward_per_token: 0,
+            last_update_time: now,
+            tip_vault: [0; 32],
+            crank_tip: 0,
+            max_total_stake: 0,
+            max_stake_per_user: 0,
+            min_stake_amount: 0,
+            stake_start: 0,
+            stake_end: 0,
+            timelock_delay: 0,
+            emergency_mode: 0,
+            vault: vault.to_bytes(),
+            reward_vault: reward_vault.to_bytes(),
+            committed_rewards: 0,
+            min_stake_age: 0,
+            vault_authority_bump,
+            legacy_vault_authority: 1,
+        }
+    }
+
+    /// Fails if admin actions have to be queued rather than applied directly.
+    pub fn check_unlocked(&self) -> Result<(), StakingError> {
+        if self.timelock_delay != 0 {
+            return Err(StakingError::TimelockActive);
+        }
+        Ok(())
+    }
+
+    pub fn set_limits(&mut self, limits: &StakeLimits) -> Result<(), StakingError> {
+        DepositLimits {
+            stake_start: timestamp(limits.stake_start),
+            stake_end: timestamp(limits.stake_end),
+            ..DepositLimits::default()
+        }
+        .validate()?;
+
+        self.max_total_stake = limits.max_total_stake;
+        self.max_stake_per_user = limits.max_stake_per_user;
+        self.min_stake_amount = limits.min_stake_amount;
+        self.stake_start = limits.stake_start;
+        self.stake_end = limits.stake_end;
+        self.min_stake_age = limits.min_stake_age;
+        Ok(())
+    }
+
+    /// Checks that a deposit of `amount` at `now` into a position already
+    /// holding `position_amount` stays within the pool's limits.
+    pub fn check_stake(&self, amount: u64, position_amount: u64, now: u64) -> Result<(), StakingError> {
+        if self.emergency_mode != 0 {
+            return Err(StakingError::PoolInEmergency);
+        }
+        self.deposit_limits()
+            .check_deposit(amount, position_amount, self.total_staked, timestamp(now))?;
+        Ok(())
+    }
+
+    fn deposit_limits(&self) -> DepositLimits {
+        DepositLimits {
+            max_total_stake: self.max_total_stake,
+            max_stake_per_user: self.max_stake_per_user,
+            min_stake_amount: self.min_stake_amount,
+            stake_start: timestamp(self.stake_start),
+            stake_end: timestamp(self.stake_end),
+        }
+    }
+
+    /// Moves everything `position` earned up to the pool's accumulator into
+    /// its rewards, including what its warming tranche earned since maturing,
+    /// and releases what the pool committed to the tranche before that. The
+    /// tranche earns like the rest of the stake from then on if it is old
+    /// enough at `now`. The pool must be updated first. Returns whether the
+    /// tranche started earning, or `None` on overflow.
+    pub fn settle(&mut self, position: &mut UserStakeInfo, now: u64) -> Option<bool> {
+        let (forfeited, matured_rewards) =
+            self.split_warming(position, self.reward_per_token, now)?;
+        position.rewards = position
+            .pending_rewards(self.reward_per_token)?
+            .checked_add(matured_rewards)?;
+        position.reward_per_token_paid = self.reward_per_token;
+        self.committed_rewards = self.committed_rewards.saturating_sub(forfeited);
+
+        let matured = position.warming_amount != 0
+            && !lock::is_warming(
+                timestamp(position.last_stake_time),
+                timestamp(self.min_stake_age),
+                timestamp(now),
+            );
+        if matured {
+            position.warming_amount = 0;
+        }
+        Some(matured)
+    }
+
+    /// Rewards `position` could claim if the pool were updated and the
+    /// position settled at `now`. Returns `None` on overflow.
+    pub fn pending_rewards_at(&self, position: &UserStakeInfo, now: u64) -> Option<u64> {
+        let reward_per_token = self.reward_per_token_at(now)?;
+        let (_, matured_rewards) = self.split_warming(position, reward_per_token, now)?;
+        position.pending_rewards(reward_per_token)?.checked_add(matured_rewards)
+    }
+
+    /// Splits the rewards `position`'s warming tranche saw up to
+    /// `reward_per_token` at `now` into those from before it matured, which
+    /// it forfeits, and those from after, which it earned.
+    fn split_warming(
+        &self,
+        position: &UserStakeInfo,
+        reward_per_token: u128,
+        now: u64,
+    ) -> Option<(u64, u64)> {
+        let split = lock::maturity_reward_per_token(
+            position.reward_per_token_paid,
+            reward_per_token,
+            self.reward_rate,
+            lock::matures_at(timestamp(position.last_stake_time), timestamp(self.min_stake_age)),
+            timestamp(now),
+        );
+        Some((
+            position.warming_rewards(split)?,
+            rewards::earned(position.warming_amount, reward_per_token, split)?,
+        ))
+    }
+
+    /// Whether cranking the pool at `now` earns the crank tip.
+    pub fn crank_tip_due(&self, now: u64) -> bool {
+        crank::tip_due(timestamp(self.last_update_time), timestamp(now))
+    }
+
+    /// `reward_per_token` as it would be if the pool were updated at `now`.
+    /// Returns `None` on overflow.
+    pub fn reward_per_token_at(&self, now: u64) -> Option<u128> {
+        rewards::reward_per_token_at(
+            self.reward_per_token,
+            self.reward_rate,
+            timestamp(self.last_update_time),
+            timestamp(now),
+        )
+    }
+
+    /// `committed_rewards` as it would be if the pool were updated at `now`.
+    /// Returns `None` on overflow.
+    pub fn committed_rewards_at(&self, now: u64) -> Option<u64> {
+        let accrued = rewards::earned(
+            self.total_staked,
+            self.reward_per_token_at(now)?,
+            self.reward_per_token,
+        )?;
+
+        accrued.checked_add(self.committed_rewards)
+    }
+
+    /// Advances the accumulator to `now`, returning whether any time passed.
+    pub fn update(&mut self, now: u64) -> Option<bool> {
+        if now <= self.last_update_time {
+            return Some(false);
+        }
+
+        self.committed_rewards = self.committed_rewards_at(now)?;
+        self.reward_per_token = self.reward_per_token_at(now)?;
+        self.last_update_time = now;
+        Some(true)
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, ShankAccount)]
 pub struct UserStakeInfo {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    #[idl_type(Pubkey)]
+    pub staker: [u8; 32],
+    pub amount: u64,
+    pub last_stake_time: u64,
+    /// Key allowed to claim on the staker's behalf, all zeroes if none.
+    #[idl_type(Pubkey)]
+    pub claimer: [u8; 32],
+    /// Token account that delegated claims must pay into.
+    #[idl_type(Pubkey)]
+    pub claim_destination: [u8; 32],
+    /// Pool `reward_per_token` at the last settlement.
+    pub reward_per_token_paid: u128,
+    /// Rewards settled but not yet claimed.
+    pub rewards: u64,
+    /// Bump of this position's PDA.
+    pub bump: u8,
+    /// Part of `amount` staked at `last_stake_time` that earns nothing until
+    /// it is the pool's `min_stake_age` old. A top-up while it is still
+    /// warming joins it, moving `last_stake_time` to the two deposits' times
+    /// weighted by amount.
+    pub warming_amount: u64,
+}
+
+impl ProgramAccount for UserStakeInfo {
+    const DISCRIMINATOR: [u8; 8] = [219, 233, 236, 123, 28, 113, 89, 56];
+    const VERSION: u8 = 3;
+
+    fn upgrade(
+        version: u8,
+        body: &[u8],
+        location: &AccountLocation,
+    ) -> Result<Option<Vec<u8>>, ProgramError> {
+        // Version 2 appended `bump` and version 3 `warming_amount`, which
+        // starts out empty so existing stake keeps earning.
+        let current = Self::LEN - ACCOUNT_HEADER_LEN;
+        match version {
+            1 if body.len() == current - 9 => {
+                let pool = location.pool.ok_or(ProgramError::NotEnoughAccountKeys)?;
+                let (address, bump) = Pubkey::find_program_address(
+                    &[b"user-stake", &body[..32], pool.as_ref()],
+                    location.program_id,
+                );
+                if address != *location.address {
+                    return Err(StakingError::InvalidPda.into());
+                }
+                Ok(Some(Self::with_header(body, &[bump, 0, 0, 0, 0, 0, 0, 0, 0])))
+            }
+            2 if body.len() == current - 8 => Ok(Some(Self::with_header(body, &[0; 8]))),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl ZeroCopyAccount for UserStakeInfo {}
+
+const _: () = assert!(size_of::<UserStakeInfo>() == UserStakeInfo::LEN);
+
+impl UserStakeInfo {
+    pub const LEN: usize = ACCOUNT_HEADER_LEN + 32 + 8 + 8 + 32 + 32 + 16 + 8 + 1 + 8;
+
+    /// Upgrades a position written by the first release into `pool`, which
+    /// must be updated to `now` first. It is credited what the first release
+    /// would have paid had it claimed at `now` and earns through the pool's
+    /// accumulator from then on. Returns `None` on overflow.
+    pub fn from_baseline(
+        baseline: &BaselinePosition,
+        bump: u8,
+        pool: &StakingPool,
+        now: u64,
+    ) -> Option<Self> {
+        let owed = rewards::reward_per_token_at(
+            0,
+            pool.reward_rate,
+            timestamp(baseline.last_stake_time),
+            timestamp(now),
+        )?;
+
+        Some(Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            staker: baseline.staker.to_bytes(),
+            amount: baseline.amount,
+            last_stake_time: baseline.last_stake_time,
+            claimer: [0; 32],
+            claim_destination: [0; 32],
+            reward_per_token_paid: pool.reward_per_token,
+            rewards: rewards::earned(baseline.amount, owed, 0)?,
+            bump,
+            warming_amount: 0,
+        })
+    }
+
+    /// Rewards claimable once the pool's accumulator reaches
+    /// `reward_per_token`. Returns `None` on overflow.
+    pub fn pending_rewards(&self, reward_per_token: u128) -> Option<u64> {
+        let earning = self.amount - self.warming_amount;
+        rewards::earned(earning, reward_per_token, self.reward_per_token_paid)?
+            .checked_add(self.rewards)
+    }
+
+    /// Rewards the pool commits to the warming tranche until the accumulator
+    /// reaches `reward_per_token`. Returns `None` on overflow.
+    pub fn warming_rewards(&self, reward_per_token: u128) -> Option<u64> {
+        rewards::earned(self.warming_amount, reward_per_token, self.reward_per_token_paid)
+    }
+}
+
+/// Admin action waiting out the pool's timelock, stored at the PDA seeded by
+/// `queued-change` and the pool. It is closed once executed or cancelled.
+#[derive(BorshSerialize, BorshDeserialize, Debug, ShankAccount)]
+pub struct QueuedChange {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    #[idl_type(Pubkey)]
+    pub pool: [u8; 32],
+    /// Unix time from which `ExecuteAction` may apply `action`.
+    pub eta: u64,
+    pub action: AdminAction,
+}
+
+impl ProgramAccount for QueuedChange {
+    const DISCRIMINATOR: [u8; 8] = [178, 209, 212, 240, 49, 65, 50, 3];
+    const VERSION: u8 = 1;
+
+    fn upgrade(
+        _version: u8,
+        _body: &[u8],
+        _location: &AccountLocation,
+    ) -> Result<Option<Vec<u8>>, ProgramError> {
+        // Queued changes have only ever been written at version 1.
+        Ok(None)
+    }
+}
+
+impl QueuedChange {
+    /// Deserializes `data`, checking its discriminator and layout version.
+    pub fn load(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::check_header(data)?;
+        Ok(Self::try_from_slice(data)?)
+    }
+}
+
+/// Marks a token account of the program-wide vault authority as belonging to
+/// one pool migrated from the first release, stored at the PDA seeded by
+/// `vault-claim` and the token account. The first release recorded no vaults
+/// and let every pool pay out of any of them, so this is what stops a second
+/// pool from naming a vault another already took.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, ShankAccount)]
+pub struct VaultClaim {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    #[idl_type(Pubkey)]
+    pub pool: [u8; 32],
+}
+
+impl ProgramAccount for VaultClaim {
+    const DISCRIMINATOR: [u8; 8] = [4, 165, 43, 219, 187, 176, 163, 230];
+    const VERSION: u8 = 1;
+
+    fn upgrade(
+        _version: u8,
+        _body: &[u8],
+        _location: &AccountLocation,
+    ) -> Result<Option<Vec<u8>>, ProgramError> {
+        // Vault claims have only ever been written at version 1.
+        Ok(None)
+    }
+}
+
+impl ZeroCopyAccount for VaultClaim {}
+
+const _: () = assert!(size_of::<VaultClaim>() == VaultClaim::LEN);
+
+impl VaultClaim {
+    pub const LEN: usize = ACCOUNT_HEADER_LEN + 32;
+}
+
+/// Pool configuration changes that can be queued behind the timelock, each
+/// mirroring the admin instruction of the same name.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, ShankType)]
+pub enum AdminAction {
+    SetRewardRate { reward_rate: u64 },
+    SetCrankTip { tip_vault: Pubkey, crank_tip: u64 },
+    SetStakeLimits { limits: StakeLimits },
+    SetTimelockDelay { timelock_delay: u64 },
+}
+
+/// Deposit limits passed to `InitializePool` and `SetStakeLimits`, plus how
+/// long a deposit must sit before it earns rewards. The default places no
+/// limits on staking.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq, ShankType)]
+pub struct StakeLimits {
+    pub max_total_stake: u64,
+    pub max_stake_per_user: u64,
+    pub min_stake_amount: u64,
+    pub stake_start: u64,
+    pub stake_end: u64,
+    pub min_stake_age: u64,
+}
+
+/// Returned by `GetPoolStats` through `set_return_data`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, ShankType)]
+pub struct PoolStats {
+    #[idl_type(Pubkey)]
+    pub admin: [u8; 32],
+    pub reward_rate: u64,
+    pub total_staked: u64,
+    pub emission_per_second: u64,
+    pub reward_per_token: u128,
+    pub last_update_time: u64,
+    pub timestamp: u64,
+}
+
+/// Returned by `GetPosition` through `set_return_data`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, ShankType)]
+pub struct PositionInfo {
+    #[idl_type(Pubkey)]
     pub staker: [u8; 32],
     pub amount: u64,
     pub last_stake_time: u64,
+    pub pending_rewards: u64,
+    pub timestamp: u64,
 }
@@ -0,0 +1,287 @@
+//! Loads SBF builds of the program into `solana-program-test`, and runs one
+//! through a pool's life to report the compute units each instruction uses,
+//! for `compute_units.rs` and the budget check in `staking.rs`. Also holds
+//! the `send` and `warp` helpers both test crates share.
+
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    bpf_loader,
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_instruction, sysvar,
+    transaction::{Transaction, TransactionError},
+};
+use staking_contract::{
+    instruction::StakingInstruction,
+    state::{StakeLimits, StakingPool},
+};
+use std::path::PathBuf;
+
+/// The SBF build under test: `STAKING_PROGRAM_SO` if set, such as the
+/// `staking_pinocchio` build, otherwise this crate's, looked up where
+/// `cargo build-sbf` and `cargo test-sbf` leave it.
+pub fn program_so() -> Option<PathBuf> {
+    if let Some(so) = std::env::var_os("STAKING_PROGRAM_SO") {
+        return Some(PathBuf::from(so));
+    }
+    ["SBF_OUT_DIR", "BPF_OUT_DIR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .map(PathBuf::from)
+        .chain([PathBuf::from("target/deploy")])
+        .map(|dir| dir.join("staking_contract.so"))
+        .find(|path| path.exists())
+}
+
+/// Deploys the SBF program `so` at `program_id`.
+pub fn add_sbf_program(program_test: &mut ProgramTest, program_id: Pubkey, so: Vec<u8>) {
+    program_test.add_account(
+        program_id,
+        Account {
+            lamports: Rent::default().minimum_balance(so.len()),
+            data: so,
+            owner: bpf_loader::id(),
+            executable: true,
+            rent_epoch: 0,
+        },
+    );
+}
+
+/// Sends `ixs` signed by the payer and `signers`, failing with the error of
+/// the first instruction that fails.
+pub async fn send(
+    context: &mut ProgramTestContext,
+    ixs: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let tx = Transaction::new_signed_with_payer(
+        ixs,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context
+        .banks_client
+        .process_transaction(tx)
+        .await
+        .map_err(|err| err.unwrap())
+}
+
+/// Moves the clock `seconds` forward.
+pub async fn warp(context: &mut ProgramTestContext, seconds: i64) {
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += seconds;
+    context.set_sysvar(&clock);
+}
+
+/// Compute units `ix` consumes on its own. The instruction is landed unless
+/// it is a view.
+async fn units(context: &mut ProgramTestContext, ix: Instruction, view: bool) -> u64 {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        std::slice::from_ref(&ix),
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        blockhash,
+    );
+
+    let simulation = context.banks_client.simulate_transaction(tx).await.unwrap();
+    simulation.result.unwrap().unwrap();
+    if !view {
+        send(context, &[ix], &[]).await.unwrap();
+    }
+    simulation.simulation_details.unwrap().units_consumed
+}
+
+/// Runs a pool through its life with the program built as `so`, returning
+/// the compute units of each step.
+pub async fn measure(so: Vec<u8>) -> Vec<(&'static str, u64)> {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::default();
+    add_sbf_program(&mut program_test, program_id, so);
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    let staking_pool = Keypair::new();
+    let pool = staking_pool.pubkey();
+    let mint = Keypair::new();
+    let vault = Keypair::new();
+    let user_token_account = Keypair::new();
+    let (vault_authority, _) =
+        Pubkey::find_program_address(&[b"vault-auth", pool.as_ref()], &program_id);
+    let (user_stake_info, _) = Pubkey::find_program_address(
+        &[b"user-stake", payer.as_ref(), pool.as_ref()],
+        &program_id,
+    );
+
+    let mut setup_ixs = vec![
+        system_instruction::create_account(
+            &payer,
+            &pool,
+            rent.minimum_balance(StakingPool::LEN),
+            StakingPool::LEN as u64,
+            &program_id,
+        ),
+        system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer, None, 6)
+            .unwrap(),
+    ];
+    for (account, owner) in [(&vault, vault_authority), (&user_token_account, payer)] {
+        setup_ixs.push(system_instruction::create_account(
+            &payer,
+            &account.pubkey(),
+            rent.minimum_balance(spl_token::state::Account::LEN),
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ));
+        setup_ixs.push(
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &account.pubkey(),
+                &mint.pubkey(),
+                &owner,
+            )
+            .unwrap(),
+        );
+    }
+    for (account, amount) in [(&user_token_account, 1_000), (&vault, 1_000_000)] {
+        setup_ixs.push(
+            spl_token::instruction::mint_to(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &account.pubkey(),
+                &payer,
+                &[],
+                amount,
+            )
+            .unwrap(),
+        );
+    }
+    send(
+        &mut context,
+        &setup_ixs,
+        &[&staking_pool, &mint, &vault, &user_token_account],
+    )
+    .await
+    .unwrap();
+
+    let ix = |accounts: Vec<AccountMeta>, data: StakingInstruction| Instruction {
+        program_id,
+        accounts,
+        data: borsh::to_vec(&data).unwrap(),
+    };
+    let stake_ix = |amount: u64| {
+        ix(
+            vec![
+                AccountMeta::new(pool, false),
+                AccountMeta::new(vault.pubkey(), false),
+                AccountMeta::new(payer, true),
+                AccountMeta::new(user_token_account.pubkey(), false),
+                AccountMeta::new(user_stake_info, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+            ],
+            StakingInstruction::Stake { amount },
+        )
+    };
+    let position = vec![
+        AccountMeta::new_readonly(pool, false),
+        AccountMeta::new_readonly(user_stake_info, false),
+    ];
+
+    let mut report = Vec::new();
+    let init_ix = ix(
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(vault.pubkey(), false),
+            AccountMeta::new_readonly(vault.pubkey(), false),
+        ],
+        StakingInstruction::InitializePool {
+            reward_rate: 1,
+            limits: StakeLimits::default(),
+        },
+    );
+    report.push(("InitializePool", units(&mut context, init_ix, false).await));
+    report.push(("Stake (new position)", units(&mut context, stake_ix(500), false).await));
+
+    warp(&mut context, 100).await;
+    report.push(("Stake (top-up)", units(&mut context, stake_ix(100), false).await));
+
+    warp(&mut context, 100).await;
+    let update_ix = ix(
+        vec![AccountMeta::new(pool, false)],
+        StakingInstruction::UpdatePool,
+    );
+    report.push(("UpdatePool", units(&mut context, update_ix, false).await));
+
+    warp(&mut context, 100).await;
+    let claim_ix = ix(
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(vault.pubkey(), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(user_token_account.pubkey(), false),
+            AccountMeta::new(user_stake_info, false),
+            AccountMeta::new_readonly(vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ],
+        StakingInstruction::ClaimRewards,
+    );
+    report.push(("ClaimRewards", units(&mut context, claim_ix, false).await));
+
+    let unstake_ix = ix(
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(vault.pubkey(), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(user_token_account.pubkey(), false),
+            AccountMeta::new(user_stake_info, false),
+            AccountMeta::new_readonly(vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        StakingInstruction::UnStake { amount: 100 },
+    );
+    report.push(("UnStake", units(&mut context, unstake_ix, false).await));
+
+    let limits_ix = ix(
+        vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(payer, true),
+        ],
+        StakingInstruction::SetStakeLimits {
+            limits: StakeLimits::default(),
+        },
+    );
+    report.push(("SetStakeLimits", units(&mut context, limits_ix, false).await));
+
+    let stats_ix = ix(
+        vec![AccountMeta::new_readonly(pool, false)],
+        StakingInstruction::GetPoolStats,
+    );
+    report.push(("GetPoolStats", units(&mut context, stats_ix, true).await));
+    let position_ix = ix(position, StakingInstruction::GetPosition);
+    report.push(("GetPosition", units(&mut context, position_ix, true).await));
+
+    report
+}
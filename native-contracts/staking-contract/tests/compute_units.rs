@@ -0,0 +1,65 @@
+//! Compute units used by each instruction when the program runs as SBF. The
+//! `processor!` tests in `staking.rs` run the program natively, where its own
+//! work is not metered, so this needs `cargo build-sbf` first. It is ignored
+//! by default and fails without the build when run with `--include-ignored`:
+//!
+//! ```sh
+//! cargo build-sbf
+//! cargo test --test compute_units -- --include-ignored --nocapture
+//! ```
+//!
+//! Point `STAKING_BASELINE_SO` at the program built from another revision with
+//! the same instructions and account layouts, such as the last one that
+//! borsh-deserialized its state, to compare the two per instruction:
+//!
+//! ```sh
+//! cargo build-sbf
+//! STAKING_BASELINE_SO=/tmp/baseline/staking_contract.so \
+//!     cargo test --test compute_units -- --include-ignored --nocapture
+//! ```
+//!
+//! `STAKING_PROGRAM_SO` swaps in another build as the current one, so this
+//! compares the `staking_pinocchio` build against this crate's:
+//!
+//! ```sh
+//! (cd ../staking-pinocchio && cargo build-sbf)
+//! cargo build-sbf
+//! STAKING_PROGRAM_SO=../staking-pinocchio/target/deploy/staking_pinocchio.so \
+//!     STAKING_BASELINE_SO=target/deploy/staking_contract.so \
+//!     cargo test --test compute_units -- --include-ignored --nocapture
+//! ```
+
+mod common;
+
+use common::{measure, program_so};
+use solana_program_test::tokio;
+
+#[tokio::test]
+#[ignore = "needs `cargo build-sbf` first, then run with `--include-ignored`"]
+async fn test_compute_units() {
+    let so = program_so().expect(
+        "no SBF build of staking_contract: run `cargo build-sbf` or set STAKING_PROGRAM_SO",
+    );
+
+    let so = std::fs::read(so).unwrap();
+    println!("binary size: {} bytes", so.len());
+    let current = measure(so).await;
+    let Some(baseline_so) = std::env::var_os("STAKING_BASELINE_SO") else {
+        println!("{:<22} {:>8}", "instruction", "CUs");
+        for (name, units) in &current {
+            println!("{name:<22} {units:>8}");
+        }
+        return;
+    };
+
+    let baseline_so = std::fs::read(baseline_so).unwrap();
+    println!("baseline binary size: {} bytes", baseline_so.len());
+    let baseline = measure(baseline_so).await;
+    println!("{:<22} {:>8} {:>8} {:>8}", "instruction", "baseline", "CUs", "saved");
+    for ((name, before), (_, after)) in baseline.iter().zip(&current) {
+        println!("{name:<22} {before:>8} {after:>8} {:>8}", *before as i64 - *after as i64);
+    }
+    for ((name, before), (_, after)) in baseline.iter().zip(&current) {
+        assert!(after <= before, "{name} went from {before} to {after} CUs");
+    }
+}
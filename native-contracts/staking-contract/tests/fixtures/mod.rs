@@ -0,0 +1,448 @@
+//! Builders for what the staking tests need on chain: mints, funded token
+//! accounts, initialized pools and staked users, plus clock warps. The test
+//! payer is the mint authority and the admin of every pool built here.
+
+use borsh::BorshDeserialize;
+use solana_program_test::*;
+use solana_sdk::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, system_program, sysvar,
+    transaction::{Transaction, TransactionError},
+};
+use staking_contract::{
+    error::StakingError,
+    instruction::StakingInstruction,
+    state::{StakeLimits, StakingPool, UserStakeInfo, ZeroCopyAccount},
+};
+
+pub use crate::common::{send, warp};
+
+/// Lamports each new staker gets to pay for its position.
+const STAKER_LAMPORTS: u64 = 100_000_000;
+
+/// What `send` returns when instruction `index` fails with `error`.
+pub fn rejected(index: u8, error: StakingError) -> Result<(), TransactionError> {
+    failed(index, InstructionError::Custom(error as u32))
+}
+
+pub fn failed(index: u8, error: InstructionError) -> Result<(), TransactionError> {
+    Err(TransactionError::InstructionError(index, error))
+}
+
+pub async fn now(context: &mut ProgramTestContext) -> u64 {
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp as u64
+}
+
+pub async fn token_balance(context: &mut ProgramTestContext, account: &Pubkey) -> u64 {
+    let account = context
+        .banks_client
+        .get_account(*account)
+        .await
+        .unwrap()
+        .unwrap();
+    spl_token::state::Account::unpack(&account.data).unwrap().amount
+}
+
+/// Creates a mint with the payer as its mint authority.
+pub async fn create_mint(context: &mut ProgramTestContext) -> Pubkey {
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let mint = Keypair::new();
+
+    let ixs = [
+        system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer, None, 6)
+            .unwrap(),
+    ];
+    send(context, &ixs, &[&mint]).await.unwrap();
+    mint.pubkey()
+}
+
+/// Creates a token account of `mint` owned by `owner` and mints `amount`
+/// into it.
+pub async fn create_token_account(
+    context: &mut ProgramTestContext,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+) -> Pubkey {
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let account = Keypair::new();
+
+    let mut ixs = vec![
+        system_instruction::create_account(
+            &payer,
+            &account.pubkey(),
+            rent.minimum_balance(spl_token::state::Account::LEN),
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_account3(
+            &spl_token::id(),
+            &account.pubkey(),
+            mint,
+            owner,
+        )
+        .unwrap(),
+    ];
+    if amount > 0 {
+        ixs.push(mint_to(mint, &account.pubkey(), &payer, amount));
+    }
+    send(context, &ixs, &[&account]).await.unwrap();
+    account.pubkey()
+}
+
+pub fn mint_to(mint: &Pubkey, account: &Pubkey, authority: &Pubkey, amount: u64) -> Instruction {
+    spl_token::instruction::mint_to(&spl_token::id(), mint, account, authority, &[], amount)
+        .unwrap()
+}
+
+pub fn vault_authority(program_id: &Pubkey, pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"vault-auth", pool.as_ref()], program_id).0
+}
+
+/// Creates a mint and a token account of it owned by `pool`'s vault
+/// authority, for pools that never move tokens.
+pub async fn create_vault(
+    context: &mut ProgramTestContext,
+    program_id: &Pubkey,
+    pool: &Pubkey,
+) -> Pubkey {
+    let mint = create_mint(context).await;
+    create_token_account(context, &mint, &vault_authority(program_id, pool), 0).await
+}
+
+/// Builds an initialized pool. By default rewards are paid out of the stake
+/// vault, which starts empty.
+pub struct PoolBuilder {
+    reward_rate: u64,
+    limits: StakeLimits,
+    reward_vault: Option<u64>,
+}
+
+impl Default for PoolBuilder {
+    fn default() -> Self {
+        PoolBuilder {
+            reward_rate: 1,
+            limits: StakeLimits::default(),
+            reward_vault: None,
+        }
+    }
+}
+
+impl PoolBuilder {
+    pub fn reward_rate(mut self, reward_rate: u64) -> Self {
+        self.reward_rate = reward_rate;
+        self
+    }
+
+    pub fn limits(mut self, limits: StakeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Pays rewards out of a separate vault funded with `amount`.
+    pub fn reward_vault(mut self, amount: u64) -> Self {
+        self.reward_vault = Some(amount);
+        self
+    }
+
+    pub async fn build(self, context: &mut ProgramTestContext, program_id: &Pubkey) -> TestPool {
+        let payer = context.payer.pubkey();
+        let rent = context.banks_client.get_rent().await.unwrap();
+        let pool = Keypair::new();
+        let authority = vault_authority(program_id, &pool.pubkey());
+
+        let mint = create_mint(context).await;
+        let vault = create_token_account(context, &mint, &authority, 0).await;
+        let reward_vault = match self.reward_vault {
+            Some(amount) => create_token_account(context, &mint, &authority, amount).await,
+            None => vault,
+        };
+
+        let test_pool = TestPool {
+            program_id: *program_id,
+            address: pool.pubkey(),
+            mint,
+            vault,
+            reward_vault,
+            vault_authority: authority,
+        };
+        let ixs = [
+            system_instruction::create_account(
+                &payer,
+                &pool.pubkey(),
+                rent.minimum_balance(StakingPool::LEN),
+                StakingPool::LEN as u64,
+                program_id,
+            ),
+            test_pool.initialize_ix(&payer, self.reward_rate, self.limits),
+        ];
+        send(context, &ixs, &[&pool]).await.unwrap();
+        test_pool
+    }
+}
+
+/// A wallet with a funded token account of a pool's mint.
+pub struct Staker {
+    pub wallet: Keypair,
+    pub token_account: Pubkey,
+}
+
+impl Staker {
+    pub fn pubkey(&self) -> Pubkey {
+        self.wallet.pubkey()
+    }
+}
+
+/// An initialized pool and its accounts, with builders for its instructions.
+pub struct TestPool {
+    pub program_id: Pubkey,
+    pub address: Pubkey,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub reward_vault: Pubkey,
+    pub vault_authority: Pubkey,
+}
+
+impl TestPool {
+    /// A new wallet holding `balance` of the pool's mint.
+    pub async fn new_staker(&self, context: &mut ProgramTestContext, balance: u64) -> Staker {
+        let wallet = Keypair::new();
+        let token_account =
+            create_token_account(context, &self.mint, &wallet.pubkey(), balance).await;
+        let payer = context.payer.pubkey();
+        let fund = system_instruction::transfer(&payer, &wallet.pubkey(), STAKER_LAMPORTS);
+        send(context, &[fund], &[]).await.unwrap();
+
+        Staker {
+            wallet,
+            token_account,
+        }
+    }
+
+    /// A new wallet that holds `balance` and has staked `amount` of it.
+    pub async fn staked_user(
+        &self,
+        context: &mut ProgramTestContext,
+        balance: u64,
+        amount: u64,
+    ) -> Staker {
+        let staker = self.new_staker(context, balance).await;
+        send(context, &[self.stake_ix(&staker, amount)], &[&staker.wallet])
+            .await
+            .unwrap();
+        staker
+    }
+
+    pub fn position_address(&self, staker: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"user-stake", staker.as_ref(), self.address.as_ref()],
+            &self.program_id,
+        )
+        .0
+    }
+
+    pub async fn state(&self, context: &mut ProgramTestContext) -> StakingPool {
+        let account = context
+            .banks_client
+            .get_account(self.address)
+            .await
+            .unwrap()
+            .unwrap();
+        *StakingPool::load(&account.data).unwrap()
+    }
+
+    /// The staker's position, or `None` once it is closed.
+    pub async fn position(
+        &self,
+        context: &mut ProgramTestContext,
+        staker: &Pubkey,
+    ) -> Option<UserStakeInfo> {
+        let account = context
+            .banks_client
+            .get_account(self.position_address(staker))
+            .await
+            .unwrap()?;
+        Some(*UserStakeInfo::load(&account.data).unwrap())
+    }
+
+    /// Simulates the view instruction `ix` and decodes its return data.
+    pub async fn view<T: BorshDeserialize>(
+        &self,
+        context: &mut ProgramTestContext,
+        ix: Instruction,
+    ) -> T {
+        let blockhash = context.get_new_latest_blockhash().await.unwrap();
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            blockhash,
+        );
+
+        let simulation = context.banks_client.simulate_transaction(tx).await.unwrap();
+        simulation.result.unwrap().unwrap();
+        let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+        assert_eq!(return_data.program_id, self.program_id);
+        T::try_from_slice(&return_data.data).unwrap()
+    }
+
+    fn ix(&self, accounts: Vec<AccountMeta>, data: StakingInstruction) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: borsh::to_vec(&data).unwrap(),
+        }
+    }
+
+    /// `InitializePool` with the pool's vaults, signed by `admin`.
+    pub fn initialize_ix(
+        &self,
+        admin: &Pubkey,
+        reward_rate: u64,
+        limits: StakeLimits,
+    ) -> Instruction {
+        self.ix(
+            vec![
+                AccountMeta::new(self.address, false),
+                AccountMeta::new(*admin, true),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+                AccountMeta::new_readonly(self.vault, false),
+                AccountMeta::new_readonly(self.reward_vault, false),
+            ],
+            StakingInstruction::InitializePool {
+                reward_rate,
+                limits,
+            },
+        )
+    }
+
+    pub fn stake_ix(&self, staker: &Staker, amount: u64) -> Instruction {
+        self.ix(
+            vec![
+                AccountMeta::new(self.address, false),
+                AccountMeta::new(self.vault, false),
+                AccountMeta::new(staker.pubkey(), true),
+                AccountMeta::new(staker.token_account, false),
+                AccountMeta::new(self.position_address(&staker.pubkey()), false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+            ],
+            StakingInstruction::Stake { amount },
+        )
+    }
+
+    /// `UnStake` by `signer` out of `staker`'s position.
+    pub fn unstake_ix(&self, signer: &Staker, staker: &Pubkey, amount: u64) -> Instruction {
+        self.ix(
+            vec![
+                AccountMeta::new(self.address, false),
+                AccountMeta::new(self.vault, false),
+                AccountMeta::new(signer.pubkey(), true),
+                AccountMeta::new(signer.token_account, false),
+                AccountMeta::new(self.position_address(staker), false),
+                AccountMeta::new_readonly(self.vault_authority, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+            StakingInstruction::UnStake { amount },
+        )
+    }
+
+    /// `ClaimRewards` for `staker`'s position, signed by `claimer` and paid
+    /// into `destination`.
+    pub fn claim_ix(&self, claimer: &Pubkey, staker: &Pubkey, destination: &Pubkey) -> Instruction {
+        self.ix(
+            vec![
+                AccountMeta::new(self.address, false),
+                AccountMeta::new(self.reward_vault, false),
+                AccountMeta::new_readonly(*claimer, true),
+                AccountMeta::new(*destination, false),
+                AccountMeta::new(self.position_address(staker), false),
+                AccountMeta::new_readonly(self.vault_authority, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(sysvar::clock::id(), false),
+                AccountMeta::new_readonly(sysvar::instructions::id(), false),
+            ],
+            StakingInstruction::ClaimRewards,
+        )
+    }
+
+    /// The staker claiming its own rewards into its token account.
+    pub fn claim_own_ix(&self, staker: &Staker) -> Instruction {
+        let staker_key = staker.pubkey();
+        self.claim_ix(&staker_key, &staker_key, &staker.token_account)
+    }
+
+    /// `AuthorizeClaimer` signed by `signer` for `staker`'s position.
+    pub fn authorize_claimer_ix(
+        &self,
+        signer: &Pubkey,
+        staker: &Pubkey,
+        claimer: Pubkey,
+        destination: Pubkey,
+    ) -> Instruction {
+        self.ix(
+            vec![
+                AccountMeta::new_readonly(self.address, false),
+                AccountMeta::new_readonly(*signer, true),
+                AccountMeta::new(self.position_address(staker), false),
+            ],
+            StakingInstruction::AuthorizeClaimer {
+                claimer,
+                destination,
+            },
+        )
+    }
+
+    /// `GetPendingRewards` or `GetPosition` for `staker`'s position.
+    pub fn position_view_ix(&self, staker: &Pubkey, data: StakingInstruction) -> Instruction {
+        self.ix(
+            vec![
+                AccountMeta::new_readonly(self.address, false),
+                AccountMeta::new_readonly(self.position_address(staker), false),
+            ],
+            data,
+        )
+    }
+
+    pub fn pool_stats_ix(&self) -> Instruction {
+        self.ix(
+            vec![AccountMeta::new_readonly(self.address, false)],
+            StakingInstruction::GetPoolStats,
+        )
+    }
+
+    /// `UpdatePool` without the tip accounts.
+    pub fn update_ix(&self) -> Instruction {
+        self.ix(
+            vec![AccountMeta::new(self.address, false)],
+            StakingInstruction::UpdatePool,
+        )
+    }
+
+    /// A single-key admin instruction such as `SetStakeLimits`, signed by
+    /// `admin`.
+    pub fn admin_ix(&self, admin: &Pubkey, data: StakingInstruction) -> Instruction {
+        self.ix(
+            vec![
+                AccountMeta::new(self.address, false),
+                AccountMeta::new_readonly(*admin, true),
+            ],
+            data,
+        )
+    }
+}
@@ -5,33 +5,52 @@ use solana_sdk::{
     pubkey::Pubkey,
     system_instruction,
     sysvar,
-    instruction::AccountMeta,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    transaction::TransactionError,
+    clock::Clock,
+    program_pack::Pack,
 };
-use spl_token::{
-    instruction::initialize_mint,
-    state::Mint,
-    id as token_program_id,
-};
-use borsh::{BorshDeserialize, };
-use borsh::BorshSerialize;
-use borsh::to_vec;
+use borsh::BorshDeserialize;
+use staking_core::crank::TIP_INTERVAL;
 
 
 
 use staking_contract::{
+    error::StakingError,
     process_instruction,
-    state::{StakingPool, UserStakeInfo},
+    state::{
+        AdminAction, PoolStats, PositionInfo, ProgramAccount, QueuedChange, StakeLimits,
+        StakingPool, UserStakeInfo, VaultClaim, ZeroCopyAccount, ACCOUNT_HEADER_LEN, BASELINE_LEN,
+    },
     instruction::StakingInstruction,
 };
 
+mod common;
+mod fixtures;
+
+use fixtures::{
+    create_mint, create_token_account, create_vault, failed, now, rejected, send, token_balance,
+    vault_authority, warp, PoolBuilder, Staker, TestPool,
+};
+
+/// Starts a test validator running the program natively, or the SBF build at
+/// `STAKING_PROGRAM_SO` if set, so the same tests cover other builds such as
+/// `staking_pinocchio`.
 async fn setup_test_env() -> (ProgramTestContext, Pubkey) {
     let program_id = Pubkey::new_unique();
 
-    let program_test = ProgramTest::new(
-        "staking_contract", 
-        program_id,
-        processor!(process_instruction),
-    );
+    let program_test = match std::env::var_os("STAKING_PROGRAM_SO") {
+        Some(so) => {
+            let mut program_test = ProgramTest::default();
+            common::add_sbf_program(&mut program_test, program_id, std::fs::read(so).unwrap());
+            program_test
+        }
+        None => ProgramTest::new(
+            "staking_contract",
+            program_id,
+            processor!(process_instruction),
+        ),
+    };
 
     let context = program_test.start_with_context().await;
     (context, program_id)
@@ -39,80 +58,2539 @@ async fn setup_test_env() -> (ProgramTestContext, Pubkey) {
 
 #[tokio::test]
 async fn test_initialize_pool() {
-    let ( context, program_id) = setup_test_env().await;
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+
+    // 1. Build a pool paying rewards out of a separate vault
+    let pool = PoolBuilder::default()
+        .reward_rate(5)
+        .reward_vault(0)
+        .build(&mut context, &program_id)
+        .await;
 
-    // 1. Create a new account for the staking pool
+    // 2. Assert the data was correctly initialized
+    let state = pool.state(&mut context).await;
+    assert_eq!({ state.reward_rate }, 5);
+    assert_eq!({ state.total_staked }, 0);
+    assert_eq!({ state.last_update_time }, now(&mut context).await);
+    assert_eq!(state.admin, payer.to_bytes());
+    assert_eq!(state.vault, pool.vault.to_bytes());
+    assert_eq!(state.reward_vault, pool.reward_vault.to_bytes());
+
+    // 3. A pool cannot be initialized twice
+    let init_ix = pool.initialize_ix(&payer, 1, StakeLimits::default());
+    assert_eq!(
+        send(&mut context, &[init_ix], &[]).await,
+        rejected(0, StakingError::PoolAlreadyInitialized),
+    );
+
+    // 4. The vaults must belong to the new pool's vault authority
+    let rent = context.banks_client.get_rent().await.unwrap();
     let staking_pool = Keypair::new();
+    let other = TestPool {
+        address: staking_pool.pubkey(),
+        ..pool
+    };
+    let create_ix = system_instruction::create_account(
+        &payer,
+        &other.address,
+        rent.minimum_balance(StakingPool::LEN),
+        StakingPool::LEN as u64,
+        &program_id,
+    );
+    let init_ix = other.initialize_ix(&payer, 1, StakeLimits::default());
+    assert_eq!(
+        send(&mut context, &[create_ix, init_ix], &[&staking_pool]).await,
+        failed(1, InstructionError::InvalidAccountData),
+    );
+}
+
+#[tokio::test]
+async fn test_get_pool_stats() {
+    let (mut context, program_id) = setup_test_env().await;
+    let pool = PoolBuilder::default()
+        .reward_rate(7)
+        .build(&mut context, &program_id)
+        .await;
+
+    // 1. An empty pool emits nothing
+    let stats: PoolStats = pool.view(&mut context, pool.pool_stats_ix()).await;
+    assert_eq!(stats.admin, context.payer.pubkey().to_bytes());
+    assert_eq!(stats.reward_rate, 7);
+    assert_eq!(stats.total_staked, 0);
+    assert_eq!(stats.emission_per_second, 0);
+
+    // 2. Stats include rewards accrued since the pool was last written to,
+    // without writing them back
+    pool.staked_user(&mut context, 1_000, 400).await;
+    let staked_at = now(&mut context).await;
+    warp(&mut context, 50).await;
+
+    let stats: PoolStats = pool.view(&mut context, pool.pool_stats_ix()).await;
+    assert_eq!(stats.total_staked, 400);
+    assert_eq!(stats.emission_per_second, 7 * 400);
+    assert_eq!(stats.reward_per_token, 7 * 50);
+    assert_eq!(stats.last_update_time, staked_at);
+    assert_eq!(stats.timestamp, staked_at + 50);
+    assert_eq!({ pool.state(&mut context).await.reward_per_token }, 0);
+}
 
-    let pool_space = std::mem::size_of::<StakingPool>();
+#[tokio::test]
+async fn test_stake_unstake_and_claim() {
+    let (mut context, program_id) = setup_test_env().await;
+    let pool = PoolBuilder::default()
+        .reward_vault(1_000_000)
+        .build(&mut context, &program_id)
+        .await;
+    let pending_rewards_ix = |staker: &Staker| {
+        pool.position_view_ix(&staker.pubkey(), StakingInstruction::GetPendingRewards)
+    };
+
+    // 1. Alice stakes 500 tokens, and Bob 500 more 100 seconds later
+    let alice = pool.staked_user(&mut context, 1_000, 500).await;
+    warp(&mut context, 100).await;
+    let bob = pool.staked_user(&mut context, 1_000, 500).await;
+    warp(&mut context, 100).await;
+
+    assert_eq!(token_balance(&mut context, &pool.vault).await, 1_000);
+    assert_eq!({ pool.state(&mut context).await.total_staked }, 1_000);
+    let pending: u64 = pool.view(&mut context, pending_rewards_ix(&alice)).await;
+    assert_eq!(pending, 500 * 200);
+    let pending: u64 = pool.view(&mut context, pending_rewards_ix(&bob)).await;
+    assert_eq!(pending, 500 * 100);
+
+    // 2. Claiming pays out what the position earned, once
+    send(&mut context, &[pool.claim_own_ix(&alice)], &[&alice.wallet])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &alice.token_account).await, 500 + 500 * 200);
+    assert_eq!(
+        send(&mut context, &[pool.claim_own_ix(&alice)], &[&alice.wallet]).await,
+        rejected(0, StakingError::NothingToClaim),
+    );
+
+    // 3. Staking again tops up the existing position
+    send(&mut context, &[pool.stake_ix(&alice, 100)], &[&alice.wallet])
+        .await
+        .unwrap();
+    assert_eq!({ pool.position(&mut context, &alice.pubkey()).await.unwrap().amount }, 600);
+
+    // 4. Unstaking settles the rewards earned so far
+    assert_eq!(
+        send(&mut context, &[pool.unstake_ix(&bob, &bob.pubkey(), 501)], &[&bob.wallet]).await,
+        failed(0, InstructionError::InsufficientFunds),
+    );
+    send(&mut context, &[pool.unstake_ix(&bob, &bob.pubkey(), 200)], &[&bob.wallet])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &bob.token_account).await, 700);
+    let position = pool.position(&mut context, &bob.pubkey()).await.unwrap();
+    assert_eq!({ position.amount }, 300);
+    assert_eq!({ position.rewards }, 500 * 100);
+
+    warp(&mut context, 10).await;
+    let now = now(&mut context).await;
+    let ix = pool.position_view_ix(&bob.pubkey(), StakingInstruction::GetPosition);
+    let info: PositionInfo = pool.view(&mut context, ix).await;
+    assert_eq!(info.staker, bob.pubkey().to_bytes());
+    assert_eq!(info.amount, 300);
+    assert_eq!(info.last_stake_time, now - 110);
+    assert_eq!(info.pending_rewards, 500 * 100 + 300 * 10);
+    assert_eq!(info.timestamp, now);
+
+    // 5. A position with rewards left stays open after its principal is
+    // out, and closes once it holds nothing
+    send(&mut context, &[pool.unstake_ix(&bob, &bob.pubkey(), 300)], &[&bob.wallet])
+        .await
+        .unwrap();
+    assert!(pool.position(&mut context, &bob.pubkey()).await.is_some());
+    send(&mut context, &[pool.claim_own_ix(&bob)], &[&bob.wallet])
+        .await
+        .unwrap();
+    assert_eq!(
+        token_balance(&mut context, &bob.token_account).await,
+        1_000 + 500 * 100 + 300 * 10
+    );
+    send(&mut context, &[pool.unstake_ix(&bob, &bob.pubkey(), 0)], &[&bob.wallet])
+        .await
+        .unwrap();
+    assert!(pool.position(&mut context, &bob.pubkey()).await.is_none());
+
+    // 6. Anyone can move the accumulator forward
+    let before = pool.state(&mut context).await;
+    warp(&mut context, 10).await;
+    send(&mut context, &[pool.update_ix()], &[]).await.unwrap();
+    let after = pool.state(&mut context).await;
+    assert_eq!({ after.total_staked }, 600);
+    assert_eq!({ after.reward_per_token }, before.reward_per_token + 10);
+    assert_eq!({ after.last_update_time }, now + 10);
+}
+
+#[tokio::test]
+async fn test_authorize_claimer() {
+    let (mut context, program_id) = setup_test_env().await;
+    let pool = PoolBuilder::default()
+        .reward_vault(1_000_000)
+        .build(&mut context, &program_id)
+        .await;
+    let staker = pool.staked_user(&mut context, 100, 100).await;
+    let delegate = pool.new_staker(&mut context, 0).await;
+    let destination = create_token_account(&mut context, &pool.mint, &staker.pubkey(), 0).await;
+    let delegated_claim_ix =
+        |destination: &Pubkey| pool.claim_ix(&delegate.pubkey(), &staker.pubkey(), destination);
+    warp(&mut context, 10).await;
+
+    // 1. Nobody else can claim until the staker names them
+    assert_eq!(
+        send(&mut context, &[delegated_claim_ix(&destination)], &[&delegate.wallet]).await,
+        rejected(0, StakingError::UnauthorizedClaimer),
+    );
+
+    // 2. Only the staker can name a claimer for its position
+    let ix = pool.authorize_claimer_ix(
+        &delegate.pubkey(),
+        &staker.pubkey(),
+        delegate.pubkey(),
+        delegate.token_account,
+    );
+    assert_eq!(
+        send(&mut context, &[ix], &[&delegate.wallet]).await,
+        failed(0, InstructionError::IllegalOwner),
+    );
+    let ix = pool.authorize_claimer_ix(
+        &staker.pubkey(),
+        &staker.pubkey(),
+        delegate.pubkey(),
+        destination,
+    );
+    send(&mut context, &[ix], &[&staker.wallet]).await.unwrap();
+    let position = pool.position(&mut context, &staker.pubkey()).await.unwrap();
+    assert_eq!(position.claimer, delegate.pubkey().to_bytes());
+    assert_eq!(position.claim_destination, destination.to_bytes());
+
+    // 3. The claimer can only pay into the destination the staker chose
+    assert_eq!(
+        send(&mut context, &[delegated_claim_ix(&delegate.token_account)], &[&delegate.wallet])
+            .await,
+        rejected(0, StakingError::InvalidClaimDestination),
+    );
+    send(&mut context, &[delegated_claim_ix(&destination)], &[&delegate.wallet])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &destination).await, 100 * 10);
+
+    // 4. Naming the zero key revokes the claimer, and the staker can still
+    // claim for itself
+    let ix = pool.authorize_claimer_ix(
+        &staker.pubkey(),
+        &staker.pubkey(),
+        Pubkey::default(),
+        Pubkey::default(),
+    );
+    send(&mut context, &[ix], &[&staker.wallet]).await.unwrap();
+    warp(&mut context, 10).await;
+    assert_eq!(
+        send(&mut context, &[delegated_claim_ix(&destination)], &[&delegate.wallet]).await,
+        rejected(0, StakingError::UnauthorizedClaimer),
+    );
+    send(&mut context, &[pool.claim_own_ix(&staker)], &[&staker.wallet])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, &staker.token_account).await, 100 * 10);
+}
+
+#[tokio::test]
+async fn test_wrong_accounts_are_rejected() {
+    let (mut context, program_id) = setup_test_env().await;
+    let pool = PoolBuilder::default()
+        .reward_vault(1_000_000)
+        .build(&mut context, &program_id)
+        .await;
+    let other_pool = PoolBuilder::default().build(&mut context, &program_id).await;
+    let alice = pool.staked_user(&mut context, 1_000, 500).await;
+    let mallory = pool.new_staker(&mut context, 0).await;
+    warp(&mut context, 10).await;
+
+    // 1. Tokens only move through the pool's own vaults
+    let mut stake_ix = pool.stake_ix(&alice, 100);
+    stake_ix.accounts[1].pubkey = pool.reward_vault;
+    let mut unstake_ix = pool.unstake_ix(&alice, &alice.pubkey(), 100);
+    unstake_ix.accounts[1].pubkey = pool.reward_vault;
+    let mut claim_ix = pool.claim_own_ix(&alice);
+    claim_ix.accounts[1].pubkey = pool.vault;
+    for ix in [stake_ix, unstake_ix, claim_ix] {
+        assert_eq!(
+            send(&mut context, &[ix], &[&alice.wallet]).await,
+            failed(0, InstructionError::InvalidAccountData),
+        );
+    }
+
+    // 2. A signer cannot withdraw from someone else's position
+    assert_eq!(
+        send(&mut context, &[pool.unstake_ix(&mallory, &alice.pubkey(), 100)], &[&mallory.wallet])
+            .await,
+        rejected(0, StakingError::InvalidPda),
+    );
+
+    // 3. A position only belongs to the pool it was opened in
+    let mut claim_ix = pool.claim_own_ix(&alice);
+    claim_ix.accounts[0].pubkey = other_pool.address;
+    assert_eq!(
+        send(&mut context, &[claim_ix], &[&alice.wallet]).await,
+        rejected(0, StakingError::InvalidPda),
+    );
+
+    // 4. A copy of the pool held by another program is not the pool
+    let mut forged = context
+        .banks_client
+        .get_account(pool.address)
+        .await
+        .unwrap()
+        .unwrap();
+    forged.owner = Pubkey::new_unique();
+    let forged_pool = Pubkey::new_unique();
+    context.set_account(&forged_pool, &forged.into());
+    let mut stake_ix = pool.stake_ix(&alice, 100);
+    stake_ix.accounts[0].pubkey = forged_pool;
+    let mut unstake_ix = pool.unstake_ix(&alice, &alice.pubkey(), 100);
+    unstake_ix.accounts[0].pubkey = forged_pool;
+    for ix in [stake_ix, unstake_ix] {
+        assert_eq!(
+            send(&mut context, &[ix], &[&alice.wallet]).await,
+            failed(0, InstructionError::IncorrectProgramId),
+        );
+    }
+
+    assert_eq!(token_balance(&mut context, &alice.token_account).await, 500);
+    assert_eq!(token_balance(&mut context, &mallory.token_account).await, 0);
+    assert_eq!(token_balance(&mut context, &pool.vault).await, 500);
+    assert_eq!({ pool.position(&mut context, &alice.pubkey()).await.unwrap().amount }, 500);
+}
+
+#[tokio::test]
+async fn test_pool_cap_and_stake_window() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let pool = PoolBuilder::default()
+        .limits(StakeLimits {
+            max_total_stake: 600,
+            ..StakeLimits::default()
+        })
+        .build(&mut context, &program_id)
+        .await;
+
+    // 1. The pool-wide cap counts every position
+    pool.staked_user(&mut context, 1_000, 400).await;
+    let bob = pool.new_staker(&mut context, 1_000).await;
+    assert_eq!(
+        send(&mut context, &[pool.stake_ix(&bob, 300)], &[&bob.wallet]).await,
+        rejected(0, StakingError::PoolCapExceeded),
+    );
+    send(&mut context, &[pool.stake_ix(&bob, 200)], &[&bob.wallet])
+        .await
+        .unwrap();
+
+    // 2. A stake window has to end after it starts, and only the admin can
+    // set one
+    let limits = StakeLimits {
+        stake_start: 2_000,
+        stake_end: 2_000,
+        ..StakeLimits::default()
+    };
+    let ix = pool.admin_ix(&payer, StakingInstruction::SetStakeLimits { limits: limits.clone() });
+    assert_eq!(
+        send(&mut context, &[ix], &[]).await,
+        rejected(0, StakingError::InvalidStakeWindow),
+    );
+    let ix = pool.admin_ix(&bob.pubkey(), StakingInstruction::SetStakeLimits { limits });
+    assert_eq!(
+        send(&mut context, &[ix], &[&bob.wallet]).await,
+        failed(0, InstructionError::IllegalOwner),
+    );
+    assert_eq!({ pool.state(&mut context).await.max_total_stake }, 600);
+}
+
+#[tokio::test]
+async fn test_update_pool_pays_crank_tip() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
     let rent = context.banks_client.get_rent().await.unwrap();
-    let rent_lamports = rent.minimum_balance(pool_space);
 
-    let create_pool_account_ix = system_instruction::create_account(
-        &context.payer.pubkey(),
-        &staking_pool.pubkey(),
-        rent_lamports,
-        pool_space as u64,
+    let staking_pool = Keypair::new();
+    let mint = Keypair::new();
+    let vault = Keypair::new();
+    let tip_vault = Keypair::new();
+    let cranker_token_account = Keypair::new();
+    let (vault_authority, _) = Pubkey::find_program_address(
+        &[b"vault-auth", staking_pool.pubkey().as_ref()],
         &program_id,
     );
 
-    let create_tx = Transaction::new_signed_with_payer(
-        &[create_pool_account_ix],
-        Some(&context.payer.pubkey()),
-        &[&context.payer, &staking_pool],
+    // 1. Create the pool, a mint and the three token accounts
+    let mut create_ixs = vec![
+        system_instruction::create_account(
+            &payer,
+            &staking_pool.pubkey(),
+            rent.minimum_balance(StakingPool::LEN),
+            StakingPool::LEN as u64,
+            &program_id,
+        ),
+        system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer, None, 6)
+            .unwrap(),
+    ];
+    for (account, owner) in [
+        (&vault, vault_authority),
+        (&tip_vault, vault_authority),
+        (&cranker_token_account, payer),
+    ] {
+        create_ixs.push(system_instruction::create_account(
+            &payer,
+            &account.pubkey(),
+            rent.minimum_balance(spl_token::state::Account::LEN),
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ));
+        create_ixs.push(
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &account.pubkey(),
+                &mint.pubkey(),
+                &owner,
+            )
+            .unwrap(),
+        );
+    }
+    let tx = Transaction::new_signed_with_payer(
+        &create_ixs,
+        Some(&payer),
+        &[&context.payer, &staking_pool, &mint, &vault, &tip_vault, &cranker_token_account],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    // 2. Initialize the pool and fund a tip of 3 out of a 5 token vault
+    let config_ixs = vec![
+        Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(staking_pool.pubkey(), false),
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+                AccountMeta::new_readonly(vault.pubkey(), false),
+                AccountMeta::new_readonly(vault.pubkey(), false),
+            ],
+            data: borsh::to_vec(&StakingInstruction::InitializePool {
+                reward_rate: 4,
+                limits: StakeLimits::default(),
+            })
+            .unwrap(),
+        },
+        spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &tip_vault.pubkey(),
+            &payer,
+            &[],
+            5,
+        )
+        .unwrap(),
+        Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(staking_pool.pubkey(), false),
+                AccountMeta::new_readonly(payer, true),
+                AccountMeta::new_readonly(tip_vault.pubkey(), false),
+            ],
+            data: borsh::to_vec(&StakingInstruction::SetCrankTip { crank_tip: 3 }).unwrap(),
+        },
+    ];
+    let tx = Transaction::new_signed_with_payer(
+        &config_ixs,
+        Some(&payer),
+        &[&context.payer],
         context.last_blockhash,
     );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let update_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(staking_pool.pubkey(), false),
+            AccountMeta::new(tip_vault.pubkey(), false),
+            AccountMeta::new(cranker_token_account.pubkey(), false),
+            AccountMeta::new_readonly(vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::UpdatePool).unwrap(),
+    };
+    let pool = *StakingPool::load(
+        &context
+            .banks_client
+            .get_account(staking_pool.pubkey())
+            .await
+            .unwrap()
+            .unwrap()
+            .data,
+    )
+    .unwrap();
+
+    // 3. Crank twice at the same time once the pool has been idle for the
+    // tip interval: only the first one is tipped
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp = pool.last_update_time as i64 + TIP_INTERVAL;
+    context.set_sysvar(&clock);
+
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[update_ix.clone(), update_ix.clone()],
+        Some(&payer),
+        &[&context.payer],
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    assert_eq!(token_balance(&mut context, &cranker_token_account.pubkey()).await, 3);
+
+    // 4. A crank before the next interval passes moves the accumulator but
+    // is not tipped
+    clock.unix_timestamp += 10;
+    context.set_sysvar(&clock);
+
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        std::slice::from_ref(&update_ix),
+        Some(&payer),
+        &[&context.payer],
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    assert_eq!(token_balance(&mut context, &cranker_token_account.pubkey()).await, 3);
 
-    context.banks_client.process_transaction(create_tx).await.unwrap();
+    // 5. A full interval later, the next crank drains what is left of the
+    // tip vault
+    clock.unix_timestamp += TIP_INTERVAL;
+    context.set_sysvar(&clock);
 
-    // 2. Create the `InitializePool` instruction
-let instruction_data = borsh::to_vec(&StakingInstruction::InitializePool { reward_rate: 5 }).unwrap();
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[update_ix],
+        Some(&payer),
+        &[&context.payer],
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    assert_eq!(token_balance(&mut context, &cranker_token_account.pubkey()).await, 5);
+    assert_eq!(token_balance(&mut context, &tip_vault.pubkey()).await, 0);
 
+    let account = context
+        .banks_client
+        .get_account(staking_pool.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    let pool_after = *StakingPool::load(&account.data).unwrap();
+    let elapsed = 2 * TIP_INTERVAL as u64 + 10;
+    assert_eq!({ pool_after.reward_per_token }, 4 * elapsed as u128);
+    assert_eq!({ pool_after.last_update_time }, pool.last_update_time + elapsed);
+}
 
+#[tokio::test]
+async fn test_crank_tip_vault_is_checked() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let pool = PoolBuilder::default()
+        .reward_vault(1_000)
+        .build(&mut context, &program_id)
+        .await;
+    let tip_vault = create_token_account(&mut context, &pool.mint, &pool.vault_authority, 0).await;
+    let (queued_change, _) =
+        Pubkey::find_program_address(&[b"queued-change", pool.address.as_ref()], &program_id);
 
-    let ix = solana_sdk::instruction::Instruction {
+    let set_tip_ix = |tip_vault: Pubkey| {
+        let mut ix = pool.admin_ix(&payer, StakingInstruction::SetCrankTip { crank_tip: 1 });
+        ix.accounts.push(AccountMeta::new_readonly(tip_vault, false));
+        ix
+    };
+    let queue_ix = |tip_vault: Pubkey| Instruction {
         program_id,
         accounts: vec![
-            AccountMeta::new(staking_pool.pubkey(), false),
-            AccountMeta::new(context.payer.pubkey(), true),
-            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(pool.address, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(queued_change, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
         ],
-        data: instruction_data,
+        data: borsh::to_vec(&StakingInstruction::QueueAction {
+            action: AdminAction::SetCrankTip { tip_vault, crank_tip: 1 },
+        })
+        .unwrap(),
     };
+    let cancel_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(pool.address, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(queued_change, false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::CancelAction).unwrap(),
+    };
+    let execute_ix = |tip_vault: Pubkey| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool.address, false),
+            AccountMeta::new(payer, false),
+            AccountMeta::new(queued_change, false),
+            AccountMeta::new_readonly(tip_vault, false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::ExecuteAction).unwrap(),
+    };
+
+    // 1. Tips cannot be paid out of stake or committed rewards
+    for vault in [pool.vault, pool.reward_vault] {
+        assert_eq!(
+            send(&mut context, &[set_tip_ix(vault)], &[]).await,
+            failed(0, InstructionError::InvalidAccountData),
+        );
+    }
+    send(&mut context, &[set_tip_ix(tip_vault)], &[]).await.unwrap();
+
+    // 2. Nor once the change has to go through the timelock
+    let delay_ix =
+        pool.admin_ix(&payer, StakingInstruction::SetTimelockDelay { timelock_delay: 100 });
+    send(&mut context, &[delay_ix], &[]).await.unwrap();
+    for vault in [pool.vault, pool.reward_vault] {
+        send(&mut context, &[queue_ix(vault)], &[]).await.unwrap();
+        warp(&mut context, 100).await;
+        assert_eq!(
+            send(&mut context, &[execute_ix(vault)], &[]).await,
+            failed(0, InstructionError::InvalidAccountData),
+        );
+        send(&mut context, std::slice::from_ref(&cancel_ix), &[]).await.unwrap();
+    }
 
-    // 3. Send the instruction
+    // 3. A pool on the program-wide vault authority cannot set a tip vault,
+    // since that authority also owns other pools' vaults
+    send(&mut context, &[queue_ix(tip_vault)], &[]).await.unwrap();
+    warp(&mut context, 100).await;
+    let mut account = context.banks_client.get_account(pool.address).await.unwrap().unwrap();
+    StakingPool::load_mut(&mut account.data).unwrap().legacy_vault_authority = 1;
+    context.set_account(&pool.address, &account.into());
+    assert_eq!(
+        send(&mut context, &[execute_ix(tip_vault)], &[]).await,
+        rejected(0, StakingError::LegacyVaultAuthority),
+    );
+}
+
+#[tokio::test]
+async fn test_stake_limits() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    let staking_pool = Keypair::new();
+    let mint = Keypair::new();
+    let vault = Keypair::new();
+    let user_token_account = Keypair::new();
+    let (vault_authority, _) = Pubkey::find_program_address(
+        &[b"vault-auth", staking_pool.pubkey().as_ref()],
+        &program_id,
+    );
+
+    // 1. Create a pool taking 10 to 600 tokens per user, 1000 in total,
+    // between t=1000 and t=2000
+    let mut setup_ixs = vec![
+        system_instruction::create_account(
+            &payer,
+            &staking_pool.pubkey(),
+            rent.minimum_balance(StakingPool::LEN),
+            StakingPool::LEN as u64,
+            &program_id,
+        ),
+        system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer, None, 6)
+            .unwrap(),
+    ];
+    for (account, owner) in [(&vault, vault_authority), (&user_token_account, payer)] {
+        setup_ixs.push(system_instruction::create_account(
+            &payer,
+            &account.pubkey(),
+            rent.minimum_balance(spl_token::state::Account::LEN),
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ));
+        setup_ixs.push(
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &account.pubkey(),
+                &mint.pubkey(),
+                &owner,
+            )
+            .unwrap(),
+        );
+    }
     let tx = Transaction::new_signed_with_payer(
-        &[ix],
-        Some(&context.payer.pubkey()),
-        &[&context.payer],
+        &setup_ixs,
+        Some(&payer),
+        &[&context.payer, &staking_pool, &mint, &vault, &user_token_account],
         context.last_blockhash,
     );
+    context.banks_client.process_transaction(tx).await.unwrap();
 
+    let limits = StakeLimits {
+        max_total_stake: 1_000,
+        max_stake_per_user: 600,
+        min_stake_amount: 10,
+        stake_start: 1_000,
+        stake_end: 2_000,
+        min_stake_age: 0,
+    };
+    let init_ixs = vec![
+        Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(staking_pool.pubkey(), false),
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+                AccountMeta::new_readonly(vault.pubkey(), false),
+                AccountMeta::new_readonly(vault.pubkey(), false),
+            ],
+            data: borsh::to_vec(&StakingInstruction::InitializePool {
+                reward_rate: 1,
+                limits: limits.clone(),
+            })
+            .unwrap(),
+        },
+        spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &user_token_account.pubkey(),
+            &payer,
+            &[],
+            1_000,
+        )
+        .unwrap(),
+    ];
+    let tx = Transaction::new_signed_with_payer(
+        &init_ixs,
+        Some(&payer),
+        &[&context.payer],
+        context.last_blockhash,
+    );
     context.banks_client.process_transaction(tx).await.unwrap();
 
-    // 4. Fetch and deserialize the staking pool account
+    let (user_stake_info, _) = Pubkey::find_program_address(
+        &[b"user-stake", payer.as_ref(), staking_pool.pubkey().as_ref()],
+        &program_id,
+    );
+    let stake_ix = |amount: u64| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(staking_pool.pubkey(), false),
+            AccountMeta::new(vault.pubkey(), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(user_token_account.pubkey(), false),
+            AccountMeta::new(user_stake_info, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::Stake { amount }).unwrap(),
+    };
+
+    // 2. Each stake lands (or fails) at the given time
+    let cases = [
+        (999, 100, Some(StakingError::StakeWindowNotOpen)),
+        (1_000, 9, Some(StakingError::StakeBelowMinimum)),
+        (1_000, 500, None),
+        (1_500, 101, Some(StakingError::UserCapExceeded)),
+        (1_500, 100, None),
+        (2_000, 100, Some(StakingError::StakeWindowClosed)),
+    ];
+    for (now, amount, expected) in cases {
+        let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp = now;
+        context.set_sysvar(&clock);
+
+        let blockhash = context.get_new_latest_blockhash().await.unwrap();
+        let tx = Transaction::new_signed_with_payer(
+            &[stake_ix(amount)],
+            Some(&payer),
+            &[&context.payer],
+            blockhash,
+        );
+        let result = context.banks_client.process_transaction(tx).await;
+
+        match expected {
+            None => result.unwrap(),
+            Some(error) => assert_eq!(
+                result.unwrap_err().unwrap(),
+                TransactionError::InstructionError(0, InstructionError::Custom(error as u32)),
+            ),
+        }
+    }
+    assert_eq!(token_balance(&mut context, &vault.pubkey()).await, 600);
+
+    // 3. The pool-wide cap is checked against every position
     let account = context
         .banks_client
         .get_account(staking_pool.pubkey())
         .await
         .unwrap()
         .unwrap();
+    let mut pool = *StakingPool::load(&account.data).unwrap();
+    assert_eq!({ pool.total_staked }, 600);
+    pool.total_staked = 950;
+    assert!(matches!(
+        pool.check_stake(100, 0, 1_500),
+        Err(StakingError::PoolCapExceeded)
+    ));
+}
+
+#[tokio::test]
+async fn test_timelocked_admin_actions() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let staking_pool = Keypair::new();
+    let pool = staking_pool.pubkey();
+    let (queued_change, _) =
+        Pubkey::find_program_address(&[b"queued-change", pool.as_ref()], &program_id);
+
+    let admin_ix = |data: StakingInstruction| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(payer, true),
+        ],
+        data: borsh::to_vec(&data).unwrap(),
+    };
+    let queue_ix = |action: AdminAction| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(queued_change, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::QueueAction { action }).unwrap(),
+    };
+    let cancel_ix = |admin: Pubkey| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new(admin, true),
+            AccountMeta::new(queued_change, false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::CancelAction).unwrap(),
+    };
+    let execute_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(payer, false),
+            AccountMeta::new(queued_change, false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::ExecuteAction).unwrap(),
+    };
+
+    // 1. Create a pool and give it a 100 second timelock
+    let vault = create_vault(&mut context, &program_id, &pool).await;
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer,
+                &pool,
+                rent.minimum_balance(StakingPool::LEN),
+                StakingPool::LEN as u64,
+                &program_id,
+            ),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pool, false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                    AccountMeta::new_readonly(vault, false),
+                    AccountMeta::new_readonly(vault, false),
+                ],
+                data: borsh::to_vec(&StakingInstruction::InitializePool {
+                    reward_rate: 1,
+                    limits: StakeLimits::default(),
+                })
+                .unwrap(),
+            },
+            admin_ix(StakingInstruction::SetTimelockDelay { timelock_delay: 100 }),
+        ],
+        Some(&payer),
+        &[&context.payer, &staking_pool],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
 
-    let pool = StakingPool::try_from_slice(&account.data).unwrap();
+    // 2. Each step runs the given number of seconds after the pool was
+    // created and fails with the given error
+    let start: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let start = start.unix_timestamp;
+    let stranger = Keypair::new();
+    let custom = |error: StakingError| Some(InstructionError::Custom(error as u32));
+    let steps = [
+        (
+            0,
+            admin_ix(StakingInstruction::SetRewardRate { reward_rate: 0 }),
+            custom(StakingError::TimelockActive),
+        ),
+        (
+            0,
+            admin_ix(StakingInstruction::SetTimelockDelay { timelock_delay: 0 }),
+            custom(StakingError::TimelockActive),
+        ),
+        (0, execute_ix.clone(), custom(StakingError::NoQueuedAction)),
+        (0, queue_ix(AdminAction::SetRewardRate { reward_rate: 0 }), None),
+        (
+            0,
+            queue_ix(AdminAction::SetRewardRate { reward_rate: 7 }),
+            custom(StakingError::ActionAlreadyQueued),
+        ),
+        (99, execute_ix.clone(), custom(StakingError::TimelockNotExpired)),
+        (99, cancel_ix(stranger.pubkey()), Some(InstructionError::IllegalOwner)),
+        (99, cancel_ix(payer), None),
+        (100, execute_ix.clone(), custom(StakingError::NoQueuedAction)),
+        (100, queue_ix(AdminAction::SetRewardRate { reward_rate: 5 }), None),
+        (200, execute_ix.clone(), None),
+    ];
+    for (now, ix, expected) in steps {
+        let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp = start + now;
+        context.set_sysvar(&clock);
 
-    // 5. Assert the data was correctly initialized
-    assert_eq!(pool.reward_rate, 5);
-    assert_eq!(pool.total_staked, 0);
-    assert_eq!(pool.admin, context.payer.pubkey().to_bytes());
+        let blockhash = context.get_new_latest_blockhash().await.unwrap();
+        let mut signers = vec![&context.payer];
+        if ix.accounts.iter().any(|meta| meta.pubkey == stranger.pubkey()) {
+            signers.push(&stranger);
+        }
+        let tx = Transaction::new_signed_with_payer(&[ix], Some(&payer), &signers, blockhash);
+        let result = context.banks_client.process_transaction(tx).await;
+        let queued = now == 0 && expected.is_none();
+
+        match expected {
+            None => result.unwrap(),
+            Some(error) => assert_eq!(
+                result.unwrap_err().unwrap(),
+                TransactionError::InstructionError(0, error),
+            ),
+        }
+
+        // The queued change is readable on-chain until it executes or is cancelled
+        if queued {
+            let account = context
+                .banks_client
+                .get_account(queued_change)
+                .await
+                .unwrap()
+                .unwrap();
+            let change = QueuedChange::try_from_slice(&account.data).unwrap();
+            assert_eq!(change.eta, start as u64 + 100);
+            assert_eq!(change.action, AdminAction::SetRewardRate { reward_rate: 0 });
+        }
+    }
+
+    // 3. The queued rate applied from its eta and the queued change was closed
+    let account = context.banks_client.get_account(pool).await.unwrap().unwrap();
+    let pool_state = *StakingPool::load(&account.data).unwrap();
+    assert_eq!({ pool_state.reward_rate }, 5);
+    assert_eq!({ pool_state.last_update_time }, start as u64 + 200);
+    assert!(context
+        .banks_client
+        .get_account(queued_change)
+        .await
+        .unwrap()
+        .is_none());
 }
 
+#[tokio::test]
+async fn test_emergency_withdraw() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
 
+    let staking_pool = Keypair::new();
+    let pool = staking_pool.pubkey();
+    let mint = Keypair::new();
+    let vault = Keypair::new();
+    let user_token_account = Keypair::new();
+    let (vault_authority, _) =
+        Pubkey::find_program_address(&[b"vault-auth", pool.as_ref()], &program_id);
+    let (user_stake_info, _) = Pubkey::find_program_address(
+        &[b"user-stake", payer.as_ref(), pool.as_ref()],
+        &program_id,
+    );
 
+    let admin_ix = |admin: Pubkey, data: StakingInstruction| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(admin, true),
+        ],
+        data: borsh::to_vec(&data).unwrap(),
+    };
+    let stake_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(vault.pubkey(), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(user_token_account.pubkey(), false),
+            AccountMeta::new(user_stake_info, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::Stake { amount: 500 }).unwrap(),
+    };
+    let claim_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(vault.pubkey(), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(user_token_account.pubkey(), false),
+            AccountMeta::new(user_stake_info, false),
+            AccountMeta::new_readonly(vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::ClaimRewards).unwrap(),
+    };
+    let emergency_withdraw_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(vault.pubkey(), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(user_token_account.pubkey(), false),
+            AccountMeta::new(user_stake_info, false),
+            AccountMeta::new_readonly(vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::EmergencyWithdraw).unwrap(),
+    };
 
+    // 1. Stake 500 tokens into a fresh pool
+    let mut setup_ixs = vec![
+        system_instruction::create_account(
+            &payer,
+            &pool,
+            rent.minimum_balance(StakingPool::LEN),
+            StakingPool::LEN as u64,
+            &program_id,
+        ),
+        system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer, None, 6)
+            .unwrap(),
+    ];
+    for (account, owner) in [(&vault, vault_authority), (&user_token_account, payer)] {
+        setup_ixs.push(system_instruction::create_account(
+            &payer,
+            &account.pubkey(),
+            rent.minimum_balance(spl_token::state::Account::LEN),
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ));
+        setup_ixs.push(
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &account.pubkey(),
+                &mint.pubkey(),
+                &owner,
+            )
+            .unwrap(),
+        );
+    }
+    send(
+        &mut context,
+        &setup_ixs,
+        &[&staking_pool, &mint, &vault, &user_token_account],
+    )
+    .await
+    .unwrap();
+    send(
+        &mut context,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pool, false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                    AccountMeta::new_readonly(vault.pubkey(), false),
+                    AccountMeta::new_readonly(vault.pubkey(), false),
+                ],
+                data: borsh::to_vec(&StakingInstruction::InitializePool {
+                    reward_rate: 1,
+                    limits: StakeLimits::default(),
+                })
+                .unwrap(),
+            },
+            spl_token::instruction::mint_to(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &user_token_account.pubkey(),
+                &payer,
+                &[],
+                500,
+            )
+            .unwrap(),
+            stake_ix.clone(),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
 
+    // 2. Emergency withdrawals are only open in emergency mode
+    assert_eq!(
+        send(&mut context, std::slice::from_ref(&emergency_withdraw_ix), &[]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(StakingError::EmergencyModeOff as u32)
+        )),
+    );
+
+    // 3. Break the reward math so claiming overflows
+    send(
+        &mut context,
+        &[admin_ix(payer, StakingInstruction::SetRewardRate { reward_rate: u64::MAX })],
+        &[],
+    )
+    .await
+    .unwrap();
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += 10;
+    context.set_sysvar(&clock);
+    assert_eq!(
+        send(&mut context, &[claim_ix], &[]).await,
+        Err(TransactionError::InstructionError(0, InstructionError::InvalidArgument)),
+    );
+
+    // 4. Only the admin can switch emergency mode on, and the timelock does
+    // not delay it
+    let stranger = Keypair::new();
+    assert_eq!(
+        send(
+            &mut context,
+            &[admin_ix(stranger.pubkey(), StakingInstruction::SetEmergencyMode { enabled: true })],
+            &[&stranger],
+        )
+        .await,
+        Err(TransactionError::InstructionError(0, InstructionError::IllegalOwner)),
+    );
+    send(
+        &mut context,
+        &[
+            admin_ix(payer, StakingInstruction::SetTimelockDelay { timelock_delay: 100 }),
+            admin_ix(payer, StakingInstruction::SetEmergencyMode { enabled: true }),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        send(&mut context, &[stake_ix], &[]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(StakingError::PoolInEmergency as u32)
+        )),
+    );
+
+    // 5. The full principal comes back and the position is closed
+    send(&mut context, &[emergency_withdraw_ix], &[]).await.unwrap();
+
+    assert_eq!(token_balance(&mut context, &user_token_account.pubkey()).await, 500);
+    assert_eq!(token_balance(&mut context, &vault.pubkey()).await, 0);
+    assert!(context
+        .banks_client
+        .get_account(user_stake_info)
+        .await
+        .unwrap()
+        .is_none());
+
+    let account = context.banks_client.get_account(pool).await.unwrap().unwrap();
+    assert_eq!({ StakingPool::load(&account.data).unwrap().total_staked }, 0);
+}
 
+#[tokio::test]
+async fn test_recover_tokens() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
 
+    let staking_pool = Keypair::new();
+    let pool = staking_pool.pubkey();
+    let mint = Keypair::new();
+    let vault = Keypair::new();
+    let reward_vault = Keypair::new();
+    let stray_account = Keypair::new();
+    let user_token_account = Keypair::new();
+    let (vault_authority, _) =
+        Pubkey::find_program_address(&[b"vault-auth", pool.as_ref()], &program_id);
+    let (user_stake_info, _) = Pubkey::find_program_address(
+        &[b"user-stake", payer.as_ref(), pool.as_ref()],
+        &program_id,
+    );
 
+    let mint_to = |account: &Keypair, amount: u64| {
+        spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &account.pubkey(),
+            &payer,
+            &[],
+            amount,
+        )
+        .unwrap()
+    };
+    let recover_ix = |admin: Pubkey, source: &Keypair, amount: u64| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new_readonly(admin, true),
+            AccountMeta::new(source.pubkey(), false),
+            AccountMeta::new(user_token_account.pubkey(), false),
+            AccountMeta::new_readonly(vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::RecoverTokens { amount }).unwrap(),
+    };
+
+    // 1. Stake 500 tokens into a pool paying rewards from a separate vault
+    let mut setup_ixs = vec![
+        system_instruction::create_account(
+            &payer,
+            &pool,
+            rent.minimum_balance(StakingPool::LEN),
+            StakingPool::LEN as u64,
+            &program_id,
+        ),
+        system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer, None, 6)
+            .unwrap(),
+    ];
+    for (account, owner) in [
+        (&vault, vault_authority),
+        (&reward_vault, vault_authority),
+        (&stray_account, vault_authority),
+        (&user_token_account, payer),
+    ] {
+        setup_ixs.push(system_instruction::create_account(
+            &payer,
+            &account.pubkey(),
+            rent.minimum_balance(spl_token::state::Account::LEN),
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ));
+        setup_ixs.push(
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &account.pubkey(),
+                &mint.pubkey(),
+                &owner,
+            )
+            .unwrap(),
+        );
+    }
+    send(
+        &mut context,
+        &setup_ixs,
+        &[&staking_pool, &mint, &vault, &reward_vault, &stray_account, &user_token_account],
+    )
+    .await
+    .unwrap();
+    send(
+        &mut context,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pool, false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                    AccountMeta::new_readonly(vault.pubkey(), false),
+                    AccountMeta::new_readonly(reward_vault.pubkey(), false),
+                ],
+                data: borsh::to_vec(&StakingInstruction::InitializePool {
+                    reward_rate: 1,
+                    limits: StakeLimits::default(),
+                })
+                .unwrap(),
+            },
+            mint_to(&user_token_account, 500),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pool, false),
+                    AccountMeta::new(vault.pubkey(), false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new(user_token_account.pubkey(), false),
+                    AccountMeta::new(user_stake_info, false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                    AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                ],
+                data: borsh::to_vec(&StakingInstruction::Stake { amount: 500 }).unwrap(),
+            },
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    // 2. Tokens land in every account: 100 too many in the vault, 10_000 more
+    // rewards than the 50_000 owed after 100 seconds, and 50 in a stray account
+    send(
+        &mut context,
+        &[
+            mint_to(&vault, 100),
+            mint_to(&reward_vault, 60_000),
+            mint_to(&stray_account, 50),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+    let account = context.banks_client.get_account(pool).await.unwrap().unwrap();
+    let start = StakingPool::load(&account.data).unwrap().last_update_time;
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp = start as i64 + 100;
+    context.set_sysvar(&clock);
 
+    // 3. Only the excess over stake and committed rewards comes out
+    let stranger = Keypair::new();
+    let exceeds = Some(InstructionError::Custom(StakingError::RecoveryExceedsExcess as u32));
+    let steps = [
+        (recover_ix(stranger.pubkey(), &stray_account, 50), Some(InstructionError::IllegalOwner)),
+        (recover_ix(payer, &vault, 101), exceeds.clone()),
+        (recover_ix(payer, &vault, 100), None),
+        (recover_ix(payer, &reward_vault, 10_001), exceeds),
+        (recover_ix(payer, &reward_vault, 10_000), None),
+        (recover_ix(payer, &stray_account, 50), None),
+        (
+            recover_ix(payer, &user_token_account, 1),
+            Some(InstructionError::InvalidAccountData),
+        ),
+    ];
+    for (ix, expected) in steps {
+        let signers: &[&Keypair] = if ix.accounts[1].pubkey == stranger.pubkey() {
+            &[&stranger]
+        } else {
+            &[]
+        };
+        let result = send(&mut context, &[ix], signers).await;
 
+        match expected {
+            None => result.unwrap(),
+            Some(error) => assert_eq!(result, Err(TransactionError::InstructionError(0, error))),
+        }
+    }
+    assert_eq!(token_balance(&mut context, &user_token_account.pubkey()).await, 10_150);
 
+    // 4. What was left behind still covers the staker in full
+    send(
+        &mut context,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pool, false),
+                    AccountMeta::new(reward_vault.pubkey(), false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new(user_token_account.pubkey(), false),
+                    AccountMeta::new(user_stake_info, false),
+                    AccountMeta::new_readonly(vault_authority, false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                    AccountMeta::new_readonly(sysvar::clock::id(), false),
+                    AccountMeta::new_readonly(sysvar::instructions::id(), false),
+                ],
+                data: borsh::to_vec(&StakingInstruction::ClaimRewards).unwrap(),
+            },
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pool, false),
+                    AccountMeta::new(vault.pubkey(), false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new(user_token_account.pubkey(), false),
+                    AccountMeta::new(user_stake_info, false),
+                    AccountMeta::new_readonly(vault_authority, false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                ],
+                data: borsh::to_vec(&StakingInstruction::UnStake { amount: 500 }).unwrap(),
+            },
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(token_balance(&mut context, &user_token_account.pubkey()).await, 60_650);
+    assert_eq!(token_balance(&mut context, &vault.pubkey()).await, 0);
+    assert_eq!(token_balance(&mut context, &reward_vault.pubkey()).await, 0);
+
+    let account = context.banks_client.get_account(pool).await.unwrap().unwrap();
+    let pool_state = *StakingPool::load(&account.data).unwrap();
+    assert_eq!({ pool_state.total_staked }, 0);
+    assert_eq!({ pool_state.committed_rewards }, 0);
+}
+
+#[tokio::test]
+async fn test_assert_invariants() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    let staking_pool = Keypair::new();
+    let pool = staking_pool.pubkey();
+    let mint = Keypair::new();
+    let vault = Keypair::new();
+    let reward_vault = Keypair::new();
+    let user_token_account = Keypair::new();
+    let (vault_authority, _) =
+        Pubkey::find_program_address(&[b"vault-auth", pool.as_ref()], &program_id);
+    let (user_stake_info, _) = Pubkey::find_program_address(
+        &[b"user-stake", payer.as_ref(), pool.as_ref()],
+        &program_id,
+    );
+
+    let mint_to = |account: &Keypair, amount: u64| {
+        spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &account.pubkey(),
+            &payer,
+            &[],
+            amount,
+        )
+        .unwrap()
+    };
+    let assert_ix = |vault: &Keypair| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(pool, false),
+            AccountMeta::new_readonly(vault.pubkey(), false),
+            AccountMeta::new_readonly(reward_vault.pubkey(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::AssertInvariants).unwrap(),
+    };
+
+    // 1. Stake 500 tokens, with 1000 tokens of rewards funded
+    let mut setup_ixs = vec![
+        system_instruction::create_account(
+            &payer,
+            &pool,
+            rent.minimum_balance(StakingPool::LEN),
+            StakingPool::LEN as u64,
+            &program_id,
+        ),
+        system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer, None, 6)
+            .unwrap(),
+    ];
+    for (account, owner) in [
+        (&vault, vault_authority),
+        (&reward_vault, vault_authority),
+        (&user_token_account, payer),
+    ] {
+        setup_ixs.push(system_instruction::create_account(
+            &payer,
+            &account.pubkey(),
+            rent.minimum_balance(spl_token::state::Account::LEN),
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ));
+        setup_ixs.push(
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &account.pubkey(),
+                &mint.pubkey(),
+                &owner,
+            )
+            .unwrap(),
+        );
+    }
+    send(
+        &mut context,
+        &setup_ixs,
+        &[&staking_pool, &mint, &vault, &reward_vault, &user_token_account],
+    )
+    .await
+    .unwrap();
+    send(
+        &mut context,
+        &[
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pool, false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                    AccountMeta::new_readonly(vault.pubkey(), false),
+                    AccountMeta::new_readonly(reward_vault.pubkey(), false),
+                ],
+                data: borsh::to_vec(&StakingInstruction::InitializePool {
+                    reward_rate: 1,
+                    limits: StakeLimits::default(),
+                })
+                .unwrap(),
+            },
+            mint_to(&user_token_account, 500),
+            mint_to(&reward_vault, 1_000),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pool, false),
+                    AccountMeta::new(vault.pubkey(), false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new(user_token_account.pubkey(), false),
+                    AccountMeta::new(user_stake_info, false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                    AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                ],
+                data: borsh::to_vec(&StakingInstruction::Stake { amount: 500 }).unwrap(),
+            },
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+    let account = context.banks_client.get_account(pool).await.unwrap().unwrap();
+    let start = StakingPool::load(&account.data).unwrap().last_update_time;
+
+    // 2. The funded rewards last two seconds at 500 tokens per second
+    let underfunded = |error: StakingError| {
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(error as u32)))
+    };
+    for (now, expected) in [
+        (0, Ok(())),
+        (2, Ok(())),
+        (3, underfunded(StakingError::RewardVaultUnderfunded)),
+    ] {
+        let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp = start as i64 + now;
+        context.set_sysvar(&clock);
+
+        assert_eq!(send(&mut context, &[assert_ix(&vault)], &[]).await, expected);
+    }
+
+    // 3. Topping the reward vault up fixes it, as long as the right vaults are
+    // passed
+    send(&mut context, &[mint_to(&reward_vault, 500), assert_ix(&vault)], &[])
+        .await
+        .unwrap();
+    assert_eq!(
+        send(&mut context, &[assert_ix(&reward_vault)], &[]).await,
+        Err(TransactionError::InstructionError(0, InstructionError::InvalidAccountData)),
+    );
+
+    // 4. A vault that lost a token of stake is caught
+    let mut account = context
+        .banks_client
+        .get_account(vault.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut token_account = spl_token::state::Account::unpack(&account.data).unwrap();
+    token_account.amount = 499;
+    spl_token::state::Account::pack(token_account, &mut account.data).unwrap();
+    context.set_account(&vault.pubkey(), &account.into());
+
+    assert_eq!(
+        send(&mut context, &[assert_ix(&vault)], &[]).await,
+        underfunded(StakingError::VaultUnderfunded),
+    );
+}
+
+#[tokio::test]
+async fn test_claim_rejects_fresh_stake() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    let staking_pool = Keypair::new();
+    let pool = staking_pool.pubkey();
+    let mint = Keypair::new();
+    let vault = Keypair::new();
+    let user_token_account = Keypair::new();
+    let (vault_authority, _) =
+        Pubkey::find_program_address(&[b"vault-auth", pool.as_ref()], &program_id);
+    let (user_stake_info, _) = Pubkey::find_program_address(
+        &[b"user-stake", payer.as_ref(), pool.as_ref()],
+        &program_id,
+    );
+
+    let stake_ix = |amount: u64| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(vault.pubkey(), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(user_token_account.pubkey(), false),
+            AccountMeta::new(user_stake_info, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::Stake { amount }).unwrap(),
+    };
+    let claim_ix = Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(vault.pubkey(), false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new(user_token_account.pubkey(), false),
+            AccountMeta::new(user_stake_info, false),
+            AccountMeta::new_readonly(vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::ClaimRewards).unwrap(),
+    };
+    let warp = |context: &mut ProgramTestContext, clock: &Clock, seconds: i64| {
+        let mut clock = clock.clone();
+        clock.unix_timestamp += seconds;
+        context.set_sysvar(&clock);
+    };
+
+    // 1. Stake 500 tokens into a pool with a 60 second minimum stake age,
+    // paying rewards out of a well-funded vault
+    let mut setup_ixs = vec![
+        system_instruction::create_account(
+            &payer,
+            &pool,
+            rent.minimum_balance(StakingPool::LEN),
+            StakingPool::LEN as u64,
+            &program_id,
+        ),
+        system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer, None, 6)
+            .unwrap(),
+    ];
+    for (account, owner) in [(&vault, vault_authority), (&user_token_account, payer)] {
+        setup_ixs.push(system_instruction::create_account(
+            &payer,
+            &account.pubkey(),
+            rent.minimum_balance(spl_token::state::Account::LEN),
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ));
+        setup_ixs.push(
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &account.pubkey(),
+                &mint.pubkey(),
+                &owner,
+            )
+            .unwrap(),
+        );
+    }
+    send(
+        &mut context,
+        &setup_ixs,
+        &[&staking_pool, &mint, &vault, &user_token_account],
+    )
+    .await
+    .unwrap();
+    let mut init_ixs = vec![Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(vault.pubkey(), false),
+            AccountMeta::new_readonly(vault.pubkey(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::InitializePool {
+            reward_rate: 1,
+            limits: StakeLimits {
+                min_stake_age: 60,
+                ..StakeLimits::default()
+            },
+        })
+        .unwrap(),
+    }];
+    for (account, amount) in [(&user_token_account, 1_000), (&vault, 1_000_000)] {
+        init_ixs.push(
+            spl_token::instruction::mint_to(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &account.pubkey(),
+                &payer,
+                &[],
+                amount,
+            )
+            .unwrap(),
+        );
+    }
+    init_ixs.push(stake_ix(500));
+    send(&mut context, &init_ixs, &[]).await.unwrap();
+    let start: Clock = context.banks_client.get_sysvar().await.unwrap();
+
+    // 2. The stake earns nothing until it is 60 seconds old. Claiming then
+    // pays nothing but starts it earning
+    warp(&mut context, &start, 30);
+    assert_eq!(
+        send(&mut context, std::slice::from_ref(&claim_ix), &[]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(StakingError::NothingToClaim as u32)
+        )),
+    );
+
+    warp(&mut context, &start, 60);
+    send(&mut context, std::slice::from_ref(&claim_ix), &[]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &user_token_account.pubkey()).await, 500);
+
+    warp(&mut context, &start, 70);
+    send(&mut context, std::slice::from_ref(&claim_ix), &[]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &user_token_account.pubkey()).await, 500 + 5_000);
+
+    // 3. Without a minimum age, a stake earlier in the same transaction still
+    // blocks the claim
+    send(
+        &mut context,
+        &[Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pool, false),
+                AccountMeta::new_readonly(payer, true),
+            ],
+            data: borsh::to_vec(&StakingInstruction::SetStakeLimits {
+                limits: StakeLimits::default(),
+            })
+            .unwrap(),
+        }],
+        &[],
+    )
+    .await
+    .unwrap();
+    warp(&mut context, &start, 80);
+    assert_eq!(
+        send(&mut context, &[stake_ix(100), claim_ix.clone()], &[]).await,
+        Err(TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(StakingError::StakedInSameTransaction as u32)
+        )),
+    );
+
+    // 4. Claiming before topping up is fine
+    send(&mut context, &[claim_ix, stake_ix(100)], &[]).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_top_up_warms_only_the_new_stake() {
+    let (mut context, program_id) = setup_test_env().await;
+    let pool = PoolBuilder::default()
+        .limits(StakeLimits {
+            min_stake_age: 60,
+            ..StakeLimits::default()
+        })
+        .reward_vault(1_000_000)
+        .build(&mut context, &program_id)
+        .await;
+    let alice = pool.staked_user(&mut context, 1_000, 100).await;
+    let claim = [pool.claim_own_ix(&alice)];
+
+    // 1. Alice's first 100 tokens start earning once they are 60 seconds old
+    warp(&mut context, 60).await;
+    send(&mut context, &claim, &[&alice.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &alice.token_account).await, 900);
+    let position = pool.position(&mut context, &alice.pubkey()).await.unwrap();
+    assert_eq!({ position.warming_amount }, 0);
+
+    // 2. Topping up 400 more tokens leaves her existing stake earning and
+    // claimable, while the top-up earns nothing
+    warp(&mut context, 10).await;
+    send(&mut context, &[pool.stake_ix(&alice, 400)], &[&alice.wallet]).await.unwrap();
+    warp(&mut context, 10).await;
+    send(&mut context, &claim, &[&alice.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &alice.token_account).await, 500 + 100 * 20);
+    let position = pool.position(&mut context, &alice.pubkey()).await.unwrap();
+    assert_eq!({ position.warming_amount }, 400);
+
+    // 3. Once 60 seconds old the top-up earns too
+    warp(&mut context, 50).await;
+    send(&mut context, &claim, &[&alice.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &alice.token_account).await, 2_500 + 100 * 50);
+    warp(&mut context, 10).await;
+    send(&mut context, &claim, &[&alice.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &alice.token_account).await, 7_500 + 500 * 10);
+
+    // 4. Unstaking takes warming stake first
+    send(&mut context, &[pool.stake_ix(&alice, 300)], &[&alice.wallet]).await.unwrap();
+    let ix = pool.unstake_ix(&alice, &alice.pubkey(), 200);
+    send(&mut context, &[ix], &[&alice.wallet]).await.unwrap();
+    let position = pool.position(&mut context, &alice.pubkey()).await.unwrap();
+    assert_eq!(({ position.amount }, { position.warming_amount }), (600, 100));
+
+    // 5. Nothing the warming stake accrued is left committed
+    warp(&mut context, 10).await;
+    send(&mut context, &claim, &[&alice.wallet]).await.unwrap();
+    assert_eq!({ pool.state(&mut context).await.committed_rewards }, 0);
+}
+
+#[tokio::test]
+async fn test_warming_stake_earns_from_maturity() {
+    let (mut context, program_id) = setup_test_env().await;
+    let pool = PoolBuilder::default()
+        .limits(StakeLimits {
+            min_stake_age: 60,
+            ..StakeLimits::default()
+        })
+        .reward_vault(1_000_000)
+        .build(&mut context, &program_id)
+        .await;
+    let alice = pool.staked_user(&mut context, 1_000, 100).await;
+
+    // 1. Alice leaves her stake alone long past maturity, and everything
+    // after its first 60 seconds is hers to claim
+    warp(&mut context, 1_000).await;
+    send(&mut context, &[pool.claim_own_ix(&alice)], &[&alice.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &alice.token_account).await, 900 + 100 * 940);
+
+    // 2. Bob tops up the same amount 30 seconds after his first deposit,
+    // which delays his maturity by 15 seconds instead of restarting it
+    let bob = pool.staked_user(&mut context, 1_000, 100).await;
+    warp(&mut context, 30).await;
+    send(&mut context, &[pool.stake_ix(&bob, 100)], &[&bob.wallet]).await.unwrap();
+    warp(&mut context, 40).await;
+    assert_eq!(
+        send(&mut context, &[pool.claim_own_ix(&bob)], &[&bob.wallet]).await,
+        rejected(0, StakingError::NothingToClaim),
+    );
+    warp(&mut context, 10).await;
+    send(&mut context, &[pool.claim_own_ix(&bob)], &[&bob.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &bob.token_account).await, 800 + 200 * 5);
+
+    // 3. Nothing the warming stake accrued is left committed
+    send(&mut context, &[pool.claim_own_ix(&alice)], &[&alice.wallet]).await.unwrap();
+    assert_eq!({ pool.state(&mut context).await.committed_rewards }, 0);
+}
+
+#[tokio::test]
+async fn test_migrate_account() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let staking_pool = Keypair::new();
+    let pool = staking_pool.pubkey();
+    let (queued_change, _) =
+        Pubkey::find_program_address(&[b"queued-change", pool.as_ref()], &program_id);
+
+    let admin_ix = |pool: Pubkey, data: StakingInstruction| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool, false),
+            AccountMeta::new_readonly(payer, true),
+        ],
+        data: borsh::to_vec(&data).unwrap(),
+    };
+    let migrate_ix = |account: Pubkey, pool: Option<Pubkey>| {
+        let mut accounts = vec![
+            AccountMeta::new(account, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ];
+        accounts.extend(pool.map(|pool| AccountMeta::new_readonly(pool, false)));
+        Instruction {
+            program_id,
+            accounts,
+            data: borsh::to_vec(&StakingInstruction::MigrateAccount).unwrap(),
+        }
+    };
+    let rejected = |error: StakingError| {
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(error as u32)))
+    };
+
+    // 1. Create a pool with a queued action
+    let vault = create_vault(&mut context, &program_id, &pool).await;
+    send(
+        &mut context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &pool,
+                rent.minimum_balance(StakingPool::LEN),
+                StakingPool::LEN as u64,
+                &program_id,
+            ),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pool, false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                    AccountMeta::new_readonly(vault, false),
+                    AccountMeta::new_readonly(vault, false),
+                ],
+                data: borsh::to_vec(&StakingInstruction::InitializePool {
+                    reward_rate: 1,
+                    limits: StakeLimits::default(),
+                })
+                .unwrap(),
+            },
+            admin_ix(pool, StakingInstruction::SetTimelockDelay { timelock_delay: 100 }),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(pool, false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new(queued_change, false),
+                    AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+                ],
+                data: borsh::to_vec(&StakingInstruction::QueueAction {
+                    action: AdminAction::SetRewardRate { reward_rate: 2 },
+                })
+                .unwrap(),
+            },
+        ],
+        &[&staking_pool],
+    )
+    .await
+    .unwrap();
+
+    // 2. A queued change cannot stand in for a pool
+    assert_eq!(
+        send(
+            &mut context,
+            &[admin_ix(queued_change, StakingInstruction::SetEmergencyMode { enabled: true })],
+            &[],
+        )
+        .await,
+        rejected(StakingError::InvalidAccountDiscriminator),
+    );
+
+    // 3. Rewind the pool to layout version 1, before it stored the vault
+    // authority bump and legacy flag, with only enough rent for it
+    let current = context.banks_client.get_account(pool).await.unwrap().unwrap();
+    let mut legacy = current.clone();
+    legacy.data = current.data[..StakingPool::LEN - 2].to_vec();
+    legacy.data[ACCOUNT_HEADER_LEN - 1] = 1;
+    legacy.lamports = rent.minimum_balance(legacy.data.len());
+    context.set_account(&pool, &legacy.into());
+
+    assert_eq!(
+        send(
+            &mut context,
+            &[admin_ix(pool, StakingInstruction::SetEmergencyMode { enabled: true })],
+            &[],
+        )
+        .await,
+        rejected(StakingError::AccountVersionMismatch),
+    );
+
+    // 4. Migrating restores the current layout, topped up to rent exemption
+    send(&mut context, &[migrate_ix(pool, None)], &[]).await.unwrap();
+
+    let migrated = context.banks_client.get_account(pool).await.unwrap().unwrap();
+    assert_eq!(migrated.data, current.data);
+    assert_eq!(migrated.lamports, rent.minimum_balance(StakingPool::LEN));
+    send(
+        &mut context,
+        &[admin_ix(pool, StakingInstruction::SetEmergencyMode { enabled: true })],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        send(&mut context, &[migrate_ix(pool, None)], &[]).await,
+        rejected(StakingError::AccountAlreadyMigrated),
+    );
+    assert_eq!(
+        send(&mut context, &[migrate_ix(queued_change, None)], &[]).await,
+        rejected(StakingError::AccountAlreadyMigrated),
+    );
+
+    // 5. A version 1 position gets its bump back, given the pool it is in, and
+    // no warming stake
+    let (position, bump) =
+        Pubkey::find_program_address(&[b"user-stake", payer.as_ref(), pool.as_ref()], &program_id);
+    let stake_info = UserStakeInfo {
+        discriminator: UserStakeInfo::DISCRIMINATOR,
+        version: UserStakeInfo::VERSION,
+        staker: payer.to_bytes(),
+        amount: 100,
+        last_stake_time: 1,
+        claimer: [0; 32],
+        claim_destination: [0; 32],
+        reward_per_token_paid: 0,
+        rewards: 7,
+        bump,
+        warming_amount: 0,
+    };
+    let mut v1_data = bytemuck::bytes_of(&stake_info)[..UserStakeInfo::LEN - 9].to_vec();
+    v1_data[ACCOUNT_HEADER_LEN - 1] = 1;
+    let v1_position = solana_sdk::account::Account {
+        lamports: rent.minimum_balance(UserStakeInfo::LEN),
+        data: v1_data,
+        owner: program_id,
+        executable: false,
+        rent_epoch: 0,
+    };
+    context.set_account(&position, &v1_position.into());
+
+    assert_eq!(
+        send(&mut context, &[migrate_ix(position, None)], &[]).await,
+        Err(TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)),
+    );
+    assert_eq!(
+        send(&mut context, &[migrate_ix(position, Some(queued_change))], &[]).await,
+        rejected(StakingError::InvalidPda),
+    );
+    send(&mut context, &[migrate_ix(position, Some(pool))], &[]).await.unwrap();
+
+    let migrated = context.banks_client.get_account(position).await.unwrap().unwrap();
+    assert_eq!(migrated.data, bytemuck::bytes_of(&stake_info));
+
+    // 6. Layout versions this build does not know are rejected
+    let mut account = context.banks_client.get_account(pool).await.unwrap().unwrap();
+    account.data[ACCOUNT_HEADER_LEN - 1] = StakingPool::VERSION + 1;
+    context.set_account(&pool, &account.into());
+
+    assert_eq!(
+        send(
+            &mut context,
+            &[admin_ix(pool, StakingInstruction::SetEmergencyMode { enabled: false })],
+            &[],
+        )
+        .await,
+        rejected(StakingError::AccountVersionMismatch),
+    );
+    assert_eq!(
+        send(&mut context, &[migrate_ix(pool, None)], &[]).await,
+        rejected(StakingError::AccountVersionMismatch),
+    );
+}
+
+#[tokio::test]
+async fn test_migrate_baseline_accounts() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let staking_pool = Keypair::new();
+    let (legacy_authority, _) = Pubkey::find_program_address(&[b"vault-auth"], &program_id);
+    let mint = create_mint(&mut context).await;
+    let pool = TestPool {
+        program_id,
+        address: staking_pool.pubkey(),
+        mint,
+        vault: create_token_account(&mut context, &mint, &legacy_authority, 300).await,
+        reward_vault: create_token_account(&mut context, &mint, &legacy_authority, 100_000).await,
+        vault_authority: legacy_authority,
+    };
+
+    let migrate_ix = |account: Pubkey, signer: Pubkey, extra: &[AccountMeta]| {
+        let mut accounts = vec![
+            AccountMeta::new(account, account == pool.address),
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ];
+        accounts.extend_from_slice(extra);
+        Instruction {
+            program_id,
+            accounts,
+            data: borsh::to_vec(&StakingInstruction::MigrateAccount).unwrap(),
+        }
+    };
+    let claim_address = |vault: &Pubkey| {
+        Pubkey::find_program_address(&[b"vault-claim", vault.as_ref()], &program_id).0
+    };
+    let migrate_pool_ix = |pool: &Pubkey, admin: Pubkey, vault: Pubkey, reward_vault: Pubkey| {
+        migrate_ix(
+            *pool,
+            admin,
+            &[
+                AccountMeta::new(*pool, true),
+                AccountMeta::new_readonly(vault, false),
+                AccountMeta::new_readonly(reward_vault, false),
+                AccountMeta::new(claim_address(&vault), false),
+                AccountMeta::new(claim_address(&reward_vault), false),
+            ],
+        )
+    };
+    let migrate_position_ix = |staker: &Staker| {
+        migrate_ix(
+            pool.position_address(&staker.pubkey()),
+            payer,
+            &[AccountMeta::new(pool.address, false)],
+        )
+    };
+    let set_baseline = |context: &mut ProgramTestContext, address: Pubkey, data: Vec<u8>| {
+        assert_eq!(data.len(), BASELINE_LEN);
+        let account = solana_sdk::account::Account {
+            lamports: rent.minimum_balance(data.len()),
+            data,
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        };
+        context.set_account(&address, &account.into());
+    };
+
+    // 1. Write a pool and two positions as the first release did, paying 2
+    // per token per second, with the stake in a vault of the program-wide
+    // vault authority
+    let start = now(&mut context).await;
+    let alice = pool.new_staker(&mut context, 0).await;
+    let bob = pool.new_staker(&mut context, 0).await;
+    set_baseline(&mut context, pool.address, borsh::to_vec(&(payer, 2u64, 300u64)).unwrap());
+    for (staker, amount, staked_at) in [(&alice, 100u64, start - 50), (&bob, 200, start - 10)] {
+        let data = borsh::to_vec(&(staker.pubkey(), amount, staked_at)).unwrap();
+        set_baseline(&mut context, pool.position_address(&staker.pubkey()), data);
+    }
+
+    assert_eq!(
+        send(&mut context, &[pool.claim_own_ix(&alice)], &[&alice.wallet]).await,
+        rejected(0, StakingError::InvalidAccountDiscriminator),
+    );
+
+    // 2. Positions wait for their pool to be migrated
+    assert_eq!(
+        send(&mut context, &[migrate_position_ix(&alice)], &[]).await,
+        rejected(0, StakingError::InvalidAccountDiscriminator),
+    );
+
+    // 3. Only the admin, naming vaults of the program-wide authority, can
+    // migrate the pool
+    let ix = migrate_pool_ix(&pool.address, alice.pubkey(), pool.vault, pool.reward_vault);
+    assert_eq!(
+        send(&mut context, &[ix], &[&staking_pool, &alice.wallet]).await,
+        failed(0, InstructionError::IllegalOwner),
+    );
+    let stray_vault = create_token_account(&mut context, &mint, &payer, 0).await;
+    let ix = migrate_pool_ix(&pool.address, payer, stray_vault, pool.reward_vault);
+    assert_eq!(
+        send(&mut context, &[ix], &[&staking_pool]).await,
+        failed(0, InstructionError::InvalidAccountData),
+    );
+    let mut ix = migrate_pool_ix(&pool.address, payer, pool.vault, pool.reward_vault);
+    ix.accounts[6].pubkey = claim_address(&pool.reward_vault);
+    assert_eq!(
+        send(&mut context, &[ix], &[&staking_pool]).await,
+        rejected(0, StakingError::InvalidPda),
+    );
+    let ix = migrate_pool_ix(&pool.address, payer, pool.vault, pool.reward_vault);
+    send(&mut context, &[ix], &[&staking_pool]).await.unwrap();
+
+    let state = pool.state(&mut context).await;
+    assert_eq!({ state.admin }, payer.to_bytes());
+    assert_eq!({ state.reward_rate }, 2);
+    assert_eq!({ state.total_staked }, 0);
+    assert_eq!({ state.vault }, pool.vault.to_bytes());
+    assert_eq!({ state.reward_vault }, pool.reward_vault.to_bytes());
+    assert_eq!({ state.legacy_vault_authority }, 1);
+    for vault in [pool.vault, pool.reward_vault] {
+        let account = context.banks_client.get_account(claim_address(&vault)).await.unwrap();
+        let claim = *VaultClaim::load(&account.unwrap().data).unwrap();
+        assert_eq!({ claim.pool }, pool.address.to_bytes());
+    }
+
+    // 4. No other first-release pool can name the vaults it claimed, even
+    // alongside one of its own
+    let other_pool = Keypair::new();
+    let other_vault = create_token_account(&mut context, &mint, &legacy_authority, 0).await;
+    set_baseline(&mut context, other_pool.pubkey(), borsh::to_vec(&(payer, 2u64, 0u64)).unwrap());
+    for (vault, reward_vault) in [(pool.vault, other_vault), (other_vault, pool.reward_vault)] {
+        let ix = migrate_pool_ix(&other_pool.pubkey(), payer, vault, reward_vault);
+        assert_eq!(
+            send(&mut context, &[ix], &[&other_pool]).await,
+            rejected(0, StakingError::VaultAlreadyClaimed),
+        );
+    }
+    let ix = migrate_pool_ix(&other_pool.pubkey(), payer, other_vault, other_vault);
+    send(&mut context, &[ix], &[&other_pool]).await.unwrap();
+
+    // 5. A position needs its pool, and is credited what the first release
+    // would have paid for it so far
+    assert_eq!(
+        send(
+            &mut context,
+            &[migrate_ix(pool.position_address(&alice.pubkey()), payer, &[])],
+            &[],
+        )
+        .await,
+        failed(0, InstructionError::NotEnoughAccountKeys),
+    );
+    send(&mut context, &[migrate_position_ix(&alice)], &[]).await.unwrap();
+
+    let position = pool.position(&mut context, &alice.pubkey()).await.unwrap();
+    assert_eq!({ position.amount }, 100);
+    assert_eq!({ position.last_stake_time }, start - 50);
+    assert_eq!({ position.rewards }, 50 * 2 * 100);
+    assert_eq!({ position.warming_amount }, 0);
+    let state = pool.state(&mut context).await;
+    assert_eq!({ state.total_staked }, 100);
+    assert_eq!({ state.committed_rewards }, 10_000);
+
+    assert_eq!(
+        send(&mut context, &[migrate_position_ix(&alice)], &[]).await,
+        rejected(0, StakingError::AccountAlreadyMigrated),
+    );
+
+    // 6. Migrated stake earns through the accumulator, unmigrated stake as
+    // the first release paid it
+    warp(&mut context, 10).await;
+    send(&mut context, &[migrate_position_ix(&bob)], &[]).await.unwrap();
+
+    let position = pool.position(&mut context, &bob.pubkey()).await.unwrap();
+    assert_eq!({ position.rewards }, 20 * 2 * 200);
+    let state = pool.state(&mut context).await;
+    assert_eq!({ state.total_staked }, 300);
+    assert_eq!({ state.committed_rewards }, 10_000 + 10 * 2 * 100 + 8_000);
+
+    // 7. Both can claim and unstake through the program-wide authority
+    send(&mut context, &[pool.claim_own_ix(&alice)], &[&alice.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &alice.token_account).await, 12_000);
+
+    let ixs = [pool.claim_own_ix(&bob), pool.unstake_ix(&bob, &bob.pubkey(), 200)];
+    send(&mut context, &ixs, &[&bob.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &bob.token_account).await, 8_000 + 200);
+    assert_eq!(token_balance(&mut context, &pool.vault).await, 100);
+}
+
+/// Hands `account`, a token account, to `owner` behind the token program's
+/// back.
+async fn set_token_owner(context: &mut ProgramTestContext, account: &Pubkey, owner: &Pubkey) {
+    let mut token_account = context.banks_client.get_account(*account).await.unwrap().unwrap();
+    let mut state = spl_token::state::Account::unpack(&token_account.data).unwrap();
+    state.owner = *owner;
+    spl_token::state::Account::pack(state, &mut token_account.data).unwrap();
+    context.set_account(account, &token_account.into());
+}
+
+/// Writes the `VaultClaim` binding `vault` to `pool` behind the program's
+/// back, as migrating a first-release pool would have.
+fn set_vault_claim(
+    context: &mut ProgramTestContext,
+    program_id: &Pubkey,
+    vault: &Pubkey,
+    pool: &Pubkey,
+) {
+    let claim = VaultClaim {
+        discriminator: VaultClaim::DISCRIMINATOR,
+        version: VaultClaim::VERSION,
+        pool: pool.to_bytes(),
+    };
+    let account = solana_sdk::account::Account {
+        lamports: 1_000_000,
+        data: bytemuck::bytes_of(&claim).to_vec(),
+        owner: *program_id,
+        executable: false,
+        rent_epoch: 0,
+    };
+    let (address, _) = Pubkey::find_program_address(&[b"vault-claim", vault.as_ref()], program_id);
+    context.set_account(&address, &account.into());
+}
+
+/// Turns `pool` into one from before vault authorities were per pool: its
+/// vaults belong to the program-wide authority. Returns that authority.
+async fn into_legacy_pool(context: &mut ProgramTestContext, pool: &TestPool) -> Pubkey {
+    let (legacy_authority, legacy_bump) =
+        Pubkey::find_program_address(&[b"vault-auth"], &pool.program_id);
+    let mut account = context.banks_client.get_account(pool.address).await.unwrap().unwrap();
+    let state = StakingPool::load_mut(&mut account.data).unwrap();
+    state.legacy_vault_authority = 1;
+    state.vault_authority_bump = legacy_bump;
+    context.set_account(&pool.address, &account.into());
+    set_token_owner(context, &pool.vault, &legacy_authority).await;
+    set_token_owner(context, &pool.reward_vault, &legacy_authority).await;
+    legacy_authority
+}
+
+#[tokio::test]
+async fn test_migrate_vault_authority() {
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let pool = PoolBuilder::default().reward_vault(1_000).build(&mut context, &program_id).await;
+    let alice = pool.staked_user(&mut context, 1_000, 100).await;
+    let (legacy_authority, _) = Pubkey::find_program_address(&[b"vault-auth"], &program_id);
+    let legacy_pool = TestPool {
+        vault_authority: legacy_authority,
+        ..pool
+    };
+    let pool = TestPool {
+        vault_authority: vault_authority(&program_id, &legacy_pool.address),
+        ..legacy_pool
+    };
+
+    let recover_ix = |pool: &TestPool, destination: Pubkey| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(pool.address, false),
+            AccountMeta::new_readonly(payer, true),
+            AccountMeta::new(pool.reward_vault, false),
+            AccountMeta::new(destination, false),
+            AccountMeta::new_readonly(pool.vault_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::RecoverTokens { amount: 1 }).unwrap(),
+    };
+    let claim_address = |vault: &Pubkey| {
+        Pubkey::find_program_address(&[b"vault-claim", vault.as_ref()], &program_id).0
+    };
+    let migrate_ix = |admin: Pubkey| Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(pool.address, false),
+            AccountMeta::new_readonly(admin, true),
+            AccountMeta::new_readonly(legacy_authority, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(pool.vault, false),
+            AccountMeta::new(pool.reward_vault, false),
+            AccountMeta::new_readonly(claim_address(&pool.vault), false),
+            AccountMeta::new_readonly(claim_address(&pool.reward_vault), false),
+        ],
+        data: borsh::to_vec(&StakingInstruction::MigrateVaultAuthority).unwrap(),
+    };
+
+    // 1. Turn the pool into one from before vault authorities were per pool
+    into_legacy_pool(&mut context, &pool).await;
+
+    // 2. Unstaking and claiming keep working through the legacy authority,
+    // and only through it
+    warp(&mut context, 5).await;
+    assert_eq!(
+        send(&mut context, &[pool.unstake_ix(&alice, &alice.pubkey(), 10)], &[&alice.wallet]).await,
+        rejected(0, StakingError::InvalidPda),
+    );
+    let ixs = [
+        legacy_pool.unstake_ix(&alice, &alice.pubkey(), 10),
+        legacy_pool.claim_own_ix(&alice),
+    ];
+    send(&mut context, &ixs, &[&alice.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &alice.token_account).await, 900 + 10 + 500);
+
+    // 3. Recovery is refused, since the legacy authority owns other pools'
+    // vaults too
+    let destination = create_token_account(&mut context, &pool.mint, &payer, 0).await;
+    assert_eq!(
+        send(&mut context, &[recover_ix(&legacy_pool, destination)], &[]).await,
+        rejected(0, StakingError::LegacyVaultAuthority),
+    );
+
+    // 4. Only the admin can move the vaults to the pool's own authority, and
+    // only vaults claimed for this pool when it left the first release
+    assert_eq!(
+        send(&mut context, &[migrate_ix(alice.pubkey())], &[&alice.wallet]).await,
+        failed(0, InstructionError::IllegalOwner),
+    );
+    assert_eq!(
+        send(&mut context, &[migrate_ix(payer)], &[]).await,
+        failed(0, InstructionError::IncorrectProgramId),
+    );
+    set_vault_claim(&mut context, &program_id, &pool.vault, &pool.address);
+    set_vault_claim(&mut context, &program_id, &pool.reward_vault, &Pubkey::new_unique());
+    assert_eq!(
+        send(&mut context, &[migrate_ix(payer)], &[]).await,
+        rejected(0, StakingError::VaultAlreadyClaimed),
+    );
+    set_vault_claim(&mut context, &program_id, &pool.reward_vault, &pool.address);
+    send(&mut context, &[migrate_ix(payer)], &[]).await.unwrap();
+
+    let state = pool.state(&mut context).await;
+    assert_eq!({ state.legacy_vault_authority }, 0);
+    for vault in [pool.vault, pool.reward_vault] {
+        let account = context.banks_client.get_account(vault).await.unwrap().unwrap();
+        let token_account = spl_token::state::Account::unpack(&account.data).unwrap();
+        assert_eq!(token_account.owner, pool.vault_authority);
+    }
+    assert_eq!(
+        send(&mut context, &[migrate_ix(payer)], &[]).await,
+        rejected(0, StakingError::VaultAuthorityAlreadyMigrated),
+    );
+
+    // 5. From now on the pool's own authority moves its tokens
+    assert_eq!(
+        send(
+            &mut context,
+            &[legacy_pool.unstake_ix(&alice, &alice.pubkey(), 10)],
+            &[&alice.wallet],
+        )
+        .await,
+        rejected(0, StakingError::InvalidPda),
+    );
+    let ixs = [pool.unstake_ix(&alice, &alice.pubkey(), 10), recover_ix(&pool, destination)];
+    send(&mut context, &ixs, &[&alice.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &destination).await, 1);
+}
+
+/// The client builders that move a pool's tokens take the vault authority
+/// `client::pool_vault_authority_address` picks for it, legacy or not.
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn test_client_follows_legacy_vault_authority() {
+    use staking_contract::client;
+
+    let (mut context, program_id) = setup_test_env().await;
+    let payer = context.payer.pubkey();
+    let pool = PoolBuilder::default().reward_vault(1_000).build(&mut context, &program_id).await;
+    let alice = pool.staked_user(&mut context, 1_000, 100).await;
+    let bob = pool.staked_user(&mut context, 1_000, 100).await;
+    let legacy_authority = into_legacy_pool(&mut context, &pool).await;
+
+    // 1. A legacy pool pays out through the program-wide authority
+    let state = pool.state(&mut context).await;
+    let authority = client::pool_vault_authority_address(&program_id, &pool.address, &state);
+    assert_eq!(authority, legacy_authority);
+
+    warp(&mut context, 5).await;
+    let alice_wallet = alice.pubkey();
+    let ixs = [
+        client::unstake(
+            &program_id,
+            &pool.address,
+            &pool.vault,
+            &authority,
+            &alice_wallet,
+            &alice.token_account,
+            10,
+        ),
+        client::claim_rewards(
+            &program_id,
+            &pool.address,
+            &pool.reward_vault,
+            &authority,
+            &alice_wallet,
+            &alice.token_account,
+        ),
+    ];
+    send(&mut context, &ixs, &[&alice.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &alice.token_account).await, 900 + 10 + 500);
+
+    let ixs = [
+        client::set_emergency_mode(&program_id, &pool.address, &payer, true),
+        client::emergency_withdraw(
+            &program_id,
+            &pool.address,
+            &pool.vault,
+            &authority,
+            &bob.pubkey(),
+            &bob.token_account,
+        ),
+        client::set_emergency_mode(&program_id, &pool.address, &payer, false),
+    ];
+    send(&mut context, &ixs, &[&bob.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &pool.vault).await, 90);
+
+    // 2. Once migrated, it pays out through its own
+    set_vault_claim(&mut context, &program_id, &pool.vault, &pool.address);
+    set_vault_claim(&mut context, &program_id, &pool.reward_vault, &pool.address);
+    let ix = client::migrate_vault_authority(
+        &program_id,
+        &pool.address,
+        &payer,
+        &pool.vault,
+        &pool.reward_vault,
+    );
+    send(&mut context, &[ix], &[]).await.unwrap();
+
+    let state = pool.state(&mut context).await;
+    let authority = client::pool_vault_authority_address(&program_id, &pool.address, &state);
+    assert_eq!(authority, pool.vault_authority);
+
+    let destination = create_token_account(&mut context, &pool.mint, &payer, 0).await;
+    let ixs = [
+        client::unstake(
+            &program_id,
+            &pool.address,
+            &pool.vault,
+            &authority,
+            &alice_wallet,
+            &alice.token_account,
+            10,
+        ),
+        client::recover_tokens(
+            &program_id,
+            &pool.address,
+            &payer,
+            &pool.reward_vault,
+            &authority,
+            &destination,
+            1,
+        ),
+    ];
+    send(&mut context, &ixs, &[&alice.wallet]).await.unwrap();
+    assert_eq!(token_balance(&mut context, &destination).await, 1);
+}
+
+/// Most compute units each instruction may use, with headroom over what the
+/// SBF build measures. `Stake` and the views stay close to the cost of their
+/// token CPI now that PDAs are checked against stored bumps; only opening a
+/// position still searches for one.
+const COMPUTE_BUDGETS: &[(&str, u64)] = &[
+    ("InitializePool", 20_000),
+    ("Stake (new position)", 30_000),
+    ("Stake (top-up)", 15_000),
+    ("UpdatePool", 5_000),
+    ("ClaimRewards", 20_000),
+    ("UnStake", 15_000),
+    ("SetStakeLimits", 5_000),
+    ("GetPoolStats", 5_000),
+    ("GetPosition", 5_000),
+];
+
+/// Fails if an instruction goes over its entry in `COMPUTE_BUDGETS`. Needs
+/// `cargo build-sbf` first, like `compute_units.rs`, so it is ignored by
+/// default and fails without the build when run with `--include-ignored`.
+#[tokio::test]
+#[ignore = "needs `cargo build-sbf` first, then run with `--include-ignored`"]
+async fn test_compute_budgets() {
+    let so = common::program_so().expect(
+        "no SBF build of staking_contract: run `cargo build-sbf` or set STAKING_PROGRAM_SO",
+    );
+
+    let report = common::measure(std::fs::read(so).unwrap()).await;
+    assert_eq!(report.len(), COMPUTE_BUDGETS.len());
+    for ((name, units), (budgeted, budget)) in report.iter().zip(COMPUTE_BUDGETS) {
+        assert_eq!(name, budgeted);
+        assert!(units <= budget, "{name} used {units} CUs, over its budget of {budget}");
+    }
+}
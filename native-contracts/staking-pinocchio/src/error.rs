@@ -0,0 +1,55 @@
+use pinocchio::program_error::ProgramError;
+
+/// Same variants, in the same order, as `staking_contract::error::StakingError`,
+/// so both builds fail with the same custom error codes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StakingError {
+    InvalidPda,
+    PoolAlreadyInitialized,
+    /// Never raised, see `staking_contract::error::StakingError`.
+    UserAlreadyStaked,
+    NothingToClaim,
+    UnauthorizedClaimer,
+    InvalidClaimDestination,
+    StakeBelowMinimum,
+    PoolCapExceeded,
+    UserCapExceeded,
+    StakeWindowNotOpen,
+    StakeWindowClosed,
+    InvalidStakeWindow,
+    TimelockActive,
+    TimelockNotExpired,
+    ActionAlreadyQueued,
+    NoQueuedAction,
+    PoolInEmergency,
+    EmergencyModeOff,
+    RecoveryExceedsExcess,
+    VaultUnderfunded,
+    RewardVaultUnderfunded,
+    StakedInSameTransaction,
+    InvalidAccountDiscriminator,
+    AccountVersionMismatch,
+    AccountAlreadyMigrated,
+    LegacyVaultAuthority,
+    VaultAuthorityAlreadyMigrated,
+    VaultAlreadyClaimed,
+}
+
+impl From<StakingError> for ProgramError {
+    fn from(e: StakingError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl From<staking_core::Error> for StakingError {
+    fn from(e: staking_core::Error) -> Self {
+        match e {
+            staking_core::Error::StakeWindowNotOpen => StakingError::StakeWindowNotOpen,
+            staking_core::Error::StakeWindowClosed => StakingError::StakeWindowClosed,
+            staking_core::Error::StakeBelowMinimum => StakingError::StakeBelowMinimum,
+            staking_core::Error::PoolCapExceeded => StakingError::PoolCapExceeded,
+            staking_core::Error::UserCapExceeded => StakingError::UserCapExceeded,
+            staking_core::Error::InvalidStakeWindow => StakingError::InvalidStakeWindow,
+        }
+    }
+}
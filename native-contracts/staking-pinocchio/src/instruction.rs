@@ -0,0 +1,115 @@
+use crate::state::{AdminAction, Reader, StakeLimits};
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey};
+
+/// `staking_contract::instruction::StakingInstruction`, decoded by hand from
+/// the same borsh bytes. Accounts are the ones listed there.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StakingInstruction {
+    InitializePool {
+        reward_rate: u64,
+        limits: StakeLimits,
+    },
+    Stake {
+        amount: u64,
+    },
+    UnStake {
+        amount: u64,
+    },
+    ClaimRewards,
+    AuthorizeClaimer {
+        claimer: Pubkey,
+        destination: Pubkey,
+    },
+    GetPendingRewards,
+    GetPoolStats,
+    GetPosition,
+    UpdatePool,
+    SetCrankTip {
+        crank_tip: u64,
+    },
+    SetStakeLimits {
+        limits: StakeLimits,
+    },
+    SetRewardRate {
+        reward_rate: u64,
+    },
+    SetTimelockDelay {
+        timelock_delay: u64,
+    },
+    QueueAction {
+        action: AdminAction,
+    },
+    CancelAction,
+    ExecuteAction,
+    SetEmergencyMode {
+        enabled: bool,
+    },
+    EmergencyWithdraw,
+    RecoverTokens {
+        amount: u64,
+    },
+    AssertInvariants,
+    MigrateAccount,
+    MigrateVaultAuthority,
+}
+
+impl StakingInstruction {
+    /// Decodes `data` the way borsh does: a variant index byte followed by
+    /// its fields, with nothing left over.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut reader = Reader::new(data);
+
+        let instruction = match reader.u8()? {
+            0 => Self::InitializePool {
+                reward_rate: reader.u64()?,
+                limits: StakeLimits::read(&mut reader)?,
+            },
+            1 => Self::Stake {
+                amount: reader.u64()?,
+            },
+            2 => Self::UnStake {
+                amount: reader.u64()?,
+            },
+            3 => Self::ClaimRewards,
+            4 => Self::AuthorizeClaimer {
+                claimer: reader.pubkey()?,
+                destination: reader.pubkey()?,
+            },
+            5 => Self::GetPendingRewards,
+            6 => Self::GetPoolStats,
+            7 => Self::GetPosition,
+            8 => Self::UpdatePool,
+            9 => Self::SetCrankTip {
+                crank_tip: reader.u64()?,
+            },
+            10 => Self::SetStakeLimits {
+                limits: StakeLimits::read(&mut reader)?,
+            },
+            11 => Self::SetRewardRate {
+                reward_rate: reader.u64()?,
+            },
+            12 => Self::SetTimelockDelay {
+                timelock_delay: reader.u64()?,
+            },
+            13 => Self::QueueAction {
+                action: AdminAction::read(&mut reader)?,
+            },
+            14 => Self::CancelAction,
+            15 => Self::ExecuteAction,
+            16 => Self::SetEmergencyMode {
+                enabled: reader.bool()?,
+            },
+            17 => Self::EmergencyWithdraw,
+            18 => Self::RecoverTokens {
+                amount: reader.u64()?,
+            },
+            19 => Self::AssertInvariants,
+            20 => Self::MigrateAccount,
+            21 => Self::MigrateVaultAuthority,
+            _ => return Err(ProgramError::InvalidInstructionData),
+        };
+
+        reader.finish()?;
+        Ok(instruction)
+    }
+}
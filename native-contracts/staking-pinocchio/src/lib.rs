@@ -0,0 +1,29 @@
+//! `staking_contract` rebuilt on pinocchio, without `solana-program` or an
+//! allocator. It takes the same instruction data and accounts and writes the
+//! same account layouts, so clients and the program-test suite work against
+//! either build.
+#![no_std]
+
+use pinocchio::{account_info::AccountInfo, pubkey::Pubkey, ProgramResult};
+
+pub mod error;
+pub mod instruction;
+pub mod processor;
+pub mod state;
+
+#[cfg(not(feature = "no-entrypoint"))]
+mod entrypoint {
+    use pinocchio::{no_allocator, nostd_panic_handler, program_entrypoint};
+
+    program_entrypoint!(crate::process_instruction);
+    no_allocator!();
+    nostd_panic_handler!();
+}
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    processor::process(program_id, accounts, instruction_data)
+}
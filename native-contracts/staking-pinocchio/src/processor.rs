@@ -0,0 +1,1499 @@
+//! Handlers for each `StakingInstruction`, checking the same accounts in the
+//! same order as `staking_contract::processor` so both builds fail the same
+//! way on the same input.
+
+use crate::{
+    error::StakingError,
+    instruction::StakingInstruction,
+    state::{
+        migrate, AccountBuf, AccountLocation, AdminAction, BaselinePool, BaselinePosition,
+        PoolStats, PositionInfo, ProgramAccount, QueuedChange, StakeLimits, StakingPool,
+        UserStakeInfo, VaultClaim, ZeroCopyAccount, BASELINE_LEN,
+    },
+};
+use pinocchio::{
+    account_info::{AccountInfo, RefMut},
+    cpi::set_return_data,
+    instruction::{Seed, Signer},
+    program_error::ProgramError,
+    pubkey::{self, create_program_address, find_program_address, Pubkey},
+    sysvars::{clock::Clock, instructions::Instructions, rent::Rent, Sysvar},
+    ProgramResult,
+};
+use pinocchio_log::log;
+use pinocchio_system::instructions::{CreateAccount, Transfer as SystemTransfer};
+use pinocchio_token::{
+    instructions::{AuthorityType, SetAuthority, Transfer},
+    state::TokenAccount,
+};
+use staking_core::{lock, penalty, rewards, timestamp};
+
+pub fn process(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    match StakingInstruction::unpack(instruction_data)? {
+        StakingInstruction::InitializePool {
+            reward_rate,
+            limits,
+        } => process_initialize_pool(accounts, reward_rate, limits, program_id),
+        StakingInstruction::Stake { amount } => process_stake(accounts, amount, program_id),
+        StakingInstruction::UnStake { amount } => process_unstake(accounts, amount, program_id),
+        StakingInstruction::ClaimRewards => process_claim_rewards(accounts, program_id),
+        StakingInstruction::AuthorizeClaimer {
+            claimer,
+            destination,
+        } => process_authorize_claimer(accounts, claimer, destination, program_id),
+        StakingInstruction::GetPendingRewards => process_get_pending_rewards(accounts, program_id),
+        StakingInstruction::GetPoolStats => process_get_pool_stats(accounts, program_id),
+        StakingInstruction::GetPosition => process_get_position(accounts, program_id),
+        StakingInstruction::UpdatePool => process_update_pool(accounts, program_id),
+        StakingInstruction::SetCrankTip { crank_tip } => {
+            process_set_crank_tip(accounts, crank_tip, program_id)
+        }
+        StakingInstruction::SetStakeLimits { limits } => {
+            process_set_stake_limits(accounts, limits, program_id)
+        }
+        StakingInstruction::SetRewardRate { reward_rate } => {
+            process_set_reward_rate(accounts, reward_rate, program_id)
+        }
+        StakingInstruction::SetTimelockDelay { timelock_delay } => {
+            process_set_timelock_delay(accounts, timelock_delay, program_id)
+        }
+        StakingInstruction::QueueAction { action } => {
+            process_queue_action(accounts, action, program_id)
+        }
+        StakingInstruction::CancelAction => process_cancel_action(accounts, program_id),
+        StakingInstruction::ExecuteAction => process_execute_action(accounts, program_id),
+        StakingInstruction::SetEmergencyMode { enabled } => {
+            process_set_emergency_mode(accounts, enabled, program_id)
+        }
+        StakingInstruction::EmergencyWithdraw => process_emergency_withdraw(accounts, program_id),
+        StakingInstruction::RecoverTokens { amount } => {
+            process_recover_tokens(accounts, amount, program_id)
+        }
+        StakingInstruction::AssertInvariants => process_assert_invariants(accounts, program_id),
+        StakingInstruction::MigrateAccount => process_migrate_account(accounts, program_id),
+        StakingInstruction::MigrateVaultAuthority => {
+            process_migrate_vault_authority(accounts, program_id)
+        }
+    }
+}
+
+pub fn process_initialize_pool(
+    accounts: &[AccountInfo],
+    reward_rate: u64,
+    limits: StakeLimits,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [pool_account, admin_account, rent_sysvar, vault_account, reward_vault, ..] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let rent = Rent::from_account_info(rent_sysvar)?;
+    if !rent.is_exempt(pool_account.lamports(), pool_account.data_len()) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let pool_data = pool_account.try_borrow_data()?;
+    if pool_data.len() != StakingPool::LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if pool_data[..8] != [0; 8] {
+        return Err(StakingError::PoolAlreadyInitialized.into());
+    }
+    drop(pool_data);
+
+    let (vault_authority, vault_authority_bump) =
+        find_program_address(&[b"vault-auth", pool_account.key()], program_id);
+    check_vault_account(vault_account, &vault_authority)?;
+    check_vault_account(reward_vault, &vault_authority)?;
+
+    let mut pool = StakingPool {
+        discriminator: StakingPool::DISCRIMINATOR,
+        version: StakingPool::VERSION,
+        admin: *admin_account.key(),
+        reward_rate,
+        total_staked: 0,
+        reward_per_token: 0,
+        last_update_time: Clock::get()?.unix_timestamp as u64,
+        tip_vault: [0; 32],
+        crank_tip: 0,
+        max_total_stake: 0,
+        max_stake_per_user: 0,
+        min_stake_amount: 0,
+        stake_start: 0,
+        stake_end: 0,
+        timelock_delay: 0,
+        emergency_mode: 0,
+        vault: *vault_account.key(),
+        reward_vault: *reward_vault.key(),
+        committed_rewards: 0,
+        min_stake_age: 0,
+        vault_authority_bump,
+        legacy_vault_authority: 0,
+    };
+    pool.set_limits(&limits)?;
+
+    pool.write_to(pool_account)?;
+    log!("Staking pool initialized");
+    Ok(())
+}
+
+pub fn process_stake(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey) -> ProgramResult {
+    let [
+        pool_account,
+        vault_account,
+        user_wallet,
+        user_token_account,
+        user_stake_info_account,
+        token_program,
+        _system_program,
+        rent_sysvar,
+        ..
+    ] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let existing_amount = if user_stake_info_account.data_is_empty() {
+        None
+    } else {
+        let stake_info = UserStakeInfo::from_account(user_stake_info_account)?;
+        check_position_address(
+            user_stake_info_account,
+            &stake_info,
+            user_wallet.key(),
+            pool_account.key(),
+            program_id,
+        )?;
+        Some(stake_info.amount)
+    };
+
+    let now = Clock::get()?.unix_timestamp as u64;
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    if *vault_account.key() != pool.vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    pool.check_stake(amount, existing_amount.unwrap_or(0), now)?;
+
+    if existing_amount.is_none() {
+        let (expected_pda, bump) = find_program_address(
+            &[b"user-stake", user_wallet.key(), pool_account.key()],
+            program_id,
+        );
+        if user_stake_info_account.key() != &expected_pda {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let lamports = Rent::from_account_info(rent_sysvar)?.minimum_balance(UserStakeInfo::LEN);
+        let bump_seed = [bump];
+        let seeds = [
+            Seed::from(b"user-stake"),
+            Seed::from(user_wallet.key()),
+            Seed::from(pool_account.key()),
+            Seed::from(&bump_seed),
+        ];
+        CreateAccount {
+            from: user_wallet,
+            to: user_stake_info_account,
+            lamports,
+            space: UserStakeInfo::LEN as u64,
+            owner: program_id,
+        }
+        .invoke_signed(&[Signer::from(&seeds)])?;
+
+        UserStakeInfo {
+            discriminator: UserStakeInfo::DISCRIMINATOR,
+            version: UserStakeInfo::VERSION,
+            staker: *user_wallet.key(),
+            amount: 0,
+            last_stake_time: now,
+            claimer: [0; 32],
+            claim_destination: [0; 32],
+            reward_per_token_paid: pool.reward_per_token,
+            rewards: 0,
+            bump,
+            warming_amount: 0,
+        }
+        .write_to(user_stake_info_account)?;
+    }
+
+    if token_program.key() != &pinocchio_token::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Transfer {
+        from: user_token_account,
+        to: vault_account,
+        authority: user_wallet,
+        amount,
+    }
+    .invoke()?;
+    pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+
+    let mut stake_info = UserStakeInfo::from_account_mut(user_stake_info_account)?;
+    pool.settle(&mut stake_info, now)
+        .ok_or(ProgramError::InvalidArgument)?;
+    stake_info.amount = stake_info
+        .amount
+        .checked_add(amount)
+        .ok_or(ProgramError::InvalidArgument)?;
+    // New stake only starts earning once it has sat through the minimum
+    // stake age. A top-up joins stake that is still warming at their
+    // weighted deposit time.
+    stake_info.last_stake_time = lock::top_up_stake_time(
+        timestamp(stake_info.last_stake_time),
+        stake_info.warming_amount,
+        amount,
+        timestamp(now),
+    ) as u64;
+    if pool.min_stake_age != 0 {
+        stake_info.warming_amount += amount;
+    }
+
+    pool.total_staked += amount;
+
+    log!("User staked {} tokens at {}", amount, now);
+    Ok(())
+}
+
+pub fn process_unstake(
+    accounts: &[AccountInfo],
+    amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [
+        pool_account,
+        vault_account,
+        user_wallet,
+        user_token_account,
+        user_stake_info_account,
+        vault_authority,
+        token_program,
+        ..
+    ] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !user_wallet.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut stake_info = UserStakeInfo::from_account_mut(user_stake_info_account)?;
+    check_position_address(
+        user_stake_info_account,
+        &stake_info,
+        user_wallet.key(),
+        pool_account.key(),
+        program_id,
+    )?;
+    if stake_info.amount < amount {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    if *vault_account.key() != pool.vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if token_program.key() != &pinocchio_token::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    check_vault_authority(pool_account.key(), &pool, vault_authority, program_id)?;
+
+    transfer_from_vault(
+        vault_account,
+        user_token_account,
+        vault_authority,
+        pool_account.key(),
+        &pool,
+        amount,
+    )?;
+
+    let now = Clock::get()?.unix_timestamp as u64;
+    pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+    pool.settle(&mut stake_info, now)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    // Withdraw stake that is not earning yet first.
+    stake_info.amount -= amount;
+    stake_info.warming_amount = stake_info.warming_amount.saturating_sub(amount);
+    pool.total_staked -= amount;
+
+    // Keep the position around while it still has rewards to claim.
+    let closed = stake_info.amount == 0 && stake_info.rewards == 0;
+    drop(stake_info);
+    if closed {
+        close_account(user_stake_info_account, user_wallet)?;
+    }
+
+    Ok(())
+}
+
+pub fn process_claim_rewards(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let [
+        pool_account,
+        reward_vault,
+        user_wallet,
+        user_token_account,
+        user_stake_info_account,
+        vault_authority,
+        token_program,
+        clock_sysvar,
+        instructions_sysvar,
+        ..
+    ] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !user_wallet.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut stake_info = load_position(pool_account, user_stake_info_account, program_id)?;
+
+    // The signer is either the staker itself or the claimer it authorized,
+    // who may only pay into the staker's chosen destination.
+    if *user_wallet.key() != stake_info.staker {
+        if stake_info.claimer == [0; 32] || *user_wallet.key() != stake_info.claimer {
+            return Err(StakingError::UnauthorizedClaimer.into());
+        }
+        if *user_token_account.key() != stake_info.claim_destination {
+            return Err(StakingError::InvalidClaimDestination.into());
+        }
+    }
+
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    if *reward_vault.key() != pool.reward_vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if token_program.key() != &pinocchio_token::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    check_vault_authority(pool_account.key(), &pool, vault_authority, program_id)?;
+
+    // A position must not have been topped up earlier in this transaction.
+    // Stake younger than the minimum stake age earns nothing anyway.
+    let now = Clock::from_account_info(clock_sysvar)?.unix_timestamp as u64;
+    check_no_stake_before(
+        instructions_sysvar,
+        user_stake_info_account.key(),
+        program_id,
+    )?;
+
+    pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+    let matured = pool
+        .settle(&mut stake_info, now)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    // A position whose only stake just matured has nothing to claim yet, but
+    // keeps the settlement so that stake starts earning.
+    let reward = stake_info.rewards;
+    if reward == 0 && matured {
+        log!("Matured stake starts earning");
+        return Ok(());
+    }
+    if reward == 0 {
+        return Err(StakingError::NothingToClaim.into());
+    }
+
+    transfer_from_vault(
+        reward_vault,
+        user_token_account,
+        vault_authority,
+        pool_account.key(),
+        &pool,
+        reward,
+    )?;
+
+    pool.committed_rewards = pool.committed_rewards.saturating_sub(reward);
+    stake_info.rewards = 0;
+
+    Ok(())
+}
+
+pub fn process_authorize_claimer(
+    accounts: &[AccountInfo],
+    claimer: Pubkey,
+    destination: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [pool_account, user_wallet, user_stake_info_account, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !user_wallet.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut stake_info = load_position(pool_account, user_stake_info_account, program_id)?;
+    if stake_info.staker != *user_wallet.key() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    stake_info.claimer = claimer;
+    stake_info.claim_destination = destination;
+
+    log!("Authorized claimer, paying into");
+    pubkey::log(&claimer);
+    pubkey::log(&destination);
+    Ok(())
+}
+
+fn load_position<'a>(
+    pool_account: &AccountInfo,
+    user_stake_info_account: &'a AccountInfo,
+    program_id: &Pubkey,
+) -> Result<RefMut<'a, UserStakeInfo>, ProgramError> {
+    if !user_stake_info_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let stake_info = UserStakeInfo::from_account_mut(user_stake_info_account)?;
+    check_position_address(
+        user_stake_info_account,
+        &stake_info,
+        &{ stake_info.staker },
+        pool_account.key(),
+        program_id,
+    )?;
+
+    Ok(stake_info)
+}
+
+pub fn process_get_pending_rewards(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let [pool_account, user_stake_info_account, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let stake_info = load_position(pool_account, user_stake_info_account, program_id)?;
+    let pool = StakingPool::from_account(pool_account)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+
+    let reward = pool
+        .pending_rewards_at(&stake_info, now)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    set_return_data(&reward.to_le_bytes());
+    Ok(())
+}
+
+pub fn process_get_pool_stats(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let [pool_account, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let pool = StakingPool::from_account(pool_account)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+
+    let stats = PoolStats {
+        admin: pool.admin,
+        reward_rate: pool.reward_rate,
+        total_staked: pool.total_staked,
+        emission_per_second: rewards::emission_per_second(pool.reward_rate, pool.total_staked),
+        reward_per_token: pool
+            .reward_per_token_at(now)
+            .ok_or(ProgramError::InvalidArgument)?,
+        last_update_time: pool.last_update_time,
+        timestamp: now,
+    };
+
+    set_return_data(bytemuck::bytes_of(&stats));
+    Ok(())
+}
+
+pub fn process_get_position(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let [pool_account, user_stake_info_account, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let stake_info = load_position(pool_account, user_stake_info_account, program_id)?;
+    let pool = StakingPool::from_account(pool_account)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+
+    let position = PositionInfo {
+        staker: stake_info.staker,
+        amount: stake_info.amount,
+        last_stake_time: stake_info.last_stake_time,
+        pending_rewards: pool
+            .pending_rewards_at(&stake_info, now)
+            .ok_or(ProgramError::InvalidArgument)?,
+        timestamp: now,
+    };
+
+    set_return_data(bytemuck::bytes_of(&position));
+    Ok(())
+}
+
+/// Advances the pool's reward accumulator. Anyone may call it; when the tip
+/// accounts are passed and time has moved on, the caller is paid the pool's
+/// `crank_tip` out of its tip vault.
+pub fn process_update_pool(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let [pool_account, tip_accounts @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+    let tip_due = pool.crank_tip_due(now);
+    pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+
+    let [tip_vault, cranker_token_account, vault_authority, token_program, ..] = tip_accounts
+    else {
+        return Ok(());
+    };
+
+    if !tip_due || pool.crank_tip == 0 {
+        return Ok(());
+    }
+
+    if *tip_vault.key() != pool.tip_vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if token_program.key() != &pinocchio_token::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    check_vault_authority(pool_account.key(), &pool, vault_authority, program_id)?;
+
+    // Pay what is left rather than failing once the vault runs dry.
+    let balance = TokenAccount::from_account_info(tip_vault)?.amount();
+    let tip = pool.crank_tip.min(balance);
+    if tip == 0 {
+        return Ok(());
+    }
+
+    transfer_from_vault(
+        tip_vault,
+        cranker_token_account,
+        vault_authority,
+        pool_account.key(),
+        &pool,
+        tip,
+    )?;
+
+    log!("Pool updated to {}, paid crank tip {}", now, tip);
+    Ok(())
+}
+
+pub fn process_set_crank_tip(
+    accounts: &[AccountInfo],
+    crank_tip: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [pool_account, admin_account, tip_vault, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    pool.check_unlocked()?;
+    check_tip_vault(tip_vault, pool_account.key(), &pool, program_id)?;
+
+    pool.tip_vault = *tip_vault.key();
+    pool.crank_tip = crank_tip;
+
+    log!("Crank tip set to {}", crank_tip);
+    Ok(())
+}
+
+pub fn process_set_stake_limits(
+    accounts: &[AccountInfo],
+    limits: StakeLimits,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [pool_account, admin_account, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    pool.check_unlocked()?;
+
+    pool.set_limits(&limits)?;
+
+    log!("Stake limits updated");
+    Ok(())
+}
+
+pub fn process_set_reward_rate(
+    accounts: &[AccountInfo],
+    reward_rate: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [pool_account, admin_account, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    pool.check_unlocked()?;
+
+    let now = Clock::get()?.unix_timestamp as u64;
+    apply_action(
+        &mut pool,
+        pool_account.key(),
+        AdminAction::SetRewardRate { reward_rate },
+        None,
+        now,
+        program_id,
+    )?;
+
+    log!("Reward rate set to {}", reward_rate);
+    Ok(())
+}
+
+/// Sets the pool's timelock. Only possible directly while the pool has none;
+/// afterwards changing it has to wait out the current delay like any other
+/// admin action.
+pub fn process_set_timelock_delay(
+    accounts: &[AccountInfo],
+    timelock_delay: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [pool_account, admin_account, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    pool.check_unlocked()?;
+
+    pool.timelock_delay = timelock_delay;
+
+    log!("Timelock delay set to {}", timelock_delay);
+    Ok(())
+}
+
+/// Schedules `action` to become executable once the pool's timelock delay has
+/// passed. A pool has at most one queued action at a time.
+pub fn process_queue_action(
+    accounts: &[AccountInfo],
+    action: AdminAction,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [pool_account, admin_account, queued_change_account, _system_program, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let pool = load_admin_pool(pool_account, admin_account, program_id)?;
+
+    let (expected_pda, bump) =
+        find_program_address(&[b"queued-change", pool_account.key()], program_id);
+    if queued_change_account.key() != &expected_pda {
+        return Err(StakingError::InvalidPda.into());
+    }
+    if !queued_change_account.data_is_empty() {
+        return Err(StakingError::ActionAlreadyQueued.into());
+    }
+
+    let now = Clock::get()?.unix_timestamp as u64;
+    let queued_change = QueuedChange {
+        pool: *pool_account.key(),
+        eta: now.saturating_add(pool.timelock_delay),
+        action,
+    };
+    let data = queued_change.to_bytes()?;
+
+    let bump_seed = [bump];
+    let seeds = [
+        Seed::from(b"queued-change"),
+        Seed::from(pool_account.key()),
+        Seed::from(&bump_seed),
+    ];
+    CreateAccount {
+        from: admin_account,
+        to: queued_change_account,
+        lamports: Rent::get()?.minimum_balance(data.len()),
+        space: data.len() as u64,
+        owner: program_id,
+    }
+    .invoke_signed(&[Signer::from(&seeds)])?;
+    queued_change_account
+        .try_borrow_mut_data()?
+        .copy_from_slice(&data);
+
+    log!(
+        "Queued {} until {}",
+        queued_change.action.name(),
+        queued_change.eta
+    );
+    Ok(())
+}
+
+pub fn process_cancel_action(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let [pool_account, admin_account, queued_change_account, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    drop(load_admin_pool(pool_account, admin_account, program_id)?);
+    let queued_change = load_queued_change(pool_account, queued_change_account, program_id)?;
+    close_account(queued_change_account, admin_account)?;
+
+    log!("Cancelled {}", queued_change.action.name());
+    Ok(())
+}
+
+/// Applies the pool's queued action once its eta has passed. Anyone may call
+/// it; the queued change's rent goes back to the admin.
+pub fn process_execute_action(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let [pool_account, admin_account, queued_change_account, rest @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    let tip_vault = rest.first();
+
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    if pool.admin != *admin_account.key() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let queued_change = load_queued_change(pool_account, queued_change_account, program_id)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+    if now < queued_change.eta {
+        return Err(StakingError::TimelockNotExpired.into());
+    }
+
+    apply_action(
+        &mut pool,
+        pool_account.key(),
+        queued_change.action.clone(),
+        tip_vault,
+        now,
+        program_id,
+    )?;
+    close_account(queued_change_account, admin_account)?;
+
+    log!("Executed {}", queued_change.action.name());
+    Ok(())
+}
+
+/// Turns emergency mode on or off. Not subject to the timelock, so the admin
+/// can react at once.
+pub fn process_set_emergency_mode(
+    accounts: &[AccountInfo],
+    enabled: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [pool_account, admin_account, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    pool.emergency_mode = enabled as u8;
+
+    log!("Emergency mode {}", if enabled { "on" } else { "off" });
+    Ok(())
+}
+
+/// Returns a position's whole principal and closes it, forfeiting any rewards.
+/// Only available in emergency mode, and deliberately touches no reward
+/// state, so it keeps working when the reward math does not.
+pub fn process_emergency_withdraw(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let [
+        pool_account,
+        vault_account,
+        user_wallet,
+        user_token_account,
+        user_stake_info_account,
+        vault_authority,
+        token_program,
+        ..
+    ] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !user_wallet.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    if pool.emergency_mode == 0 {
+        return Err(StakingError::EmergencyModeOff.into());
+    }
+    if *vault_account.key() != pool.vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let stake_info = load_position(pool_account, user_stake_info_account, program_id)?;
+    if stake_info.staker != *user_wallet.key() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    if token_program.key() != &pinocchio_token::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    check_vault_authority(pool_account.key(), &pool, vault_authority, program_id)?;
+
+    let amount = stake_info.amount;
+    if amount > 0 {
+        transfer_from_vault(
+            vault_account,
+            user_token_account,
+            vault_authority,
+            pool_account.key(),
+            &pool,
+            amount,
+        )?;
+    }
+
+    // Release what the pool had committed to the position up to the last
+    // update. If the reward math has broken, it simply stays committed.
+    let forfeited = penalty::forfeited_rewards(
+        amount,
+        stake_info.rewards,
+        pool.reward_per_token,
+        stake_info.reward_per_token_paid,
+    );
+    if let Some(forfeited) = forfeited {
+        pool.committed_rewards = pool.committed_rewards.saturating_sub(forfeited);
+    }
+    pool.total_staked = pool.total_staked.saturating_sub(amount);
+    let forfeited = forfeited.unwrap_or(stake_info.rewards);
+    drop(stake_info);
+    close_account(user_stake_info_account, user_wallet)?;
+
+    log!(
+        "Emergency withdrew {} tokens, forfeiting {} in rewards",
+        amount,
+        forfeited
+    );
+    Ok(())
+}
+
+/// Moves `amount` out of a token account owned by the pool's vault authority,
+/// such as tokens sent to a vault by mistake. The vault always keeps
+/// `total_staked` and the reward vault the rewards committed so far.
+pub fn process_recover_tokens(
+    accounts: &[AccountInfo],
+    amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [pool_account, admin_account, source, destination, vault_authority, token_program, ..] =
+        accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    // The program-wide authority also owns other pools' vaults.
+    if pool.legacy_vault_authority != 0 {
+        return Err(StakingError::LegacyVaultAuthority.into());
+    }
+    if token_program.key() != &pinocchio_token::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    check_vault_authority(pool_account.key(), &pool, vault_authority, program_id)?;
+    let balance = check_vault_account(source, vault_authority.key())?;
+
+    let mut locked = 0u64;
+    if *source.key() == pool.vault {
+        locked = pool.total_staked;
+    }
+    if *source.key() == pool.reward_vault {
+        let committed = pool
+            .committed_rewards_at(Clock::get()?.unix_timestamp as u64)
+            .ok_or(ProgramError::InvalidArgument)?;
+        locked = locked.saturating_add(committed);
+    }
+    if amount > balance.saturating_sub(locked) {
+        return Err(StakingError::RecoveryExceedsExcess.into());
+    }
+
+    transfer_from_vault(
+        source,
+        destination,
+        vault_authority,
+        pool_account.key(),
+        &pool,
+        amount,
+    )?;
+
+    log!("Recovered {} tokens", amount);
+    Ok(())
+}
+
+/// Fails unless the vault covers `total_staked` and the reward vault covers
+/// the rewards committed up to now. Anyone may call it.
+pub fn process_assert_invariants(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let [pool_account, vault_account, reward_vault, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let pool = StakingPool::from_account(pool_account)?;
+    if *vault_account.key() != pool.vault || *reward_vault.key() != pool.reward_vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let vault_authority = vault_authority_address(pool_account.key(), &pool, program_id)?;
+    let vault_balance = check_vault_account(vault_account, &vault_authority)?;
+    let mut reward_balance = check_vault_account(reward_vault, &vault_authority)?;
+    let committed = pool
+        .committed_rewards_at(Clock::get()?.unix_timestamp as u64)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    let total_staked = pool.total_staked;
+    if vault_balance < total_staked {
+        return Err(StakingError::VaultUnderfunded.into());
+    }
+    // A pool paying rewards out of its vault needs both on top of each other.
+    if pool.vault == pool.reward_vault {
+        reward_balance -= total_staked;
+    }
+    if reward_balance < committed {
+        return Err(StakingError::RewardVaultUnderfunded.into());
+    }
+
+    log!(
+        "Vault holds {} of {} staked, reward vault {} of {} committed",
+        vault_balance,
+        total_staked,
+        reward_balance,
+        committed
+    );
+    Ok(())
+}
+
+/// Upgrades an account written by an older build of the program to the
+/// current layout in place, growing it if needed.
+pub fn process_migrate_account(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let [account, payer, _system_program, rest @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    let pool = rest.first();
+
+    if !account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !payer.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let data = account.try_borrow_data()?;
+    let migrated = if data.len() == BASELINE_LEN && data.iter().any(|byte| *byte != 0) {
+        // First-release pools and positions share a length, so the caller
+        // says which it is by passing the account itself as the pool.
+        let pool = pool.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if pool.key() == account.key() {
+            let [_, vault, reward_vault, vault_claim, reward_vault_claim, ..] = rest else {
+                return Err(ProgramError::NotEnoughAccountKeys);
+            };
+            migrate_baseline_pool(
+                account,
+                &data,
+                payer,
+                [(vault, vault_claim), (reward_vault, reward_vault_claim)],
+                program_id,
+            )?
+        } else {
+            migrate_baseline_position(account, &data, pool, program_id)?
+        }
+    } else {
+        let location = AccountLocation {
+            address: account.key(),
+            pool: pool.map(|pool| pool.key()),
+            program_id,
+        };
+        migrate(&data, &location)?
+    };
+    drop(data);
+
+    let required = Rent::get()?.minimum_balance(migrated.len());
+    if account.lamports() < required {
+        SystemTransfer {
+            from: payer,
+            to: account,
+            lamports: required - account.lamports(),
+        }
+        .invoke()?;
+    }
+    account.resize(migrated.len())?;
+    account.try_borrow_mut_data()?.copy_from_slice(&migrated);
+
+    log!("Migrated account");
+    pubkey::log(account.key());
+    Ok(())
+}
+
+/// Upgrades a pool written by the first release, which recorded no vaults, so
+/// its admin names them, each with the `VaultClaim` that binds it to this
+/// pool alone. The pool must sign: it was created from a keypair, while a
+/// position of the same length is a PDA and cannot.
+fn migrate_baseline_pool(
+    pool_account: &AccountInfo,
+    data: &[u8],
+    admin_account: &AccountInfo,
+    vaults: [(&AccountInfo, &AccountInfo); 2],
+    program_id: &Pubkey,
+) -> Result<AccountBuf, ProgramError> {
+    if !pool_account.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let baseline = BaselinePool::read(data).map_err(|_| ProgramError::InvalidAccountData)?;
+    if baseline.admin != *admin_account.key() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let (vault_authority, vault_authority_bump) =
+        find_program_address(&[b"vault-auth"], program_id);
+    let [(vault_account, _), (reward_vault, _)] = vaults;
+    check_vault_account(vault_account, &vault_authority)?;
+    check_vault_account(reward_vault, &vault_authority)?;
+
+    // A pool paying rewards out of its stake vault claims it once.
+    let claims = if reward_vault.key() == vault_account.key() {
+        &vaults[..1]
+    } else {
+        &vaults[..]
+    };
+    for (token_account, claim_account) in claims {
+        claim_vault(
+            pool_account.key(),
+            token_account,
+            claim_account,
+            admin_account,
+            program_id,
+        )?;
+    }
+
+    let pool = StakingPool::from_baseline(
+        &baseline,
+        vault_account.key(),
+        reward_vault.key(),
+        vault_authority_bump,
+        Clock::get()?.unix_timestamp as u64,
+    );
+    AccountBuf::of(&pool)
+}
+
+/// Creates the `VaultClaim` binding `token_account` to the pool, failing if
+/// another pool already claimed it.
+fn claim_vault(
+    pool_key: &Pubkey,
+    token_account: &AccountInfo,
+    claim_account: &AccountInfo,
+    payer: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (expected_pda, bump) =
+        find_program_address(&[b"vault-claim", token_account.key()], program_id);
+    if claim_account.key() != &expected_pda {
+        return Err(StakingError::InvalidPda.into());
+    }
+    if !claim_account.data_is_empty() {
+        return Err(StakingError::VaultAlreadyClaimed.into());
+    }
+
+    let bump_seed = [bump];
+    let seeds = [
+        Seed::from(b"vault-claim"),
+        Seed::from(token_account.key()),
+        Seed::from(&bump_seed),
+    ];
+    CreateAccount {
+        from: payer,
+        to: claim_account,
+        lamports: Rent::get()?.minimum_balance(VaultClaim::LEN),
+        space: VaultClaim::LEN as u64,
+        owner: program_id,
+    }
+    .invoke_signed(&[Signer::from(&seeds)])?;
+    VaultClaim {
+        discriminator: VaultClaim::DISCRIMINATOR,
+        version: VaultClaim::VERSION,
+        pool: *pool_key,
+    }
+    .write_to(claim_account)
+}
+
+/// Upgrades a position written by the first release, crediting what it had
+/// earned and adding its stake to `pool_account`, which must already be
+/// migrated.
+fn migrate_baseline_position(
+    account: &AccountInfo,
+    data: &[u8],
+    pool_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<AccountBuf, ProgramError> {
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let baseline = BaselinePosition::read(data).map_err(|_| ProgramError::InvalidAccountData)?;
+    let (address, bump) = find_program_address(
+        &[b"user-stake", &baseline.staker, pool_account.key()],
+        program_id,
+    );
+    if address != *account.key() {
+        return Err(StakingError::InvalidPda.into());
+    }
+
+    let mut pool = StakingPool::from_account_mut(pool_account)?;
+    let now = Clock::get()?.unix_timestamp as u64;
+    pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+    let position = UserStakeInfo::from_baseline(&baseline, bump, &pool, now)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    pool.total_staked = pool
+        .total_staked
+        .checked_add(position.amount)
+        .ok_or(ProgramError::InvalidArgument)?;
+    pool.committed_rewards = pool
+        .committed_rewards
+        .checked_add(position.rewards)
+        .ok_or(ProgramError::InvalidArgument)?;
+    AccountBuf::of(&position)
+}
+
+/// Hands the pool's vault and reward vault from the program-wide vault
+/// authority to the pool's own, once each is shown to be claimed for the
+/// pool. Not subject to the timelock, since no tokens move.
+pub fn process_migrate_vault_authority(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let [
+        pool_account,
+        admin_account,
+        vault_authority,
+        token_program,
+        vault_account,
+        reward_vault,
+        vault_claim,
+        reward_vault_claim,
+        ..
+    ] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let mut pool = load_admin_pool(pool_account, admin_account, program_id)?;
+    if pool.legacy_vault_authority == 0 {
+        return Err(StakingError::VaultAuthorityAlreadyMigrated.into());
+    }
+    if token_program.key() != &pinocchio_token::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    check_vault_authority(pool_account.key(), &pool, vault_authority, program_id)?;
+    let (new_authority, new_bump) =
+        find_program_address(&[b"vault-auth", pool_account.key()], program_id);
+
+    let bump_seed = [pool.vault_authority_bump];
+    let seeds = [Seed::from(b"vault-auth"), Seed::from(&bump_seed)];
+    let expected = [pool.vault, pool.reward_vault];
+    let passed = [
+        (vault_account, vault_claim),
+        (reward_vault, reward_vault_claim),
+    ];
+    let mut moved = 0u64;
+    for (index, (expected_key, (account, claim_account))) in expected.iter().zip(passed).enumerate()
+    {
+        if account.key() != expected_key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if expected[..index].contains(expected_key) {
+            continue;
+        }
+        check_vault_claim(pool_account.key(), account, claim_account, program_id)?;
+        check_vault_account(account, vault_authority.key())?;
+
+        SetAuthority {
+            account,
+            authority: vault_authority,
+            authority_type: AuthorityType::AccountOwner,
+            new_authority: Some(&new_authority),
+        }
+        .invoke_signed(&[Signer::from(&seeds)])?;
+        moved += 1;
+    }
+
+    pool.vault_authority_bump = new_bump;
+    pool.legacy_vault_authority = 0;
+
+    log!(
+        "Moved {} token accounts to the pool's vault authority",
+        moved
+    );
+    Ok(())
+}
+
+fn apply_action(
+    pool: &mut StakingPool,
+    pool_key: &Pubkey,
+    action: AdminAction,
+    tip_vault: Option<&AccountInfo>,
+    now: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    match action {
+        AdminAction::SetRewardRate { reward_rate } => {
+            // Settle what accrued at the old rate before switching.
+            pool.update(now).ok_or(ProgramError::InvalidArgument)?;
+            pool.reward_rate = reward_rate;
+        }
+        AdminAction::SetCrankTip {
+            tip_vault: expected_tip_vault,
+            crank_tip,
+        } => {
+            let tip_vault = tip_vault.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if tip_vault.key() != &expected_tip_vault {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            check_tip_vault(tip_vault, pool_key, pool, program_id)?;
+
+            pool.tip_vault = *tip_vault.key();
+            pool.crank_tip = crank_tip;
+        }
+        AdminAction::SetStakeLimits { limits } => pool.set_limits(&limits)?,
+        AdminAction::SetTimelockDelay { timelock_delay } => pool.timelock_delay = timelock_delay,
+    }
+    Ok(())
+}
+
+/// Loads the pool, checking that `admin_account` is its admin and signed.
+fn load_admin_pool<'a>(
+    pool_account: &'a AccountInfo,
+    admin_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<RefMut<'a, StakingPool>, ProgramError> {
+    if !pool_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !admin_account.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pool = StakingPool::from_account_mut(pool_account)?;
+    if pool.admin != *admin_account.key() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    Ok(pool)
+}
+
+fn load_queued_change(
+    pool_account: &AccountInfo,
+    queued_change_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<QueuedChange, ProgramError> {
+    let (expected_pda, _bump) =
+        find_program_address(&[b"queued-change", pool_account.key()], program_id);
+    if queued_change_account.key() != &expected_pda {
+        return Err(StakingError::InvalidPda.into());
+    }
+    if queued_change_account.data_is_empty() {
+        return Err(StakingError::NoQueuedAction.into());
+    }
+    if !queued_change_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    QueuedChange::load(&queued_change_account.try_borrow_data()?)
+}
+
+/// The pool's vault authority, the PDA that owns its token accounts, derived
+/// from the bump stored on the pool: seeded by vault-auth and the pool, or by
+/// vault-auth alone for pools still on the program-wide authority.
+fn vault_authority_address(
+    pool_key: &Pubkey,
+    pool: &StakingPool,
+    program_id: &Pubkey,
+) -> Result<Pubkey, ProgramError> {
+    let bump = [pool.vault_authority_bump];
+    let seeds: &[&[u8]] = if pool.legacy_vault_authority != 0 {
+        &[b"vault-auth", &bump]
+    } else {
+        &[b"vault-auth", pool_key, &bump]
+    };
+    create_program_address(seeds, program_id).map_err(|_| StakingError::InvalidPda.into())
+}
+
+/// Checks that `vault_authority` is the pool's vault authority.
+fn check_vault_authority(
+    pool_key: &Pubkey,
+    pool: &StakingPool,
+    vault_authority: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if *vault_authority.key() != vault_authority_address(pool_key, pool, program_id)? {
+        return Err(StakingError::InvalidPda.into());
+    }
+    Ok(())
+}
+
+/// Checks that `user_stake_info_account` is the PDA of a position held by
+/// `staker` in the pool, derived from the bump stored on the position.
+fn check_position_address(
+    user_stake_info_account: &AccountInfo,
+    stake_info: &UserStakeInfo,
+    staker: &[u8],
+    pool_key: &Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let expected_pda = create_program_address(
+        &[b"user-stake", staker, pool_key, &[stake_info.bump]],
+        program_id,
+    )
+    .map_err(|_| StakingError::InvalidPda)?;
+    if user_stake_info_account.key() != &expected_pda {
+        return Err(StakingError::InvalidPda.into());
+    }
+    Ok(())
+}
+
+/// Checks that `tip_vault` can fund crank tips: a token account of the
+/// pool's own vault authority that is neither its vault nor its reward vault.
+fn check_tip_vault(
+    tip_vault: &AccountInfo,
+    pool_key: &Pubkey,
+    pool: &StakingPool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if pool.legacy_vault_authority != 0 {
+        return Err(StakingError::LegacyVaultAuthority.into());
+    }
+    if *tip_vault.key() == pool.vault || *tip_vault.key() == pool.reward_vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    check_vault_account(tip_vault, &vault_authority_address(pool_key, pool, program_id)?)?;
+    Ok(())
+}
+
+/// Checks that `claim_account` is the `VaultClaim` of `token_account` and
+/// names the pool.
+fn check_vault_claim(
+    pool_key: &Pubkey,
+    token_account: &AccountInfo,
+    claim_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (expected_pda, _bump) =
+        find_program_address(&[b"vault-claim", token_account.key()], program_id);
+    if claim_account.key() != &expected_pda {
+        return Err(StakingError::InvalidPda.into());
+    }
+    if !claim_account.is_owned_by(program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if VaultClaim::from_account(claim_account)?.pool != *pool_key {
+        return Err(StakingError::VaultAlreadyClaimed.into());
+    }
+    Ok(())
+}
+
+/// Checks that `account` is an initialized token account owned by
+/// `vault_authority`, and returns its balance.
+fn check_vault_account(
+    account: &AccountInfo,
+    vault_authority: &Pubkey,
+) -> Result<u64, ProgramError> {
+    if !account.is_owned_by(&pinocchio_token::ID) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let token_account = TokenAccount::from_account_info(account)?;
+    if !token_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if token_account.owner() != vault_authority {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(token_account.amount())
+}
+
+/// Pays `amount` out of `from`, a token account owned by the pool's vault
+/// authority.
+fn transfer_from_vault(
+    from: &AccountInfo,
+    to: &AccountInfo,
+    vault_authority: &AccountInfo,
+    pool_key: &Pubkey,
+    pool: &StakingPool,
+    amount: u64,
+) -> ProgramResult {
+    let bump_seed = [pool.vault_authority_bump];
+    let per_pool = [
+        Seed::from(b"vault-auth"),
+        Seed::from(pool_key),
+        Seed::from(&bump_seed),
+    ];
+    let program_wide = [Seed::from(b"vault-auth"), Seed::from(&bump_seed)];
+    let seeds: &[Seed] = if pool.legacy_vault_authority != 0 {
+        &program_wide
+    } else {
+        &per_pool
+    };
+    Transfer {
+        from,
+        to,
+        authority: vault_authority,
+        amount,
+    }
+    .invoke_signed(&[Signer::from(seeds)])
+}
+
+/// Fails if an instruction before the current one in this transaction is a
+/// `Stake` into `position`.
+fn check_no_stake_before(
+    instructions: &AccountInfo,
+    position: &Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let instructions = Instructions::try_from(instructions)?;
+    let current = instructions.load_current_index();
+
+    for index in 0..current {
+        let ix = instructions.load_instruction_at(index as usize)?;
+        if ix.get_program_id() == program_id
+            && matches!(
+                StakingInstruction::unpack(ix.get_instruction_data()),
+                Ok(StakingInstruction::Stake { .. })
+            )
+            && (0..)
+                .map_while(|meta| ix.get_account_meta_at(meta).ok())
+                .any(|meta| meta.key == *position)
+        {
+            return Err(StakingError::StakedInSameTransaction.into());
+        }
+    }
+    Ok(())
+}
+
+fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    *destination.try_borrow_mut_lamports()? += account.lamports();
+    *account.try_borrow_mut_lamports()? = 0;
+    account.try_borrow_mut_data()?.fill(0);
+    Ok(())
+}
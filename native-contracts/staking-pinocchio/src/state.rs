@@ -0,0 +1,860 @@
+//! Account layouts, byte for byte those of `staking_contract::state`.
+
+use crate::error::StakingError;
+use bytemuck::{Pod, Zeroable};
+use core::{
+    mem::size_of,
+    ops::{Deref, DerefMut},
+};
+use pinocchio::{
+    account_info::{AccountInfo, Ref, RefMut},
+    program_error::ProgramError,
+    pubkey::{find_program_address, Pubkey},
+};
+use staking_core::{crank, limits::DepositLimits, lock, rewards, timestamp};
+
+/// Bytes every account starts with: an 8-byte discriminator naming its type,
+/// followed by the version of its layout.
+pub const ACCOUNT_HEADER_LEN: usize = 8 + 1;
+
+/// Reads borsh-encoded fields off the front of a byte slice.
+pub struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self(data)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ProgramError> {
+        let (head, rest) = self
+            .0
+            .split_first_chunk::<N>()
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ProgramError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub fn u64(&mut self) -> Result<u64, ProgramError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, ProgramError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ProgramError::InvalidInstructionData),
+        }
+    }
+
+    pub fn pubkey(&mut self) -> Result<Pubkey, ProgramError> {
+        self.take()
+    }
+
+    /// Fails unless every byte was read, as borsh's `try_from_slice` does.
+    pub fn finish(self) -> Result<(), ProgramError> {
+        if !self.0.is_empty() {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        Ok(())
+    }
+}
+
+/// Account data built on the stack, large enough for any layout.
+pub struct AccountBuf {
+    data: [u8; StakingPool::LEN],
+    len: usize,
+}
+
+impl AccountBuf {
+    fn new() -> Self {
+        Self {
+            data: [0; StakingPool::LEN],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<(), ProgramError> {
+        let end = self.len + bytes.len();
+        self.data
+            .get_mut(self.len..end)
+            .ok_or(ProgramError::InvalidAccountData)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    /// A copy of `account`'s bytes.
+    pub fn of<T: Pod>(account: &T) -> Result<Self, ProgramError> {
+        let mut buf = Self::new();
+        buf.push(bytemuck::bytes_of(account))?;
+        Ok(buf)
+    }
+}
+
+impl Deref for AccountBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl DerefMut for AccountBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data[..self.len]
+    }
+}
+
+/// An account owned by the program, tagged with a discriminator and a layout
+/// version so one account type cannot be passed off as another.
+pub trait ProgramAccount: Sized {
+    /// First 8 bytes of `sha256("account:<TypeName>")`.
+    const DISCRIMINATOR: [u8; 8];
+    /// Layout version written by this build of the program.
+    const VERSION: u8;
+
+    /// Checks the discriminator and layout version at the start of `data`.
+    fn check_header(data: &[u8]) -> Result<(), ProgramError> {
+        if data.len() < ACCOUNT_HEADER_LEN || data[..8] != Self::DISCRIMINATOR {
+            return Err(StakingError::InvalidAccountDiscriminator.into());
+        }
+        if data[8] != Self::VERSION {
+            return Err(StakingError::AccountVersionMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// Rewrites `body`, the data after the header of an account saved at
+    /// layout `version`, into the current layout. Returns `None` if `body` is
+    /// not a `Self` at that version.
+    fn upgrade(
+        version: u8,
+        body: &[u8],
+        location: &AccountLocation,
+    ) -> Result<Option<AccountBuf>, ProgramError>;
+
+    /// `body` behind the current header, followed by `appended`.
+    fn with_header(body: &[u8], appended: &[u8]) -> Result<AccountBuf, ProgramError> {
+        let mut upgraded = AccountBuf::new();
+        upgraded.push(&Self::DISCRIMINATOR)?;
+        upgraded.push(&[Self::VERSION])?;
+        upgraded.push(body)?;
+        upgraded.push(appended)?;
+        Ok(upgraded)
+    }
+}
+
+/// Where an account being migrated lives, for layouts that store the bump of
+/// a PDA derived from it.
+pub struct AccountLocation<'a> {
+    pub address: &'a Pubkey,
+    /// Pool a position belongs to, which positions do not record themselves.
+    pub pool: Option<&'a Pubkey>,
+    pub program_id: &'a Pubkey,
+}
+
+/// A `ProgramAccount` read and written in place. Fields of a packed struct
+/// cannot be borrowed: copy them out with `{ pool.total_staked }` where a
+/// reference would be taken.
+pub trait ZeroCopyAccount: ProgramAccount + Pod {
+    fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        Self::check_header(data)?;
+        bytemuck::try_from_bytes(data).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Borrows `account`'s data as `Self`.
+    fn from_account(account: &AccountInfo) -> Result<Ref<'_, Self>, ProgramError> {
+        let data = account.try_borrow_data()?;
+        Self::load(&data)?;
+        Ok(Ref::map(data, |data| bytemuck::from_bytes(data)))
+    }
+
+    /// Mutably borrows `account`'s data as `Self`.
+    fn from_account_mut(account: &AccountInfo) -> Result<RefMut<'_, Self>, ProgramError> {
+        let data = account.try_borrow_mut_data()?;
+        Self::load(&data)?;
+        Ok(RefMut::map(data, |data| bytemuck::from_bytes_mut(data)))
+    }
+
+    /// Writes `self` over a newly allocated account.
+    fn write_to(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+        let mut data = account.try_borrow_mut_data()?;
+        if data.len() != size_of::<Self>() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        data.copy_from_slice(bytemuck::bytes_of(self));
+        Ok(())
+    }
+}
+
+/// Rewrites an account's data into the current layout of its type. Accounts
+/// written by the first release have no header and need more than their own
+/// data to upgrade; see `BaselinePool` and `BaselinePosition`.
+pub fn migrate(data: &[u8], location: &AccountLocation) -> Result<AccountBuf, ProgramError> {
+    if data.len() >= ACCOUNT_HEADER_LEN {
+        let (version, body) = (data[8], &data[ACCOUNT_HEADER_LEN..]);
+        let upgraded = match data[..8].try_into() {
+            Ok(StakingPool::DISCRIMINATOR) => {
+                Some(upgrade_tagged::<StakingPool>(version, body, location))
+            }
+            Ok(UserStakeInfo::DISCRIMINATOR) => {
+                Some(upgrade_tagged::<UserStakeInfo>(version, body, location))
+            }
+            Ok(QueuedChange::DISCRIMINATOR) => {
+                Some(upgrade_tagged::<QueuedChange>(version, body, location))
+            }
+            Ok(VaultClaim::DISCRIMINATOR) => {
+                Some(upgrade_tagged::<VaultClaim>(version, body, location))
+            }
+            _ => None,
+        };
+        if let Some(upgraded) = upgraded {
+            return upgraded;
+        }
+    }
+    if data.iter().all(|byte| *byte == 0) {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Err(ProgramError::InvalidAccountData)
+}
+
+/// Size of the untagged pools and positions written by the first release,
+/// which share a length and so cannot be told apart by their data alone.
+pub const BASELINE_LEN: usize = 32 + 8 + 8;
+
+/// A pool as the first release wrote it. It recorded no vaults, and its
+/// token accounts are owned by the program-wide vault authority.
+#[derive(Debug)]
+pub struct BaselinePool {
+    pub admin: Pubkey,
+    pub reward_rate: u64,
+    pub total_staked: u64,
+}
+
+impl BaselinePool {
+    pub fn read(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut reader = Reader::new(data);
+        let pool = Self {
+            admin: reader.pubkey()?,
+            reward_rate: reader.u64()?,
+            total_staked: reader.u64()?,
+        };
+        reader.finish()?;
+        Ok(pool)
+    }
+}
+
+/// A position as the first release wrote it, at the same PDA positions use
+/// now. It earned `reward_rate` per token for every second since
+/// `last_stake_time`, paid out at the pool's rate when claimed.
+#[derive(Debug)]
+pub struct BaselinePosition {
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub last_stake_time: u64,
+}
+
+impl BaselinePosition {
+    pub fn read(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut reader = Reader::new(data);
+        let position = Self {
+            staker: reader.pubkey()?,
+            amount: reader.u64()?,
+            last_stake_time: reader.u64()?,
+        };
+        reader.finish()?;
+        Ok(position)
+    }
+}
+
+fn upgrade_tagged<T: ProgramAccount>(
+    version: u8,
+    body: &[u8],
+    location: &AccountLocation,
+) -> Result<AccountBuf, ProgramError> {
+    if version == T::VERSION {
+        return Err(StakingError::AccountAlreadyMigrated.into());
+    }
+    T::upgrade(version, body, location)?.ok_or(StakingError::AccountVersionMismatch.into())
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct StakingPool {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub admin: Pubkey,
+    pub reward_rate: u64,
+    pub total_staked: u64,
+    pub reward_per_token: u128,
+    pub last_update_time: u64,
+    pub tip_vault: Pubkey,
+    pub crank_tip: u64,
+    pub max_total_stake: u64,
+    pub max_stake_per_user: u64,
+    pub min_stake_amount: u64,
+    pub stake_start: u64,
+    pub stake_end: u64,
+    pub timelock_delay: u64,
+    pub emergency_mode: u8,
+    pub vault: Pubkey,
+    pub reward_vault: Pubkey,
+    pub committed_rewards: u64,
+    pub min_stake_age: u64,
+    pub vault_authority_bump: u8,
+    pub legacy_vault_authority: u8,
+}
+
+impl ProgramAccount for StakingPool {
+    const DISCRIMINATOR: [u8; 8] = [203, 19, 214, 220, 220, 154, 24, 102];
+    const VERSION: u8 = 3;
+
+    fn upgrade(
+        version: u8,
+        body: &[u8],
+        location: &AccountLocation,
+    ) -> Result<Option<AccountBuf>, ProgramError> {
+        let current = Self::LEN - ACCOUNT_HEADER_LEN;
+        match version {
+            1 if body.len() == current - 2 => {
+                let (_, bump) =
+                    find_program_address(&[b"vault-auth", location.address], location.program_id);
+                Self::with_header(body, &[bump, 0]).map(Some)
+            }
+            2 if body.len() == current - 1 => Self::with_header(body, &[0]).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl ZeroCopyAccount for StakingPool {}
+
+const _: () = assert!(size_of::<StakingPool>() == StakingPool::LEN);
+
+impl StakingPool {
+    pub const LEN: usize = ACCOUNT_HEADER_LEN
+        + 32
+        + 8
+        + 8
+        + 16
+        + 8
+        + 32
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 1
+        + 32
+        + 32
+        + 8
+        + 8
+        + 1
+        + 1;
+
+    /// Upgrades a pool written by the first release, which pays from `vault`
+    /// and `reward_vault` under the program-wide vault authority whose bump is
+    /// `vault_authority_bump`. Its stake is left out of `total_staked` until
+    /// each position is migrated.
+    pub fn from_baseline(
+        baseline: &BaselinePool,
+        vault: &Pubkey,
+        reward_vault: &Pubkey,
+        vault_authority_bump: u8,
+        now: u64,
+    ) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            admin: baseline.admin,
+            reward_rate: baseline.reward_rate,
+            total_staked: 0,
+            reward_per_token: 0,
+            last_update_time: now,
+            tip_vault: [0; 32],
+            crank_tip: 0,
+            max_total_stake: 0,
+            max_stake_per_user: 0,
+            min_stake_amount: 0,
+            stake_start: 0,
+            stake_end: 0,
+            timelock_delay: 0,
+            emergency_mode: 0,
+            vault: *vault,
+            reward_vault: *reward_vault,
+            committed_rewards: 0,
+            min_stake_age: 0,
+            vault_authority_bump,
+            legacy_vault_authority: 1,
+        }
+    }
+
+    /// Fails if admin actions have to be queued rather than applied directly.
+    pub fn check_unlocked(&self) -> Result<(), StakingError> {
+        if self.timelock_delay != 0 {
+            return Err(StakingError::TimelockActive);
+        }
+        Ok(())
+    }
+
+    pub fn set_limits(&mut self, limits: &StakeLimits) -> Result<(), StakingError> {
+        DepositLimits {
+            stake_start: timestamp(limits.stake_start),
+            stake_end: timestamp(limits.stake_end),
+            ..DepositLimits::default()
+        }
+        .validate()?;
+
+        self.max_total_stake = limits.max_total_stake;
+        self.max_stake_per_user = limits.max_stake_per_user;
+        self.min_stake_amount = limits.min_stake_amount;
+        self.stake_start = limits.stake_start;
+        self.stake_end = limits.stake_end;
+        self.min_stake_age = limits.min_stake_age;
+        Ok(())
+    }
+
+    /// Checks that a deposit of `amount` at `now` into a position already
+    /// holding `position_amount` stays within the pool's limits.
+    pub fn check_stake(
+        &self,
+        amount: u64,
+        position_amount: u64,
+        now: u64,
+    ) -> Result<(), StakingError> {
+        if self.emergency_mode != 0 {
+            return Err(StakingError::PoolInEmergency);
+        }
+        self.deposit_limits().check_deposit(
+            amount,
+            position_amount,
+            self.total_staked,
+            timestamp(now),
+        )?;
+        Ok(())
+    }
+
+    fn deposit_limits(&self) -> DepositLimits {
+        DepositLimits {
+            max_total_stake: self.max_total_stake,
+            max_stake_per_user: self.max_stake_per_user,
+            min_stake_amount: self.min_stake_amount,
+            stake_start: timestamp(self.stake_start),
+            stake_end: timestamp(self.stake_end),
+        }
+    }
+
+    /// Moves everything `position` earned up to the pool's accumulator into
+    /// its rewards, including what its warming tranche earned since maturing,
+    /// and releases what the pool committed to the tranche before that. The
+    /// tranche earns like the rest of the stake from then on if it is old
+    /// enough at `now`. The pool must be updated first. Returns whether the
+    /// tranche started earning, or `None` on overflow.
+    pub fn settle(&mut self, position: &mut UserStakeInfo, now: u64) -> Option<bool> {
+        let (forfeited, matured_rewards) =
+            self.split_warming(position, self.reward_per_token, now)?;
+        position.rewards = position
+            .pending_rewards(self.reward_per_token)?
+            .checked_add(matured_rewards)?;
+        position.reward_per_token_paid = self.reward_per_token;
+        self.committed_rewards = self.committed_rewards.saturating_sub(forfeited);
+
+        let matured = position.warming_amount != 0
+            && !lock::is_warming(
+                timestamp(position.last_stake_time),
+                timestamp(self.min_stake_age),
+                timestamp(now),
+            );
+        if matured {
+            position.warming_amount = 0;
+        }
+        Some(matured)
+    }
+
+    /// Rewards `position` could claim if the pool were updated and the
+    /// position settled at `now`. Returns `None` on overflow.
+    pub fn pending_rewards_at(&self, position: &UserStakeInfo, now: u64) -> Option<u64> {
+        let reward_per_token = self.reward_per_token_at(now)?;
+        let (_, matured_rewards) = self.split_warming(position, reward_per_token, now)?;
+        position
+            .pending_rewards(reward_per_token)?
+            .checked_add(matured_rewards)
+    }
+
+    /// Splits the rewards `position`'s warming tranche saw up to
+    /// `reward_per_token` at `now` into those from before it matured, which
+    /// it forfeits, and those from after, which it earned.
+    fn split_warming(
+        &self,
+        position: &UserStakeInfo,
+        reward_per_token: u128,
+        now: u64,
+    ) -> Option<(u64, u64)> {
+        let split = lock::maturity_reward_per_token(
+            position.reward_per_token_paid,
+            reward_per_token,
+            self.reward_rate,
+            lock::matures_at(
+                timestamp(position.last_stake_time),
+                timestamp(self.min_stake_age),
+            ),
+            timestamp(now),
+        );
+        Some((
+            position.warming_rewards(split)?,
+            rewards::earned(position.warming_amount, reward_per_token, split)?,
+        ))
+    }
+
+    /// Whether cranking the pool at `now` earns the crank tip.
+    pub fn crank_tip_due(&self, now: u64) -> bool {
+        crank::tip_due(timestamp(self.last_update_time), timestamp(now))
+    }
+
+    /// `reward_per_token` as it would be if the pool were updated at `now`.
+    /// Returns `None` on overflow.
+    pub fn reward_per_token_at(&self, now: u64) -> Option<u128> {
+        rewards::reward_per_token_at(
+            self.reward_per_token,
+            self.reward_rate,
+            timestamp(self.last_update_time),
+            timestamp(now),
+        )
+    }
+
+    /// `committed_rewards` as it would be if the pool were updated at `now`.
+    /// Returns `None` on overflow.
+    pub fn committed_rewards_at(&self, now: u64) -> Option<u64> {
+        let accrued = rewards::earned(
+            self.total_staked,
+            self.reward_per_token_at(now)?,
+            self.reward_per_token,
+        )?;
+
+        accrued.checked_add(self.committed_rewards)
+    }
+
+    /// Advances the accumulator to `now`, returning whether any time passed.
+    pub fn update(&mut self, now: u64) -> Option<bool> {
+        if now <= self.last_update_time {
+            return Some(false);
+        }
+
+        self.committed_rewards = self.committed_rewards_at(now)?;
+        self.reward_per_token = self.reward_per_token_at(now)?;
+        self.last_update_time = now;
+        Some(true)
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct UserStakeInfo {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub last_stake_time: u64,
+    pub claimer: Pubkey,
+    pub claim_destination: Pubkey,
+    pub reward_per_token_paid: u128,
+    pub rewards: u64,
+    pub bump: u8,
+    pub warming_amount: u64,
+}
+
+impl ProgramAccount for UserStakeInfo {
+    const DISCRIMINATOR: [u8; 8] = [219, 233, 236, 123, 28, 113, 89, 56];
+    const VERSION: u8 = 3;
+
+    fn upgrade(
+        version: u8,
+        body: &[u8],
+        location: &AccountLocation,
+    ) -> Result<Option<AccountBuf>, ProgramError> {
+        let current = Self::LEN - ACCOUNT_HEADER_LEN;
+        match version {
+            1 if body.len() == current - 9 => {
+                let pool = location.pool.ok_or(ProgramError::NotEnoughAccountKeys)?;
+                let (address, bump) =
+                    find_program_address(&[b"user-stake", &body[..32], pool], location.program_id);
+                if address != *location.address {
+                    return Err(StakingError::InvalidPda.into());
+                }
+                Self::with_header(body, &[bump, 0, 0, 0, 0, 0, 0, 0, 0]).map(Some)
+            }
+            2 if body.len() == current - 8 => Self::with_header(body, &[0; 8]).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl ZeroCopyAccount for UserStakeInfo {}
+
+const _: () = assert!(size_of::<UserStakeInfo>() == UserStakeInfo::LEN);
+
+impl UserStakeInfo {
+    pub const LEN: usize = ACCOUNT_HEADER_LEN + 32 + 8 + 8 + 32 + 32 + 16 + 8 + 1 + 8;
+
+    /// Upgrades a position written by the first release into `pool`, which
+    /// must be updated to `now` first, crediting what the first release would
+    /// have paid had it claimed at `now`. Returns `None` on overflow.
+    pub fn from_baseline(
+        baseline: &BaselinePosition,
+        bump: u8,
+        pool: &StakingPool,
+        now: u64,
+    ) -> Option<Self> {
+        let owed = rewards::reward_per_token_at(
+            0,
+            pool.reward_rate,
+            timestamp(baseline.last_stake_time),
+            timestamp(now),
+        )?;
+
+        Some(Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            staker: baseline.staker,
+            amount: baseline.amount,
+            last_stake_time: baseline.last_stake_time,
+            claimer: [0; 32],
+            claim_destination: [0; 32],
+            reward_per_token_paid: pool.reward_per_token,
+            rewards: rewards::earned(baseline.amount, owed, 0)?,
+            bump,
+            warming_amount: 0,
+        })
+    }
+
+    /// Rewards claimable once the pool's accumulator reaches
+    /// `reward_per_token`. Returns `None` on overflow.
+    pub fn pending_rewards(&self, reward_per_token: u128) -> Option<u64> {
+        let earning = self.amount - self.warming_amount;
+        rewards::earned(earning, reward_per_token, self.reward_per_token_paid)?
+            .checked_add(self.rewards)
+    }
+
+    /// Rewards the pool commits to the warming tranche until the accumulator
+    /// reaches `reward_per_token`. Returns `None` on overflow.
+    pub fn warming_rewards(&self, reward_per_token: u128) -> Option<u64> {
+        rewards::earned(
+            self.warming_amount,
+            reward_per_token,
+            self.reward_per_token_paid,
+        )
+    }
+}
+
+/// Admin action waiting out the pool's timelock, stored at the PDA seeded by
+/// `queued-change` and the pool. Its borsh layout depends on the action, so
+/// it is encoded by hand rather than read in place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedChange {
+    pub pool: Pubkey,
+    /// Unix time from which `ExecuteAction` may apply `action`.
+    pub eta: u64,
+    pub action: AdminAction,
+}
+
+impl ProgramAccount for QueuedChange {
+    const DISCRIMINATOR: [u8; 8] = [178, 209, 212, 240, 49, 65, 50, 3];
+    const VERSION: u8 = 1;
+
+    fn upgrade(
+        _version: u8,
+        _body: &[u8],
+        _location: &AccountLocation,
+    ) -> Result<Option<AccountBuf>, ProgramError> {
+        // Queued changes have only ever been written at version 1.
+        Ok(None)
+    }
+}
+
+impl QueuedChange {
+    /// Decodes `data`, checking its discriminator and layout version.
+    pub fn load(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::check_header(data)?;
+        Self::read_body(&data[ACCOUNT_HEADER_LEN..]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    fn read_body(body: &[u8]) -> Result<Self, ProgramError> {
+        let mut reader = Reader::new(body);
+        let queued_change = Self {
+            pool: reader.pubkey()?,
+            eta: reader.u64()?,
+            action: AdminAction::read(&mut reader)?,
+        };
+        reader.finish()?;
+        Ok(queued_change)
+    }
+
+    /// Encodes `self` as the account data `load` reads back.
+    pub fn to_bytes(&self) -> Result<AccountBuf, ProgramError> {
+        let mut data = AccountBuf::new();
+        data.push(&Self::DISCRIMINATOR)?;
+        data.push(&[Self::VERSION])?;
+        data.push(&self.pool)?;
+        data.push(&self.eta.to_le_bytes())?;
+        self.action.write(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Marks a token account of the program-wide vault authority as belonging to
+/// one pool migrated from the first release, stored at the PDA seeded by
+/// `vault-claim` and the token account.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct VaultClaim {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub pool: Pubkey,
+}
+
+impl ProgramAccount for VaultClaim {
+    const DISCRIMINATOR: [u8; 8] = [4, 165, 43, 219, 187, 176, 163, 230];
+    const VERSION: u8 = 1;
+
+    fn upgrade(
+        _version: u8,
+        _body: &[u8],
+        _location: &AccountLocation,
+    ) -> Result<Option<AccountBuf>, ProgramError> {
+        // Vault claims have only ever been written at version 1.
+        Ok(None)
+    }
+}
+
+impl ZeroCopyAccount for VaultClaim {}
+
+const _: () = assert!(size_of::<VaultClaim>() == VaultClaim::LEN);
+
+impl VaultClaim {
+    pub const LEN: usize = ACCOUNT_HEADER_LEN + 32;
+}
+
+/// Pool configuration changes that can be queued behind the timelock, each
+/// mirroring the admin instruction of the same name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminAction {
+    SetRewardRate { reward_rate: u64 },
+    SetCrankTip { tip_vault: Pubkey, crank_tip: u64 },
+    SetStakeLimits { limits: StakeLimits },
+    SetTimelockDelay { timelock_delay: u64 },
+}
+
+impl AdminAction {
+    pub fn read(reader: &mut Reader) -> Result<Self, ProgramError> {
+        Ok(match reader.u8()? {
+            0 => Self::SetRewardRate {
+                reward_rate: reader.u64()?,
+            },
+            1 => Self::SetCrankTip {
+                tip_vault: reader.pubkey()?,
+                crank_tip: reader.u64()?,
+            },
+            2 => Self::SetStakeLimits {
+                limits: StakeLimits::read(reader)?,
+            },
+            3 => Self::SetTimelockDelay {
+                timelock_delay: reader.u64()?,
+            },
+            _ => return Err(ProgramError::InvalidInstructionData),
+        })
+    }
+
+    fn write(&self, data: &mut AccountBuf) -> Result<(), ProgramError> {
+        match self {
+            Self::SetRewardRate { reward_rate } => {
+                data.push(&[0])?;
+                data.push(&reward_rate.to_le_bytes())
+            }
+            Self::SetCrankTip {
+                tip_vault,
+                crank_tip,
+            } => {
+                data.push(&[1])?;
+                data.push(tip_vault)?;
+                data.push(&crank_tip.to_le_bytes())
+            }
+            Self::SetStakeLimits { limits } => {
+                data.push(&[2])?;
+                data.push(bytemuck::bytes_of(limits))
+            }
+            Self::SetTimelockDelay { timelock_delay } => {
+                data.push(&[3])?;
+                data.push(&timelock_delay.to_le_bytes())
+            }
+        }
+    }
+
+    /// Variant name, for logs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SetRewardRate { .. } => "SetRewardRate",
+            Self::SetCrankTip { .. } => "SetCrankTip",
+            Self::SetStakeLimits { .. } => "SetStakeLimits",
+            Self::SetTimelockDelay { .. } => "SetTimelockDelay",
+        }
+    }
+}
+
+/// Deposit limits passed to `InitializePool` and `SetStakeLimits`, plus how
+/// long a deposit must sit before it earns rewards. The default places no
+/// limits on staking.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct StakeLimits {
+    pub max_total_stake: u64,
+    pub max_stake_per_user: u64,
+    pub min_stake_amount: u64,
+    pub stake_start: u64,
+    pub stake_end: u64,
+    pub min_stake_age: u64,
+}
+
+impl StakeLimits {
+    pub fn read(reader: &mut Reader) -> Result<Self, ProgramError> {
+        Ok(Self {
+            max_total_stake: reader.u64()?,
+            max_stake_per_user: reader.u64()?,
+            min_stake_amount: reader.u64()?,
+            stake_start: reader.u64()?,
+            stake_end: reader.u64()?,
+            min_stake_age: reader.u64()?,
+        })
+    }
+}
+
+/// Returned by `GetPoolStats` through `set_return_data`, laid out as its
+/// borsh encoding.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct PoolStats {
+    pub admin: Pubkey,
+    pub reward_rate: u64,
+    pub total_staked: u64,
+    pub emission_per_second: u64,
+    pub reward_per_token: u128,
+    pub last_update_time: u64,
+    pub timestamp: u64,
+}
+
+/// Returned by `GetPosition` through `set_return_data`, laid out as its
+/// borsh encoding.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct PositionInfo {
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub last_stake_time: u64,
+    pub pending_rewards: u64,
+    pub timestamp: u64,
+}
@@ -0,0 +1,11 @@
+//! When cranking a pool's accumulator earns its crank tip.
+
+/// Time the accumulator must have stood still before a crank earns the tip,
+/// so a pool pays at most one tip per interval however often it is cranked.
+pub const TIP_INTERVAL: i64 = 60;
+
+/// Whether a crank at `now` of a pool last updated at `last_update_time`
+/// earns the tip.
+pub fn tip_due(last_update_time: i64, now: i64) -> bool {
+    now.saturating_sub(last_update_time) >= TIP_INTERVAL
+}
@@ -0,0 +1,11 @@
+/// Why a staking rule rejected an operation. Each program maps these onto
+/// its `StakingError` variant of the same name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    StakeWindowNotOpen,
+    StakeWindowClosed,
+    StakeBelowMinimum,
+    PoolCapExceeded,
+    UserCapExceeded,
+    InvalidStakeWindow,
+}
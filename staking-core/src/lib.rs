@@ -0,0 +1,22 @@
+//! Staking rules shared by the native, pinocchio and Anchor staking programs,
+//! kept free of any Solana dependency so they can be tested off-chain.
+//!
+//! Times are unix timestamps in seconds, as `i64` like the Solana clock.
+//! Programs storing them as `u64` convert with [`timestamp`].
+
+#![no_std]
+
+pub mod crank;
+mod error;
+pub mod limits;
+pub mod lock;
+pub mod penalty;
+pub mod rewards;
+
+pub use error::Error;
+
+/// `seconds` as a timestamp, saturating at `i64::MAX` so comparisons with the
+/// clock keep their order.
+pub fn timestamp(seconds: u64) -> i64 {
+    i64::try_from(seconds).unwrap_or(i64::MAX)
+}
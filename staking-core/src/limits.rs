@@ -0,0 +1,60 @@
+//! Deposit caps and the stake window.
+
+use crate::Error;
+
+/// Limits on deposits into a pool. Zero caps and a zero `stake_end` place no
+/// limit; the default places none at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DepositLimits {
+    /// Cap on the pool's total stake.
+    pub max_total_stake: u64,
+    /// Cap on a single position's amount.
+    pub max_stake_per_user: u64,
+    /// Smallest amount a single deposit may add.
+    pub min_stake_amount: u64,
+    /// Time deposits open at.
+    pub stake_start: i64,
+    /// Time deposits close at.
+    pub stake_end: i64,
+}
+
+impl DepositLimits {
+    /// Fails if the stake window closes before it opens.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.stake_end != 0 && self.stake_end <= self.stake_start {
+            return Err(Error::InvalidStakeWindow);
+        }
+        Ok(())
+    }
+
+    /// Checks that depositing `amount` at `now` into a position holding
+    /// `position_amount`, in a pool holding `total_staked`, stays within the
+    /// limits. Existing stake above a lowered cap is kept; it just cannot grow.
+    pub fn check_deposit(
+        &self,
+        amount: u64,
+        position_amount: u64,
+        total_staked: u64,
+        now: i64,
+    ) -> Result<(), Error> {
+        if now < self.stake_start {
+            return Err(Error::StakeWindowNotOpen);
+        }
+        if self.stake_end != 0 && now >= self.stake_end {
+            return Err(Error::StakeWindowClosed);
+        }
+        if amount < self.min_stake_amount {
+            return Err(Error::StakeBelowMinimum);
+        }
+        if self.max_total_stake != 0 && total_staked.saturating_add(amount) > self.max_total_stake
+        {
+            return Err(Error::PoolCapExceeded);
+        }
+        if self.max_stake_per_user != 0
+            && position_amount.saturating_add(amount) > self.max_stake_per_user
+        {
+            return Err(Error::UserCapExceeded);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+//! How long a deposit waits before it earns rewards.
+
+/// Time stake deposited at `last_stake_time` starts earning, once it has been
+/// staked for `min_stake_age` seconds.
+pub fn matures_at(last_stake_time: i64, min_stake_age: i64) -> i64 {
+    last_stake_time.saturating_add(min_stake_age)
+}
+
+/// Whether stake deposited at `last_stake_time` is still too young to earn
+/// at `now`.
+pub fn is_warming(last_stake_time: i64, min_stake_age: i64, now: i64) -> bool {
+    now < matures_at(last_stake_time, min_stake_age)
+}
+
+/// Accumulator value at which the growth a warming tranche saw, from
+/// `reward_per_token_paid` up to `reward_per_token` at `now`, splits at the
+/// tranche's maturity `matures_at`. Growth up to it came before maturity and
+/// is forfeited; growth past it is earned. Growth since maturity is taken at
+/// `reward_rate`, the rate in force at `now`, and the split stays within
+/// `reward_per_token_paid..=reward_per_token`.
+pub fn maturity_reward_per_token(
+    reward_per_token_paid: u128,
+    reward_per_token: u128,
+    reward_rate: u64,
+    matures_at: i64,
+    now: i64,
+) -> u128 {
+    let since_maturity = now.saturating_sub(matures_at).max(0) as u128;
+
+    reward_per_token
+        .saturating_sub(since_maturity.saturating_mul(reward_rate as u128))
+        .max(reward_per_token_paid)
+        .min(reward_per_token)
+}
+
+/// Deposit time of a warming tranche of `warming_amount`, deposited at
+/// `last_stake_time`, once `amount` more joins it at `now`: the two times
+/// weighted by amount and rounded up, so a top-up neither restarts the
+/// tranche's clock nor lets the new stake skip most of its own wait.
+pub fn top_up_stake_time(last_stake_time: i64, warming_amount: u64, amount: u64, now: i64) -> i64 {
+    if warming_amount == 0 {
+        return now;
+    }
+    if now <= last_stake_time {
+        return last_stake_time;
+    }
+
+    let elapsed = now.abs_diff(last_stake_time) as u128;
+    let total = warming_amount as u128 + amount as u128;
+    let shift = (elapsed * amount as u128).div_ceil(total);
+    // `shift` is at most `elapsed`, so this lands between the two times.
+    last_stake_time.saturating_add_unsigned(shift as u64)
+}
@@ -0,0 +1,17 @@
+//! What a staker gives up by withdrawing in an emergency. The principal
+//! comes back in full, but every reward the position holds is forfeited.
+
+use crate::rewards;
+
+/// Rewards a position of `amount` tokens forfeits by withdrawing in an
+/// emergency: the `rewards` settled into it plus everything its whole stake,
+/// warming or not, accrued from `reward_per_token_paid` to `reward_per_token`.
+/// Returns `None` on overflow.
+pub fn forfeited_rewards(
+    amount: u64,
+    rewards: u64,
+    reward_per_token: u128,
+    reward_per_token_paid: u128,
+) -> Option<u64> {
+    rewards::earned(amount, reward_per_token, reward_per_token_paid)?.checked_add(rewards)
+}
@@ -0,0 +1,35 @@
+//! Reward accrual. A pool's `reward_per_token` accumulator grows by its
+//! reward rate every second, and a position earns its amount times the
+//! growth since it was last settled.
+
+/// `reward_per_token` advanced from `last_update_time` to `now` at
+/// `reward_rate` per token per second. A `now` before `last_update_time`
+/// leaves it unchanged. Returns `None` on overflow.
+pub fn reward_per_token_at(
+    reward_per_token: u128,
+    reward_rate: u64,
+    last_update_time: i64,
+    now: i64,
+) -> Option<u128> {
+    let elapsed = now.saturating_sub(last_update_time).max(0) as u128;
+
+    elapsed
+        .checked_mul(reward_rate as u128)?
+        .checked_add(reward_per_token)
+}
+
+/// Rewards `amount` tokens earned while the accumulator went from
+/// `reward_per_token_paid` to `reward_per_token`. Returns `None` if they do
+/// not fit in a `u64`.
+pub fn earned(amount: u64, reward_per_token: u128, reward_per_token_paid: u128) -> Option<u64> {
+    let earned = reward_per_token
+        .saturating_sub(reward_per_token_paid)
+        .checked_mul(amount as u128)?;
+
+    u64::try_from(earned).ok()
+}
+
+/// Rewards the whole pool accrues per second, saturating at `u64::MAX`.
+pub fn emission_per_second(reward_rate: u64, total_staked: u64) -> u64 {
+    reward_rate.saturating_mul(total_staked)
+}
@@ -0,0 +1,24 @@
+use staking_core::crank::{tip_due, TIP_INTERVAL};
+
+#[test]
+fn tips_are_due_once_the_interval_passes() {
+    assert!(!tip_due(1_000, 1_000));
+    assert!(!tip_due(1_000, 1_000 + TIP_INTERVAL - 1));
+    assert!(tip_due(1_000, 1_000 + TIP_INTERVAL));
+    assert!(tip_due(1_000, 5_000));
+}
+
+#[test]
+fn back_to_back_cranks_pay_one_tip() {
+    let first = 1_000 + TIP_INTERVAL;
+    assert!(tip_due(1_000, first));
+    // The first crank moved the accumulator to `first`.
+    assert!(!tip_due(first, first));
+    assert!(!tip_due(first, first + 1));
+}
+
+#[test]
+fn elapsed_time_saturates() {
+    assert!(tip_due(i64::MIN, i64::MAX));
+    assert!(!tip_due(i64::MAX, i64::MIN));
+}
@@ -0,0 +1,158 @@
+use staking_core::{limits::DepositLimits, Error};
+
+const NOW: i64 = 1_000;
+
+#[test]
+fn default_limits_allow_any_deposit() {
+    let limits = DepositLimits::default();
+
+    assert_eq!(limits.validate(), Ok(()));
+    for amount in [0, 1, u64::MAX] {
+        assert_eq!(limits.check_deposit(amount, u64::MAX, u64::MAX, 0), Ok(()));
+        assert_eq!(limits.check_deposit(amount, 0, 0, i64::MAX), Ok(()));
+    }
+}
+
+#[test]
+fn stake_window_must_end_after_it_starts() {
+    let window = |stake_start, stake_end| DepositLimits {
+        stake_start,
+        stake_end,
+        ..DepositLimits::default()
+    };
+
+    assert_eq!(window(100, 0).validate(), Ok(()));
+    assert_eq!(window(100, 101).validate(), Ok(()));
+    assert_eq!(window(-10, -5).validate(), Ok(()));
+    assert_eq!(window(100, 100).validate(), Err(Error::InvalidStakeWindow));
+    assert_eq!(window(100, 99).validate(), Err(Error::InvalidStakeWindow));
+    assert_eq!(window(0, -1).validate(), Err(Error::InvalidStakeWindow));
+}
+
+#[test]
+fn deposits_only_go_through_while_the_window_is_open() {
+    let limits = DepositLimits {
+        stake_start: 100,
+        stake_end: 200,
+        ..DepositLimits::default()
+    };
+
+    assert_eq!(limits.check_deposit(1, 0, 0, 99), Err(Error::StakeWindowNotOpen));
+    assert_eq!(limits.check_deposit(1, 0, 0, 100), Ok(()));
+    assert_eq!(limits.check_deposit(1, 0, 0, 199), Ok(()));
+    assert_eq!(limits.check_deposit(1, 0, 0, 200), Err(Error::StakeWindowClosed));
+
+    let open_ended = DepositLimits {
+        stake_end: 0,
+        ..limits
+    };
+    assert_eq!(open_ended.check_deposit(1, 0, 0, i64::MAX), Ok(()));
+}
+
+#[test]
+fn deposits_below_the_minimum_are_rejected() {
+    let limits = DepositLimits {
+        min_stake_amount: 10,
+        ..DepositLimits::default()
+    };
+
+    assert_eq!(limits.check_deposit(9, 0, 0, NOW), Err(Error::StakeBelowMinimum));
+    assert_eq!(limits.check_deposit(0, 50, 0, NOW), Err(Error::StakeBelowMinimum));
+    assert_eq!(limits.check_deposit(10, 0, 0, NOW), Ok(()));
+}
+
+#[test]
+fn pool_cap_counts_every_position() {
+    let limits = DepositLimits {
+        max_total_stake: 100,
+        ..DepositLimits::default()
+    };
+
+    assert_eq!(limits.check_deposit(40, 0, 60, NOW), Ok(()));
+    assert_eq!(limits.check_deposit(41, 0, 60, NOW), Err(Error::PoolCapExceeded));
+    assert_eq!(limits.check_deposit(1, 0, u64::MAX, NOW), Err(Error::PoolCapExceeded));
+    assert_eq!(limits.check_deposit(u64::MAX, 0, 1, NOW), Err(Error::PoolCapExceeded));
+}
+
+#[test]
+fn user_cap_counts_only_the_position() {
+    let limits = DepositLimits {
+        max_stake_per_user: 100,
+        ..DepositLimits::default()
+    };
+
+    assert_eq!(limits.check_deposit(40, 60, 1_000_000, NOW), Ok(()));
+    assert_eq!(limits.check_deposit(41, 60, 0, NOW), Err(Error::UserCapExceeded));
+    assert_eq!(limits.check_deposit(u64::MAX, 1, 0, NOW), Err(Error::UserCapExceeded));
+}
+
+#[test]
+fn positions_above_a_lowered_cap_cannot_grow() {
+    let limits = DepositLimits {
+        max_stake_per_user: 100,
+        ..DepositLimits::default()
+    };
+
+    assert_eq!(limits.check_deposit(0, 150, 150, NOW), Err(Error::UserCapExceeded));
+    assert_eq!(limits.check_deposit(1, 150, 150, NOW), Err(Error::UserCapExceeded));
+}
+
+#[test]
+fn rules_are_checked_in_a_fixed_order() {
+    let limits = DepositLimits {
+        max_total_stake: 10,
+        max_stake_per_user: 10,
+        min_stake_amount: 5,
+        stake_start: 100,
+        stake_end: 200,
+    };
+
+    // A deposit breaking every rule reports the window first, then the
+    // minimum, then the pool cap and finally the user cap.
+    assert_eq!(limits.check_deposit(1, 100, 100, 50), Err(Error::StakeWindowNotOpen));
+    assert_eq!(limits.check_deposit(1, 100, 100, 250), Err(Error::StakeWindowClosed));
+    assert_eq!(limits.check_deposit(1, 100, 100, 150), Err(Error::StakeBelowMinimum));
+    assert_eq!(limits.check_deposit(6, 100, 100, 150), Err(Error::PoolCapExceeded));
+    assert_eq!(limits.check_deposit(6, 100, 0, 150), Err(Error::UserCapExceeded));
+    assert_eq!(limits.check_deposit(6, 0, 0, 150), Ok(()));
+}
+
+#[test]
+fn deposits_match_a_reference_model_over_small_values() {
+    for max_total_stake in 0..6 {
+        for max_stake_per_user in 0..6 {
+            for min_stake_amount in 0..4 {
+                let limits = DepositLimits {
+                    max_total_stake,
+                    max_stake_per_user,
+                    min_stake_amount,
+                    stake_start: 2,
+                    stake_end: 4,
+                };
+                for amount in 0..7 {
+                    for position_amount in 0..7 {
+                        for total_staked in position_amount..8 {
+                            for now in 0..6 {
+                                let allowed = (2..4).contains(&now)
+                                    && amount >= min_stake_amount
+                                    && (max_total_stake == 0
+                                        || total_staked + amount <= max_total_stake)
+                                    && (max_stake_per_user == 0
+                                        || position_amount + amount <= max_stake_per_user);
+
+                                assert_eq!(
+                                    limits
+                                        .check_deposit(amount, position_amount, total_staked, now)
+                                        .is_ok(),
+                                    allowed,
+                                    "{limits:?} amount {amount} position {position_amount} \
+                                     total {total_staked} now {now}",
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use staking_core::lock::{is_warming, matures_at, maturity_reward_per_token, top_up_stake_time};
+
+#[test]
+fn deposits_mature_once_the_minimum_age_passes() {
+    assert_eq!(matures_at(1_000, 60), 1_060);
+    assert!(is_warming(1_000, 60, 1_059));
+    assert!(!is_warming(1_000, 60, 1_060));
+    assert!(!is_warming(1_000, 60, 5_000));
+}
+
+#[test]
+fn zero_age_matures_right_after_staking() {
+    assert!(!is_warming(1_000, 0, 1_000));
+    assert!(is_warming(1_000, 0, 999));
+}
+
+#[test]
+fn maturity_saturates() {
+    assert_eq!(matures_at(i64::MAX, 1), i64::MAX);
+    assert_eq!(matures_at(i64::MAX - 5, i64::MAX), i64::MAX);
+    assert!(!is_warming(i64::MAX - 5, 60, i64::MAX));
+    assert!(is_warming(1, i64::MAX, i64::MAX - 1));
+}
+
+#[test]
+fn maturity_matches_a_reference_model_over_small_values() {
+    for last_stake_time in -5..5 {
+        for min_stake_age in 0..5 {
+            for now in -10..10 {
+                assert_eq!(
+                    is_warming(last_stake_time, min_stake_age, now),
+                    now - last_stake_time < min_stake_age,
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn warming_growth_splits_at_maturity() {
+    // Paid at 100, now at 500 growing 2 per second: matured 50 seconds ago,
+    // so the last 100 of growth is earned.
+    assert_eq!(maturity_reward_per_token(100, 500, 2, 1_000, 1_050), 400);
+    // Not yet matured: all of it is forfeited.
+    assert_eq!(maturity_reward_per_token(100, 500, 2, 1_051, 1_050), 500);
+    assert_eq!(maturity_reward_per_token(100, 500, 2, 1_050, 1_050), 500);
+    // Matured before the last settlement: all of it is earned.
+    assert_eq!(maturity_reward_per_token(100, 500, 2, 0, 1_050), 100);
+    assert_eq!(maturity_reward_per_token(100, 500, 0, 0, 1_050), 500);
+}
+
+#[test]
+fn warming_split_handles_extreme_values() {
+    assert_eq!(maturity_reward_per_token(0, 1_000, u64::MAX, i64::MIN, i64::MAX), 0);
+    assert_eq!(
+        maturity_reward_per_token(0, u128::MAX, u64::MAX, i64::MAX, i64::MIN),
+        u128::MAX
+    );
+    assert_eq!(maturity_reward_per_token(7, 5, 1, 0, 10), 5);
+}
+
+#[test]
+fn top_ups_join_the_warming_tranche_at_their_weighted_time() {
+    // Nothing warming: the top-up starts a tranche of its own.
+    assert_eq!(top_up_stake_time(1_000, 0, 50, 2_000), 2_000);
+    // Equal amounts meet halfway, rounding towards the later time.
+    assert_eq!(top_up_stake_time(1_000, 100, 100, 1_030), 1_015);
+    assert_eq!(top_up_stake_time(1_000, 100, 100, 1_031), 1_016);
+    assert_eq!(top_up_stake_time(1_000, 300, 100, 1_040), 1_010);
+    assert_eq!(top_up_stake_time(1_000, 100, 0, 1_040), 1_000);
+    // A clock behind the tranche keeps its time.
+    assert_eq!(top_up_stake_time(1_000, 100, 100, 900), 1_000);
+}
+
+#[test]
+fn top_up_time_handles_extreme_values() {
+    assert_eq!(top_up_stake_time(i64::MIN, u64::MAX, u64::MAX, i64::MAX), 0);
+    assert_eq!(top_up_stake_time(i64::MIN, 1, u64::MAX, i64::MAX), i64::MAX);
+    assert_eq!(
+        top_up_stake_time(i64::MIN, u64::MAX, 1, i64::MAX),
+        i64::MIN + 1
+    );
+}
+
+#[test]
+fn top_up_time_matches_a_reference_model_over_small_values() {
+    for last_stake_time in -5i64..5 {
+        for now in last_stake_time..10 {
+            for warming_amount in 1u64..5 {
+                for amount in 0u64..5 {
+                    let weighted =
+                        last_stake_time as f64 * warming_amount as f64 + now as f64 * amount as f64;
+                    let expected = (weighted / (warming_amount + amount) as f64).ceil() as i64;
+                    assert_eq!(
+                        top_up_stake_time(last_stake_time, warming_amount, amount, now),
+                        expected,
+                    );
+                }
+            }
+        }
+    }
+}
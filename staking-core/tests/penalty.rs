@@ -0,0 +1,40 @@
+use staking_core::penalty::forfeited_rewards;
+
+#[test]
+fn emergency_withdrawal_forfeits_settled_and_accrued_rewards() {
+    assert_eq!(forfeited_rewards(100, 0, 50, 50), Some(0));
+    assert_eq!(forfeited_rewards(100, 7, 50, 50), Some(7));
+    assert_eq!(forfeited_rewards(100, 7, 50, 20), Some(3_007));
+    assert_eq!(forfeited_rewards(0, 7, 50, 20), Some(7));
+    // An accumulator behind the position accrued nothing.
+    assert_eq!(forfeited_rewards(100, 7, 20, 50), Some(7));
+}
+
+#[test]
+fn forfeited_rewards_fail_instead_of_overflowing() {
+    assert_eq!(forfeited_rewards(1, u64::MAX, 1, 0), None);
+    assert_eq!(forfeited_rewards(2, 0, u64::MAX as u128, 0), None);
+    assert_eq!(forfeited_rewards(u64::MAX, 0, u128::MAX, 0), None);
+    assert_eq!(forfeited_rewards(1, u64::MAX - 1, 1, 0), Some(u64::MAX));
+}
+
+#[test]
+fn forfeited_rewards_match_a_reference_model_over_small_values() {
+    for amount in 0u64..6 {
+        for rewards in 0u64..6 {
+            for reward_per_token_paid in 0u128..6 {
+                for reward_per_token in 0u128..6 {
+                    let expected = if reward_per_token > reward_per_token_paid {
+                        amount * (reward_per_token - reward_per_token_paid) as u64 + rewards
+                    } else {
+                        rewards
+                    };
+                    assert_eq!(
+                        forfeited_rewards(amount, rewards, reward_per_token, reward_per_token_paid),
+                        Some(expected),
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use staking_core::{
+    rewards::{earned, emission_per_second, reward_per_token_at},
+    timestamp,
+};
+
+#[test]
+fn accumulator_grows_by_rate_each_second() {
+    assert_eq!(reward_per_token_at(0, 5, 100, 100), Some(0));
+    assert_eq!(reward_per_token_at(0, 5, 100, 101), Some(5));
+    assert_eq!(reward_per_token_at(7, 5, 100, 110), Some(57));
+    assert_eq!(reward_per_token_at(7, 0, 100, 1_000), Some(7));
+}
+
+#[test]
+fn accumulator_ignores_a_clock_behind_the_last_update() {
+    assert_eq!(reward_per_token_at(42, 5, 100, 99), Some(42));
+    assert_eq!(reward_per_token_at(42, 5, 100, i64::MIN), Some(42));
+    assert_eq!(reward_per_token_at(42, 5, i64::MAX, -1), Some(42));
+}
+
+#[test]
+fn accumulator_handles_extreme_times() {
+    // The elapsed time saturates rather than wrapping.
+    assert_eq!(
+        reward_per_token_at(0, 1, i64::MIN, i64::MAX),
+        Some(i64::MAX as u128)
+    );
+    assert_eq!(
+        reward_per_token_at(0, u64::MAX, 0, i64::MAX),
+        Some(i64::MAX as u128 * u64::MAX as u128)
+    );
+}
+
+#[test]
+fn accumulator_reports_overflow() {
+    assert_eq!(reward_per_token_at(u128::MAX, 1, 0, 1), None);
+    assert_eq!(reward_per_token_at(u128::MAX, 0, 0, 1), Some(u128::MAX));
+    assert_eq!(reward_per_token_at(u128::MAX - 1, 1, 0, 1), Some(u128::MAX));
+}
+
+#[test]
+fn accrual_does_not_depend_on_how_often_the_pool_updates() {
+    for rate in [0, 1, 3, 1_000] {
+        let mut reward_per_token = 0;
+        let mut last_update_time = 0;
+        for now in [1, 2, 5, 5, 13, 40, 41] {
+            reward_per_token =
+                reward_per_token_at(reward_per_token, rate, last_update_time, now).unwrap();
+            last_update_time = now;
+        }
+        assert_eq!(reward_per_token_at(0, rate, 0, 41), Some(reward_per_token));
+    }
+}
+
+#[test]
+fn positions_earn_their_share_of_accumulator_growth() {
+    assert_eq!(earned(0, 1_000, 0), Some(0));
+    assert_eq!(earned(10, 0, 0), Some(0));
+    assert_eq!(earned(10, 7, 7), Some(0));
+    assert_eq!(earned(10, 12, 7), Some(50));
+    assert_eq!(earned(3, 100, 0), Some(300));
+}
+
+#[test]
+fn positions_paid_ahead_of_the_accumulator_earn_nothing() {
+    assert_eq!(earned(10, 7, 12), Some(0));
+    assert_eq!(earned(u64::MAX, 0, u128::MAX), Some(0));
+}
+
+#[test]
+fn earnings_that_do_not_fit_a_u64_overflow() {
+    assert_eq!(earned(u64::MAX, 1, 0), Some(u64::MAX));
+    assert_eq!(earned(u64::MAX, 2, 0), None);
+    assert_eq!(earned(2, u64::MAX as u128, 0), None);
+    assert_eq!(earned(2, u128::MAX, 0), None);
+    assert_eq!(earned(1, u64::MAX as u128 + 1, 1), Some(u64::MAX));
+}
+
+#[test]
+fn earnings_of_split_positions_add_up() {
+    for (reward_per_token, paid) in [(0, 0), (5, 0), (1_000, 999), (77, 13)] {
+        for amount in 0..50 {
+            for part in 0..=amount {
+                assert_eq!(
+                    earned(part, reward_per_token, paid).unwrap()
+                        + earned(amount - part, reward_per_token, paid).unwrap(),
+                    earned(amount, reward_per_token, paid).unwrap()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn pool_emission_is_rate_times_stake() {
+    assert_eq!(emission_per_second(0, 1_000), 0);
+    assert_eq!(emission_per_second(3, 1_000), 3_000);
+    assert_eq!(emission_per_second(u64::MAX, 2), u64::MAX);
+}
+
+#[test]
+fn stored_seconds_convert_to_timestamps_in_order() {
+    assert_eq!(timestamp(0), 0);
+    assert_eq!(timestamp(1_700_000_000), 1_700_000_000);
+    assert_eq!(timestamp(i64::MAX as u64), i64::MAX);
+    assert_eq!(timestamp(i64::MAX as u64 + 1), i64::MAX);
+    assert_eq!(timestamp(u64::MAX), i64::MAX);
+}
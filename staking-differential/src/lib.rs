@@ -0,0 +1,662 @@
+//! Runs the native and Anchor staking programs side by side in one
+//! `solana-program-test` bank, so the same operations can be replayed against
+//! both and what their users end up with compared.
+//!
+//! The programs pay rewards differently: the native program transfers them
+//! out of a funded reward vault, the Anchor program mints them. Each program
+//! gets its own mint, and a `Snapshot` counts rewards paid either way.
+
+use anchor_lang::{
+    prelude::AccountInfo, solana_program::entrypoint::ProgramResult, system_program,
+    AccountDeserialize, InstructionData, ToAccountMetas,
+};
+use solana_program_test::*;
+use solana_sdk::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, sysvar,
+    transaction::Transaction,
+};
+use staking::state::UserStakeAccount;
+use staking_contract::{
+    instruction::StakingInstruction,
+    state::{StakingPool, UserStakeInfo, ZeroCopyAccount},
+};
+
+/// Tokens the native pool's reward vault starts with, far more than any
+/// sequence pays out.
+pub const REWARD_FUNDING: u64 = 1 << 60;
+
+fn anchor_entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    staking::entry(program_id, accounts, data)
+}
+
+/// SplitMix64, so a failing sequence can be replayed from its seed without
+/// pulling in a random number crate.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`, or 0 if `n` is 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        self.next_u64() % n
+    }
+}
+
+/// Pool settings both programs are initialized with.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolConfig {
+    pub reward_rate: u64,
+    pub max_total_stake: u64,
+    pub max_stake_per_user: u64,
+    pub min_stake_amount: u64,
+    pub min_stake_age: u64,
+}
+
+impl PoolConfig {
+    /// A reward rate and, about half the time each, caps, a minimum deposit
+    /// and a minimum stake age scaled to users holding `balance` tokens.
+    pub fn random(rng: &mut Rng, balance: u64) -> Self {
+        let mut limit = |max: u64| {
+            if rng.below(2) == 0 {
+                0
+            } else {
+                rng.below(max) + 1
+            }
+        };
+
+        let max_total_stake = limit(balance * 2);
+        let max_stake_per_user = limit(balance);
+        let min_stake_amount = limit(balance / 10);
+        let min_stake_age = limit(300);
+
+        Self {
+            reward_rate: [0, 1, 3, 1_000][rng.below(4) as usize],
+            max_total_stake,
+            max_stake_per_user,
+            min_stake_amount,
+            min_stake_age,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    Stake { user: usize, amount: u64 },
+    /// `None` unstakes the whole position.
+    Unstake { user: usize, amount: Option<u64> },
+    Claim { user: usize },
+    Warp { seconds: i64 },
+}
+
+impl Op {
+    /// An operation by one of `users` users holding about `balance` tokens
+    /// each. Amounts sometimes exceed what a user has, so failures are
+    /// compared too.
+    pub fn random(rng: &mut Rng, users: usize, balance: u64) -> Self {
+        let user = rng.below(users as u64) as usize;
+
+        match rng.below(20) {
+            0..=6 => Op::Stake {
+                user,
+                amount: rng.below(balance / 2),
+            },
+            7..=9 => Op::Unstake { user, amount: None },
+            10..=11 => Op::Unstake {
+                user,
+                amount: Some(rng.below(balance / 2)),
+            },
+            12..=15 => Op::Claim { user },
+            16..=18 => Op::Warp {
+                seconds: rng.below(600) as i64 + 1,
+            },
+            _ => Op::Warp {
+                seconds: rng.below(7 * 86_400) as i64,
+            },
+        }
+    }
+}
+
+/// What one program holds for its users after an operation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Each user's token balance.
+    pub balances: Vec<u64>,
+    /// Each user's staked amount and settled rewards, if it has a position.
+    pub positions: Vec<Option<(u64, u64)>>,
+    /// Principal held by the pool's stake vault.
+    pub staked: u64,
+    /// Rewards paid out so far.
+    pub paid: u64,
+}
+
+struct User {
+    keypair: Keypair,
+    native_tokens: Pubkey,
+    anchor_tokens: Pubkey,
+}
+
+pub struct Harness {
+    context: ProgramTestContext,
+    now: i64,
+    native_pool: Pubkey,
+    native_vault: Pubkey,
+    native_reward_vault: Pubkey,
+    native_vault_authority: Pubkey,
+    anchor_mint: Pubkey,
+    anchor_pool: Pubkey,
+    anchor_vault: Pubkey,
+    /// Supply of `anchor_mint` before any rewards were minted.
+    anchor_supply: u64,
+    users: Vec<User>,
+}
+
+impl Harness {
+    /// Starts both programs with a pool configured as `config` and `users`
+    /// users holding `balance` tokens of each program's mint.
+    pub async fn new(users: usize, balance: u64, config: PoolConfig) -> Self {
+        let mut program_test = ProgramTest::new(
+            "staking_contract",
+            staking_contract::ID,
+            processor!(staking_contract::process_instruction),
+        );
+        program_test.add_program("staking", staking::ID, processor!(anchor_entry));
+        let mut context = program_test.start_with_context().await;
+        let now = context
+            .banks_client
+            .get_sysvar::<Clock>()
+            .await
+            .unwrap()
+            .unix_timestamp;
+        let payer = context.payer.pubkey();
+
+        let native_mint = create_mint(&mut context).await;
+        let anchor_mint = create_mint(&mut context).await;
+
+        let native_pool = Keypair::new();
+        let (native_vault_authority, _) = Pubkey::find_program_address(
+            &[b"vault-auth", native_pool.pubkey().as_ref()],
+            &staking_contract::ID,
+        );
+        let native_vault =
+            create_token_account(&mut context, &native_mint, &native_vault_authority).await;
+        let native_reward_vault =
+            create_token_account(&mut context, &native_mint, &native_vault_authority).await;
+        let (anchor_pool, _) =
+            Pubkey::find_program_address(&[b"staking_pool", anchor_mint.as_ref()], &staking::ID);
+        let anchor_vault = create_token_account(&mut context, &anchor_mint, &anchor_pool).await;
+
+        let mut harness = Self {
+            context,
+            now,
+            native_pool: native_pool.pubkey(),
+            native_vault,
+            native_reward_vault,
+            native_vault_authority,
+            anchor_mint,
+            anchor_pool,
+            anchor_vault,
+            anchor_supply: balance * users as u64,
+            users: Vec::new(),
+        };
+
+        for _ in 0..users {
+            let keypair = Keypair::new();
+            let native_tokens =
+                create_token_account(&mut harness.context, &native_mint, &keypair.pubkey()).await;
+            let anchor_tokens =
+                create_token_account(&mut harness.context, &anchor_mint, &keypair.pubkey()).await;
+            let ixs = [
+                system_instruction::transfer(&payer, &keypair.pubkey(), 1_000_000_000),
+                mint_to(&native_mint, &native_tokens, &payer, balance),
+                mint_to(&anchor_mint, &anchor_tokens, &payer, balance),
+            ];
+            harness.send(&ixs, &[]).await.unwrap();
+
+            harness.users.push(User {
+                keypair,
+                native_tokens,
+                anchor_tokens,
+            });
+        }
+
+        let rent = harness.context.banks_client.get_rent().await.unwrap();
+        let native_init = [
+            mint_to(&native_mint, &native_reward_vault, &payer, REWARD_FUNDING),
+            system_instruction::create_account(
+                &payer,
+                &native_pool.pubkey(),
+                rent.minimum_balance(StakingPool::LEN),
+                StakingPool::LEN as u64,
+                &staking_contract::ID,
+            ),
+            native_ix(
+                vec![
+                    AccountMeta::new(native_pool.pubkey(), false),
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new_readonly(sysvar::rent::ID, false),
+                    AccountMeta::new_readonly(native_vault, false),
+                    AccountMeta::new_readonly(native_reward_vault, false),
+                ],
+                StakingInstruction::InitializePool {
+                    reward_rate: config.reward_rate,
+                    limits: staking_contract::state::StakeLimits {
+                        max_total_stake: config.max_total_stake,
+                        max_stake_per_user: config.max_stake_per_user,
+                        min_stake_amount: config.min_stake_amount,
+                        min_stake_age: config.min_stake_age,
+                        ..Default::default()
+                    },
+                },
+            ),
+        ];
+        harness.send(&native_init, &[&native_pool]).await.unwrap();
+
+        // The Anchor pool mints rewards, so it takes over the mint once the
+        // users are funded.
+        let anchor_init = [
+            Instruction {
+                program_id: staking::ID,
+                accounts: staking::accounts::InitializePool {
+                    staking_pool: anchor_pool,
+                    authority: payer,
+                    vault: anchor_vault,
+                    mint: anchor_mint,
+                    system_program: system_program::ID,
+                    token_program: spl_token::ID,
+                    rent: sysvar::rent::ID,
+                }
+                .to_account_metas(None),
+                data: staking::instruction::InitializePool {
+                    reward_rate: config.reward_rate,
+                    limits: staking::state::StakeLimits {
+                        max_total_stake: config.max_total_stake,
+                        max_stake_per_user: config.max_stake_per_user,
+                        min_stake_amount: config.min_stake_amount,
+                        min_stake_age: config.min_stake_age as i64,
+                        ..Default::default()
+                    },
+                }
+                .data(),
+            },
+            spl_token::instruction::set_authority(
+                &spl_token::ID,
+                &anchor_mint,
+                Some(&anchor_pool),
+                spl_token::instruction::AuthorityType::MintTokens,
+                &payer,
+                &[],
+            )
+            .unwrap(),
+        ];
+        harness.send(&anchor_init, &[]).await.unwrap();
+
+        harness
+    }
+
+    /// Applies `op` to both programs at the same clock time, returning
+    /// whether the native and the Anchor program accepted it.
+    pub async fn apply(&mut self, op: Op) -> (bool, bool) {
+        match op {
+            Op::Warp { seconds } => {
+                self.now += seconds;
+                (true, true)
+            }
+            Op::Stake { user, amount } => {
+                let native = self.native_stake_ix(user, amount);
+                let anchor = self.anchor_stake_ix(user, amount);
+                self.send_both(user, native, anchor).await
+            }
+            Op::Unstake { user, amount } => {
+                let native_amount = match amount {
+                    Some(amount) => amount,
+                    None => self.native_position(user).await.map_or(0, |(amount, _)| amount),
+                };
+                let anchor_amount = match amount {
+                    Some(amount) => amount,
+                    None => self.anchor_position(user).await.map_or(0, |(amount, _)| amount),
+                };
+                let native = self.native_unstake_ix(user, native_amount);
+                let anchor = self.anchor_unstake_ix(user, anchor_amount);
+                self.send_both(user, native, anchor).await
+            }
+            Op::Claim { user } => {
+                let native = self.native_claim_ix(user);
+                let anchor = self.anchor_claim_ix(user);
+                self.send_both(user, native, anchor).await
+            }
+        }
+    }
+
+    pub async fn native_snapshot(&mut self) -> Snapshot {
+        let mut balances = Vec::new();
+        let mut positions = Vec::new();
+        for user in 0..self.users.len() {
+            let tokens = self.users[user].native_tokens;
+            balances.push(self.token_balance(tokens).await);
+            positions.push(self.native_position(user).await);
+        }
+
+        Snapshot {
+            balances,
+            positions,
+            staked: self.token_balance(self.native_vault).await,
+            paid: REWARD_FUNDING - self.token_balance(self.native_reward_vault).await,
+        }
+    }
+
+    pub async fn anchor_snapshot(&mut self) -> Snapshot {
+        let mut balances = Vec::new();
+        let mut positions = Vec::new();
+        for user in 0..self.users.len() {
+            let tokens = self.users[user].anchor_tokens;
+            balances.push(self.token_balance(tokens).await);
+            positions.push(self.anchor_position(user).await);
+        }
+
+        let mint = self.account_data(self.anchor_mint).await.unwrap();
+        let supply = spl_token::state::Mint::unpack(&mint).unwrap().supply;
+        Snapshot {
+            balances,
+            positions,
+            staked: self.token_balance(self.anchor_vault).await,
+            paid: supply - self.anchor_supply,
+        }
+    }
+
+    async fn send_both(
+        &mut self,
+        user: usize,
+        native: Instruction,
+        anchor: Instruction,
+    ) -> (bool, bool) {
+        let user = self.users[user].keypair.insecure_clone();
+        let native = self.send(&[native], &[&user]).await.is_ok();
+        let anchor = self.send(&[anchor], &[&user]).await.is_ok();
+        (native, anchor)
+    }
+
+    /// Sends `ixs` with the clock set to the harness's time, so operations
+    /// on either program see the same timestamp.
+    async fn send(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), BanksClientError> {
+        let blockhash = self.context.get_new_latest_blockhash().await.unwrap();
+        let mut clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp = self.now;
+        self.context.set_sysvar(&clock);
+
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let tx = Transaction::new_signed_with_payer(
+            ixs,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+        self.context.banks_client.process_transaction(tx).await
+    }
+
+    async fn account_data(&mut self, address: Pubkey) -> Option<Vec<u8>> {
+        let account = self.context.banks_client.get_account(address).await.unwrap()?;
+        Some(account.data)
+    }
+
+    async fn token_balance(&mut self, account: Pubkey) -> u64 {
+        let data = self.account_data(account).await.unwrap();
+        spl_token::state::Account::unpack(&data).unwrap().amount
+    }
+
+    async fn native_position(&mut self, user: usize) -> Option<(u64, u64)> {
+        let data = self.account_data(self.native_position_address(user)).await?;
+        let info = UserStakeInfo::load(&data).unwrap();
+        Some(({ info.amount }, { info.rewards }))
+    }
+
+    async fn anchor_position(&mut self, user: usize) -> Option<(u64, u64)> {
+        let data = self.account_data(self.anchor_position_address(user)).await?;
+        let info = UserStakeAccount::try_deserialize(&mut data.as_slice()).unwrap();
+        Some((info.amount, info.pending_rewards))
+    }
+
+    fn native_position_address(&self, user: usize) -> Pubkey {
+        let user = self.users[user].keypair.pubkey();
+        Pubkey::find_program_address(
+            &[b"user-stake", user.as_ref(), self.native_pool.as_ref()],
+            &staking_contract::ID,
+        )
+        .0
+    }
+
+    fn anchor_position_address(&self, user: usize) -> Pubkey {
+        let user = self.users[user].keypair.pubkey();
+        Pubkey::find_program_address(
+            &[b"user_stake", self.anchor_pool.as_ref(), user.as_ref()],
+            &staking::ID,
+        )
+        .0
+    }
+
+    fn native_stake_ix(&self, user: usize, amount: u64) -> Instruction {
+        native_ix(
+            vec![
+                AccountMeta::new(self.native_pool, false),
+                AccountMeta::new(self.native_vault, false),
+                AccountMeta::new(self.users[user].keypair.pubkey(), true),
+                AccountMeta::new(self.users[user].native_tokens, false),
+                AccountMeta::new(self.native_position_address(user), false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+                AccountMeta::new_readonly(system_program::ID, false),
+                AccountMeta::new_readonly(sysvar::rent::ID, false),
+            ],
+            StakingInstruction::Stake { amount },
+        )
+    }
+
+    fn native_unstake_ix(&self, user: usize, amount: u64) -> Instruction {
+        native_ix(
+            vec![
+                AccountMeta::new(self.native_pool, false),
+                AccountMeta::new(self.native_vault, false),
+                AccountMeta::new(self.users[user].keypair.pubkey(), true),
+                AccountMeta::new(self.users[user].native_tokens, false),
+                AccountMeta::new(self.native_position_address(user), false),
+                AccountMeta::new_readonly(self.native_vault_authority, false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+            ],
+            StakingInstruction::UnStake { amount },
+        )
+    }
+
+    fn native_claim_ix(&self, user: usize) -> Instruction {
+        native_ix(
+            vec![
+                AccountMeta::new(self.native_pool, false),
+                AccountMeta::new(self.native_reward_vault, false),
+                AccountMeta::new(self.users[user].keypair.pubkey(), true),
+                AccountMeta::new(self.users[user].native_tokens, false),
+                AccountMeta::new(self.native_position_address(user), false),
+                AccountMeta::new_readonly(self.native_vault_authority, false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+                AccountMeta::new_readonly(sysvar::instructions::ID, false),
+            ],
+            StakingInstruction::ClaimRewards,
+        )
+    }
+
+    fn anchor_stake_ix(&self, user: usize, amount: u64) -> Instruction {
+        let wallet = self.users[user].keypair.pubkey();
+        Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::Stake {
+                user: wallet,
+                beneficiary: wallet,
+                user_token_account: self.users[user].anchor_tokens,
+                vault: self.anchor_vault,
+                mint: self.anchor_mint,
+                staking_pool: self.anchor_pool,
+                user_stake_account: self.anchor_position_address(user),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::Stake {
+                amount,
+                allowlist: None,
+            }
+            .data(),
+        }
+    }
+
+    fn anchor_unstake_ix(&self, user: usize, amount: u64) -> Instruction {
+        Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::Unstake {
+                user: self.users[user].keypair.pubkey(),
+                user_token_account: self.users[user].anchor_tokens,
+                vault: self.anchor_vault,
+                mint: self.anchor_mint,
+                staking_pool: self.anchor_pool,
+                user_stake_account: self.anchor_position_address(user),
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: sysvar::rent::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::Unstake { amount }.data(),
+        }
+    }
+
+    fn anchor_claim_ix(&self, user: usize) -> Instruction {
+        let wallet = self.users[user].keypair.pubkey();
+        Instruction {
+            program_id: staking::ID,
+            accounts: staking::accounts::ClaimRewards {
+                claimer: wallet,
+                user: wallet,
+                user_token_account: self.users[user].anchor_tokens,
+                mint: self.anchor_mint,
+                vault: self.anchor_vault,
+                staking_pool: self.anchor_pool,
+                user_stake_account: self.anchor_position_address(user),
+                token_program: spl_token::ID,
+                instructions: sysvar::instructions::ID,
+            }
+            .to_account_metas(None),
+            data: staking::instruction::ClaimRewards {}.data(),
+        }
+    }
+}
+
+fn native_ix(accounts: Vec<AccountMeta>, data: StakingInstruction) -> Instruction {
+    Instruction {
+        program_id: staking_contract::ID,
+        accounts,
+        data: borsh::to_vec(&data).unwrap(),
+    }
+}
+
+fn mint_to(mint: &Pubkey, account: &Pubkey, authority: &Pubkey, amount: u64) -> Instruction {
+    spl_token::instruction::mint_to(&spl_token::ID, mint, account, authority, &[], amount).unwrap()
+}
+
+async fn send(context: &mut ProgramTestContext, ixs: &[Instruction], signers: &[&Keypair]) {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let tx = Transaction::new_signed_with_payer(
+        ixs,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+}
+
+async fn create_mint(context: &mut ProgramTestContext) -> Pubkey {
+    let mint = Keypair::new();
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    send(
+        context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::ID,
+                &mint.pubkey(),
+                &payer,
+                None,
+                6,
+            )
+            .unwrap(),
+        ],
+        &[&mint],
+    )
+    .await;
+
+    mint.pubkey()
+}
+
+async fn create_token_account(
+    context: &mut ProgramTestContext,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Pubkey {
+    let account = Keypair::new();
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    send(
+        context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &account.pubkey(),
+                rent.minimum_balance(spl_token::state::Account::LEN),
+                spl_token::state::Account::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::ID,
+                &account.pubkey(),
+                mint,
+                owner,
+            )
+            .unwrap(),
+        ],
+        &[&account],
+    )
+    .await;
+
+    account.pubkey()
+}